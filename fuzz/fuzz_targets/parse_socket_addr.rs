@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_anysocket::SocketAddr;
+
+fuzz_target!(|s: &str| {
+    let Ok(addr) = s.parse::<SocketAddr>() else {
+        return;
+    };
+
+    // Whatever parses must format to something that parses back to the same
+    // thing.
+    let formatted = addr.to_string();
+    let reparsed: SocketAddr = formatted
+        .parse()
+        .expect("formatted address should parse");
+    assert_eq!(formatted, reparsed.to_string(), "round-trip is not stable");
+});
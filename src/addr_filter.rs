@@ -0,0 +1,471 @@
+use std::fmt;
+use std::io::Error;
+use std::net::IpAddr;
+use std::os::unix::ffi::OsStrExt;
+use std::str::FromStr;
+
+use crate::socket_addr::unescape;
+use crate::utils::Result;
+use crate::{AddrParseError, SocketAddr, Verdict};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A set of addresses an [`AddrFilter`] rule applies to.
+///
+/// Parsed from one of:
+///
+/// - `*`, every address.
+/// - `tcp://*`, every TCP address.
+/// - `tcp://<ip>/<len>`, the TCP addresses whose IP is in the CIDR prefix,
+///   like `tcp://10.0.0.0/8` or `tcp://fd00::/8`. Without `/<len>`, only that
+///   one IP. IPv6 addresses may be enclosed in brackets. Bits after the prefix
+///   are ignored. Ports and IPv6 scope IDs are never compared.
+/// - `unix://*`, every unix address, including unnamed ones.
+/// - `unix://<glob>`, the unix paths matching the glob, like
+///   `unix:///run/trusted/*.sock`. `*` matches any bytes except `/`, `**`
+///   matches any bytes including `/` and `?` matches one byte except `/`.
+///   Everything else, including `%2A` for a literal `*`, is percent-decoded
+///   like in [`SocketAddr`] and must match exactly.
+/// - `unix://@<name>`, the abstract name, compared exactly.
+///
+/// `unix:` may be used instead of `unix://`, and schemes are matched
+/// case-insensitively. [`Display`](fmt::Display) writes a pattern that parses
+/// back to the same one.
+///
+/// IPv4 prefixes also match IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`),
+/// which is how dual-stack listeners report IPv4 peers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddrPattern {
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Any,
+    AnyTcp,
+    Cidr(IpAddr, u8),
+    AnyUnix,
+    /// The glob as written, and its parsed form.
+    Path(String, Vec<Glob>),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Abstract(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Glob {
+    Literal(Vec<u8>),
+    /// `?`
+    Byte,
+    /// `*`
+    Component,
+    /// `**`
+    Any,
+}
+
+impl AddrPattern {
+    #[must_use]
+    pub fn matches(&self, addr: &SocketAddr) -> bool {
+        match (&self.kind, addr) {
+            (Kind::Any, _) | (Kind::AnyTcp, SocketAddr::Tcp(_)) => true,
+            (Kind::AnyUnix, SocketAddr::Unix(_)) => true,
+            (Kind::Cidr(net, len), SocketAddr::Tcp(x)) => {
+                let ip = match net {
+                    IpAddr::V4(_) => x.ip().to_canonical(),
+                    IpAddr::V6(_) => x.ip(),
+                };
+
+                in_prefix(ip, *net, *len)
+            }
+            (Kind::Path(_, glob), _) => addr
+                .as_pathname()
+                .is_some_and(|x| glob_matches(glob, x.as_os_str().as_bytes())),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            (Kind::Abstract(name), _) => addr.as_abstract_name() == Some(name.as_slice()),
+            _ => false,
+        }
+    }
+}
+
+/// Whether `ip` is in the prefix of length `len` of `net`.
+fn in_prefix(ip: IpAddr, net: IpAddr, len: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net)
+        }
+        _ => false,
+    }
+}
+
+/// Clear the bits of `ip` after the prefix of length `len`.
+fn mask(ip: IpAddr, len: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0);
+            IpAddr::V4((u32::from(ip) & mask).into())
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+            IpAddr::V6((u128::from(ip) & mask).into())
+        }
+    }
+}
+
+fn glob_matches(glob: &[Glob], s: &[u8]) -> bool {
+    let Some((first, rest)) = glob.split_first() else {
+        return s.is_empty();
+    };
+
+    match first {
+        Glob::Literal(x) => s.starts_with(x) && glob_matches(rest, &s[x.len()..]),
+        Glob::Byte => s.first().is_some_and(|&x| x != b'/') && glob_matches(rest, &s[1..]),
+        Glob::Component => {
+            let end = s.iter().position(|&x| x == b'/').unwrap_or(s.len());
+            (0..=end).any(|i| glob_matches(rest, &s[i..]))
+        }
+        Glob::Any => (0..=s.len()).any(|i| glob_matches(rest, &s[i..])),
+    }
+}
+
+fn parse_glob(s: &str) -> Result<Vec<Glob>> {
+    let mut glob = Vec::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find(['*', '?']) {
+        if i != 0 {
+            glob.push(Glob::Literal(unescape(&rest.as_bytes()[..i])?));
+        }
+
+        rest = &rest[i..];
+        let (x, len) = match rest.as_bytes() {
+            [b'*', b'*', ..] => (Glob::Any, 2),
+            [b'*', ..] => (Glob::Component, 1),
+            _ => (Glob::Byte, 1),
+        };
+
+        glob.push(x);
+        rest = &rest[len..];
+    }
+
+    if !rest.is_empty() {
+        glob.push(Glob::Literal(unescape(rest.as_bytes())?));
+    }
+
+    if glob.is_empty() {
+        return Err(AddrParseError::EmptyPath.into());
+    }
+
+    if glob
+        .iter()
+        .any(|x| matches!(x, Glob::Literal(x) if x.contains(&0)))
+    {
+        return Err(AddrParseError::NulByte.into());
+    }
+
+    Ok(glob)
+}
+
+fn parse_cidr(s: &str) -> Result<Kind> {
+    let (ip, len) = match s.split_once('/') {
+        Some((ip, len)) => (ip, Some(len)),
+        None => (s, None),
+    };
+
+    let ip = ip
+        .strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .unwrap_or(ip);
+    let ip: IpAddr = ip.parse().map_err(AddrParseError::InvalidTcpAddr)?;
+
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    let len = match len {
+        Some(x) => x
+            .parse()
+            .ok()
+            .filter(|&x| x <= max)
+            .ok_or(AddrParseError::InvalidPrefixLen)?,
+        None => max,
+    };
+
+    Ok(Kind::Cidr(mask(ip, len), len))
+}
+
+impl fmt::Display for AddrPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Kind::Any => f.write_str("*"),
+            Kind::AnyTcp => f.write_str("tcp://*"),
+            Kind::Cidr(ip, len) => write!(f, "tcp://{ip}/{len}"),
+            Kind::AnyUnix => f.write_str("unix://*"),
+            Kind::Path(x, _) => write!(f, "unix://{x}"),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Kind::Abstract(name) => write!(f, "unix://@{}", crate::socket_addr::Escaped(name)),
+        }
+    }
+}
+
+impl FromStr for AddrPattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "*" {
+            return Ok(Self { kind: Kind::Any });
+        }
+
+        let Some((scheme, rest)) = s.split_once(':') else {
+            return Err(AddrParseError::InvalidScheme.into());
+        };
+
+        let kind = if scheme.eq_ignore_ascii_case("tcp") {
+            let Some(rest) = rest.strip_prefix("//") else {
+                return Err(AddrParseError::InvalidScheme.into());
+            };
+
+            match rest {
+                "*" => Kind::AnyTcp,
+                _ => parse_cidr(rest)?,
+            }
+        } else if scheme.eq_ignore_ascii_case("unix") {
+            let rest = rest.strip_prefix("//").unwrap_or(rest);
+
+            match rest {
+                "*" => Kind::AnyUnix,
+                _ => match rest.strip_prefix('@') {
+                    Some(name) => parse_abstract(name)?,
+                    None => Kind::Path(rest.to_owned(), parse_glob(rest)?),
+                },
+            }
+        } else {
+            return Err(AddrParseError::InvalidScheme.into());
+        };
+
+        Ok(Self { kind })
+    }
+}
+
+fn parse_abstract(s: &str) -> Result<Kind> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        // Checks the length.
+        let name = unescape(s.as_bytes())?;
+        SocketAddr::unix_abstract(&name)?;
+        Ok(Kind::Abstract(name))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = s;
+        Err(crate::utils::unsupported("the abstract namespace"))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// An access policy for peer addresses, as a list of rules that allow or deny
+/// an [`AddrPattern`].
+///
+/// The first rule whose pattern matches an address decides, and addresses
+/// that no rule matches are denied. Put exceptions before the rules they are
+/// exceptions to, and end with `allow *` to allow everything that is not
+/// denied:
+///
+/// ```text
+/// deny tcp://10.1.0.0/16, allow tcp://10.0.0.0/8, allow unix:///run/trusted/*.sock
+/// ```
+///
+/// Rules are parsed from `allow <pattern>` or `deny <pattern>` and separated
+/// by commas, so a comma in a unix path must be written as `%2C`. An empty
+/// rule, like in `a,,b`, is an [`AddrParseError::EmptyListEntry`] error.
+/// [`Display`](fmt::Display) writes the rules in the same form.
+///
+/// With the `serde` feature, the filter is serialized as a list of rules, and
+/// is deserialized from either that or a single string of comma-separated
+/// rules.
+///
+/// Unix clients rarely bind their sockets, so most unix peers are unnamed and
+/// only match `unix://*` and `*`. Use the credentials given to
+/// [`Listener::filtered`](crate::Listener::filtered) to tell those apart.
+///
+/// See [`Listener::filtered_addrs`](crate::Listener::filtered_addrs).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddrFilter {
+    rules: Vec<(Verdict, AddrPattern)>,
+}
+
+impl AddrFilter {
+    /// A filter without rules, which denies everything.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule that allows `pattern`.
+    #[must_use]
+    pub fn allow(mut self, pattern: AddrPattern) -> Self {
+        self.rules.push((Verdict::Accept, pattern));
+        self
+    }
+
+    /// Add a rule that denies `pattern`.
+    #[must_use]
+    pub fn deny(mut self, pattern: AddrPattern) -> Self {
+        self.rules.push((Verdict::Reject, pattern));
+        self
+    }
+
+    /// The rules in the order they are checked.
+    #[must_use]
+    pub fn rules(&self) -> &[(Verdict, AddrPattern)] {
+        &self.rules
+    }
+
+    /// What the first rule that matches `addr` says, or [`Verdict::Reject`] if
+    /// none does.
+    #[must_use]
+    pub fn verdict(&self, addr: &SocketAddr) -> Verdict {
+        self.rules
+            .iter()
+            .find(|(_, x)| x.matches(addr))
+            .map_or(Verdict::Reject, |&(x, _)| x)
+    }
+
+    /// Whether `addr` is allowed.
+    #[must_use]
+    pub fn matches(&self, addr: &SocketAddr) -> bool {
+        self.verdict(addr) == Verdict::Accept
+    }
+}
+
+fn fmt_rule(f: &mut fmt::Formatter<'_>, verdict: Verdict, pattern: &AddrPattern) -> fmt::Result {
+    match verdict {
+        Verdict::Accept => write!(f, "allow {pattern}"),
+        Verdict::Reject => write!(f, "deny {pattern}"),
+    }
+}
+
+fn parse_rule(s: &str) -> Result<(Verdict, AddrPattern)> {
+    let (verdict, pattern) = s
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or(AddrParseError::InvalidFilterRule)?;
+
+    let verdict = match verdict {
+        "allow" => Verdict::Accept,
+        "deny" => Verdict::Reject,
+        _ => return Err(AddrParseError::InvalidFilterRule.into()),
+    };
+
+    Ok((verdict, pattern.trim_start().parse()?))
+}
+
+impl fmt::Display for AddrFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (verdict, pattern)) in self.rules.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+
+            fmt_rule(f, *verdict, pattern)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for AddrFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Vec::new();
+
+        for part in s.split(',') {
+            if part.trim().is_empty() {
+                return Err(AddrParseError::EmptyListEntry.into());
+            }
+
+            rules.push(parse_rule(part)?);
+        }
+
+        Ok(Self { rules })
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+
+    use serde::de::{Error, SeqAccess, Visitor};
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    struct Rule<'a>(Verdict, &'a AddrPattern);
+
+    impl fmt::Display for Rule<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt_rule(f, self.0, self.1)
+        }
+    }
+
+    /// A list of rules as strings, like `["allow tcp://10.0.0.0/8"]`.
+    impl Serialize for AddrFilter {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let mut seq = serializer.serialize_seq(Some(self.rules.len()))?;
+            for (verdict, pattern) in &self.rules {
+                seq.serialize_element(&Rule(*verdict, pattern).to_string())?;
+            }
+
+            seq.end()
+        }
+    }
+
+    /// Accepts a list of rules, or a single string of comma-separated rules in
+    /// human-readable formats.
+    impl<'de> Deserialize<'de> for AddrFilter {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            struct FilterVisitor;
+
+            impl<'de> Visitor<'de> for FilterVisitor {
+                type Value = AddrFilter;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a list of address filter rules")
+                }
+
+                fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                where
+                    E: Error,
+                {
+                    v.parse().map_err(E::custom)
+                }
+
+                fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                where
+                    A: SeqAccess<'de>,
+                {
+                    let mut rules = Vec::new();
+                    while let Some(rule) = seq.next_element::<String>()? {
+                        rules.push(parse_rule(&rule).map_err(A::Error::custom)?);
+                    }
+
+                    Ok(AddrFilter { rules })
+                }
+            }
+
+            if deserializer.is_human_readable() {
+                deserializer.deserialize_any(FilterVisitor)
+            } else {
+                deserializer.deserialize_seq(FilterVisitor)
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::utils::Result;
+use crate::{OwnedReadHalf, OwnedWriteHalf, PeerCred, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Bytes read ahead from the inner stream.
+struct ReadBuffer {
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+}
+
+impl ReadBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    fn buffered(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    fn poll_fill_buf<R>(&mut self, inner: &mut R, cx: &mut Context<'_>) -> Poll<Result<&[u8]>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        if self.pos == self.filled {
+            let mut buf = ReadBuf::new(&mut self.buf);
+            ready!(Pin::new(inner).poll_read(cx, &mut buf))?;
+
+            self.filled = buf.filled().len();
+            self.pos = 0;
+        }
+
+        Poll::Ready(Ok(self.buffered()))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = usize::min(self.pos + amt, self.filled);
+    }
+
+    fn poll_read<R>(
+        &mut self,
+        inner: &mut R,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        // Nothing to gain from copying through the buffer.
+        if self.pos == self.filled && buf.remaining() >= self.buf.len() {
+            return Pin::new(inner).poll_read(cx, buf);
+        }
+
+        let available = ready!(self.poll_fill_buf(inner, cx))?;
+        let n = usize::min(available.len(), buf.remaining());
+        buf.put_slice(&available[..n]);
+        self.consume(n);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for ReadBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.filled - self.pos, self.buf.len())
+    }
+}
+
+/// Bytes written to the wrapper but not yet to the inner stream.
+struct WriteBuffer {
+    buf: Vec<u8>,
+    capacity: usize,
+    written: usize,
+}
+
+impl WriteBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+            capacity,
+            written: 0,
+        }
+    }
+
+    fn poll_flush_buf<W>(&mut self, inner: &mut W, cx: &mut Context<'_>) -> Poll<Result<()>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        while self.written < self.buf.len() {
+            let n = ready!(Pin::new(&mut *inner).poll_write(cx, &self.buf[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write the buffered data",
+                )));
+            }
+
+            self.written += n;
+        }
+
+        self.buf.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write<W>(
+        &mut self,
+        inner: &mut W,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if self.buf.len() + buf.len() > self.capacity {
+            ready!(self.poll_flush_buf(inner, cx))?;
+        }
+
+        // Nothing to gain from copying through the buffer.
+        if buf.len() >= self.capacity {
+            return Pin::new(inner).poll_write(cx, buf);
+        }
+
+        self.buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush<W>(&mut self, inner: &mut W, cx: &mut Context<'_>) -> Poll<Result<()>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        ready!(self.poll_flush_buf(inner, cx))?;
+        Pin::new(inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown<W>(&mut self, inner: &mut W, cx: &mut Context<'_>) -> Poll<Result<()>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        ready!(self.poll_flush_buf(inner, cx))?;
+        Pin::new(inner).poll_shutdown(cx)
+    }
+}
+
+impl fmt::Debug for WriteBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.buf.len() - self.written, self.capacity)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+macro_rules! impl_buffered {
+    ($T:ident, $inner:ty) => {
+        impl $T {
+            /// The inner stream. Reading from or writing to it directly
+            /// bypasses the buffers.
+            #[must_use]
+            pub fn get_ref(&self) -> &$inner {
+                &self.inner
+            }
+
+            /// See [`get_ref`](Self::get_ref).
+            #[must_use]
+            pub fn get_mut(&mut self) -> &mut $inner {
+                &mut self.inner
+            }
+
+            /// The inner stream. Buffered data is lost, so flush first.
+            pub fn into_inner(self) -> $inner {
+                self.inner
+            }
+
+            #[must_use]
+            pub fn is_tcp(&self) -> bool {
+                self.inner.is_tcp()
+            }
+
+            #[must_use]
+            pub fn is_unix(&self) -> bool {
+                self.inner.is_unix()
+            }
+
+            #[must_use]
+            pub fn scheme(&self) -> &'static str {
+                self.inner.scheme()
+            }
+
+            pub fn local_addr(&self) -> Result<SocketAddr> {
+                self.inner.local_addr()
+            }
+
+            pub fn peer_addr(&self) -> Result<SocketAddr> {
+                self.inner.peer_addr()
+            }
+        }
+    };
+}
+
+macro_rules! impl_buffered_read {
+    ($T:ident) => {
+        impl $T {
+            pub fn peer_cred(&self) -> Result<Option<PeerCred>> {
+                self.inner.peer_cred()
+            }
+
+            /// The bytes that have been read ahead and not returned yet.
+            #[must_use]
+            pub fn read_buffer(&self) -> &[u8] {
+                self.read.buffered()
+            }
+        }
+
+        impl AsyncRead for $T {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<Result<()>> {
+                let this = self.get_mut();
+                this.read.poll_read(&mut this.inner, cx, buf)
+            }
+        }
+
+        impl AsyncBufRead for $T {
+            fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+                let this = self.get_mut();
+                this.read.poll_fill_buf(&mut this.inner, cx)
+            }
+
+            fn consume(self: Pin<&mut Self>, amt: usize) {
+                self.get_mut().read.consume(amt);
+            }
+        }
+    };
+}
+
+macro_rules! impl_buffered_write {
+    ($T:ident) => {
+        impl $T {
+            /// The bytes that have been written but not passed to the inner
+            /// stream yet.
+            #[must_use]
+            pub fn write_buffer(&self) -> &[u8] {
+                &self.write.buf[self.write.written..]
+            }
+        }
+
+        impl AsyncWrite for $T {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<Result<usize>> {
+                let this = self.get_mut();
+                this.write.poll_write(&mut this.inner, cx, buf)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                let this = self.get_mut();
+                this.write.poll_flush(&mut this.inner, cx)
+            }
+
+            /// Flush the write buffer, then shut down the inner stream.
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                let this = self.get_mut();
+                this.write.poll_shutdown(&mut this.inner, cx)
+            }
+        }
+    };
+}
+
+/// A [`Stream`] with a read and a write buffer.
+///
+/// Unlike wrapping a stream in `tokio::io::BufStream`, this keeps the address
+/// and identity methods of the stream, and can be split into buffered halves
+/// with [`BufferedStream::into_split`].
+///
+/// Reads are served from the read buffer, which is refilled with one read from
+/// the stream once it is empty. Reads at least as large as the buffer go to
+/// the stream directly while the buffer is empty. Writes are collected in the
+/// write buffer until it is full or flushed. Writes at least as large as the
+/// buffer go to the stream directly once it is flushed.
+///
+/// Nothing is flushed implicitly. Dropping the stream discards the write
+/// buffer, so call `flush` or `shutdown` (which flushes first) when done.
+///
+/// The `try_*` and readiness methods of [`Stream`] are left out on purpose,
+/// since they would skip the buffers. They are still available through
+/// [`BufferedStream::get_ref`] for code that takes care of that.
+///
+/// Created by [`Stream::buffered`].
+#[derive(Debug)]
+pub struct BufferedStream {
+    inner: Stream,
+    read: ReadBuffer,
+    write: WriteBuffer,
+}
+
+impl BufferedStream {
+    /// # Panics
+    ///
+    /// If either capacity is zero.
+    pub fn new(stream: Stream, read_capacity: usize, write_capacity: usize) -> Self {
+        assert!(read_capacity > 0, "read capacity must not be zero");
+        assert!(write_capacity > 0, "write capacity must not be zero");
+
+        Self {
+            inner: stream,
+            read: ReadBuffer::new(read_capacity),
+            write: WriteBuffer::new(write_capacity),
+        }
+    }
+
+    /// Split into halves that each keep their buffer, along with any data in
+    /// it.
+    pub fn into_split(self) -> (BufferedOwnedReadHalf, BufferedOwnedWriteHalf) {
+        let (r, w) = self.inner.into_split();
+
+        let r = BufferedOwnedReadHalf {
+            inner: r,
+            read: self.read,
+        };
+
+        let w = BufferedOwnedWriteHalf {
+            inner: w,
+            write: self.write,
+        };
+
+        (r, w)
+    }
+}
+
+impl_buffered!(BufferedStream, Stream);
+impl_buffered_read!(BufferedStream);
+impl_buffered_write!(BufferedStream);
+
+/// The read half of a [`BufferedStream`].
+#[derive(Debug)]
+pub struct BufferedOwnedReadHalf {
+    inner: OwnedReadHalf,
+    read: ReadBuffer,
+}
+
+impl_buffered!(BufferedOwnedReadHalf, OwnedReadHalf);
+impl_buffered_read!(BufferedOwnedReadHalf);
+
+/// The write half of a [`BufferedStream`].
+#[derive(Debug)]
+pub struct BufferedOwnedWriteHalf {
+    inner: OwnedWriteHalf,
+    write: WriteBuffer,
+}
+
+impl_buffered!(BufferedOwnedWriteHalf, OwnedWriteHalf);
+impl_buffered_write!(BufferedOwnedWriteHalf);
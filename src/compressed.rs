@@ -0,0 +1,390 @@
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use async_compression::Level;
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::Stream;
+use crate::utils::Result;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A compression algorithm of a [`CompressedStream`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Zstandard, with one frame per flush.
+    #[default]
+    Zstd,
+    /// Gzip, with one member per flush.
+    Gzip,
+}
+
+/// Configuration of a [`CompressedStream`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CompressionConfig {
+    /// The algorithm of both directions.
+    pub algorithm: Compression,
+    /// The compression level, in the range of the algorithm. Levels outside of
+    /// it are clamped. `None` uses the default of the algorithm.
+    pub level: Option<i32>,
+    /// Pass data through unchanged, in both directions.
+    pub passthrough: bool,
+}
+
+impl CompressionConfig {
+    #[must_use]
+    pub fn algorithm(mut self, algorithm: Compression) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    #[must_use]
+    pub fn level(mut self, level: Option<i32>) -> Self {
+        self.level = level;
+        self
+    }
+
+    #[must_use]
+    pub fn passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A stream that compresses what is written to it and decompresses what is
+/// read from it.
+///
+/// Written data is compressed into a frame (a member, for gzip) that is only
+/// completed by [`flush`](tokio::io::AsyncWriteExt::flush). Until then, the
+/// compressor may hold back any of the data, so a request must be flushed for
+/// the peer to see it. Each flush ends the frame and writes it out in full,
+/// which costs a few bytes of overhead and the dictionary built so far, so
+/// flush at message boundaries rather than after every write. Flushing without
+/// anything written since the last flush sends nothing.
+///
+/// Reads accept any number of frames back to back, and return data as soon as
+/// the decompressor has it. The peer must be a [`CompressedStream`] with the
+/// same algorithm, or anything that writes concatenated frames.
+///
+/// With [`CompressionConfig::passthrough`], reads and writes go to the inner
+/// stream unchanged, so connections that did not negotiate compression can use
+/// the same type.
+///
+/// Only available with the `compression` feature.
+#[derive(Debug)]
+pub struct CompressedStream<S = Stream> {
+    inner: S,
+    config: CompressionConfig,
+    /// The frame being written, if anything has been written since the last
+    /// flush.
+    encoder: Option<Encoder>,
+    /// Compressed bytes that have not been written to `inner` yet.
+    outgoing: Vec<u8>,
+    /// How many bytes of `outgoing` have been written.
+    written: usize,
+    decoder: Decoder,
+}
+
+impl<S> CompressedStream<S> {
+    /// Wrap `stream`.
+    pub fn new(stream: S, config: CompressionConfig) -> Self {
+        Self {
+            inner: stream,
+            decoder: Decoder::new(&config),
+            config,
+            encoder: None,
+            outgoing: Vec::new(),
+            written: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &CompressionConfig {
+        &self.config
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    #[must_use]
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Get the inner stream.
+    ///
+    /// Data written since the last flush and received data that has not been
+    /// read yet are discarded.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> CompressedStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    /// Write out all of `outgoing`.
+    fn poll_outgoing(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.written < self.outgoing.len() {
+            let n =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &self.outgoing[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write compressed data",
+                )));
+            }
+
+            self.written += n;
+        }
+
+        self.outgoing.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncRead for CompressedStream<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        if this.config.passthrough {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        loop {
+            this.decoder.incoming().starved = false;
+
+            if let Poll::Ready(x) = this.decoder.poll_read(cx, buf) {
+                return Poll::Ready(x);
+            }
+
+            if !this.decoder.incoming().starved {
+                return Poll::Pending;
+            }
+
+            let mut scratch = [0u8; 8192];
+            let mut scratch = ReadBuf::new(&mut scratch);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut scratch))?;
+
+            let incoming = this.decoder.incoming();
+            match scratch.filled() {
+                [] => incoming.eof = true,
+                x => incoming.buf.extend_from_slice(x),
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for CompressedStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+
+        if this.config.passthrough {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        // Compress nothing new before the previous output is out, so that
+        // `outgoing` stays bounded when the inner stream is slow.
+        ready!(this.poll_outgoing(cx))?;
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let encoder = this
+            .encoder
+            .get_or_insert_with(|| Encoder::new(&this.config));
+
+        let n = ready!(encoder.poll_write(cx, buf))?;
+        this.outgoing.append(encoder.output());
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        if this.config.passthrough {
+            return Pin::new(&mut this.inner).poll_flush(cx);
+        }
+
+        if let Some(encoder) = &mut this.encoder {
+            ready!(encoder.poll_shutdown(cx))?;
+            this.outgoing.append(encoder.output());
+            this.encoder = None;
+        }
+
+        ready!(this.poll_outgoing(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The compressor of the frame being written. It writes into a [`Vec`], which
+/// never blocks, so its polls are always ready.
+#[derive(Debug)]
+enum Encoder {
+    Zstd(ZstdEncoder<Vec<u8>>),
+    Gzip(GzipEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(config: &CompressionConfig) -> Self {
+        let level = config.level.map_or(Level::Default, Level::Precise);
+
+        match config.algorithm {
+            Compression::Zstd => Self::Zstd(ZstdEncoder::with_quality(Vec::new(), level)),
+            Compression::Gzip => Self::Gzip(GzipEncoder::with_quality(Vec::new(), level)),
+        }
+    }
+
+    /// The compressed bytes produced so far.
+    fn output(&mut self) -> &mut Vec<u8> {
+        match self {
+            Self::Zstd(x) => x.get_mut(),
+            Self::Gzip(x) => x.get_mut(),
+        }
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        match self {
+            Self::Zstd(x) => Pin::new(x).poll_write(cx, buf),
+            Self::Gzip(x) => Pin::new(x).poll_write(cx, buf),
+        }
+    }
+
+    /// End the frame.
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Zstd(x) => Pin::new(x).poll_shutdown(cx),
+            Self::Gzip(x) => Pin::new(x).poll_shutdown(cx),
+        }
+    }
+}
+
+/// The decompressor of the read path, fed through an [`Incoming`].
+#[derive(Debug)]
+enum Decoder {
+    Zstd(ZstdDecoder<Incoming>),
+    Gzip(GzipDecoder<Incoming>),
+}
+
+impl Decoder {
+    fn new(config: &CompressionConfig) -> Self {
+        match config.algorithm {
+            Compression::Zstd => {
+                let mut x = ZstdDecoder::new(Incoming::default());
+                x.multiple_members(true);
+                Self::Zstd(x)
+            }
+            Compression::Gzip => {
+                let mut x = GzipDecoder::new(Incoming::default());
+                x.multiple_members(true);
+                Self::Gzip(x)
+            }
+        }
+    }
+
+    fn incoming(&mut self) -> &mut Incoming {
+        match self {
+            Self::Zstd(x) => x.get_mut(),
+            Self::Gzip(x) => x.get_mut(),
+        }
+    }
+
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Zstd(x) => Pin::new(x).poll_read(cx, buf),
+            Self::Gzip(x) => Pin::new(x).poll_read(cx, buf),
+        }
+    }
+}
+
+/// Compressed bytes received from the inner stream.
+///
+/// When it runs out, it returns [`Poll::Pending`] without registering the
+/// waker and sets `starved`. The [`CompressedStream`] then reads more from the
+/// inner stream, which registers the waker, and polls the decoder again.
+#[derive(Debug, Default)]
+struct Incoming {
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    starved: bool,
+}
+
+impl AsyncRead for Incoming {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let data = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = usize::min(data.len(), buf.remaining());
+        buf.put_slice(&data[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncBufRead for Incoming {
+    fn poll_fill_buf(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<&[u8]>> {
+        let this = self.get_mut();
+
+        if this.pos == this.buf.len() && !this.eof {
+            this.starved = true;
+            return Poll::Pending;
+        }
+
+        Poll::Ready(Ok(&this.buf[this.pos..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+
+        this.pos += amt;
+        if this.pos == this.buf.len() {
+            this.buf.clear();
+            this.pos = 0;
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+impl Stream {
+    /// Compress writes and decompress reads.
+    ///
+    /// See [`CompressedStream`].
+    ///
+    /// Only available with the `compression` feature.
+    pub fn compressed(self, config: CompressionConfig) -> CompressedStream {
+        CompressedStream::new(self, config)
+    }
+}
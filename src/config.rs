@@ -0,0 +1,363 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::de::{Error as _, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::utils::Result;
+use crate::{
+    AcceptOptions, BindOptions, ConnectOptions, KeepaliveConfig, Listener, SocketAddr, Stream,
+};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A listener as described in a configuration file.
+///
+/// The address can be a single address or a list of them, which are tried in
+/// order like the addresses given to [`Listener::bind`]. All other fields are
+/// optional and default to what [`BindOptions`] and [`AcceptOptions`] do.
+/// Unknown fields are rejected.
+///
+/// ```toml
+/// addr = "unix:///run/app.sock"
+/// mode = 0o660
+/// backlog = 128
+/// ```
+///
+/// Options that only apply to some addresses are checked when binding, like
+/// with [`Listener::bind_with`]. Setting [`ListenerConfig::mode`] for a TCP
+/// address fails then, while the accept options that only apply to TCP are
+/// skipped for unix connections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct ListenerConfig {
+    #[serde(
+        serialize_with = "serialize_addrs",
+        deserialize_with = "deserialize_addrs"
+    )]
+    pub addr: Vec<SocketAddr>,
+
+    /// See [`BindOptions::backlog`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backlog: Option<u32>,
+
+    /// See [`BindOptions::reuse_address`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reuse_address: Option<bool>,
+
+    /// See [`BindOptions::reuse_port`].
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub reuse_port: bool,
+
+    /// See [`BindOptions::v6_only`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v6_only: Option<bool>,
+
+    /// See [`BindOptions::reclaim_stale_unix`].
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub reclaim_stale_unix: bool,
+
+    /// See [`BindOptions::mode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+
+    /// See [`BindOptions::uid`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+
+    /// See [`BindOptions::gid`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+
+    /// See [`BindOptions::create_dirs`].
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub create_dirs: bool,
+
+    /// See [`BindOptions::dir_mode`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<u32>,
+
+    /// See [`AcceptOptions::nodelay`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nodelay: Option<bool>,
+
+    /// See [`AcceptOptions::keepalive`].
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "keepalive")]
+    pub keepalive: Option<KeepaliveConfig>,
+
+    /// See [`AcceptOptions::send_buffer_size`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_buffer_size: Option<usize>,
+
+    /// See [`AcceptOptions::recv_buffer_size`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recv_buffer_size: Option<usize>,
+}
+
+impl ListenerConfig {
+    #[must_use]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr: vec![addr],
+            backlog: None,
+            reuse_address: None,
+            reuse_port: false,
+            v6_only: None,
+            reclaim_stale_unix: false,
+            mode: None,
+            uid: None,
+            gid: None,
+            create_dirs: false,
+            dir_mode: None,
+            nodelay: None,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+        }
+    }
+
+    /// The options [`Listener::bind_config`] binds with.
+    #[must_use]
+    pub fn bind_options(&self) -> BindOptions {
+        let defaults = BindOptions::default();
+
+        let mut options = BindOptions::default()
+            .backlog(self.backlog.unwrap_or(defaults.backlog))
+            .reuse_address(self.reuse_address.unwrap_or(defaults.reuse_address))
+            .reuse_port(self.reuse_port)
+            .reclaim_stale_unix(self.reclaim_stale_unix)
+            .create_dirs(self.create_dirs);
+
+        options.v6_only = self.v6_only;
+        options.mode = self.mode;
+        options.uid = self.uid;
+        options.gid = self.gid;
+        options.dir_mode = self.dir_mode;
+        options
+    }
+
+    /// The options to pass to [`Listener::accept_with`] for the listener
+    /// bound by [`Listener::bind_config`].
+    #[must_use]
+    pub fn accept_options(&self) -> AcceptOptions {
+        AcceptOptions {
+            nodelay: self.nodelay,
+            keepalive: self.keepalive,
+            send_buffer_size: self.send_buffer_size,
+            recv_buffer_size: self.recv_buffer_size,
+            ..AcceptOptions::default()
+        }
+    }
+}
+
+impl Listener {
+    /// Bind a listener as described by `config`.
+    ///
+    /// The accept options of `config` apply to each connection, so accept
+    /// them with [`Listener::accept_with`] and
+    /// [`ListenerConfig::accept_options`].
+    pub async fn bind_config(config: &ListenerConfig) -> Result<Self> {
+        Self::bind_with(config.addr.as_slice(), &config.bind_options()).await
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A connection as described in a configuration file.
+///
+/// The address can be a single address or a list of them, which are tried in
+/// order like the addresses given to [`Stream::connect`]. All other fields
+/// are optional and leave the system defaults alone. Unknown fields are
+/// rejected.
+///
+/// ```toml
+/// addr = ["tcp://10.0.0.1:5432", "tcp://10.0.0.2:5432"]
+/// nodelay = true
+/// keepalive = { time = 60, interval = 10, retries = 5 }
+/// ```
+///
+/// The options that only apply to TCP connections are skipped for unix
+/// ones, except for [`ConnectConfig::tos`], which fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct ConnectConfig {
+    #[serde(
+        serialize_with = "serialize_addrs",
+        deserialize_with = "deserialize_addrs"
+    )]
+    pub addr: Vec<SocketAddr>,
+
+    /// See [`ConnectOptions::local_addr`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_addr: Option<SocketAddr>,
+
+    /// See [`ConnectOptions::tos`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tos: Option<u32>,
+
+    /// See [`Stream::set_nodelay`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nodelay: Option<bool>,
+
+    /// See [`Stream::set_keepalive`].
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "keepalive")]
+    pub keepalive: Option<KeepaliveConfig>,
+
+    /// See [`Stream::set_send_buffer_size`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_buffer_size: Option<usize>,
+
+    /// See [`Stream::set_recv_buffer_size`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recv_buffer_size: Option<usize>,
+}
+
+impl ConnectConfig {
+    #[must_use]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr: vec![addr],
+            local_addr: None,
+            tos: None,
+            nodelay: None,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+        }
+    }
+
+    /// The options [`Stream::connect_config`] connects with.
+    #[must_use]
+    pub fn connect_options(&self) -> ConnectOptions {
+        ConnectOptions {
+            local_addr: self.local_addr.clone(),
+            tos: self.tos,
+            ..ConnectOptions::default()
+        }
+    }
+}
+
+impl Stream {
+    /// Connect as described by `config`.
+    ///
+    /// The socket options are set before connecting, the others right after.
+    /// If one of those cannot be set, the connection is closed and the error
+    /// is returned.
+    pub async fn connect_config(config: &ConnectConfig) -> Result<Self> {
+        let stream = Self::connect_with(config.addr.as_slice(), &config.connect_options()).await?;
+
+        let options = AcceptOptions {
+            nodelay: config.nodelay,
+            keepalive: config.keepalive,
+            send_buffer_size: config.send_buffer_size,
+            recv_buffer_size: config.recv_buffer_size,
+            ..AcceptOptions::default()
+        };
+
+        options.apply(&stream)?;
+        Ok(stream)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(x: &bool) -> bool {
+    !x
+}
+
+fn serialize_addrs<S>(addrs: &[SocketAddr], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match addrs {
+        [addr] if serializer.is_human_readable() => addr.serialize(serializer),
+        _ => addrs.serialize(serializer),
+    }
+}
+
+/// Accepts an address or a non-empty list of them.
+fn deserialize_addrs<'de, D>(deserializer: D) -> Result<Vec<SocketAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct AddrsVisitor;
+
+    impl<'de> Visitor<'de> for AddrsVisitor {
+        type Value = Vec<SocketAddr>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a socket address or a non-empty list of them")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            v.parse().map(|x| vec![x]).map_err(E::custom)
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut addrs = Vec::new();
+            while let Some(addr) = seq.next_element()? {
+                addrs.push(addr);
+            }
+
+            if addrs.is_empty() {
+                return Err(A::Error::invalid_length(0, &self));
+            }
+
+            Ok(addrs)
+        }
+    }
+
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(AddrsVisitor)
+    } else {
+        deserializer.deserialize_seq(AddrsVisitor)
+    }
+}
+
+/// [`KeepaliveConfig`] with the durations in whole seconds, which is all the
+/// kernel keeps.
+mod keepalive {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Secs {
+        time: u64,
+        interval: u64,
+        retries: u32,
+    }
+
+    pub fn serialize<S>(x: &Option<KeepaliveConfig>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        x.map(|x| Secs {
+            time: x.time.as_secs(),
+            interval: x.interval.as_secs(),
+            retries: x.retries,
+        })
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<KeepaliveConfig>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let x = Option::<Secs>::deserialize(deserializer)?;
+
+        Ok(x.map(|x| KeepaliveConfig {
+            time: Duration::from_secs(x.time),
+            interval: Duration::from_secs(x.interval),
+            retries: x.retries,
+        }))
+    }
+}
@@ -0,0 +1,44 @@
+use tonic::transport::server::Connected;
+
+use crate::{PeerCred, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Information about a connection served by tonic.
+///
+/// tonic adds it to the extensions of each request on the connection, so
+/// services get it with `request.extensions().get::<ConnectInfo>()`.
+/// `Request::remote_addr` only knows about tonic's own TCP streams and stays
+/// `None`.
+///
+/// Only available with the `tonic` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectInfo {
+    pub local_addr: Option<SocketAddr>,
+    pub peer_addr: Option<SocketAddr>,
+    /// The credentials of the peer process, for unix connections on
+    /// platforms that report them.
+    pub peer_cred: Option<PeerCred>,
+}
+
+impl ConnectInfo {
+    /// Collect the information of `stream`. Whatever the stream fails to
+    /// report is left out.
+    pub fn new(stream: &Stream) -> Self {
+        Self {
+            local_addr: stream.local_addr().ok(),
+            peer_addr: stream.peer_addr().ok(),
+            peer_cred: stream.peer_cred().ok().flatten(),
+        }
+    }
+}
+
+/// Only available with the `tonic` feature.
+impl Connected for Stream {
+    type ConnectInfo = ConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        ConnectInfo::new(self)
+    }
+}
@@ -0,0 +1,126 @@
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::Uri;
+
+use crate::utils::Result;
+use crate::{ConnectOptions, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The future returned by [`Connector`] as a [`tower_service::Service`].
+pub type Connecting = Pin<Box<dyn Future<Output = Result<Stream>> + Send>>;
+
+/// Connects to the server of a URI, for HTTP clients built on tower, like the
+/// legacy `Client` of hyper-util.
+///
+/// `http://host:port` URIs connect to `host` over TCP, on port 80 if there is
+/// none. `unix://` and `http+unix://` URIs connect to the unix socket whose
+/// path is the authority, hex-encoded like hyperlocal does, since URIs do not
+/// allow percent-encoding there. [`Connector::unix_uri`] creates them. Other
+/// schemes fail with [`ErrorKind::InvalidInput`], as does TLS, which has to
+/// be layered on top.
+///
+/// As a service it is always ready, and clones share the options.
+///
+/// Only available with the `tower` feature.
+#[derive(Debug, Clone, Default)]
+pub struct Connector {
+    options: Arc<ConnectOptions>,
+}
+
+impl Connector {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create sockets according to `options`.
+    #[must_use]
+    pub fn with_options(options: ConnectOptions) -> Self {
+        Self {
+            options: Arc::new(options),
+        }
+    }
+
+    #[must_use]
+    pub fn options(&self) -> &ConnectOptions {
+        &self.options
+    }
+
+    /// Connect to the server of `uri`.
+    pub async fn connect(&self, uri: &Uri) -> Result<Stream> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidInput, format!("{msg}: {uri}"));
+
+        let host = uri.host().ok_or_else(|| invalid("no host in URI"))?;
+
+        match uri.scheme_str() {
+            Some("http") => {
+                Stream::connect_host_with(host, uri.port_u16().unwrap_or(80), &self.options).await
+            }
+            Some("unix" | "http+unix") => {
+                let path = hex_decode(host).ok_or_else(|| invalid("invalid unix socket path"))?;
+                let addr = SocketAddr::unix(OsStr::from_bytes(&path))?;
+                Stream::connect_with(addr, &self.options).await
+            }
+            _ => Err(invalid("unsupported URI scheme")),
+        }
+    }
+
+    /// Create a `unix://` URI for `path_and_query` on the server at `socket`.
+    ///
+    /// Fails with [`ErrorKind::InvalidInput`] if `path_and_query` is not a
+    /// valid path of a URI.
+    pub fn unix_uri(socket: impl AsRef<Path>, path_and_query: &str) -> Result<Uri> {
+        let mut uri = String::from("unix://");
+        for x in socket.as_ref().as_os_str().as_bytes() {
+            let _ = write!(uri, "{x:02x}");
+        }
+        uri.push_str(":0");
+        uri.push_str(path_and_query);
+
+        uri.parse()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+    }
+}
+
+impl tower_service::Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = Error;
+    type Future = Connecting;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let this = self.clone();
+        Box::pin(async move { this.connect(&uri).await })
+    }
+}
+
+/// Only available with the `hyper-util` feature.
+#[cfg(feature = "hyper-util")]
+impl hyper_util::client::legacy::connect::Connection for Stream {
+    fn connected(&self) -> hyper_util::client::legacy::connect::Connected {
+        hyper_util::client::legacy::connect::Connected::new()
+    }
+}
+
+fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|x| x.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,261 @@
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::fd::{AsFd, OwnedFd};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use rustix::io::Errno;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use rustix::pipe::{PipeFlags, SpliceFlags};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use tokio::io::Interest;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::Stream;
+use crate::utils::Result;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Most bytes moved into the pipe at once. This is the default pipe capacity,
+/// so splicing into an empty pipe never blocks on the pipe itself.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SPLICE_LEN: usize = 64 * 1024;
+
+const BUF_LEN: usize = 8 * 1024;
+
+/// Copy data in both directions between `a` and `b`.
+///
+/// When one stream reaches end-of-file, the write side of the other is shut
+/// down, and copying continues in the other direction until it also reaches
+/// end-of-file. Returns the number of bytes copied from `a` to `b` and from
+/// `b` to `a`.
+///
+/// This behaves like [`tokio::io::copy_bidirectional`]. On Linux, data is
+/// moved with `splice(2)` through a pipe and never enters userspace. Where
+/// splice is not available, or if the kernel rejects it for a socket with
+/// `EINVAL`, data is copied through a buffer instead.
+pub async fn copy_bidirectional(a: &mut Stream, b: &mut Stream) -> Result<(u64, u64)> {
+    let mut a_to_b = Transfer::new()?;
+    let mut b_to_a = Transfer::new()?;
+
+    poll_fn(|cx| {
+        let a_to_b = a_to_b.poll(cx, a, b)?;
+        let b_to_a = b_to_a.poll(cx, b, a)?;
+
+        match (a_to_b, b_to_a) {
+            (Poll::Ready(a_to_b), Poll::Ready(b_to_a)) => Poll::Ready(Ok((a_to_b, b_to_a))),
+            _ => Poll::Pending,
+        }
+    })
+    .await
+}
+
+/// One direction of [`copy_bidirectional`].
+struct Transfer {
+    mode: Mode,
+    amt: u64,
+    done: bool,
+}
+
+enum Mode {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Splice(Pipe),
+    Buffered(Buffer),
+}
+
+impl Transfer {
+    fn new() -> Result<Self> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let mode = Mode::Splice(Pipe::new()?);
+
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let mode = Mode::Buffered(Buffer::new(Vec::new()));
+
+        Ok(Self {
+            mode,
+            amt: 0,
+            done: false,
+        })
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &mut Stream,
+        dst: &mut Stream,
+    ) -> Poll<Result<u64>> {
+        if self.done {
+            return Poll::Ready(Ok(self.amt));
+        }
+
+        loop {
+            let result = match &mut self.mode {
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                Mode::Splice(pipe) => ready!(pipe.poll_copy(cx, src, dst, &mut self.amt)),
+                Mode::Buffered(buf) => ready!(buf.poll_copy(cx, src, dst, &mut self.amt)),
+            };
+
+            match result {
+                Ok(()) => break,
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                Err(e) if e.raw_os_error() == Some(Errno::INVAL.raw_os_error()) => {
+                    if let Mode::Splice(pipe) = &mut self.mode {
+                        self.mode = Mode::Buffered(pipe.drain()?);
+                    }
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+
+        ready!(Pin::new(&mut *dst).poll_flush(cx))?;
+        ready!(Pin::new(&mut *dst).poll_shutdown(cx))?;
+
+        self.done = true;
+        Poll::Ready(Ok(self.amt))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Copies with `splice(2)` through a pipe.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+    /// Bytes in the pipe which have not been written to the destination yet.
+    len: usize,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Pipe {
+    fn new() -> Result<Self> {
+        let (read, write) = rustix::pipe::pipe_with(PipeFlags::NONBLOCK | PipeFlags::CLOEXEC)?;
+        Ok(Self {
+            read,
+            write,
+            len: 0,
+        })
+    }
+
+    /// Copy until `src` reaches end-of-file.
+    ///
+    /// Errors are returned as-is, so `EINVAL` can be told apart.
+    fn poll_copy(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &Stream,
+        dst: &Stream,
+        amt: &mut u64,
+    ) -> Poll<Result<()>> {
+        loop {
+            // The pipe is only filled once it is empty, so `EAGAIN` can only
+            // come from the socket and it is safe to clear its readiness.
+            if self.len > 0 {
+                ready!(dst.poll_write_ready(cx))?;
+
+                match dst.try_io(Interest::WRITABLE, || splice(&self.read, dst, self.len)) {
+                    Ok(0) => return Poll::Ready(Err(ErrorKind::WriteZero.into())),
+                    Ok(n) => {
+                        self.len -= n;
+                        *amt += n as u64;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            } else {
+                ready!(src.poll_read_ready(cx))?;
+
+                match src.try_io(Interest::READABLE, || splice(src, &self.write, SPLICE_LEN)) {
+                    Ok(0) => return Poll::Ready(Ok(())),
+                    Ok(n) => self.len = n,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+        }
+    }
+
+    /// Move the bytes left in the pipe to a buffer to continue without splice.
+    fn drain(&mut self) -> Result<Buffer> {
+        let mut data = vec![0; self.len];
+        let mut filled = 0;
+
+        while filled < data.len() {
+            match rustix::io::read(&self.read, &mut data[filled..])? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+
+        self.len = 0;
+        Ok(Buffer::new(data))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn splice(from: impl AsFd, to: impl AsFd, len: usize) -> Result<usize> {
+    let flags = SpliceFlags::NONBLOCK | SpliceFlags::MOVE;
+    Ok(rustix::pipe::splice(from, None, to, None, len, flags)?)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Copies through a userspace buffer.
+struct Buffer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+}
+
+impl Buffer {
+    /// Start with `pending` waiting to be written.
+    fn new(mut pending: Vec<u8>) -> Self {
+        let cap = pending.len();
+        pending.resize(cap.max(BUF_LEN), 0);
+
+        Self {
+            buf: pending.into_boxed_slice(),
+            pos: 0,
+            cap,
+        }
+    }
+
+    /// Copy until `src` reaches end-of-file.
+    fn poll_copy(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &mut Stream,
+        dst: &mut Stream,
+        amt: &mut u64,
+    ) -> Poll<Result<()>> {
+        loop {
+            while self.pos < self.cap {
+                let buf = &self.buf[self.pos..self.cap];
+                let n = ready!(Pin::new(&mut *dst).poll_write(cx, buf))?;
+
+                if n == 0 {
+                    return Poll::Ready(Err(Error::new(
+                        ErrorKind::WriteZero,
+                        "write zero bytes into writer",
+                    )));
+                }
+
+                self.pos += n;
+                *amt += n as u64;
+            }
+
+            let mut buf = ReadBuf::new(&mut self.buf);
+            ready!(Pin::new(&mut *src).poll_read(cx, &mut buf))?;
+
+            let n = buf.filled().len();
+            if n == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            self.pos = 0;
+            self.cap = n;
+        }
+    }
+}
@@ -0,0 +1,656 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, IoSlice};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::task::{Context, Poll};
+
+use socket2::{Domain, SockRef};
+use tokio::io::{Interest, Ready};
+
+use crate::utils::{
+    DisplayAddr, Result, check_datagram_socket, socket_cookie, to_bind_sock_addr,
+    unix_addr_to_path, unsupported,
+};
+use crate::{AddrErrors, SocketAddr, ToSocketAddrs};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Most messages [`Datagram::send_many`] and [`Datagram::recv_many`] handle
+/// at once (`UIO_MAXIOV`).
+const MAX_BATCH: usize = 1024;
+
+/// A datagram socket, either UDP or unix (`SOCK_DGRAM`).
+///
+/// IP addresses are represented by [`SocketAddr::Tcp`], so the same addresses
+/// work for both [`Stream`](crate::Stream) and `Datagram`. Sending to an
+/// address of the other family fails with [`ErrorKind::InvalidInput`].
+pub enum Datagram {
+    Udp(tokio::net::UdpSocket),
+    Unix(tokio::net::UnixDatagram),
+}
+
+impl From<tokio::net::UdpSocket> for Datagram {
+    #[inline]
+    fn from(x: tokio::net::UdpSocket) -> Self {
+        Self::Udp(x)
+    }
+}
+
+impl From<tokio::net::UnixDatagram> for Datagram {
+    #[inline]
+    fn from(x: tokio::net::UnixDatagram) -> Self {
+        Self::Unix(x)
+    }
+}
+
+/// Wrap an inherited socket, detecting whether it is a UDP or unix socket.
+///
+/// Fails with [`ErrorKind::InvalidInput`] if the descriptor is not a datagram
+/// socket of a supported family. The socket may be bound or connected
+/// already, and keeps any options set on it.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+impl TryFrom<OwnedFd> for Datagram {
+    type Error = Error;
+
+    fn try_from(fd: OwnedFd) -> Result<Self> {
+        if check_datagram_socket(fd.as_fd())? == Domain::UNIX {
+            Self::from_std_unix(fd.into())
+        } else {
+            Self::from_std(fd.into())
+        }
+    }
+}
+
+impl Datagram {
+    #[must_use]
+    pub fn is_udp(&self) -> bool {
+        matches!(self, Self::Udp(..))
+    }
+
+    #[must_use]
+    pub fn is_unix(&self) -> bool {
+        matches!(self, Self::Unix(..))
+    }
+
+    /// Register a standard library UDP socket with the current tokio runtime.
+    ///
+    /// The socket is switched to non-blocking mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn from_std(socket: std::net::UdpSocket) -> Result<Self> {
+        socket.set_nonblocking(true)?;
+        tokio::net::UdpSocket::from_std(socket).map(Into::into)
+    }
+
+    /// Register a standard library unix datagram socket with the current
+    /// tokio runtime.
+    ///
+    /// The socket is switched to non-blocking mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn from_std_unix(socket: std::os::unix::net::UnixDatagram) -> Result<Self> {
+        socket.set_nonblocking(true)?;
+        tokio::net::UnixDatagram::from_std(socket).map(Into::into)
+    }
+
+    /// Deregister the socket from the runtime and turn it into a standard
+    /// library socket.
+    ///
+    /// The socket is switched back to blocking mode.
+    pub fn into_std(self) -> Result<StdDatagram> {
+        match self {
+            Self::Udp(x) => {
+                let x = x.into_std()?;
+                x.set_nonblocking(false)?;
+                Ok(x.into())
+            }
+            Self::Unix(x) => {
+                let x = x.into_std()?;
+                x.set_nonblocking(false)?;
+                Ok(x.into())
+            }
+        }
+    }
+}
+
+impl Datagram {
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let addrs = addr.to_socket_addrs_async().await?;
+
+        let mut errors = Vec::new();
+        for addr in addrs {
+            match Self::_bind(addr.clone()).await {
+                Ok(x) => return Ok(x),
+                Err(e) => errors.push((addr, e)),
+            }
+        }
+
+        Err(AddrErrors::into_error(errors))
+    }
+
+    async fn _bind(addr: SocketAddr) -> Result<Self> {
+        if addr.is_unnamed() {
+            let socket = tokio::net::UnixDatagram::unbound()?;
+            SockRef::from(&socket).bind(&to_bind_sock_addr(&addr)?)?;
+            return Ok(socket.into());
+        }
+
+        match addr {
+            SocketAddr::Tcp(x) => tokio::net::UdpSocket::bind(x).await.map(Into::into),
+            SocketAddr::Unix(x) => {
+                let x = unix_target(x)?;
+
+                if let Some(path) = x.as_pathname() {
+                    let _ = tokio::fs::remove_file(path).await;
+                }
+
+                tokio::net::UnixDatagram::bind(unix_addr_to_path(&x)).map(Into::into)
+            }
+        }
+    }
+
+    /// Create a unix datagram socket that is not bound to any address.
+    ///
+    /// It can send datagrams, but receivers see it as an unnamed address they
+    /// cannot reply to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn unbound_unix() -> Result<Self> {
+        tokio::net::UnixDatagram::unbound().map(Into::into)
+    }
+
+    /// Set the default destination of [`Datagram::send`] and only receive
+    /// datagrams from it.
+    pub async fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<()> {
+        let addrs = addr.to_socket_addrs_async().await?;
+
+        let mut errors = Vec::new();
+        for addr in addrs {
+            match self._connect(addr.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => errors.push((addr, e)),
+            }
+        }
+
+        Err(AddrErrors::into_error(errors))
+    }
+
+    async fn _connect(&self, addr: SocketAddr) -> Result<()> {
+        match (self, addr) {
+            (Self::Udp(x), SocketAddr::Tcp(addr)) => x.connect(addr).await,
+            (Self::Unix(x), SocketAddr::Unix(addr)) => {
+                let addr = unix_target(addr)?;
+                x.connect(unix_addr_to_path(&addr))
+            }
+            _ => Err(family_mismatch()),
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        match self {
+            Self::Udp(x) => x.local_addr().map(Into::into),
+            Self::Unix(x) => x.local_addr().map(Into::into),
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        match self {
+            Self::Udp(x) => x.peer_addr().map(Into::into),
+            Self::Unix(x) => x.peer_addr().map(Into::into),
+        }
+    }
+
+    pub fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Udp(x) => x.poll_recv_ready(cx),
+            Self::Unix(x) => x.poll_recv_ready(cx),
+        }
+    }
+
+    pub fn poll_send_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Udp(x) => x.poll_send_ready(cx),
+            Self::Unix(x) => x.poll_send_ready(cx),
+        }
+    }
+
+    pub async fn readable(&self) -> Result<()> {
+        match self {
+            Self::Udp(x) => x.readable().await,
+            Self::Unix(x) => x.readable().await,
+        }
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready> {
+        match self {
+            Self::Udp(x) => x.ready(interest).await,
+            Self::Unix(x) => x.ready(interest).await,
+        }
+    }
+
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Udp(x) => x.recv(buf).await,
+            Self::Unix(x) => x.recv(buf).await,
+        }
+    }
+
+    /// Receive a datagram and the address it was sent from.
+    ///
+    /// Datagrams from unbound unix sockets have an unnamed address.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        match self {
+            Self::Udp(x) => x.recv_from(buf).await.map(|(n, addr)| (n, addr.into())),
+            Self::Unix(x) => x.recv_from(buf).await.map(|(n, addr)| (n, addr.into())),
+        }
+    }
+
+    pub async fn send(&self, buf: &[u8]) -> Result<usize> {
+        match self {
+            Self::Udp(x) => x.send(buf).await,
+            Self::Unix(x) => x.send(buf).await,
+        }
+    }
+
+    pub async fn send_to(&self, buf: &[u8], target: &SocketAddr) -> Result<usize> {
+        match (self, target) {
+            (Self::Udp(x), SocketAddr::Tcp(target)) => x.send_to(buf, target).await,
+            (Self::Unix(x), SocketAddr::Unix(target)) => {
+                let target = unix_target(target.clone())?;
+                x.send_to(buf, unix_addr_to_path(&target)).await
+            }
+            _ => Err(family_mismatch()),
+        }
+    }
+
+    /// Wait until the socket is writable, then send as many of `msgs` as
+    /// possible without waiting again.
+    ///
+    /// Each message is sent to its address, or to the connected peer if it
+    /// has none. Returns how many messages were sent, always from the start
+    /// of `msgs` and at least one. If sending fails after at least one message
+    /// was sent, that count is returned and the failing message is the first
+    /// one not sent, so sending the rest again reports the error. At most
+    /// 1024 messages are sent per call.
+    ///
+    /// On Linux, this is a single `sendmmsg` call. Elsewhere, the messages are
+    /// sent one by one.
+    pub async fn send_many(&self, msgs: &[(&[u8], Option<SocketAddr>)]) -> Result<usize> {
+        let msgs = &msgs[..msgs.len().min(MAX_BATCH)];
+        if msgs.is_empty() {
+            return Ok(0);
+        }
+
+        for (_, addr) in msgs {
+            match (self, addr) {
+                (_, None) | (Self::Udp(_), Some(SocketAddr::Tcp(_))) => {}
+                (Self::Unix(_), Some(SocketAddr::Unix(_))) => {}
+                _ => return Err(family_mismatch()),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            use rustix::net::{MMsgHdr, SendAncillaryBuffer, SendFlags, sendmmsg};
+
+            let addrs = msgs
+                .iter()
+                .map(|(_, addr)| addr.as_ref().map(to_rustix_addr).transpose())
+                .collect::<Result<Vec<_>>>()?;
+            let iovs: Vec<_> = msgs.iter().map(|(buf, _)| [IoSlice::new(buf)]).collect();
+            let mut controls: Vec<_> = msgs
+                .iter()
+                .map(|_| SendAncillaryBuffer::default())
+                .collect();
+
+            let mut hdrs: Vec<_> = addrs
+                .iter()
+                .zip(&iovs)
+                .zip(&mut controls)
+                .map(|((addr, iov), control)| match addr {
+                    Some(addr) => MMsgHdr::new_with_addr(addr, iov, control),
+                    None => MMsgHdr::new(iov, control),
+                })
+                .collect();
+
+            self.async_io(Interest::WRITABLE, || {
+                Ok(sendmmsg(self, &mut hdrs, SendFlags::empty())?)
+            })
+            .await
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        loop {
+            self.writable().await?;
+
+            let mut n = 0;
+            for (buf, addr) in msgs {
+                let result = match addr {
+                    Some(addr) => self.try_send_to(buf, addr),
+                    None => self.try_send(buf),
+                };
+
+                match result {
+                    Ok(_) => n += 1,
+                    Err(e) if n == 0 && e.kind() != ErrorKind::WouldBlock => return Err(e),
+                    Err(_) => break,
+                }
+            }
+
+            if n != 0 {
+                return Ok(n);
+            }
+        }
+    }
+
+    /// Wait until the socket is readable, then receive datagrams into `bufs`
+    /// until none are left or all buffers are used.
+    ///
+    /// Returns the length and sender of every datagram received, in the order
+    /// of `bufs`, and always at least one. Datagrams longer than their buffer
+    /// are truncated. If receiving fails after at least one datagram was
+    /// received, those are returned and the error is discarded. Errors that
+    /// persist are returned by the next call. At most 1024 datagrams are
+    /// received per call.
+    ///
+    /// The datagrams are received one by one.
+    pub async fn recv_many<B: AsMut<[u8]>>(
+        &self,
+        bufs: &mut [B],
+    ) -> Result<Vec<(usize, SocketAddr)>> {
+        let n = bufs.len().min(MAX_BATCH);
+        let bufs = &mut bufs[..n];
+        let mut received = Vec::new();
+
+        while !bufs.is_empty() {
+            self.readable().await?;
+
+            for buf in &mut *bufs {
+                match self.try_recv_from(buf.as_mut()) {
+                    Ok(x) => received.push(x),
+                    Err(e) if received.is_empty() && e.kind() != ErrorKind::WouldBlock => {
+                        return Err(e);
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            if !received.is_empty() {
+                break;
+            }
+        }
+
+        Ok(received)
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn async_io<R>(&self, interest: Interest, f: impl FnMut() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Udp(x) => x.async_io(interest, f).await,
+            Self::Unix(x) => x.async_io(interest, f).await,
+        }
+    }
+
+    /// The kernel's unique identifier for this socket (`SO_COOKIE`).
+    ///
+    /// Only supported on Linux.
+    pub fn socket_cookie(&self) -> Result<u64> {
+        socket_cookie(self.as_fd())
+    }
+
+    /// Join the IPv4 multicast group `multiaddr` on the interface with the
+    /// address `interface`, or one picked by the system if it is
+    /// [`Ipv4Addr::UNSPECIFIED`] (`IP_ADD_MEMBERSHIP`).
+    ///
+    /// Joining only subscribes the interface to the group. To receive the
+    /// group's datagrams, the socket must also be bound to the group's port,
+    /// on either `0.0.0.0` or the group address. Bound to `0.0.0.0`, it also
+    /// receives unicast datagrams to that port and, on Linux, datagrams of
+    /// every group joined by any socket on the host (`IP_MULTICAST_ALL`).
+    /// Bound to the group address, it only receives that group's datagrams,
+    /// but it cannot send with it as the source. Other systems, like Windows,
+    /// do not allow binding to a group address at all.
+    ///
+    /// Only supported for UDP sockets, like all multicast options.
+    pub fn join_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        self.udp("multicast")?
+            .join_multicast_v4(multiaddr, interface)
+    }
+
+    /// Leave a group joined with [`Datagram::join_multicast_v4`].
+    pub fn leave_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> Result<()> {
+        self.udp("multicast")?
+            .leave_multicast_v4(multiaddr, interface)
+    }
+
+    /// Join the IPv6 multicast group `multiaddr` on the interface with the
+    /// index `interface`, or one picked by the system if it is `0`
+    /// (`IPV6_ADD_MEMBERSHIP`).
+    ///
+    /// See [`Datagram::join_multicast_v4`] for how to bind the socket. Here,
+    /// the wildcard address is `[::]`.
+    pub fn join_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> Result<()> {
+        self.udp("multicast")?
+            .join_multicast_v6(multiaddr, interface)
+    }
+
+    /// Leave a group joined with [`Datagram::join_multicast_v6`].
+    pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> Result<()> {
+        self.udp("multicast")?
+            .leave_multicast_v6(multiaddr, interface)
+    }
+
+    /// Whether multicast datagrams sent from this socket are looped back to
+    /// sockets on this host that joined the group (`IP_MULTICAST_LOOP` or
+    /// `IPV6_MULTICAST_LOOP`, depending on the family).
+    ///
+    /// This is enabled by default.
+    pub fn set_multicast_loop(&self, multicast_loop: bool) -> Result<()> {
+        let x = self.udp("multicast")?;
+
+        if x.local_addr()?.is_ipv6() {
+            x.set_multicast_loop_v6(multicast_loop)
+        } else {
+            x.set_multicast_loop_v4(multicast_loop)
+        }
+    }
+
+    /// Set how many hops multicast datagrams sent from this socket may take
+    /// (`IP_MULTICAST_TTL` or `IPV6_MULTICAST_HOPS`, depending on the family).
+    ///
+    /// Defaults to 1, which keeps them on the local network.
+    pub fn set_multicast_ttl(&self, ttl: u32) -> Result<()> {
+        let x = self.udp("multicast")?;
+
+        if x.local_addr()?.is_ipv6() {
+            SockRef::from(x).set_multicast_hops_v6(ttl)
+        } else {
+            x.set_multicast_ttl_v4(ttl)
+        }
+    }
+
+    /// Allow sending to broadcast addresses, like `255.255.255.255`
+    /// (`SO_BROADCAST`).
+    ///
+    /// Only supported for UDP sockets.
+    pub fn set_broadcast(&self, broadcast: bool) -> Result<()> {
+        self.udp("SO_BROADCAST")?.set_broadcast(broadcast)
+    }
+
+    fn udp(&self, what: &str) -> Result<&tokio::net::UdpSocket> {
+        match self {
+            Self::Udp(x) => Ok(x),
+            Self::Unix(_) => Err(unsupported(&format!("{what} on unix sockets"))),
+        }
+    }
+
+    /// The socket, for the options this crate does not wrap, like
+    /// `IP_RECVERR` or `SO_TIMESTAMPNS`.
+    ///
+    /// Getting and setting socket options through it is fine. Receiving,
+    /// sending or changing the blocking mode is not: tokio tracks the
+    /// readiness of the socket and expects it to stay non-blocking, so
+    /// blocking calls stall the runtime and receives behind its back can leave
+    /// tasks waiting for readiness that already passed. Use the methods of the
+    /// socket for that.
+    #[must_use]
+    pub fn socket_ref(&self) -> SockRef<'_> {
+        SockRef::from(self)
+    }
+
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        match self {
+            Self::Udp(x) => x.take_error(),
+            Self::Unix(x) => x.take_error(),
+        }
+    }
+
+    pub fn try_recv(&self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Udp(x) => x.try_recv(buf),
+            Self::Unix(x) => x.try_recv(buf),
+        }
+    }
+
+    pub fn try_recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        match self {
+            Self::Udp(x) => x.try_recv_from(buf).map(|(n, addr)| (n, addr.into())),
+            Self::Unix(x) => x.try_recv_from(buf).map(|(n, addr)| (n, addr.into())),
+        }
+    }
+
+    pub fn try_send(&self, buf: &[u8]) -> Result<usize> {
+        match self {
+            Self::Udp(x) => x.try_send(buf),
+            Self::Unix(x) => x.try_send(buf),
+        }
+    }
+
+    pub fn try_send_to(&self, buf: &[u8], target: &SocketAddr) -> Result<usize> {
+        match (self, target) {
+            (Self::Udp(x), SocketAddr::Tcp(target)) => x.try_send_to(buf, *target),
+            (Self::Unix(x), SocketAddr::Unix(target)) => {
+                let target = unix_target(target.clone())?;
+                x.try_send_to(buf, unix_addr_to_path(&target))
+            }
+            _ => Err(family_mismatch()),
+        }
+    }
+
+    pub async fn writable(&self) -> Result<()> {
+        match self {
+            Self::Udp(x) => x.writable().await,
+            Self::Unix(x) => x.writable().await,
+        }
+    }
+}
+
+fn unix_target(x: tokio::net::unix::SocketAddr) -> Result<std::os::unix::net::SocketAddr> {
+    let x = std::os::unix::net::SocketAddr::from(x);
+
+    if x.is_unnamed() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "cannot use an unnamed unix address",
+        ));
+    }
+
+    Ok(x)
+}
+
+/// Convert `addr` for [`rustix::net::sendmmsg`].
+#[cfg(target_os = "linux")]
+pub(crate) fn to_rustix_addr(addr: &SocketAddr) -> Result<rustix::net::SocketAddrAny> {
+    use std::os::linux::net::SocketAddrExt;
+
+    use rustix::net::SocketAddrUnix;
+    use rustix::net::addr::SocketAddrArg;
+
+    match addr {
+        SocketAddr::Tcp(x) => Ok(x.as_any()),
+        SocketAddr::Unix(x) => {
+            let x = unix_target(x.clone())?;
+
+            let x = match x.as_pathname() {
+                Some(path) => SocketAddrUnix::new(path)?,
+                None => {
+                    SocketAddrUnix::new_abstract_name(x.as_abstract_name().unwrap_or_default())?
+                }
+            };
+
+            Ok(x.as_any())
+        }
+    }
+}
+
+fn family_mismatch() -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        "address family does not match the socket",
+    )
+}
+
+impl AsFd for Datagram {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Udp(x) => x.as_fd(),
+            Self::Unix(x) => x.as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for Datagram {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Udp(x) => x.as_raw_fd(),
+            Self::Unix(x) => x.as_raw_fd(),
+        }
+    }
+}
+
+impl fmt::Debug for Datagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Udp(x) => x.fmt(f),
+            Self::Unix(x) => x.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Datagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        DisplayAddr(self.local_addr()).fmt(f)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A standard library datagram socket of either kind.
+///
+/// See [`Datagram::into_std`].
+#[derive(Debug)]
+pub enum StdDatagram {
+    Udp(std::net::UdpSocket),
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+impl From<std::net::UdpSocket> for StdDatagram {
+    fn from(x: std::net::UdpSocket) -> Self {
+        Self::Udp(x)
+    }
+}
+
+impl From<std::os::unix::net::UnixDatagram> for StdDatagram {
+    fn from(x: std::os::unix::net::UnixDatagram) -> Self {
+        Self::Unix(x)
+    }
+}
@@ -0,0 +1,490 @@
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::net::Shutdown;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker, ready};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep, sleep_until};
+
+use crate::Stream;
+use crate::utils::Result;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// When a [`Fault`] of a [`FaultPlan`] happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultTrigger {
+    /// Once this many bytes have been read.
+    ///
+    /// Reads are cut short so that one ends exactly at this offset, no matter
+    /// how much the caller asked for or the peer sent.
+    ReadBytes(u64),
+    /// Once this many bytes have been written. Writes are cut short like reads
+    /// for [`FaultTrigger::ReadBytes`].
+    WrittenBytes(u64),
+    /// Once this much time has passed since the stream was created.
+    Elapsed(Duration),
+    /// When the `n`th read starts, counting from 1.
+    NthRead(u64),
+    /// When the `n`th write starts, counting from 1.
+    NthWrite(u64),
+}
+
+/// What goes wrong in a [`FaultyStream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Shut down the connection. Reads return end of file and writes fail with
+    /// [`ErrorKind::BrokenPipe`].
+    Disconnect,
+    /// Shut down the connection. Reads and writes fail with
+    /// [`ErrorKind::ConnectionReset`]. The peer sees the connection closed,
+    /// not reset.
+    Reset,
+    /// Delay every following read and write by this much before it starts.
+    /// [`Duration::ZERO`] removes the delay.
+    Latency(Duration),
+    /// Write at most this many bytes at a time from now on.
+    ShortWrites(usize),
+    /// Make no progress in either direction for this long.
+    Stall(Duration),
+}
+
+/// A script of faults for a [`FaultyStream`].
+///
+/// Each fault happens once, when its trigger is first met. Faults whose
+/// triggers are met at the same time happen in the order they were added.
+#[derive(Debug, Clone, Default)]
+pub struct FaultPlan {
+    steps: Vec<(FaultTrigger, Fault)>,
+}
+
+impl FaultPlan {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn at(mut self, trigger: FaultTrigger, fault: Fault) -> Self {
+        self.steps.push((trigger, fault));
+        self
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Read,
+    Write,
+}
+
+#[derive(Debug)]
+struct Shared {
+    steps: Vec<(FaultTrigger, Fault)>,
+    /// The tasks waiting to read and to write, woken when steps are added.
+    wakers: [Option<Waker>; 2],
+}
+
+impl Shared {
+    fn lock(shared: &Mutex<Self>) -> MutexGuard<'_, Self> {
+        shared
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Adds faults to a [`FaultyStream`] while it is in use.
+///
+/// Returned by [`FaultyStream::handle`].
+#[derive(Debug, Clone)]
+pub struct FaultHandle {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl FaultHandle {
+    /// Add a fault to the plan. Pending reads and writes are woken up to check
+    /// its trigger.
+    pub fn add(&self, trigger: FaultTrigger, fault: Fault) {
+        let mut shared = Shared::lock(&self.shared);
+        shared.steps.push((trigger, fault));
+
+        for waker in &mut shared.wakers {
+            if let Some(x) = waker.take() {
+                x.wake();
+            }
+        }
+    }
+
+    /// Make `fault` happen right away.
+    pub fn inject(&self, fault: Fault) {
+        self.add(FaultTrigger::Elapsed(Duration::ZERO), fault);
+    }
+
+    /// Remove the faults that have not happened yet.
+    pub fn clear(&self) {
+        Shared::lock(&self.shared).steps.clear();
+    }
+
+    /// The number of faults that have not happened yet.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        Shared::lock(&self.shared).steps.len()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Side {
+    bytes: u64,
+    calls: u64,
+    /// When the current operation may start, once it has been delayed.
+    ready_at: Option<Instant>,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+/// A [`Stream`] that fails on purpose, to test how code copes with broken
+/// connections.
+///
+/// The faults are given by a [`FaultPlan`] up front, and more can be added
+/// through a [`FaultHandle`] while the stream is in use. Triggers are checked
+/// whenever a read or write is polled, and faults with an
+/// [`FaultTrigger::Elapsed`] trigger also wake up pending reads and writes.
+///
+/// Only reads and writes through [`AsyncRead`] and [`AsyncWrite`] are subject
+/// to faults. The methods of the inner stream are available through
+/// [`Deref`], but using it to read or write skips the plan.
+///
+/// Created by [`Stream::with_faults`]. Only available with the `test-util`
+/// feature.
+///
+/// # Panics
+///
+/// Delays and [`FaultTrigger::Elapsed`] panic outside of a tokio runtime with
+/// the time driver enabled.
+#[derive(Debug)]
+pub struct FaultyStream {
+    inner: Stream,
+    shared: Arc<Mutex<Shared>>,
+    start: Instant,
+    read: Side,
+    write: Side,
+    latency: Duration,
+    stalled_until: Option<Instant>,
+    max_write: usize,
+    broken: Option<Fault>,
+    scratch: Vec<u8>,
+}
+
+impl FaultyStream {
+    pub fn new(stream: Stream, plan: FaultPlan) -> Self {
+        let shared = Shared {
+            steps: plan.steps,
+            wakers: [None, None],
+        };
+
+        Self {
+            inner: stream,
+            shared: Arc::new(Mutex::new(shared)),
+            start: Instant::now(),
+            read: Side::default(),
+            write: Side::default(),
+            latency: Duration::ZERO,
+            stalled_until: None,
+            max_write: usize::MAX,
+            broken: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// A handle to add faults with while the stream is in use.
+    #[must_use]
+    pub fn handle(&self) -> FaultHandle {
+        FaultHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &Stream {
+        &self.inner
+    }
+
+    #[must_use]
+    pub fn get_mut(&mut self) -> &mut Stream {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> Stream {
+        self.inner
+    }
+
+    /// The number of bytes read so far.
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.read.bytes
+    }
+
+    /// The number of bytes written so far.
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.write.bytes
+    }
+
+    fn side(&mut self, op: Op) -> &mut Side {
+        match op {
+            Op::Read => &mut self.read,
+            Op::Write => &mut self.write,
+        }
+    }
+
+    fn is_met(&self, trigger: FaultTrigger, now: Instant, starting: Option<Op>) -> bool {
+        match trigger {
+            FaultTrigger::ReadBytes(n) => self.read.bytes >= n,
+            FaultTrigger::WrittenBytes(n) => self.write.bytes >= n,
+            FaultTrigger::Elapsed(x) => now >= self.start + x,
+            FaultTrigger::NthRead(n) => starting == Some(Op::Read) && self.read.calls + 1 >= n,
+            FaultTrigger::NthWrite(n) => starting == Some(Op::Write) && self.write.calls + 1 >= n,
+        }
+    }
+
+    /// Make the faults whose triggers are met happen.
+    fn fire(&mut self, starting: Option<Op>) {
+        let now = Instant::now();
+        let mut fired = Vec::new();
+
+        Shared::lock(&self.shared)
+            .steps
+            .retain(|&(trigger, fault)| {
+                let met = self.is_met(trigger, now, starting);
+                if met {
+                    fired.push(fault);
+                }
+
+                !met
+            });
+
+        for fault in fired {
+            self.apply(fault, now);
+        }
+    }
+
+    fn apply(&mut self, fault: Fault, now: Instant) {
+        match fault {
+            Fault::Disconnect | Fault::Reset => {
+                if self.broken.is_none() {
+                    let _ = self.inner.shutdown_socket(Shutdown::Both);
+                    self.broken = Some(fault);
+                }
+            }
+            Fault::Latency(x) => self.latency = x,
+            Fault::ShortWrites(x) => self.max_write = x,
+            Fault::Stall(x) => {
+                let until = now + x;
+                self.stalled_until = Some(self.stalled_until.map_or(until, |x| x.max(until)));
+            }
+        }
+    }
+
+    /// How many bytes `op` may transfer before a byte trigger is met.
+    fn limit(&self, op: Op) -> usize {
+        let (bytes, trigger_bytes): (u64, fn(FaultTrigger) -> Option<u64>) = match op {
+            Op::Read => (self.read.bytes, |x| match x {
+                FaultTrigger::ReadBytes(n) => Some(n),
+                _ => None,
+            }),
+            Op::Write => (self.write.bytes, |x| match x {
+                FaultTrigger::WrittenBytes(n) => Some(n),
+                _ => None,
+            }),
+        };
+
+        Shared::lock(&self.shared)
+            .steps
+            .iter()
+            .filter_map(|&(x, _)| trigger_bytes(x))
+            .filter(|&n| n > bytes)
+            .map(|n| usize::try_from(n - bytes).unwrap_or(usize::MAX))
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Wait until `op` may start, making the faults whose triggers are met
+    /// happen.
+    fn poll_start(&mut self, op: Op, cx: &mut Context<'_>) -> Poll<()> {
+        self.fire(Some(op));
+
+        if self.broken.is_some() {
+            return Poll::Ready(());
+        }
+
+        let now = Instant::now();
+        let latency = self.latency;
+        let ready_at = *self.side(op).ready_at.get_or_insert(now + latency);
+        let ready_at = self.stalled_until.map_or(ready_at, |x| x.max(ready_at));
+
+        if now >= ready_at {
+            return Poll::Ready(());
+        }
+
+        self.register(op, cx, Some(ready_at));
+        Poll::Pending
+    }
+
+    /// Wake the task once `until` has passed, a step is added or an
+    /// [`FaultTrigger::Elapsed`] trigger is met.
+    fn register(&mut self, op: Op, cx: &mut Context<'_>, until: Option<Instant>) {
+        let next = {
+            let mut shared = Shared::lock(&self.shared);
+            shared.wakers[op as usize] = Some(cx.waker().clone());
+
+            shared
+                .steps
+                .iter()
+                .filter_map(|&(x, _)| match x {
+                    FaultTrigger::Elapsed(x) => Some(self.start + x),
+                    _ => None,
+                })
+                .min()
+        };
+
+        let deadline = match (until, next) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => match a.or(b) {
+                Some(x) => x,
+                None => return,
+            },
+        };
+
+        let timer = match &mut self.side(op).timer {
+            Some(x) => {
+                x.as_mut().reset(deadline);
+                x
+            }
+            x @ None => x.insert(Box::pin(sleep_until(deadline))),
+        };
+
+        if timer.as_mut().poll(cx).is_ready() {
+            cx.waker().wake_by_ref();
+        }
+    }
+
+    fn finish(&mut self, op: Op, n: usize) {
+        let side = self.side(op);
+        side.bytes += n as u64;
+        side.calls += 1;
+        side.ready_at = None;
+
+        // Let faults after exactly this many bytes happen right away.
+        self.fire(None);
+    }
+
+    fn reset_error() -> Error {
+        Error::new(ErrorKind::ConnectionReset, "connection reset by fault plan")
+    }
+}
+
+impl Deref for FaultyStream {
+    type Target = Stream;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for FaultyStream {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl AsyncRead for FaultyStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_start(Op::Read, cx));
+
+        match this.broken {
+            Some(Fault::Reset) => return Poll::Ready(Err(Self::reset_error())),
+            Some(_) => return Poll::Ready(Ok(())),
+            None => {}
+        }
+
+        let limit = this.limit(Op::Read).min(buf.remaining());
+        this.scratch.resize(limit, 0);
+
+        let mut scratch = ReadBuf::new(&mut this.scratch);
+        match Pin::new(&mut this.inner).poll_read(cx, &mut scratch) {
+            Poll::Pending => {
+                this.register(Op::Read, cx, None);
+                Poll::Pending
+            }
+            Poll::Ready(Err(e)) => {
+                this.finish(Op::Read, 0);
+                Poll::Ready(Err(e))
+            }
+            Poll::Ready(Ok(())) => {
+                let n = scratch.filled().len();
+                buf.put_slice(scratch.filled());
+                this.finish(Op::Read, n);
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+impl AsyncWrite for FaultyStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_start(Op::Write, cx));
+
+        match this.broken {
+            Some(Fault::Reset) => return Poll::Ready(Err(Self::reset_error())),
+            Some(_) => return Poll::Ready(Err(ErrorKind::BrokenPipe.into())),
+            None => {}
+        }
+
+        let len = buf.len().min(this.max_write).min(this.limit(Op::Write));
+        match Pin::new(&mut this.inner).poll_write(cx, &buf[..len]) {
+            Poll::Pending => {
+                this.register(Op::Write, cx, None);
+                Poll::Pending
+            }
+            Poll::Ready(Err(e)) => {
+                this.finish(Op::Write, 0);
+                Poll::Ready(Err(e))
+            }
+            Poll::Ready(Ok(n)) => {
+                this.finish(Op::Write, n);
+                Poll::Ready(Ok(n))
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        match this.broken {
+            Some(Fault::Reset) => Poll::Ready(Err(Self::reset_error())),
+            Some(_) => Poll::Ready(Ok(())),
+            None => Pin::new(&mut this.inner).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        match this.broken {
+            Some(_) => Poll::Ready(Ok(())),
+            None => Pin::new(&mut this.inner).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use crate::utils::Result;
+use crate::{Listener, PeerCred, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// What a [`FilteredListener`] does with an accepted connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Return the connection from `accept`.
+    Accept,
+    /// Close the connection and keep accepting.
+    Reject,
+}
+
+/// A [`Listener`] that passes every accepted connection through a filter.
+///
+/// The filter is called with the peer address and, for unix sockets, the
+/// credentials of the peer process. These are `None` for TCP, or if they
+/// cannot be read. Connections the filter rejects are closed right away and
+/// never returned from [`FilteredListener::accept`], which keeps accepting
+/// instead. The filter sees every connection, even ones the peer has already
+/// closed again.
+///
+/// Created by [`Listener::filtered`].
+pub struct FilteredListener<F> {
+    listener: Listener,
+    filter: F,
+    reset: bool,
+    rejected: AtomicU64,
+}
+
+impl<F> FilteredListener<F>
+where
+    F: Fn(&SocketAddr, Option<&PeerCred>) -> Verdict,
+{
+    pub fn new(listener: Listener, filter: F) -> Self {
+        Self {
+            listener,
+            filter,
+            reset: false,
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Abort rejected TCP connections with a reset (`SO_LINGER` of zero)
+    /// instead of closing them normally.
+    #[must_use]
+    pub fn reset_rejected(mut self, reset: bool) -> Self {
+        self.reset = reset;
+        self
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &Listener {
+        &self.listener
+    }
+
+    pub fn into_inner(self) -> Listener {
+        self.listener
+    }
+
+    /// The number of connections the filter has rejected.
+    #[must_use]
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(Stream, SocketAddr)>> {
+        loop {
+            let (stream, addr) = ready!(self.listener.poll_accept(cx))?;
+
+            let cred = stream.peer_cred().ok().flatten();
+            if (self.filter)(&addr, cred.as_ref()) == Verdict::Accept {
+                return Poll::Ready(Ok((stream, addr)));
+            }
+
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+
+            if self.reset && stream.is_tcp() {
+                let _ = stream.set_linger(Some(Duration::ZERO));
+            }
+        }
+    }
+
+    pub async fn accept(&self) -> Result<(Stream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl<F> fmt::Debug for FilteredListener<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FilteredListener")
+            .field("listener", &self.listener)
+            .field("reset", &self.reset)
+            .field("rejected", &self.rejected.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
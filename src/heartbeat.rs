@@ -0,0 +1,281 @@
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep, sleep};
+
+use crate::Stream;
+use crate::utils::Result;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Configuration of a [`HeartbeatStream`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    /// How long the write path must be idle before a heartbeat is sent.
+    pub interval: Duration,
+
+    /// How long the peer may stay silent before the connection is considered
+    /// dead.
+    ///
+    /// Any received bytes count, not just heartbeats. This should be
+    /// comfortably larger than the peer's `interval`.
+    pub timeout: Duration,
+
+    /// The bytes of a heartbeat frame.
+    pub frame: Vec<u8>,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A stream that exchanges periodic heartbeat frames with its peer.
+///
+/// Whenever nothing has been written for [`HeartbeatConfig::interval`], the
+/// heartbeat frame is written to the inner stream. Heartbeat frames received
+/// from the peer are removed from the data returned by reads. If nothing at
+/// all is received for [`HeartbeatConfig::timeout`], reads fail with
+/// [`ErrorKind::TimedOut`].
+///
+/// Timers are only driven by reads. Heartbeats are sent and the timeout is
+/// detected while a read is pending, so the stream must be read from for the
+/// mechanism to work.
+///
+/// The heartbeat frame is matched as a plain byte sequence. Making sure that it
+/// can never appear in the data of the inner protocol (for example, by
+/// escaping) is the responsibility of the caller. Received data that ends with
+/// a prefix of the frame is held back until enough bytes arrive to tell whether
+/// it is a heartbeat, which, at the latest, happens when the peer sends its
+/// next heartbeat.
+#[derive(Debug)]
+pub struct HeartbeatStream<S = Stream> {
+    inner: S,
+    config: HeartbeatConfig,
+    send_timer: Pin<Box<Sleep>>,
+    recv_timer: Pin<Box<Sleep>>,
+    /// How much of the heartbeat frame has been written, if one is being sent.
+    outgoing: Option<usize>,
+    /// Received bytes that have not been returned yet.
+    incoming: Vec<u8>,
+    /// How many bytes at the start of `incoming` are known not to be part of a
+    /// heartbeat.
+    ready: usize,
+}
+
+impl<S> HeartbeatStream<S> {
+    /// Wrap `stream`.
+    ///
+    /// # Panics
+    ///
+    /// If `config.frame` is empty or if called outside of a tokio runtime with
+    /// the time driver enabled.
+    pub fn new(stream: S, config: HeartbeatConfig) -> Self {
+        assert!(
+            !config.frame.is_empty(),
+            "heartbeat frame must not be empty"
+        );
+
+        Self {
+            inner: stream,
+            send_timer: Box::pin(sleep(config.interval)),
+            recv_timer: Box::pin(sleep(config.timeout)),
+            config,
+            outgoing: None,
+            incoming: Vec::new(),
+            ready: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &HeartbeatConfig {
+        &self.config
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    #[must_use]
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Get the inner stream.
+    ///
+    /// Received bytes that have not been read yet are discarded.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn timed_out() -> Error {
+        Error::new(ErrorKind::TimedOut, "peer did not send anything in time")
+    }
+
+    fn reset_send_timer(&mut self) {
+        let deadline = Instant::now() + self.config.interval;
+        self.send_timer.as_mut().reset(deadline);
+    }
+
+    fn reset_recv_timer(&mut self) {
+        let deadline = Instant::now() + self.config.timeout;
+        self.recv_timer.as_mut().reset(deadline);
+    }
+
+    /// Remove complete heartbeat frames from the unchecked part of `incoming`.
+    fn filter_incoming(&mut self) {
+        let frame = &self.config.frame;
+        let mut i = self.ready;
+
+        while i < self.incoming.len() {
+            let rest = &self.incoming[i..];
+
+            if rest.starts_with(frame) {
+                self.incoming.drain(i..i + frame.len());
+            } else if frame.starts_with(rest) {
+                break;
+            } else {
+                i += 1;
+            }
+        }
+
+        self.ready = i;
+    }
+}
+
+impl<S> HeartbeatStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    /// Finish writing a heartbeat frame that has already been started.
+    fn poll_outgoing(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while let Some(off) = self.outgoing {
+            let frame = &self.config.frame;
+
+            if off == frame.len() {
+                ready!(Pin::new(&mut self.inner).poll_flush(cx))?;
+                self.outgoing = None;
+                self.reset_send_timer();
+                break;
+            }
+
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &frame[off..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write heartbeat frame",
+                )));
+            }
+
+            self.outgoing = Some(off + n);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Start a heartbeat if the write path has been idle for long enough and
+    /// drive it as far as possible.
+    ///
+    /// Registers `cx` with the send timer.
+    fn poll_heartbeat(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.outgoing.is_none() {
+            if self.send_timer.as_mut().poll(cx).is_pending() {
+                return Poll::Ready(Ok(()));
+            }
+
+            self.outgoing = Some(0);
+        }
+
+        self.poll_outgoing(cx)
+    }
+}
+
+impl<S> AsyncRead for HeartbeatStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.ready != 0 {
+                let n = usize::min(this.ready, buf.remaining());
+                buf.put_slice(&this.incoming[..n]);
+                this.incoming.drain(..n);
+                this.ready -= n;
+                return Poll::Ready(Ok(()));
+            }
+
+            // A heartbeat that cannot be written right now must not hold up
+            // reading. The waker is registered either way.
+            if let Poll::Ready(Err(e)) = this.poll_heartbeat(cx) {
+                return Poll::Ready(Err(e));
+            }
+
+            let mut scratch = [0u8; 4096];
+            let mut scratch = ReadBuf::new(&mut scratch);
+            if Pin::new(&mut this.inner)
+                .poll_read(cx, &mut scratch)?
+                .is_pending()
+            {
+                return match this.recv_timer.as_mut().poll(cx) {
+                    Poll::Ready(()) => Poll::Ready(Err(Self::timed_out())),
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+
+            let received = scratch.filled();
+            if received.is_empty() {
+                // End of file. Whatever was held back cannot be a heartbeat
+                // anymore.
+                this.ready = this.incoming.len();
+
+                if this.ready == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+
+                continue;
+            }
+
+            this.reset_recv_timer();
+            this.incoming.extend_from_slice(received);
+            this.filter_incoming();
+        }
+    }
+}
+
+impl<S> AsyncWrite for HeartbeatStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_outgoing(cx))?;
+
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        if n != 0 {
+            this.reset_send_timer();
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_outgoing(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_outgoing(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
@@ -5,16 +5,163 @@
 #![cfg_attr(tokio_anysocket_nightly, feature(doc_cfg))]
 #![forbid(unsafe_code)]
 
+mod addr_filter;
+mod buffered;
+#[cfg(feature = "compression")]
+mod compressed;
+#[cfg(feature = "serde")]
+mod config;
+#[cfg(feature = "tonic")]
+mod connect_info;
+#[cfg(feature = "tower")]
+mod connector;
+mod copy;
+mod datagram;
+#[cfg(feature = "test-util")]
+mod faulty;
+mod filtered;
+mod heartbeat;
 mod listener;
 mod macros;
+mod metered;
+mod multi_listener;
+mod pool;
+mod proxy;
+mod proxy_protocol;
 mod read_half;
+mod resolve;
+mod resumable;
+mod serve;
+mod shared_listener;
+mod socket;
 mod socket_addr;
 mod stream;
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+mod systemd;
+mod temp_socket;
+mod throttled;
+mod timed;
+mod trace;
+mod tracked;
+mod unlinking_listener;
 mod utils;
 mod write_half;
 
-pub use self::listener::Listener;
-pub use self::read_half::{OwnedReadHalf, ReadHalf};
-pub use self::socket_addr::{SocketAddr, ToSocketAddrs};
-pub use self::stream::Stream;
+#[cfg(any(feature = "tls", feature = "tls-native"))]
+pub mod tls;
+#[cfg(feature = "uring")]
+pub mod uring;
+#[cfg(feature = "websocket")]
+pub mod ws;
+
+#[cfg(feature = "axum")]
+pub use axum;
+#[cfg(feature = "bytes")]
+pub use bytes;
+#[cfg(feature = "futures-io")]
+pub use futures_io;
+#[cfg(feature = "tower")]
+pub use http;
+#[cfg(feature = "hyper")]
+pub use hyper;
+#[cfg(feature = "hyper-util")]
+pub use hyper_util;
+pub use socket2;
+#[cfg(feature = "tls-native")]
+pub use tokio_native_tls;
+#[cfg(feature = "tls")]
+pub use tokio_rustls;
+#[cfg(feature = "tokio-util")]
+pub use tokio_util;
+#[cfg(feature = "tonic")]
+pub use tonic;
+#[cfg(feature = "tower")]
+pub use tower_service;
+
+pub use self::addr_filter::{AddrFilter, AddrPattern};
+pub use self::buffered::{BufferedOwnedReadHalf, BufferedOwnedWriteHalf, BufferedStream};
+#[cfg(feature = "compression")]
+pub use self::compressed::{CompressedStream, Compression, CompressionConfig};
+#[cfg(feature = "serde")]
+pub use self::config::{ConnectConfig, ListenerConfig};
+#[cfg(feature = "tonic")]
+pub use self::connect_info::ConnectInfo;
+#[cfg(feature = "tower")]
+pub use self::connector::{Connecting, Connector};
+pub use self::copy::copy_bidirectional;
+pub use self::datagram::{Datagram, StdDatagram};
+#[cfg(feature = "test-util")]
+pub use self::faulty::{Fault, FaultHandle, FaultPlan, FaultTrigger, FaultyStream};
+pub use self::filtered::{FilteredListener, Verdict};
+pub use self::heartbeat::{HeartbeatConfig, HeartbeatStream};
+#[cfg(target_os = "linux")]
+pub use self::listener::ReuseportBalance;
+pub use self::listener::{
+    AcceptErrorAction, AcceptOptions, BindOptions, Incoming, IntoIncoming, Listener, StdListener,
+};
+pub use self::metered::{
+    ListenerMetrics, MeteredListener, MeteredOwnedReadHalf, MeteredOwnedWriteHalf, MeteredStream,
+    Metrics,
+};
+pub use self::multi_listener::MultiListener;
+pub use self::pool::{Pool, PoolConfig, PooledStream};
+pub use self::proxy::{HttpConnectProxy, Proxy, Socks5Proxy};
+pub use self::proxy_protocol::{ProxyAddrs, ProxyHeader, ProxyVersion};
+pub use self::read_half::{OwnedReadHalf, ReadHalf, ReuniteError};
+#[cfg(feature = "hickory")]
+pub use self::resolve::HickoryResolver;
+pub use self::resolve::{Resolve, Resolving, ResolvingSrv, SrvTarget, SystemResolver};
+pub use self::resumable::{ReadExactState, WriteAllState};
+pub use self::serve::{ServeHandle, ServeOptions, serve, serve_with};
+pub use self::shared_listener::SharedListener;
+#[cfg(target_os = "linux")]
+pub use self::socket::SockFilter;
+pub use self::socket::Socket;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use self::socket_addr::MAX_ABSTRACT_NAME_LEN;
+pub use self::socket_addr::{
+    AddrErrors, AddrList, AddrParseError, FromIter, FromIterAddrs, LazyAddrs, MAX_UNIX_PATH_LEN,
+    PortRange, PortRangeAddrs, SocketAddr, Tcp, ToSocketAddrs, lookup,
+};
+pub use self::stream::{
+    CandidateOrder, ConnectOptions, ConnectStrategy, HappyEyeballsConfig, KeepaliveConfig,
+    PeerCred, RetryPolicy, RoundRobin, StdStream, Stream,
+};
+pub use self::temp_socket::TempSocketGuard;
+pub use self::throttled::{
+    Limit, Limiter, ThrottledOwnedReadHalf, ThrottledOwnedWriteHalf, ThrottledStream,
+};
+pub use self::timed::{TimedOwnedReadHalf, TimedOwnedWriteHalf, TimedStream};
+pub use self::tracked::{
+    ConnectionGuard, TrackedListener, TrackedOwnedReadHalf, TrackedOwnedWriteHalf, TrackedStream,
+};
+pub use self::unlinking_listener::UnlinkingListener;
 pub use self::write_half::{OwnedWriteHalf, WriteHalf};
+
+const _: () = {
+    const fn assert_unpin_send_sync<T: Unpin + Send + Sync>() {}
+
+    assert_unpin_send_sync::<Datagram>();
+    assert_unpin_send_sync::<Listener>();
+    assert_unpin_send_sync::<MultiListener>();
+    assert_unpin_send_sync::<SharedListener>();
+    assert_unpin_send_sync::<Stream>();
+    assert_unpin_send_sync::<ReadHalf<'_>>();
+    assert_unpin_send_sync::<WriteHalf<'_>>();
+    assert_unpin_send_sync::<OwnedReadHalf>();
+    assert_unpin_send_sync::<OwnedWriteHalf>();
+
+    const fn assert_fd<T: std::os::fd::AsFd + std::os::fd::AsRawFd>() {}
+
+    assert_fd::<Datagram>();
+    assert_fd::<Listener>();
+    assert_fd::<Stream>();
+    assert_fd::<ReadHalf<'_>>();
+    assert_fd::<WriteHalf<'_>>();
+    assert_fd::<OwnedReadHalf>();
+    assert_fd::<OwnedWriteHalf>();
+
+    fn assert_send<T: Send>(_: T) {}
+
+    let _ = |addr: &'static str| assert_send(Stream::connect(addr));
+};
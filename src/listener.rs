@@ -1,9 +1,517 @@
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
 use std::fmt;
-use std::io::Error;
+use std::fs::Permissions;
+use std::future::poll_fn;
+use std::hash::BuildHasher;
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use crate::utils::{Result, into2, unix_addr_to_path};
-use crate::{SocketAddr, Stream, ToSocketAddrs};
+use rustix::io::Errno;
+use socket2::{Domain, SockRef};
+use tokio::time::Instant;
+
+#[cfg(target_os = "linux")]
+use crate::SockFilter;
+#[cfg(target_os = "linux")]
+use crate::multi_listener::Interfaces;
+use crate::trace;
+use crate::utils::{
+    DisplayAddr, Result, check_socket, into2, socket_cookie, unix_addr_to_path, unsupported,
+};
+use crate::{
+    AddrErrors, AddrFilter, FilteredListener, KeepaliveConfig, MeteredListener, MultiListener,
+    PeerCred, PortRange, SharedListener, Socket, SocketAddr, Stream, ToSocketAddrs,
+    TrackedListener, UnlinkingListener, Verdict,
+};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// How [`Listener::bind_with`] creates its socket.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct BindOptions {
+    /// Allow binding to an address that still has connections in `TIME_WAIT`
+    /// (`SO_REUSEADDR`).
+    ///
+    /// This is enabled by default, like in [`Listener::bind`]. It is ignored
+    /// for unix sockets.
+    pub reuse_address: bool,
+
+    /// Allow multiple sockets to bind to the same address and share incoming
+    /// connections (`SO_REUSEPORT`).
+    ///
+    /// Unix sockets do not support this, and binding one fails with
+    /// [`ErrorKind::InvalidInput`] if it is set.
+    pub reuse_port: bool,
+
+    /// Program which picks the listener in the `SO_REUSEPORT` group that gets
+    /// each new connection, instead of the kernel's hash.
+    ///
+    /// It is attached after binding. See [`Socket::attach_reuseport_cbpf`].
+    /// Unix sockets do not support this, and binding one fails with
+    /// [`ErrorKind::InvalidInput`] if it is set.
+    #[cfg(target_os = "linux")]
+    pub reuseport_cbpf: Option<ReuseportBalance>,
+
+    /// Whether an IPv6 socket only accepts IPv6 connections (`IPV6_V6ONLY`),
+    /// or also IPv4 connections through IPv4-mapped addresses.
+    ///
+    /// `None` keeps the system default. Binding anything other than an IPv6
+    /// address fails with [`ErrorKind::InvalidInput`] if this is set.
+    pub v6_only: Option<bool>,
+
+    /// Maximum number of pending connections.
+    ///
+    /// Defaults to 1024, like in [`Listener::bind`].
+    pub backlog: u32,
+
+    /// Interface to send and receive through (`SO_BINDTODEVICE`).
+    ///
+    /// See [`Socket::bind_device`].
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub device: Option<String>,
+
+    /// Mark of outgoing packets (`SO_MARK`).
+    ///
+    /// See [`Socket::set_mark`].
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub mark: Option<u32>,
+
+    /// Accept connections to addresses that are not local (`IP_TRANSPARENT`).
+    ///
+    /// See [`Socket::set_transparent`]. Binding a unix socket fails with
+    /// [`ErrorKind::Unsupported`] if this is set.
+    #[cfg(target_os = "linux")]
+    pub transparent: bool,
+
+    /// Accept Multipath TCP connections, falling back to plain TCP if the
+    /// kernel does not support it.
+    ///
+    /// See [`Socket::new_mptcp_for`]. Binding a unix address fails with
+    /// [`ErrorKind::InvalidInput`] if this is set.
+    #[cfg(target_os = "linux")]
+    pub mptcp: bool,
+
+    /// Type-of-service field of outgoing packets (`IP_TOS`).
+    ///
+    /// See [`Socket::set_tos`].
+    pub tos: Option<u32>,
+
+    /// Only remove a file at a unix socket path if it is a stale socket.
+    ///
+    /// By default, a socket at the path is removed before binding, even if
+    /// another listener is accepting on it. A file that is not a socket is
+    /// left alone and binding fails with [`ErrorKind::AddrInUse`]. With this
+    /// set, the socket is first connected to. Only if that is refused is it
+    /// considered stale and removed. If a listener answers, binding fails the
+    /// same way and the socket is left alone. If another process binds the
+    /// path between the removal and the bind, binding fails the same way.
+    ///
+    /// This is ignored for TCP and abstract addresses.
+    pub reclaim_stale_unix: bool,
+
+    /// Replace the unix socket at the path without a moment where the path is
+    /// missing or refuses connections.
+    ///
+    /// The socket is bound at a temporary path next to the target, gets
+    /// [`BindOptions::mode`] and its ownership, starts listening and is then
+    /// renamed over the target. A listener that was accepting on the old
+    /// socket keeps the connections it has, new ones reach this listener. If
+    /// anything fails, the temporary file is removed and the target is left
+    /// alone. [`Listener::local_addr`] reports the target path, but the local
+    /// address of accepted connections is the temporary path.
+    ///
+    /// A file at the path that is not a socket is only replaced if
+    /// [`BindOptions::replace_non_socket`] is set, otherwise binding fails with
+    /// [`ErrorKind::AddrInUse`]. [`BindOptions::reclaim_stale_unix`] is
+    /// ignored with this set. The temporary path is 27 bytes longer than the
+    /// path and has to fit in `sun_path` as well.
+    ///
+    /// This is ignored for TCP and abstract addresses.
+    pub unix_atomic_replace: bool,
+
+    /// Let [`BindOptions::unix_atomic_replace`] replace a file that is not a
+    /// socket.
+    pub replace_non_socket: bool,
+
+    /// Permission bits of the unix socket file, like `0o660`.
+    ///
+    /// On Linux and Android, the socket is bound with no permissions and only
+    /// gets `mode` after its owner has been changed, so it is never reachable
+    /// with looser permissions than intended. Elsewhere, the file has the
+    /// permissions given by the umask until it is changed right after binding.
+    ///
+    /// Binding anything other than a unix socket path fails with
+    /// [`ErrorKind::InvalidInput`] if this, [`BindOptions::uid`] or
+    /// [`BindOptions::gid`] is set.
+    pub mode: Option<u32>,
+
+    /// Owner of the unix socket file.
+    ///
+    /// Set [`BindOptions::mode`] too, or the file is accessible with the
+    /// permissions given by the umask for the original owner until it is
+    /// changed.
+    pub uid: Option<u32>,
+
+    /// Group of the unix socket file. See [`BindOptions::uid`].
+    pub gid: Option<u32>,
+
+    /// Create missing parent directories of a unix socket path.
+    ///
+    /// Errors name the directory which could not be created. A dangling
+    /// symlink in the path is never followed to create its target, binding
+    /// fails instead. Symlinks to existing directories are followed as usual.
+    ///
+    /// This is ignored for TCP and abstract addresses.
+    pub create_dirs: bool,
+
+    /// Permission bits of the directories created with
+    /// [`BindOptions::create_dirs`], like `0o750`.
+    ///
+    /// The umask still applies. `None` uses `0o777`, so the directories get
+    /// the default permissions given by the umask. Existing directories are
+    /// not changed.
+    pub dir_mode: Option<u32>,
+}
+
+impl Default for BindOptions {
+    fn default() -> Self {
+        Self {
+            reuse_address: true,
+            reuse_port: false,
+            #[cfg(target_os = "linux")]
+            reuseport_cbpf: None,
+            v6_only: None,
+            backlog: 1024,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            device: None,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            mark: None,
+            #[cfg(target_os = "linux")]
+            transparent: false,
+            #[cfg(target_os = "linux")]
+            mptcp: false,
+            tos: None,
+            reclaim_stale_unix: false,
+            unix_atomic_replace: false,
+            replace_non_socket: false,
+            mode: None,
+            uid: None,
+            gid: None,
+            create_dirs: false,
+            dir_mode: None,
+        }
+    }
+}
+
+impl BindOptions {
+    #[must_use]
+    pub fn reuse_address(mut self, reuse_address: bool) -> Self {
+        self.reuse_address = reuse_address;
+        self
+    }
+
+    #[must_use]
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn reuseport_cbpf(mut self, balance: ReuseportBalance) -> Self {
+        self.reuseport_cbpf = Some(balance);
+        self
+    }
+
+    #[must_use]
+    pub fn v6_only(mut self, v6_only: bool) -> Self {
+        self.v6_only = Some(v6_only);
+        self
+    }
+
+    #[must_use]
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[must_use]
+    pub fn device(mut self, device: impl Into<String>) -> Self {
+        self.device = Some(device.into());
+        self
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[must_use]
+    pub fn mark(mut self, mark: u32) -> Self {
+        self.mark = Some(mark);
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn mptcp(mut self, mptcp: bool) -> Self {
+        self.mptcp = mptcp;
+        self
+    }
+
+    #[must_use]
+    pub fn tos(mut self, tos: u32) -> Self {
+        self.tos = Some(tos);
+        self
+    }
+
+    #[must_use]
+    pub fn reclaim_stale_unix(mut self, reclaim_stale_unix: bool) -> Self {
+        self.reclaim_stale_unix = reclaim_stale_unix;
+        self
+    }
+
+    #[must_use]
+    pub fn unix_atomic_replace(mut self, unix_atomic_replace: bool) -> Self {
+        self.unix_atomic_replace = unix_atomic_replace;
+        self
+    }
+
+    #[must_use]
+    pub fn replace_non_socket(mut self, replace_non_socket: bool) -> Self {
+        self.replace_non_socket = replace_non_socket;
+        self
+    }
+
+    #[must_use]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    #[must_use]
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    #[must_use]
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    #[must_use]
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    #[must_use]
+    pub fn dir_mode(mut self, dir_mode: u32) -> Self {
+        self.dir_mode = Some(dir_mode);
+        self
+    }
+
+    fn has_file_options(&self) -> bool {
+        self.mode.is_some() || self.uid.is_some() || self.gid.is_some()
+    }
+
+    /// Set the ownership and then the mode of a bound socket file. The file is
+    /// removed if that fails.
+    fn apply_file(&self, path: &Path) -> Result<()> {
+        let mut result = Ok(());
+
+        if self.uid.is_some() || self.gid.is_some() {
+            result = std::os::unix::fs::chown(path, self.uid, self.gid);
+        }
+
+        if let (Ok(()), Some(mode)) = (&result, self.mode) {
+            result = std::fs::set_permissions(path, Permissions::from_mode(mode));
+        }
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(path);
+        }
+
+        result
+    }
+
+    fn apply(&self, socket: &Socket) -> Result<()> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            if let Some(device) = &self.device {
+                socket.bind_device(Some(device))?;
+            }
+
+            if let Some(mark) = self.mark {
+                socket.set_mark(mark)?;
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.transparent {
+            socket.set_transparent(true)?;
+        }
+
+        if let Some(tos) = self.tos {
+            socket.set_tos(tos)?;
+        }
+
+        Ok(())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// How [`BindOptions::reuseport_cbpf`] spreads connections over the listeners
+/// of a `SO_REUSEPORT` group.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReuseportBalance {
+    /// Give each connection to the listener with the index of the CPU that
+    /// handles it, for one listener per CPU bound in CPU order.
+    ///
+    /// Connections handled by CPUs without a listener are spread by the
+    /// kernel's hash.
+    Cpu,
+
+    /// Give each connection to one of the first `listeners` listeners at
+    /// random.
+    ///
+    /// Classic BPF programs keep no state between connections, so this is the
+    /// closest they get to round-robin. `listeners` must not be zero.
+    Random { listeners: u32 },
+
+    /// Run this program.
+    Custom(Vec<SockFilter>),
+}
+
+#[cfg(target_os = "linux")]
+impl ReuseportBalance {
+    fn program(&self) -> Vec<SockFilter> {
+        // Opcodes and ancillary data offsets from `linux/filter.h`.
+        const LD_W_ABS: u16 = 0x20;
+        const ALU_MOD_K: u16 = 0x94;
+        const RET_A: u16 = 0x16;
+        const SKF_AD_OFF: u32 = 0xffff_f000;
+        const SKF_AD_CPU: u32 = SKF_AD_OFF + 36;
+        const SKF_AD_RANDOM: u32 = SKF_AD_OFF + 56;
+
+        match self {
+            Self::Cpu => vec![
+                SockFilter::new(LD_W_ABS, 0, 0, SKF_AD_CPU),
+                SockFilter::new(RET_A, 0, 0, 0),
+            ],
+            Self::Random { listeners } => vec![
+                SockFilter::new(LD_W_ABS, 0, 0, SKF_AD_RANDOM),
+                SockFilter::new(ALU_MOD_K, 0, 0, *listeners),
+                SockFilter::new(RET_A, 0, 0, 0),
+            ],
+            Self::Custom(x) => x.clone(),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Options applied to every connection accepted by [`Listener::accept_with`].
+///
+/// The TCP-only options are skipped for unix connections.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct AcceptOptions {
+    /// See [`Stream::set_nodelay`].
+    pub nodelay: Option<bool>,
+
+    /// See [`Stream::set_keepalive`]. Only applies to TCP connections.
+    pub keepalive: Option<KeepaliveConfig>,
+
+    /// See [`Stream::set_send_buffer_size`].
+    pub send_buffer_size: Option<usize>,
+
+    /// See [`Stream::set_recv_buffer_size`].
+    pub recv_buffer_size: Option<usize>,
+
+    /// Keep connections for which applying an option failed.
+    ///
+    /// By default, such a connection is closed and the error is returned
+    /// instead of it.
+    pub ignore_errors: bool,
+}
+
+impl AcceptOptions {
+    #[must_use]
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    #[must_use]
+    pub fn keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    #[must_use]
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    #[must_use]
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    #[must_use]
+    pub fn ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Apply the options to an accepted connection, honoring
+    /// [`AcceptOptions::ignore_errors`].
+    pub(crate) fn apply(&self, stream: &Stream) -> Result<()> {
+        match self.try_apply(stream) {
+            Err(_) if self.ignore_errors => Ok(()),
+            x => x,
+        }
+    }
+
+    fn try_apply(&self, stream: &Stream) -> Result<()> {
+        if let Some(nodelay) = self.nodelay {
+            stream.set_nodelay(nodelay)?;
+        }
+
+        if let (Some(keepalive), true) = (self.keepalive, stream.is_tcp()) {
+            stream.set_keepalive(Some(keepalive))?;
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            stream.set_send_buffer_size(size)?;
+        }
+
+        if let Some(size) = self.recv_buffer_size {
+            stream.set_recv_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+}
 
 ///////////////////////////////////////////////////////////////////////////////
 
@@ -26,6 +534,36 @@ impl From<tokio::net::UnixListener> for Listener {
     }
 }
 
+/// Wrap an inherited socket, detecting whether it is a TCP or unix socket.
+///
+/// Fails with [`ErrorKind::InvalidInput`] if the descriptor is not a listening
+/// stream socket of a supported family.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+impl TryFrom<OwnedFd> for Listener {
+    type Error = Error;
+
+    fn try_from(fd: OwnedFd) -> Result<Self> {
+        let domain = check_socket(fd.as_fd(), true)?;
+        Self::from_fd(fd, domain)
+    }
+}
+
+impl Listener {
+    /// Wrap `fd`, a listening socket of family `domain`.
+    pub(crate) fn from_fd(fd: OwnedFd, domain: Domain) -> Result<Self> {
+        let listener = if domain == Domain::UNIX {
+            StdListener::Unix(fd.into())
+        } else {
+            StdListener::Tcp(fd.into())
+        };
+
+        Self::from_std(listener)
+    }
+}
+
 impl Listener {
     #[must_use]
     pub fn is_tcp(&self) -> bool {
@@ -36,33 +574,356 @@ impl Listener {
     pub fn is_unix(&self) -> bool {
         matches!(self, Self::Unix(..))
     }
+
+    /// `"tcp"` or `"unix"`, the scheme of the socket's addresses.
+    #[must_use]
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Tcp(..) => "tcp",
+            Self::Unix(..) => "unix",
+        }
+    }
+
+    /// The underlying tokio listener, if this is a TCP listener.
+    ///
+    /// This is an escape hatch for what this type does not wrap. Prefer its
+    /// own methods, which work the same for both kinds of sockets.
+    #[must_use]
+    pub fn as_tcp(&self) -> Option<&tokio::net::TcpListener> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_tcp_mut(&mut self) -> Option<&mut tokio::net::TcpListener> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio listener, or get `self` back if this is a
+    /// unix listener.
+    ///
+    /// See [`Listener::as_tcp`].
+    pub fn into_tcp(self) -> Result<tokio::net::TcpListener, Self> {
+        match self {
+            Self::Tcp(x) => Ok(x),
+            x => Err(x),
+        }
+    }
+
+    /// The underlying tokio listener, if this is a unix listener.
+    ///
+    /// See [`Listener::as_tcp`].
+    #[must_use]
+    pub fn as_unix(&self) -> Option<&tokio::net::UnixListener> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_unix_mut(&mut self) -> Option<&mut tokio::net::UnixListener> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio listener, or get `self` back if this is a
+    /// TCP listener.
+    ///
+    /// See [`Listener::as_tcp`].
+    pub fn into_unix(self) -> Result<tokio::net::UnixListener, Self> {
+        match self {
+            Self::Unix(x) => Ok(x),
+            x => Err(x),
+        }
+    }
 }
 
 impl Listener {
     pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
-        let addrs = addr.to_socket_addrs()?;
+        let addrs = addr.to_socket_addrs_async().await?;
 
-        let mut last_err = None;
+        let mut errors = Vec::new();
         for addr in addrs {
-            match Self::_bind(addr).await {
+            match trace::bind(addr.clone(), Self::_bind).await {
                 Ok(x) => return Ok(x),
-                Err(e) => last_err = Some(e),
+                Err(e) => errors.push((addr, e)),
             }
         }
 
-        let Some(last_err) = last_err else {
-            unreachable!()
+        Err(AddrErrors::into_error(errors))
+    }
+
+    /// Bind to the first free port of `range`.
+    ///
+    /// Like [`Listener::bind`] on the range, except that when every port is
+    /// in use, the error says which range was exhausted. Use
+    /// [`Listener::local_addr`] to find out which port was bound.
+    pub async fn bind_range(range: &PortRange) -> Result<Self> {
+        match Self::bind(range).await {
+            Err(e) if e.kind() == ErrorKind::AddrInUse => Err(Error::new(
+                ErrorKind::AddrInUse,
+                format!("all ports in {range} are in use"),
+            )),
+            x => x,
+        }
+    }
+
+    /// Bind to every address instead of only the first one that works.
+    ///
+    /// Fails with the error of the first address that cannot be bound. See
+    /// [`Listener::bind_some`] to carry on with the addresses that can.
+    pub async fn bind_all<A: ToSocketAddrs>(addr: A) -> Result<MultiListener> {
+        let addrs = addr.to_socket_addrs_async().await?;
+
+        let mut listeners = Vec::new();
+        for addr in addrs {
+            listeners.push(Self::_bind(addr).await?);
+        }
+
+        if listeners.is_empty() {
+            return Err(AddrErrors::into_error(Vec::new()));
+        }
+
+        Ok(MultiListener::new(listeners))
+    }
+
+    /// Bind to every address that can be bound.
+    ///
+    /// Returns the addresses that could not be bound along with their errors.
+    /// Only fails if no address could be bound.
+    pub async fn bind_some<A: ToSocketAddrs>(
+        addr: A,
+    ) -> Result<(MultiListener, Vec<(SocketAddr, Error)>)> {
+        let addrs = addr.to_socket_addrs_async().await?;
+
+        let mut listeners = Vec::new();
+        let mut errors = Vec::new();
+        for addr in addrs {
+            match Self::_bind(addr.clone()).await {
+                Ok(x) => listeners.push(x),
+                Err(e) => errors.push((addr, e)),
+            }
+        }
+
+        if listeners.is_empty() {
+            return Err(AddrErrors::into_error(errors));
+        }
+
+        Ok((MultiListener::new(listeners), errors))
+    }
+
+    /// Bind a TCP listener on `port` to each unicast address of the local
+    /// interfaces that are up, instead of the wildcard address.
+    ///
+    /// Loopback interfaces are only included if `loopback` is true. With port `0`, each address gets a port of its own.
+    ///
+    /// Like [`Listener::bind_some`], the addresses that could not be bound
+    /// are returned along with their errors, and this only fails if no address
+    /// could be bound. Check that none were returned to fail on any error.
+    /// Use [`MultiListener::rescan`] to follow addresses that come and go.
+    ///
+    /// Only supported on Linux.
+    #[cfg(target_os = "linux")]
+    pub async fn bind_each_interface(
+        port: u16,
+        loopback: bool,
+    ) -> Result<(MultiListener, Vec<(SocketAddr, Error)>)> {
+        let mut listener = MultiListener::for_interfaces(Interfaces { port, loopback });
+        let errors = listener.rescan().await?;
+
+        if listener.listeners().is_empty() {
+            return Err(AddrErrors::into_error(errors));
+        }
+
+        Ok((listener, errors))
+    }
+
+    /// Like [`Listener::bind`], but keep retrying for up to `timeout` while the
+    /// address is in use.
+    ///
+    /// This covers restarts that race the previous instance of a server, which
+    /// may still be holding on to the address. Only `EADDRINUSE` and
+    /// `EADDRNOTAVAIL` are retried, with a short backoff between attempts. Any
+    /// other error, or the last error once `timeout` has passed, is returned.
+    pub async fn bind_retry<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Result<Self> {
+        const MIN_BACKOFF: Duration = Duration::from_millis(10);
+        const MAX_BACKOFF: Duration = Duration::from_millis(200);
+
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs_async().await?.collect();
+        let deadline = Instant::now() + timeout;
+        let mut backoff = MIN_BACKOFF;
+
+        loop {
+            let e = match Self::bind(addrs.as_slice()).await {
+                Ok(x) => return Ok(x),
+                Err(e) => e,
+            };
+
+            if !matches!(e.kind(), ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable) {
+                return Err(e);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(e);
+            }
+
+            tokio::time::sleep_until(deadline.min(now + backoff)).await;
+            backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Like [`Listener::bind`], but create the socket according to `options`.
+    pub async fn bind_with<A: ToSocketAddrs>(addr: A, options: &BindOptions) -> Result<Self> {
+        let addrs = addr.to_socket_addrs_async().await?;
+
+        let mut errors = Vec::new();
+        for addr in addrs {
+            match trace::bind(addr.clone(), |addr| Self::_bind_with(addr, options)).await {
+                Ok(x) => return Ok(x),
+                Err(e) => errors.push((addr, e)),
+            }
+        }
+
+        Err(AddrErrors::into_error(errors))
+    }
+
+    async fn _bind_with(addr: SocketAddr, options: &BindOptions) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let socket = if options.mptcp {
+            Socket::new_mptcp_for(&addr)?
+        } else {
+            Socket::new_for(&addr)?
         };
+        #[cfg(not(target_os = "linux"))]
+        let socket = Socket::new_for(&addr)?;
+
+        if options.has_file_options() && addr.as_pathname().is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "file mode and ownership can only be set for unix socket paths",
+            ));
+        }
+
+        match &addr {
+            SocketAddr::Tcp(addr) => {
+                let x = socket.socket_ref();
+                x.set_reuse_address(options.reuse_address)?;
+                x.set_reuse_port(options.reuse_port)?;
 
-        Err(last_err)
+                if let Some(v6_only) = options.v6_only {
+                    if !addr.is_ipv6() {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            "IPV6_V6ONLY can only be set when binding an IPv6 address",
+                        ));
+                    }
+
+                    x.set_only_v6(v6_only)?;
+                }
+            }
+            SocketAddr::Unix(x) => {
+                if options.v6_only.is_some() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "IPV6_V6ONLY is not supported for unix sockets",
+                    ));
+                }
+
+                if options.reuse_port {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "SO_REUSEPORT is not supported for unix sockets",
+                    ));
+                }
+
+                #[cfg(target_os = "linux")]
+                if options.reuseport_cbpf.is_some() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "SO_ATTACH_REUSEPORT_CBPF is not supported for unix sockets",
+                    ));
+                }
+
+                if let Some(path) = x.as_pathname() {
+                    if options.create_dirs {
+                        create_parent_dirs(path, options.dir_mode).await?;
+                    }
+
+                    if options.unix_atomic_replace {
+                        check_replaceable(path, options.replace_non_socket).await?;
+                    } else if options.reclaim_stale_unix {
+                        reclaim_stale(path).await?;
+                    } else {
+                        remove_socket(path).await?;
+                    }
+                }
+            }
+        }
+
+        options.apply(&socket)?;
+
+        // The file gets the mode of the socket's inode, minus the umask.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if options.mode.is_some() {
+            rustix::fs::fchmod(&socket, rustix::fs::Mode::empty())?;
+        }
+
+        let path = addr.as_pathname();
+        let Some(path) = path.filter(|_| options.unix_atomic_replace) else {
+            socket.bind(&addr)?;
+
+            #[cfg(target_os = "linux")]
+            if let Some(balance) = &options.reuseport_cbpf {
+                socket.attach_reuseport_cbpf(&balance.program())?;
+            }
+
+            if let Some(path) = path {
+                options.apply_file(path)?;
+            }
+
+            return socket.listen(options.backlog);
+        };
+
+        let tmp = SocketAddr::unix(replacement_path(path))?;
+        let tmp_path = tmp.as_pathname().unwrap_or(path);
+
+        socket.bind(&tmp)?;
+        options.apply_file(tmp_path)?;
+
+        let listener = socket
+            .listen(options.backlog)
+            .and_then(|x| std::fs::rename(tmp_path, path).map(|()| x));
+
+        match &listener {
+            Ok(_) => {
+                let mut replaced = REPLACED.lock().unwrap_or_else(|e| e.into_inner());
+                replaced.insert(tmp_path.to_owned(), path.to_owned());
+            }
+            Err(_) => {
+                let _ = std::fs::remove_file(tmp_path);
+            }
+        }
+
+        listener
     }
 
     async fn _bind(addr: SocketAddr) -> Result<Self> {
+        if addr.is_unnamed() {
+            return Self::_bind_with(addr, &BindOptions::default()).await;
+        }
+
         match addr {
             SocketAddr::Tcp(x) => tokio::net::TcpListener::bind(x).await.map(Into::into),
             SocketAddr::Unix(x) => {
-                assert!(!x.is_unnamed(), "cannot bind to an unnamed address");
-
                 if let Some(path) = x.as_pathname() {
                     let _ = tokio::fs::remove_file(path).await;
                 }
@@ -73,6 +934,67 @@ impl Listener {
         }
     }
 
+    /// Register a standard library listener with the current tokio runtime.
+    ///
+    /// The socket is switched to non-blocking mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn from_std(listener: StdListener) -> Result<Self> {
+        match listener {
+            StdListener::Tcp(x) => {
+                x.set_nonblocking(true)?;
+                tokio::net::TcpListener::from_std(x).map(Into::into)
+            }
+            StdListener::Unix(x) => {
+                x.set_nonblocking(true)?;
+                tokio::net::UnixListener::from_std(x).map(Into::into)
+            }
+        }
+    }
+
+    /// Deregister the listener from the runtime and turn it into a standard
+    /// library listener.
+    ///
+    /// The socket is switched back to blocking mode.
+    pub fn into_std(self) -> Result<StdListener> {
+        match self {
+            Self::Tcp(x) => {
+                let x = x.into_std()?;
+                x.set_nonblocking(false)?;
+                Ok(x.into())
+            }
+            Self::Unix(x) => {
+                let x = x.into_std()?;
+                x.set_nonblocking(false)?;
+                Ok(x.into())
+            }
+        }
+    }
+
+    /// Create a new listener for the same socket, registered with the current
+    /// tokio runtime.
+    ///
+    /// The file descriptor is duplicated, so the socket stays open until all
+    /// clones are dropped. Clones compete for connections: each one is
+    /// accepted by exactly one of them. Calling this from inside another
+    /// runtime is how several runtimes accept from one bound socket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn try_clone(&self) -> Result<Self> {
+        let fd = self.as_fd().try_clone_to_owned()?;
+
+        let listener = match self {
+            Self::Tcp(..) => StdListener::Tcp(fd.into()),
+            Self::Unix(..) => StdListener::Unix(fd.into()),
+        };
+
+        Self::from_std(listener)
+    }
+
     pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(Stream, SocketAddr)>> {
         match self {
             Self::Tcp(x) => x.poll_accept(cx).map(|x| x.map(into2)),
@@ -81,27 +1003,456 @@ impl Listener {
     }
 
     pub async fn accept(&self) -> Result<(Stream, SocketAddr)> {
-        match self {
+        let result = match self {
             Self::Tcp(x) => x.accept().await.map(into2),
             Self::Unix(x) => x.accept().await.map(into2),
+        };
+
+        trace::accepted(&result);
+        result
+    }
+
+    /// Wait for a connection, then accept up to `max` pending connections
+    /// without waiting again, appending them to `out`.
+    ///
+    /// Returns how many connections were accepted. If accepting fails after at
+    /// least one connection was accepted, the connections accepted so far are
+    /// returned and the error is discarded. Errors that persist, like running
+    /// out of file descriptors, are returned by the next call. Returns `0`
+    /// right away if `max` is `0`.
+    pub async fn accept_many(
+        &self,
+        out: &mut Vec<(Stream, SocketAddr)>,
+        max: usize,
+    ) -> Result<usize> {
+        if max == 0 {
+            return Ok(0);
+        }
+
+        poll_fn(|cx| {
+            let mut n = 0;
+
+            while n < max {
+                let result = match self.poll_accept(cx) {
+                    Poll::Ready(x) => x,
+                    Poll::Pending if n == 0 => return Poll::Pending,
+                    Poll::Pending => break,
+                };
+
+                trace::accepted(&result);
+                match result {
+                    Ok(x) => {
+                        out.push(x);
+                        n += 1;
+                    }
+                    Err(e) if n == 0 => return Poll::Ready(Err(e)),
+                    Err(_) => break,
+                }
+            }
+
+            Poll::Ready(Ok(n))
+        })
+        .await
+    }
+
+    /// Like [`Listener::accept`], but apply `options` to the connection before
+    /// returning it.
+    ///
+    /// If an option cannot be applied, the connection is closed and the error
+    /// is returned, unless [`AcceptOptions::ignore_errors`] is set.
+    pub async fn accept_with(&self, options: &AcceptOptions) -> Result<(Stream, SocketAddr)> {
+        let (stream, addr) = self.accept().await?;
+        options.apply(&stream)?;
+        Ok((stream, addr))
+    }
+
+    /// Like [`Listener::accept`], but keep trying when accepting fails, as
+    /// decided by `on_error`.
+    ///
+    /// `on_error` is called with every error, so it is also the place to log
+    /// or count them. [`AcceptErrorAction::classify`] is a sensible default.
+    /// Consecutive backoffs start at 5ms and double up to `max_backoff`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `on_error` returns [`AcceptErrorAction::Backoff`] outside of a
+    /// tokio runtime with the time driver enabled.
+    pub async fn accept_robust<F>(
+        &self,
+        max_backoff: Duration,
+        mut on_error: F,
+    ) -> Result<(Stream, SocketAddr)>
+    where
+        F: FnMut(&Error) -> AcceptErrorAction,
+    {
+        let mut backoff = Duration::from_millis(5).min(max_backoff);
+
+        loop {
+            let e = match self.accept().await {
+                Ok(x) => return Ok(x),
+                Err(e) => e,
+            };
+
+            match on_error(&e) {
+                AcceptErrorAction::Retry => {}
+                AcceptErrorAction::Backoff => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2).min(max_backoff);
+                }
+                AcceptErrorAction::Fail => return Err(e),
+            }
         }
     }
 
+    /// Like [`Listener::accept`], but also returns the local address of the
+    /// accepted connection.
+    ///
+    /// This is useful with wildcard binds, where the local address tells which
+    /// interface the connection arrived on.
+    pub async fn accept_full(&self) -> Result<(Stream, SocketAddr, SocketAddr)> {
+        let (stream, peer) = self.accept().await?;
+        let local = stream.local_addr()?;
+        Ok((stream, peer, local))
+    }
+
+    /// Keep at most `max` accepted connections alive at once.
+    ///
+    /// See [`TrackedListener::with_limit`].
+    pub fn limit_connections(self, max: usize) -> TrackedListener {
+        TrackedListener::with_limit(self, max)
+    }
+
+    /// Share this listener between accept loops and allow replacing it.
+    ///
+    /// See [`SharedListener`].
+    pub fn shared(self) -> SharedListener {
+        SharedListener::new(self)
+    }
+
+    /// Count accepted connections and accept errors.
+    ///
+    /// See [`MeteredListener`].
+    pub fn metered(self) -> MeteredListener {
+        MeteredListener::new(self)
+    }
+
+    /// Close connections rejected by `filter` right after accepting them.
+    ///
+    /// See [`FilteredListener`].
+    pub fn filtered<F>(self, filter: F) -> FilteredListener<F>
+    where
+        F: Fn(&SocketAddr, Option<&PeerCred>) -> Verdict,
+    {
+        FilteredListener::new(self, filter)
+    }
+
+    /// Close connections from peers that `filter` denies right after accepting
+    /// them.
+    ///
+    /// See [`AddrFilter`] and [`FilteredListener`].
+    pub fn filtered_addrs(
+        self,
+        filter: AddrFilter,
+    ) -> FilteredListener<impl Fn(&SocketAddr, Option<&PeerCred>) -> Verdict + Send + Sync + 'static>
+    {
+        self.filtered(move |addr, _| filter.verdict(addr))
+    }
+
+    /// Remove the unix socket file when the listener is dropped.
+    ///
+    /// See [`UnlinkingListener`].
+    pub fn unlink_on_drop(self) -> Result<UnlinkingListener> {
+        UnlinkingListener::new(self)
+    }
+
+    /// The accepted connections as a stream. See [`Incoming`].
+    #[must_use]
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+
+    /// Like [`Listener::incoming`], but take ownership of the listener.
+    #[must_use]
+    pub fn into_incoming(self) -> IntoIncoming {
+        IntoIncoming { listener: self }
+    }
+
     pub fn local_addr(&self) -> Result<SocketAddr> {
         match self {
             Self::Tcp(x) => x.local_addr().map(Into::into),
-            Self::Unix(x) => x.local_addr().map(Into::into),
+            Self::Unix(x) => x.local_addr().map(|x| replaced_addr(x.into())),
+        }
+    }
+
+    /// The kernel's unique identifier for this socket (`SO_COOKIE`).
+    ///
+    /// Only supported on Linux.
+    pub fn socket_cookie(&self) -> Result<u64> {
+        match self {
+            Self::Tcp(x) => socket_cookie(x.as_fd()),
+            Self::Unix(x) => socket_cookie(x.as_fd()),
+        }
+    }
+
+    /// The time-to-live of IP packets sent by accepted connections (`IP_TTL`).
+    ///
+    /// Like with [`Stream::ttl`], this is an [`ErrorKind::Unsupported`] error
+    /// for unix sockets.
+    pub fn ttl(&self) -> Result<u32> {
+        match self {
+            Self::Tcp(x) => x.ttl(),
+            Self::Unix(_) => Err(unsupported("time-to-live on unix sockets")),
+        }
+    }
+
+    /// See [`Listener::ttl`].
+    pub fn set_ttl(&self, ttl: u32) -> Result<()> {
+        match self {
+            Self::Tcp(x) => x.set_ttl(ttl),
+            Self::Unix(_) => Err(unsupported("time-to-live on unix sockets")),
+        }
+    }
+
+    /// The hop limit of IPv6 packets sent by accepted connections
+    /// (`IPV6_UNICAST_HOPS`).
+    ///
+    /// This is the IPv6 counterpart of [`Listener::ttl`], which only applies
+    /// to IPv4. See [`Stream::unicast_hops_v6`].
+    pub fn unicast_hops_v6(&self) -> Result<u32> {
+        match self {
+            Self::Tcp(x) => SockRef::from(x).unicast_hops_v6(),
+            Self::Unix(_) => Err(unsupported("hop limit on unix sockets")),
+        }
+    }
+
+    /// See [`Listener::unicast_hops_v6`].
+    pub fn set_unicast_hops_v6(&self, hops: u32) -> Result<()> {
+        match self {
+            Self::Tcp(x) => SockRef::from(x).set_unicast_hops_v6(hops),
+            Self::Unix(_) => Err(unsupported("hop limit on unix sockets")),
         }
     }
 
+    /// The size of the kernel send buffer (`SO_SNDBUF`).
+    ///
+    /// On Linux, this is double the value that was set, because the kernel
+    /// reserves the extra space for bookkeeping.
+    pub fn send_buffer_size(&self) -> Result<usize> {
+        SockRef::from(self).send_buffer_size()
+    }
+
+    /// Set the size of the kernel send buffer (`SO_SNDBUF`).
+    ///
+    /// Accepted connections inherit this setting.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<()> {
+        SockRef::from(self).set_send_buffer_size(size)
+    }
+
+    /// The size of the kernel receive buffer (`SO_RCVBUF`).
+    ///
+    /// On Linux, this is double the value that was set, because the kernel
+    /// reserves the extra space for bookkeeping.
+    pub fn recv_buffer_size(&self) -> Result<usize> {
+        SockRef::from(self).recv_buffer_size()
+    }
+
+    /// Set the size of the kernel receive buffer (`SO_RCVBUF`).
+    ///
+    /// Accepted connections inherit this setting.
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<()> {
+        SockRef::from(self).set_recv_buffer_size(size)
+    }
+
+    /// The socket, for the options this crate does not wrap.
+    ///
+    /// Getting and setting socket options through it is fine, but accept
+    /// through the listener, not the socket. See [`Stream::as_socket`].
+    #[must_use]
+    pub fn as_socket(&self) -> SockRef<'_> {
+        SockRef::from(self)
+    }
+
     pub fn take_error(&self) -> Result<Option<Error>> {
         match self {
-            Self::Tcp(_) => Ok(None),
+            Self::Tcp(x) => SockRef::from(x).take_error(),
             Self::Unix(x) => x.take_error(),
         }
     }
 }
 
+/// Remove the socket file at `path`, but fail if it is not a socket.
+async fn remove_socket(path: &Path) -> Result<()> {
+    check_replaceable(path, false).await?;
+
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Remove the socket file at `path` if nothing is listening on it.
+///
+/// See [`BindOptions::reclaim_stale_unix`].
+async fn reclaim_stale(path: &Path) -> Result<()> {
+    let meta = match tokio::fs::symlink_metadata(path).await {
+        Ok(x) => x,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if !meta.file_type().is_socket() {
+        return Err(Error::new(
+            ErrorKind::AddrInUse,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match tokio::net::UnixStream::connect(path).await {
+        Ok(_) => {
+            return Err(Error::new(
+                ErrorKind::AddrInUse,
+                format!("a listener is accepting on {}", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {}
+        Err(e) => return Err(e),
+    }
+
+    // Only remove the socket that was probed, not one bound since.
+    let now = tokio::fs::symlink_metadata(path).await?;
+    if now.dev() != meta.dev() || now.ino() != meta.ino() {
+        return Err(Error::new(
+            ErrorKind::AddrInUse,
+            format!("{} was replaced while checking it", path.display()),
+        ));
+    }
+
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The paths sockets bound with [`BindOptions::unix_atomic_replace`] were
+/// renamed to, by the temporary path the kernel keeps reporting for them.
+///
+/// Entries are never removed, but there is only one per bind and the temporary
+/// paths are random, so a path is never looked up for the wrong socket.
+static REPLACED: Mutex<BTreeMap<PathBuf, PathBuf>> = Mutex::new(BTreeMap::new());
+
+/// Fail unless the file at `path`, if any, may be replaced.
+///
+/// See [`BindOptions::unix_atomic_replace`].
+async fn check_replaceable(path: &Path, replace_non_socket: bool) -> Result<()> {
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(x) if !x.file_type().is_socket() && !replace_non_socket => Err(Error::new(
+            ErrorKind::AddrInUse,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The temporary path to bind before renaming over `path`.
+fn replacement_path(path: &Path) -> PathBuf {
+    let random = RandomState::new().hash_one(path);
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".anysocket-{random:016x}"));
+    tmp.into()
+}
+
+/// The path a socket bound with [`BindOptions::unix_atomic_replace`] was
+/// renamed to, since the kernel keeps reporting the temporary one. Other
+/// addresses are returned unchanged.
+pub(crate) fn replaced_addr(addr: SocketAddr) -> SocketAddr {
+    let Some(path) = addr.as_pathname() else {
+        return addr;
+    };
+
+    let replaced = REPLACED.lock().unwrap_or_else(|e| e.into_inner());
+    match replaced.get(path) {
+        Some(target) => SocketAddr::unix(target).unwrap_or(addr),
+        None => addr,
+    }
+}
+
+/// Create the missing parent directories of `path`, outermost first.
+///
+/// See [`BindOptions::create_dirs`].
+async fn create_parent_dirs(path: &Path, mode: Option<u32>) -> Result<()> {
+    let Some(parent) = path.parent() else {
+        return Ok(());
+    };
+
+    let mut missing = Vec::new();
+    for dir in parent.ancestors() {
+        if dir.as_os_str().is_empty() {
+            break;
+        }
+
+        match tokio::fs::metadata(dir).await {
+            Ok(x) if x.is_dir() => break,
+            Ok(_) => return Err(not_a_directory(dir)),
+            Err(e) if e.kind() == ErrorKind::NotFound => missing.push(dir),
+            Err(e) => return Err(dir_error(dir, e)),
+        }
+    }
+
+    let mut builder = tokio::fs::DirBuilder::new();
+    builder.mode(mode.unwrap_or(0o777));
+
+    for dir in missing.into_iter().rev() {
+        match builder.create(dir).await {
+            Ok(()) => {}
+            // Either created by someone else in the meantime, which is fine,
+            // or a dangling symlink, which mkdir does not follow.
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                match tokio::fs::symlink_metadata(dir).await {
+                    Ok(x) if x.is_dir() => {}
+                    _ => return Err(not_a_directory(dir)),
+                }
+            }
+            Err(e) => return Err(dir_error(dir, e)),
+        }
+    }
+
+    Ok(())
+}
+
+fn not_a_directory(dir: &Path) -> Error {
+    Error::new(
+        ErrorKind::NotADirectory,
+        format!("{} exists and is not a directory", dir.display()),
+    )
+}
+
+fn dir_error(dir: &Path, e: Error) -> Error {
+    Error::new(
+        e.kind(),
+        format!("failed to create directory {}: {e}", dir.display()),
+    )
+}
+
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(x) => x.as_fd(),
+            Self::Unix(x) => x.as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Tcp(x) => x.as_raw_fd(),
+            Self::Unix(x) => x.as_raw_fd(),
+        }
+    }
+}
+
 impl fmt::Debug for Listener {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -110,3 +1461,176 @@ impl fmt::Debug for Listener {
         }
     }
 }
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        DisplayAddr(self.local_addr()).fmt(f)
+    }
+}
+
+/// Only available with the `tokio-util` feature.
+#[cfg(feature = "tokio-util")]
+impl tokio_util::net::Listener for Listener {
+    type Io = Stream;
+    type Addr = SocketAddr;
+
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<Result<(Stream, SocketAddr)>> {
+        Self::poll_accept(self, cx)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Self::local_addr(self)
+    }
+}
+
+/// axum expects listeners to handle accept errors themselves, so none are
+/// returned. They are retried as [`AcceptErrorAction::classify`] decides,
+/// except that the errors it would fail with back off as well, for up to a
+/// second like axum's own listeners.
+///
+/// Only available with the `axum` feature.
+#[cfg(feature = "axum")]
+impl axum::serve::Listener for Listener {
+    type Io = Stream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Stream, SocketAddr) {
+        let on_error = |e: &Error| match AcceptErrorAction::classify(e) {
+            AcceptErrorAction::Fail => AcceptErrorAction::Backoff,
+            x => x,
+        };
+
+        // `on_error` never fails, so this only loops for the types.
+        loop {
+            if let Ok(x) = self.accept_robust(Duration::from_secs(1), on_error).await {
+                return x;
+            }
+        }
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Self::local_addr(self)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// What [`Listener::accept_robust`] does after an accept error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptErrorAction {
+    /// Accept again right away.
+    Retry,
+    /// Accept again after a delay that grows with consecutive backoffs.
+    Backoff,
+    /// Return the error.
+    Fail,
+}
+
+impl AcceptErrorAction {
+    /// The default policy.
+    ///
+    /// Errors which only affect a single connection (`ECONNABORTED`,
+    /// `ECONNRESET`, `EINTR`) are retried right away. Running out of resources
+    /// (`EMFILE`, `ENFILE`, `ENOBUFS`, `ENOMEM`) backs off, as retrying
+    /// immediately would just spin. Anything else fails.
+    #[must_use]
+    pub fn classify(e: &Error) -> Self {
+        if matches!(
+            e.kind(),
+            ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset | ErrorKind::Interrupted
+        ) {
+            return Self::Retry;
+        }
+
+        let errno = e.raw_os_error().map(Errno::from_raw_os_error);
+        if matches!(
+            errno,
+            Some(Errno::MFILE | Errno::NFILE | Errno::NOBUFS | Errno::NOMEM)
+        ) {
+            return Self::Backoff;
+        }
+
+        Self::Fail
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The connections accepted by a [`Listener`], one after another.
+///
+/// [`Incoming::poll_next`] has the signature of `Stream::poll_next` from
+/// `futures-core`, so it can be adapted with `futures::stream::poll_fn`.
+///
+/// Errors do not end the stream. Most accept errors are transient, so after
+/// one the next connection is accepted as usual. The stream never ends on its
+/// own. Like [`Listener::accept`], it is cancel safe.
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a Listener,
+}
+
+impl Incoming<'_> {
+    pub fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(Stream, SocketAddr)>>> {
+        self.listener.poll_accept(cx).map(Some)
+    }
+
+    pub async fn next(&mut self) -> Option<Result<(Stream, SocketAddr)>> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+/// Like [`Incoming`], but owns the listener.
+///
+/// See [`Listener::into_incoming`].
+#[derive(Debug)]
+pub struct IntoIncoming {
+    listener: Listener,
+}
+
+impl IntoIncoming {
+    #[must_use]
+    pub fn get_ref(&self) -> &Listener {
+        &self.listener
+    }
+
+    pub fn into_inner(self) -> Listener {
+        self.listener
+    }
+
+    pub fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(Stream, SocketAddr)>>> {
+        self.listener.poll_accept(cx).map(Some)
+    }
+
+    pub async fn next(&mut self) -> Option<Result<(Stream, SocketAddr)>> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A standard library listener of either kind.
+///
+/// See [`Listener::from_std`] and [`Listener::into_std`].
+#[derive(Debug)]
+pub enum StdListener {
+    Tcp(std::net::TcpListener),
+    Unix(std::os::unix::net::UnixListener),
+}
+
+impl From<std::net::TcpListener> for StdListener {
+    fn from(x: std::net::TcpListener) -> Self {
+        Self::Tcp(x)
+    }
+}
+
+impl From<std::os::unix::net::UnixListener> for StdListener {
+    fn from(x: std::os::unix::net::UnixListener) -> Self {
+        Self::Unix(x)
+    }
+}
@@ -1,7 +1,6 @@
 macro_rules! impl_async_read {
     (
-        type: $T:ty,
-        proj: $proj:ident,
+        type: $T:ty $(,)?
     ) => {
         impl AsyncRead for $T {
             fn poll_read(
@@ -9,19 +8,45 @@ macro_rules! impl_async_read {
                 cx: &mut Context<'_>,
                 buf: &mut ReadBuf<'_>,
             ) -> Poll<Result<()>> {
-                match self.project() {
-                    $proj::Tcp(x) => x.poll_read(cx, buf),
-                    $proj::Unix(x) => x.poll_read(cx, buf),
+                match self.get_mut() {
+                    Self::Tcp(x) => Pin::new(x).poll_read(cx, buf),
+                    Self::Unix(x) => Pin::new(x).poll_read(cx, buf),
                 }
             }
         }
+
+        #[cfg(feature = "futures-io")]
+        impl futures_io::AsyncRead for $T {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                let mut buf = ReadBuf::new(buf);
+                match AsyncRead::poll_read(self, cx, &mut buf) {
+                    Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+                    Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+        }
+
+        #[cfg(feature = "hyper")]
+        impl hyper::rt::Read for $T {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: hyper::rt::ReadBufCursor<'_>,
+            ) -> Poll<std::io::Result<()>> {
+                $crate::utils::poll_read_hyper(self, cx, buf)
+            }
+        }
     };
 }
 
 macro_rules! impl_async_write {
     (
-        type: $T:ty,
-        proj: $proj:ident,
+        type: $T:ty $(,)?
     ) => {
         impl AsyncWrite for $T {
             fn poll_write(
@@ -29,23 +54,23 @@ macro_rules! impl_async_write {
                 cx: &mut Context<'_>,
                 buf: &[u8],
             ) -> Poll<Result<usize>> {
-                match self.project() {
-                    $proj::Tcp(x) => x.poll_write(cx, buf),
-                    $proj::Unix(x) => x.poll_write(cx, buf),
+                match self.get_mut() {
+                    Self::Tcp(x) => Pin::new(x).poll_write(cx, buf),
+                    Self::Unix(x) => Pin::new(x).poll_write(cx, buf),
                 }
             }
 
             fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
-                match self.project() {
-                    $proj::Tcp(x) => x.poll_flush(cx),
-                    $proj::Unix(x) => x.poll_flush(cx),
+                match self.get_mut() {
+                    Self::Tcp(x) => Pin::new(x).poll_flush(cx),
+                    Self::Unix(x) => Pin::new(x).poll_flush(cx),
                 }
             }
 
             fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
-                match self.project() {
-                    $proj::Tcp(x) => x.poll_shutdown(cx),
-                    $proj::Unix(x) => x.poll_shutdown(cx),
+                match self.get_mut() {
+                    Self::Tcp(x) => Pin::new(x).poll_shutdown(cx),
+                    Self::Unix(x) => Pin::new(x).poll_shutdown(cx),
                 }
             }
 
@@ -54,9 +79,9 @@ macro_rules! impl_async_write {
                 cx: &mut Context<'_>,
                 bufs: &[IoSlice<'_>],
             ) -> Poll<Result<usize>> {
-                match self.project() {
-                    $proj::Tcp(x) => x.poll_write_vectored(cx, bufs),
-                    $proj::Unix(x) => x.poll_write_vectored(cx, bufs),
+                match self.get_mut() {
+                    Self::Tcp(x) => Pin::new(x).poll_write_vectored(cx, bufs),
+                    Self::Unix(x) => Pin::new(x).poll_write_vectored(cx, bufs),
                 }
             }
 
@@ -67,6 +92,67 @@ macro_rules! impl_async_write {
                 }
             }
         }
+
+        #[cfg(feature = "futures-io")]
+        impl futures_io::AsyncWrite for $T {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<std::io::Result<usize>> {
+                AsyncWrite::poll_write(self, cx, buf)
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[IoSlice<'_>],
+            ) -> Poll<std::io::Result<usize>> {
+                AsyncWrite::poll_write_vectored(self, cx, bufs)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                AsyncWrite::poll_flush(self, cx)
+            }
+
+            fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                AsyncWrite::poll_shutdown(self, cx)
+            }
+        }
+
+        #[cfg(feature = "hyper")]
+        impl hyper::rt::Write for $T {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<std::io::Result<usize>> {
+                AsyncWrite::poll_write(self, cx, buf)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+                AsyncWrite::poll_flush(self, cx)
+            }
+
+            fn poll_shutdown(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<std::io::Result<()>> {
+                AsyncWrite::poll_shutdown(self, cx)
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                AsyncWrite::is_write_vectored(self)
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[IoSlice<'_>],
+            ) -> Poll<std::io::Result<usize>> {
+                AsyncWrite::poll_write_vectored(self, cx, bufs)
+            }
+        }
     };
 }
 
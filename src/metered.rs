@@ -0,0 +1,387 @@
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{IoSlice, IoSliceMut};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll, ready};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::utils::Result;
+use crate::{Listener, OwnedReadHalf, OwnedWriteHalf, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+struct StreamCounters {
+    created: Instant,
+    read: AtomicU64,
+    written: AtomicU64,
+}
+
+/// Byte counters of a [`MeteredStream`].
+///
+/// This is a cheap handle which can be cloned and read from any task while
+/// the stream is in use. Split halves update the same counters.
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<StreamCounters>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            inner: Arc::new(StreamCounters {
+                created: Instant::now(),
+                read: AtomicU64::new(0),
+                written: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Total number of bytes read from the stream.
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.inner.read.load(Ordering::Relaxed)
+    }
+
+    /// Total number of bytes written to the stream.
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.inner.written.load(Ordering::Relaxed)
+    }
+
+    /// Time since the stream started being metered.
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.inner.created.elapsed()
+    }
+
+    fn add_read(&self, n: usize) {
+        self.inner.read.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn add_written(&self, n: usize) {
+        self.inner.written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn count_read(&self, result: Result<usize>) -> Result<usize> {
+        result.inspect(|&n| self.add_read(n))
+    }
+
+    fn count_written(&self, result: Result<usize>) -> Result<usize> {
+        result.inspect(|&n| self.add_written(n))
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("bytes_read", &self.bytes_read())
+            .field("bytes_written", &self.bytes_written())
+            .field("duration", &self.duration())
+            .finish()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+macro_rules! impl_metered {
+    ($T:ident, $inner:ty) => {
+        impl $T {
+            #[must_use]
+            pub fn get_ref(&self) -> &$inner {
+                &self.inner
+            }
+
+            /// Get the inner value.
+            ///
+            /// I/O done directly on it is not counted.
+            #[must_use]
+            pub fn get_mut(&mut self) -> &mut $inner {
+                &mut self.inner
+            }
+
+            pub fn into_parts(self) -> ($inner, Metrics) {
+                (self.inner, self.metrics)
+            }
+
+            #[must_use]
+            pub fn metrics(&self) -> &Metrics {
+                &self.metrics
+            }
+        }
+
+        impl Deref for $T {
+            type Target = $inner;
+
+            fn deref(&self) -> &Self::Target {
+                &self.inner
+            }
+        }
+
+        impl DerefMut for $T {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.inner
+            }
+        }
+
+        impl fmt::Debug for $T {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.inner.fmt(f)
+            }
+        }
+    };
+}
+
+macro_rules! impl_metered_read {
+    ($T:ident) => {
+        impl $T {
+            pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
+                self.metrics.count_read(self.inner.try_read(buf))
+            }
+
+            pub fn try_read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> Result<usize> {
+                self.metrics.count_read(self.inner.try_read_vectored(bufs))
+            }
+        }
+
+        impl AsyncRead for $T {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<Result<()>> {
+                let this = self.get_mut();
+
+                let before = buf.filled().len();
+                ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+                this.metrics.add_read(buf.filled().len() - before);
+
+                Poll::Ready(Ok(()))
+            }
+        }
+    };
+}
+
+macro_rules! impl_metered_write {
+    ($T:ident) => {
+        impl $T {
+            pub fn try_write(&self, buf: &[u8]) -> Result<usize> {
+                self.metrics.count_written(self.inner.try_write(buf))
+            }
+
+            pub fn try_write_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+                self.metrics
+                    .count_written(self.inner.try_write_vectored(bufs))
+            }
+        }
+
+        impl AsyncWrite for $T {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<Result<usize>> {
+                let this = self.get_mut();
+                let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf));
+                Poll::Ready(this.metrics.count_written(n))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[IoSlice<'_>],
+            ) -> Poll<Result<usize>> {
+                let this = self.get_mut();
+                let n = ready!(Pin::new(&mut this.inner).poll_write_vectored(cx, bufs));
+                Poll::Ready(this.metrics.count_written(n))
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                self.inner.is_write_vectored()
+            }
+        }
+    };
+}
+
+/// A [`Stream`] that counts the bytes read from and written to it.
+///
+/// Reads and writes through [`AsyncRead`], [`AsyncWrite`] and the `try_*`
+/// methods are counted. Everything else is available through [`Deref`] and
+/// is not counted.
+///
+/// Created by [`Stream::metered`].
+pub struct MeteredStream {
+    inner: Stream,
+    metrics: Metrics,
+}
+
+impl MeteredStream {
+    pub fn new(stream: Stream) -> Self {
+        Self {
+            inner: stream,
+            metrics: Metrics::new(),
+        }
+    }
+
+    pub fn into_split(self) -> (MeteredOwnedReadHalf, MeteredOwnedWriteHalf) {
+        let (r, w) = self.inner.into_split();
+
+        let r = MeteredOwnedReadHalf {
+            inner: r,
+            metrics: self.metrics.clone(),
+        };
+
+        let w = MeteredOwnedWriteHalf {
+            inner: w,
+            metrics: self.metrics,
+        };
+
+        (r, w)
+    }
+}
+
+impl From<Stream> for MeteredStream {
+    fn from(stream: Stream) -> Self {
+        Self::new(stream)
+    }
+}
+
+impl_metered!(MeteredStream, Stream);
+impl_metered_read!(MeteredStream);
+impl_metered_write!(MeteredStream);
+
+/// The read half of a [`MeteredStream`].
+pub struct MeteredOwnedReadHalf {
+    inner: OwnedReadHalf,
+    metrics: Metrics,
+}
+
+impl_metered!(MeteredOwnedReadHalf, OwnedReadHalf);
+impl_metered_read!(MeteredOwnedReadHalf);
+
+/// The write half of a [`MeteredStream`].
+pub struct MeteredOwnedWriteHalf {
+    inner: OwnedWriteHalf,
+    metrics: Metrics,
+}
+
+impl_metered!(MeteredOwnedWriteHalf, OwnedWriteHalf);
+impl_metered_write!(MeteredOwnedWriteHalf);
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+struct ListenerCounters {
+    accepted: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// Accept counters of a [`MeteredListener`].
+///
+/// Like [`Metrics`], this can be cloned and read from any task.
+#[derive(Clone, Default)]
+pub struct ListenerMetrics {
+    inner: Arc<ListenerCounters>,
+}
+
+impl ListenerMetrics {
+    /// Number of connections accepted successfully.
+    #[must_use]
+    pub fn accepted(&self) -> u64 {
+        self.inner.accepted.load(Ordering::Relaxed)
+    }
+
+    /// Number of accepts that failed.
+    #[must_use]
+    pub fn accept_errors(&self) -> u64 {
+        self.inner.errors.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for ListenerMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListenerMetrics")
+            .field("accepted", &self.accepted())
+            .field("accept_errors", &self.accept_errors())
+            .finish()
+    }
+}
+
+/// A [`Listener`] that counts accepted connections and accept errors.
+///
+/// Created by [`Listener::metered`].
+pub struct MeteredListener {
+    listener: Listener,
+    metrics: ListenerMetrics,
+}
+
+impl From<Listener> for MeteredListener {
+    fn from(listener: Listener) -> Self {
+        Self::new(listener)
+    }
+}
+
+impl MeteredListener {
+    pub fn new(listener: Listener) -> Self {
+        Self {
+            listener,
+            metrics: ListenerMetrics::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &Listener {
+        &self.listener
+    }
+
+    pub fn into_inner(self) -> Listener {
+        self.listener
+    }
+
+    #[must_use]
+    pub fn metrics(&self) -> &ListenerMetrics {
+        &self.metrics
+    }
+
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(Stream, SocketAddr)>> {
+        let result = ready!(self.listener.poll_accept(cx));
+
+        let counter = match result {
+            Ok(_) => &self.metrics.inner.accepted,
+            Err(_) => &self.metrics.inner.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        Poll::Ready(result)
+    }
+
+    pub async fn accept(&self) -> Result<(Stream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl fmt::Debug for MeteredListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeteredListener")
+            .field("listener", &self.listener)
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
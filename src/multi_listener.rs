@@ -0,0 +1,197 @@
+use std::fmt;
+use std::future::poll_fn;
+#[cfg(target_os = "linux")]
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use crate::utils::{DisplayAddr, Result};
+use crate::{Listener, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Several listeners that are accepted from as one.
+///
+/// See [`Listener::bind_all`], [`Listener::bind_some`] and
+/// `Listener::bind_each_interface`.
+#[derive(Debug)]
+pub struct MultiListener {
+    listeners: Vec<Listener>,
+    /// The listener to poll first on the next accept.
+    next: AtomicUsize,
+    /// What [`MultiListener::rescan`] binds to, if the listeners were bound
+    /// to the addresses of the local interfaces.
+    #[cfg(target_os = "linux")]
+    interfaces: Option<Interfaces>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Interfaces {
+    pub(crate) port: u16,
+    pub(crate) loopback: bool,
+}
+
+impl MultiListener {
+    pub(crate) fn new(listeners: Vec<Listener>) -> Self {
+        Self {
+            listeners,
+            next: AtomicUsize::new(0),
+            #[cfg(target_os = "linux")]
+            interfaces: None,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn for_interfaces(interfaces: Interfaces) -> Self {
+        Self {
+            interfaces: Some(interfaces),
+            ..Self::new(Vec::new())
+        }
+    }
+
+    #[must_use]
+    pub fn listeners(&self) -> &[Listener] {
+        &self.listeners
+    }
+
+    #[must_use]
+    pub fn into_listeners(self) -> Vec<Listener> {
+        self.listeners
+    }
+
+    /// Accept a connection from any of the listeners.
+    ///
+    /// Each call starts polling at the listener after the one the previous
+    /// call started at, so a busy listener cannot starve the others.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(Stream, SocketAddr)>> {
+        let len = self.listeners.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        for i in 0..len {
+            let listener = &self.listeners[(start + i) % len];
+
+            if let Poll::Ready(x) = listener.poll_accept(cx) {
+                return Poll::Ready(x);
+            }
+        }
+
+        Poll::Pending
+    }
+
+    pub async fn accept(&self) -> Result<(Stream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// The local addresses of the listeners, in the order they were bound.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>> {
+        self.listeners.iter().map(Listener::local_addr).collect()
+    }
+
+    /// Follow the addresses of the local interfaces after
+    /// [`Listener::bind_each_interface`].
+    ///
+    /// Addresses that appeared since the listeners were bound or last
+    /// rescanned are bound, and the listeners of addresses that disappeared
+    /// are closed. Returns the addresses that could not be bound along with
+    /// their errors, which are tried again on the next rescan.
+    ///
+    /// Fails with [`ErrorKind::InvalidInput`] if the listeners were not bound
+    /// by [`Listener::bind_each_interface`].
+    #[cfg(target_os = "linux")]
+    pub async fn rescan(&mut self) -> Result<Vec<(SocketAddr, Error)>> {
+        let Some(interfaces) = self.interfaces else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the listeners were not bound to the local interfaces",
+            ));
+        };
+
+        let addrs = interface_addrs(interfaces.port, interfaces.loopback)?;
+        self.listeners.retain(|x| match x.local_addr() {
+            Ok(SocketAddr::Tcp(x)) => addrs.iter().any(|addr| same_ip(addr, &x)),
+            _ => false,
+        });
+
+        let mut errors = Vec::new();
+        for addr in addrs {
+            let bound = self.listeners.iter().any(|x| match x.local_addr() {
+                Ok(SocketAddr::Tcp(x)) => same_ip(&addr, &x),
+                _ => false,
+            });
+
+            if bound {
+                continue;
+            }
+
+            match Listener::bind(addr).await {
+                Ok(x) => self.listeners.push(x),
+                Err(e) => errors.push((SocketAddr::Tcp(addr), e)),
+            }
+        }
+
+        Ok(errors)
+    }
+}
+
+/// The unicast addresses of the local interfaces that are up, with `port`.
+#[cfg(target_os = "linux")]
+fn interface_addrs(port: u16, loopback: bool) -> Result<Vec<std::net::SocketAddr>> {
+    use nix::ifaddrs::getifaddrs;
+    use nix::net::if_::InterfaceFlags;
+    use std::net::{SocketAddrV4, SocketAddrV6};
+
+    let mut addrs = Vec::new();
+    for x in getifaddrs()? {
+        if !x.flags.contains(InterfaceFlags::IFF_UP) {
+            continue;
+        }
+
+        if !loopback && x.flags.contains(InterfaceFlags::IFF_LOOPBACK) {
+            continue;
+        }
+
+        let Some(addr) = x.address else {
+            continue;
+        };
+
+        let addr: std::net::SocketAddr = if let Some(x) = addr.as_sockaddr_in() {
+            SocketAddrV4::new(x.ip(), port).into()
+        } else if let Some(x) = addr.as_sockaddr_in6() {
+            SocketAddrV6::new(x.ip(), port, 0, x.scope_id()).into()
+        } else {
+            continue;
+        };
+
+        if !addr.ip().is_multicast() && !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+
+    Ok(addrs)
+}
+
+/// Whether `a` and `b` are the same interface address, ignoring the port.
+#[cfg(target_os = "linux")]
+fn same_ip(a: &std::net::SocketAddr, b: &std::net::SocketAddr) -> bool {
+    match (a, b) {
+        (std::net::SocketAddr::V6(a), std::net::SocketAddr::V6(b)) => {
+            a.ip() == b.ip() && a.scope_id() == b.scope_id()
+        }
+        _ => a.ip() == b.ip(),
+    }
+}
+
+impl fmt::Display for MultiListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, listener) in self.listeners.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+
+            DisplayAddr(listener.local_addr()).fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::IoSlice;
+use std::ops::{Deref, DerefMut};
+use std::os::fd::AsFd;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use rustix::event::{PollFd, PollFlags, Timespec};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::utils::Result;
+use crate::{SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PoolConfig {
+    /// Maximum number of idle connections kept for a single address.
+    pub max_idle_per_key: usize,
+    /// Maximum number of idle connections kept across all addresses.
+    pub max_idle_total: usize,
+    /// How long a connection may stay idle before it is discarded.
+    pub idle_timeout: Option<Duration>,
+    /// Check that an idle connection has not been closed by the peer before
+    /// handing it out.
+    pub health_check: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_key: 8,
+            max_idle_total: 64,
+            idle_timeout: Some(Duration::from_secs(90)),
+            health_check: true,
+        }
+    }
+}
+
+impl PoolConfig {
+    #[must_use]
+    pub fn max_idle_per_key(mut self, max: usize) -> Self {
+        self.max_idle_per_key = max;
+        self
+    }
+
+    #[must_use]
+    pub fn max_idle_total(mut self, max: usize) -> Self {
+        self.max_idle_total = max;
+        self
+    }
+
+    #[must_use]
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    #[must_use]
+    pub fn health_check(mut self, health_check: bool) -> Self {
+        self.health_check = health_check;
+        self
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+struct Idle {
+    stream: Stream,
+    since: Instant,
+}
+
+struct Shared {
+    config: PoolConfig,
+    idle: Mutex<HashMap<SocketAddr, Vec<Idle>>>,
+}
+
+impl Shared {
+    fn idle(&self) -> MutexGuard<'_, HashMap<SocketAddr, Vec<Idle>>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_expired(&self, idle: &Idle, now: Instant) -> bool {
+        self.config
+            .idle_timeout
+            .is_some_and(|x| now.saturating_duration_since(idle.since) >= x)
+    }
+
+    fn take(&self, key: &SocketAddr) -> Option<Stream> {
+        let now = Instant::now();
+        let mut idle = self.idle();
+
+        idle.retain(|_, x| {
+            x.retain(|x| !self.is_expired(x, now));
+            !x.is_empty()
+        });
+
+        let entries = idle.get_mut(key)?;
+        let mut found = None;
+
+        while let Some(x) = entries.pop() {
+            if !self.config.health_check || is_idle_healthy(&x.stream) {
+                found = Some(x.stream);
+                break;
+            }
+        }
+
+        if entries.is_empty() {
+            idle.remove(key);
+        }
+
+        found
+    }
+
+    fn put(&self, key: SocketAddr, stream: Stream) {
+        if self.config.max_idle_per_key == 0 || self.config.max_idle_total == 0 {
+            return;
+        }
+
+        let mut idle = self.idle();
+
+        let entries = idle.entry(key).or_default();
+        if entries.len() >= self.config.max_idle_per_key {
+            entries.remove(0);
+        }
+
+        entries.push(Idle {
+            stream,
+            since: Instant::now(),
+        });
+
+        while idle.values().map(Vec::len).sum::<usize>() > self.config.max_idle_total {
+            let oldest = idle
+                .iter()
+                .filter_map(|(k, v)| v.first().map(|x| (k, x.since)))
+                .min_by_key(|(_, since)| *since)
+                .map(|(k, _)| k.clone());
+
+            let Some(oldest) = oldest else {
+                break;
+            };
+
+            if let Some(entries) = idle.get_mut(&oldest) {
+                entries.remove(0);
+
+                if entries.is_empty() {
+                    idle.remove(&oldest);
+                }
+            }
+        }
+    }
+}
+
+/// An idle connection should have nothing to read. If it is readable, the peer
+/// either closed it, reset it, or sent data nobody asked for.
+fn is_idle_healthy(stream: &Stream) -> bool {
+    let mut fds = [PollFd::new(stream, PollFlags::IN)];
+    let timeout = Timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    match rustix::event::poll(&mut fds, Some(&timeout)) {
+        Ok(0) => true,
+        Ok(_) | Err(_) => false,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A pool of idle connections keyed by address.
+///
+/// Idle connections are evicted lazily whenever [`Pool::get`] is called.
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Self::new(PoolConfig::default())
+    }
+}
+
+impl Pool {
+    pub fn new(config: PoolConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                idle: Mutex::default(),
+            }),
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &PoolConfig {
+        &self.shared.config
+    }
+
+    /// Get an idle connection to `addr`, or connect a new one.
+    pub async fn get(&self, addr: &SocketAddr) -> Result<PooledStream> {
+        let (stream, reused) = match self.shared.take(addr) {
+            Some(x) => (x, true),
+            None => (Stream::connect(addr).await?, false),
+        };
+
+        Ok(PooledStream {
+            stream: Some(stream),
+            key: addr.clone(),
+            pool: Arc::downgrade(&self.shared),
+            poisoned: false,
+            reused,
+        })
+    }
+
+    /// Number of idle connections currently held.
+    #[must_use]
+    pub fn idle_connections(&self) -> usize {
+        self.shared.idle().values().map(Vec::len).sum()
+    }
+
+    /// Drop all idle connections.
+    pub fn clear(&self) {
+        self.shared.idle().clear();
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("config", &self.shared.config)
+            .field("idle_connections", &self.idle_connections())
+            .finish()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A connection borrowed from a [`Pool`].
+///
+/// The connection is returned to the pool when dropped, unless it was
+/// poisoned. A connection is poisoned automatically when any I/O on it fails.
+pub struct PooledStream {
+    stream: Option<Stream>,
+    key: SocketAddr,
+    pool: Weak<Shared>,
+    poisoned: bool,
+    reused: bool,
+}
+
+impl PooledStream {
+    /// Prevent the connection from being returned to the pool.
+    pub fn poison(&mut self) {
+        self.poisoned = true;
+    }
+
+    #[must_use]
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Whether the connection was taken from the idle set instead of being
+    /// newly connected.
+    #[must_use]
+    pub fn is_reused(&self) -> bool {
+        self.reused
+    }
+
+    /// Detach the connection from the pool.
+    pub fn into_inner(mut self) -> Stream {
+        let Some(stream) = self.stream.take() else {
+            unreachable!()
+        };
+
+        stream
+    }
+
+    fn stream_pin(&mut self) -> Pin<&mut Stream> {
+        Pin::new(self.stream.as_mut().expect("stream is only taken on drop"))
+    }
+
+    fn track<T>(&mut self, x: Poll<Result<T>>) -> Poll<Result<T>> {
+        if let Poll::Ready(Err(_)) = x {
+            self.poisoned = true;
+        }
+
+        x
+    }
+}
+
+impl Drop for PooledStream {
+    fn drop(&mut self) {
+        let Some(stream) = self.stream.take() else {
+            return;
+        };
+
+        if self.poisoned {
+            return;
+        }
+
+        if let Some(pool) = self.pool.upgrade() {
+            pool.put(self.key.clone(), stream);
+        }
+    }
+}
+
+impl Deref for PooledStream {
+    type Target = Stream;
+
+    fn deref(&self) -> &Self::Target {
+        self.stream.as_ref().expect("stream is only taken on drop")
+    }
+}
+
+impl DerefMut for PooledStream {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.stream.as_mut().expect("stream is only taken on drop")
+    }
+}
+
+impl AsFd for PooledStream {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        (**self).as_fd()
+    }
+}
+
+impl AsyncRead for PooledStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let x = this.stream_pin().poll_read(cx, buf);
+        this.track(x)
+    }
+}
+
+impl AsyncWrite for PooledStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        let x = this.stream_pin().poll_write(cx, buf);
+        this.track(x)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let x = this.stream_pin().poll_flush(cx);
+        this.track(x)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        // A connection that was shut down cannot be reused.
+        let this = self.get_mut();
+        this.poisoned = true;
+        this.stream_pin().poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        let x = this.stream_pin().poll_write_vectored(cx, bufs);
+        this.track(x)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        (**self).is_write_vectored()
+    }
+}
+
+impl fmt::Debug for PooledStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledStream")
+            .field("stream", &self.stream)
+            .field("poisoned", &self.poisoned)
+            .field("reused", &self.reused)
+            .finish()
+    }
+}
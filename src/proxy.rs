@@ -0,0 +1,389 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+
+use crate::utils::Result;
+use crate::{ReadExactState, SocketAddr, Stream, WriteAllState};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A proxy that outbound connections are tunneled through.
+///
+/// See [`ConnectOptions::proxy`](crate::ConnectOptions::proxy).
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Proxy {
+    Socks5(Socks5Proxy),
+    HttpConnect(HttpConnectProxy),
+}
+
+impl From<Socks5Proxy> for Proxy {
+    #[inline]
+    fn from(x: Socks5Proxy) -> Self {
+        Self::Socks5(x)
+    }
+}
+
+impl From<HttpConnectProxy> for Proxy {
+    #[inline]
+    fn from(x: HttpConnectProxy) -> Self {
+        Self::HttpConnect(x)
+    }
+}
+
+impl Proxy {
+    /// Address of the proxy itself.
+    #[must_use]
+    pub fn addr(&self) -> &SocketAddr {
+        match self {
+            Self::Socks5(x) => &x.addr,
+            Self::HttpConnect(x) => &x.addr,
+        }
+    }
+
+    /// Ask the proxy on the other end of `stream` to connect to `host`.
+    pub(crate) async fn handshake(
+        &self,
+        stream: &mut Stream,
+        host: Host<'_>,
+        port: u16,
+    ) -> Result<()> {
+        match self {
+            Self::Socks5(x) => x.handshake(stream, host, port).await,
+            Self::HttpConnect(x) => x.handshake(stream, host, port).await,
+        }
+    }
+}
+
+/// The target of a proxied connection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Host<'a> {
+    Ip(IpAddr),
+    /// Resolved by the proxy.
+    Domain(&'a str),
+}
+
+impl<'a> Host<'a> {
+    pub(crate) fn new(host: &'a str) -> Self {
+        let ip = host
+            .strip_prefix('[')
+            .and_then(|x| x.strip_suffix(']'))
+            .unwrap_or(host);
+
+        match ip.parse() {
+            Ok(x) => Self::Ip(x),
+            Err(_) => Self::Domain(host),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+const SOCKS_VERSION: u8 = 0x05;
+const AUTH_VERSION: u8 = 0x01;
+
+const METHOD_NONE: u8 = 0x00;
+const METHOD_PASSWORD: u8 = 0x02;
+const METHOD_UNACCEPTABLE: u8 = 0xff;
+
+const CMD_CONNECT: u8 = 0x01;
+
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// A SOCKS5 proxy ([RFC 1928]).
+///
+/// Supports the "no authentication" and username/password ([RFC 1929])
+/// methods.
+///
+/// [RFC 1928]: https://www.rfc-editor.org/rfc/rfc1928
+/// [RFC 1929]: https://www.rfc-editor.org/rfc/rfc1929
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Socks5Proxy {
+    pub addr: SocketAddr,
+
+    /// Username and password to offer the proxy.
+    ///
+    /// Each must be between 1 and 255 bytes long.
+    pub auth: Option<(String, String)>,
+}
+
+impl Socks5Proxy {
+    #[must_use]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr, auth: None }
+    }
+
+    #[must_use]
+    pub fn auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+
+    async fn handshake(&self, stream: &mut Stream, host: Host<'_>, port: u16) -> Result<()> {
+        let methods: &[u8] = match self.auth {
+            Some(_) => &[METHOD_NONE, METHOD_PASSWORD],
+            None => &[METHOD_NONE],
+        };
+
+        let mut greeting = vec![SOCKS_VERSION, methods.len() as u8];
+        greeting.extend_from_slice(methods);
+        write_all(stream, greeting).await?;
+
+        let reply = read_exact(stream, 2).await?;
+        if reply[0] != SOCKS_VERSION {
+            return Err(invalid("SOCKS5 proxy replied with an unknown version"));
+        }
+
+        match (reply[1], &self.auth) {
+            (METHOD_NONE, _) => {}
+            (METHOD_PASSWORD, Some((username, password))) => {
+                authenticate(stream, username, password).await?;
+            }
+            (METHOD_UNACCEPTABLE, _) => {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    "SOCKS5 proxy accepted none of the authentication methods",
+                ));
+            }
+            _ => return Err(invalid("SOCKS5 proxy chose a method that was not offered")),
+        }
+
+        let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0x00];
+        match host {
+            Host::Ip(IpAddr::V4(x)) => {
+                request.push(ATYP_IPV4);
+                request.extend_from_slice(&x.octets());
+            }
+            Host::Ip(IpAddr::V6(x)) => {
+                request.push(ATYP_IPV6);
+                request.extend_from_slice(&x.octets());
+            }
+            Host::Domain(x) => {
+                request.push(ATYP_DOMAIN);
+                request.push(field_len(x, "hostname")?);
+                request.extend_from_slice(x.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        write_all(stream, request).await?;
+
+        let reply = read_exact(stream, 4).await?;
+        if reply[0] != SOCKS_VERSION {
+            return Err(invalid("SOCKS5 proxy replied with an unknown version"));
+        }
+
+        if reply[1] != 0x00 {
+            return Err(reply_error(reply[1]));
+        }
+
+        // The address the proxy bound to is of no use to us, but it has to be
+        // consumed before the tunneled data.
+        let len = match reply[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => read_exact(stream, 1).await?[0].into(),
+            _ => return Err(invalid("SOCKS5 proxy replied with an unknown address type")),
+        };
+        read_exact(stream, len + 2).await?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socks5Proxy")
+            .field("addr", &self.addr)
+            .field("auth", &self.auth.as_ref().map(|(username, _)| username))
+            .finish()
+    }
+}
+
+async fn authenticate(stream: &mut Stream, username: &str, password: &str) -> Result<()> {
+    let mut request = vec![AUTH_VERSION, field_len(username, "username")?];
+    request.extend_from_slice(username.as_bytes());
+    request.push(field_len(password, "password")?);
+    request.extend_from_slice(password.as_bytes());
+    write_all(stream, request).await?;
+
+    let reply = read_exact(stream, 2).await?;
+    if reply[1] != 0x00 {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "SOCKS5 proxy rejected the username or password",
+        ));
+    }
+
+    Ok(())
+}
+
+fn reply_error(code: u8) -> Error {
+    let (kind, msg) = match code {
+        0x02 => (
+            ErrorKind::PermissionDenied,
+            "connection not allowed by ruleset",
+        ),
+        0x03 => (ErrorKind::NetworkUnreachable, "network unreachable"),
+        0x04 => (ErrorKind::HostUnreachable, "host unreachable"),
+        0x05 => (ErrorKind::ConnectionRefused, "connection refused"),
+        0x06 => (ErrorKind::TimedOut, "TTL expired"),
+        0x07 => (ErrorKind::Unsupported, "command not supported"),
+        0x08 => (ErrorKind::Unsupported, "address type not supported"),
+        _ => (ErrorKind::Other, "general failure"),
+    };
+
+    Error::new(kind, format!("SOCKS5 proxy: {msg}"))
+}
+
+fn field_len(x: &str, what: &str) -> Result<u8> {
+    match u8::try_from(x.len()) {
+        Ok(len) if len > 0 => Ok(len),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("SOCKS5 {what} must be between 1 and 255 bytes long"),
+        )),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Longest response head accepted from an HTTP proxy.
+const HTTP_MAX_HEAD_LEN: usize = 8192;
+
+/// An HTTP proxy that tunnels connections with the `CONNECT` method.
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct HttpConnectProxy {
+    pub addr: SocketAddr,
+
+    /// Username and password sent with `Basic` authentication.
+    pub auth: Option<(String, String)>,
+}
+
+impl HttpConnectProxy {
+    #[must_use]
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr, auth: None }
+    }
+
+    #[must_use]
+    pub fn auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+
+    async fn handshake(&self, stream: &mut Stream, host: Host<'_>, port: u16) -> Result<()> {
+        let authority = match host {
+            Host::Ip(IpAddr::V6(x)) => format!("[{x}]:{port}"),
+            Host::Ip(IpAddr::V4(x)) => format!("{x}:{port}"),
+            Host::Domain(x) => format!("{x}:{port}"),
+        };
+
+        let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+        if let Some((username, password)) = &self.auth {
+            let credentials = base64(format!("{username}:{password}").as_bytes());
+            request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+        }
+        request.push_str("\r\n");
+        write_all(stream, request.into_bytes()).await?;
+
+        // The end of the response head is not known in advance, and reading
+        // past it would consume tunneled data.
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            if head.len() == HTTP_MAX_HEAD_LEN {
+                return Err(invalid("HTTP proxy response is too long"));
+            }
+
+            head.extend_from_slice(&read_exact(stream, 1).await?);
+        }
+
+        let status_line = head
+            .split(|&x| x == b'\n')
+            .next()
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .map(str::trim_end)
+            .ok_or_else(|| invalid("HTTP proxy response is not valid"))?;
+
+        let (code, reason) = parse_status_line(status_line)
+            .ok_or_else(|| invalid("HTTP proxy response is not valid"))?;
+
+        if (200..300).contains(&code) {
+            return Ok(());
+        }
+
+        let kind = match code {
+            403 | 407 => ErrorKind::PermissionDenied,
+            502 => ErrorKind::ConnectionRefused,
+            504 => ErrorKind::TimedOut,
+            _ => ErrorKind::Other,
+        };
+
+        Err(Error::new(kind, format!("HTTP proxy: {code} {reason}")))
+    }
+}
+
+impl fmt::Debug for HttpConnectProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpConnectProxy")
+            .field("addr", &self.addr)
+            .field("auth", &self.auth.as_ref().map(|(username, _)| username))
+            .finish()
+    }
+}
+
+/// Parse `HTTP/1.x <code> <reason>`.
+fn parse_status_line(line: &str) -> Option<(u16, &str)> {
+    let rest = line.strip_prefix("HTTP/1.")?;
+    let (_, rest) = rest.split_once(' ')?;
+    let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+
+    if code.len() != 3 {
+        return None;
+    }
+
+    Some((code.parse().ok()?, reason))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f].into());
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+async fn write_all(stream: &mut Stream, buf: Vec<u8>) -> Result<()> {
+    WriteAllState::new(buf).progress(stream).await
+}
+
+async fn read_exact(stream: &mut Stream, len: usize) -> Result<Vec<u8>> {
+    let mut state = ReadExactState::new(len);
+    state.progress(stream).await?;
+    Ok(state.into_inner())
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,412 @@
+use std::ffi::OsStr;
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::pin::Pin;
+use std::time::Duration;
+
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::socket_addr::unix_addr_from_abstract_name;
+use crate::socket_addr::unix_addr_from_pathname;
+use crate::utils::Result;
+use crate::{ReadExactState, SocketAddr, WriteAllState};
+
+///////////////////////////////////////////////////////////////////////////////
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_INET_LEN: usize = 12;
+const V2_INET6_LEN: usize = 36;
+const V2_UNIX_LEN: usize = 216;
+const V2_UNIX_PATH_LEN: usize = 108;
+
+/// The version of the PROXY protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyVersion {
+    /// The human-readable text format.
+    V1,
+    /// The binary format.
+    V2,
+}
+
+/// The original addresses of a proxied connection.
+#[derive(Debug, Clone)]
+pub struct ProxyAddrs {
+    /// The address of the client that connected to the proxy.
+    pub source: SocketAddr,
+    /// The address the client connected to.
+    pub destination: SocketAddr,
+}
+
+/// A PROXY protocol header, as sent by HAProxy and compatible load balancers
+/// before the data of a connection.
+///
+/// See the [specification](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt).
+#[derive(Debug, Clone)]
+pub struct ProxyHeader {
+    pub version: ProxyVersion,
+
+    /// The addresses of the original connection.
+    ///
+    /// `None` if the proxy did not forward a connection (v2 `LOCAL`, for
+    /// example health checks), or if the addresses are unknown (v1 `UNKNOWN`,
+    /// v2 `AF_UNSPEC`).
+    pub addrs: Option<ProxyAddrs>,
+}
+
+impl ProxyHeader {
+    /// Read a v1 or v2 header from the start of a connection.
+    ///
+    /// Exactly the bytes of the header are read, so `reader` is left at the
+    /// first byte of the proxied data. v2 TLVs are skipped.
+    ///
+    /// A malformed header is an [`ErrorKind::InvalidData`] error. If the peer
+    /// does not send the whole header within `timeout`, this fails with
+    /// [`ErrorKind::TimedOut`]. Call this in the task that handles the
+    /// accepted connection, so a slow client does not hold up the others.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with the time driver
+    /// enabled.
+    pub async fn read_from<R>(reader: &mut R, timeout: Duration) -> Result<Self>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+    {
+        tokio::time::timeout(timeout, read(reader))
+            .await
+            .map_err(|_| {
+                Error::new(
+                    ErrorKind::TimedOut,
+                    "timed out reading PROXY protocol header",
+                )
+            })?
+    }
+
+    /// Encode the header.
+    ///
+    /// Addresses that the version cannot represent are sent as unknown: v1
+    /// has no unix addresses and neither version can mix address families.
+    /// Without addresses, v1 sends `UNKNOWN` and v2 sends `LOCAL`.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.version {
+            ProxyVersion::V1 => encode_v1(self.addrs.as_ref()).into_bytes(),
+            ProxyVersion::V2 => encode_v2(self.addrs.as_ref()),
+        }
+    }
+
+    /// Write the header to the start of a connection, as a proxy would.
+    pub async fn write_to<W>(&self, writer: &mut W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let mut state = WriteAllState::new(self.to_bytes());
+        state.progress(writer).await?;
+        poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await
+    }
+}
+
+async fn read<R>(reader: &mut R) -> Result<ProxyHeader>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    let mut prefix = ReadExactState::new(V1_PREFIX.len());
+    prefix.progress(reader).await?;
+    let prefix = prefix.into_inner();
+
+    if prefix == V1_PREFIX {
+        read_v1(reader, prefix).await
+    } else if V2_SIGNATURE.starts_with(&prefix) {
+        read_v2(reader, prefix).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+fn encode_v1(addrs: Option<&ProxyAddrs>) -> String {
+    let inet = addrs.and_then(|x| match (&x.source, &x.destination) {
+        (SocketAddr::Tcp(src), SocketAddr::Tcp(dst)) => Some((src, dst)),
+        _ => None,
+    });
+
+    match inet {
+        Some((src, dst)) if src.is_ipv4() && dst.is_ipv4() => format!(
+            "PROXY TCP4 {} {} {} {}\r\n",
+            src.ip(),
+            dst.ip(),
+            src.port(),
+            dst.port()
+        ),
+        Some((src, dst)) if src.is_ipv6() && dst.is_ipv6() => format!(
+            "PROXY TCP6 {} {} {} {}\r\n",
+            src.ip(),
+            dst.ip(),
+            src.port(),
+            dst.port()
+        ),
+        _ => "PROXY UNKNOWN\r\n".to_owned(),
+    }
+}
+
+fn encode_v2(addrs: Option<&ProxyAddrs>) -> Vec<u8> {
+    // Version 2 in the high nibble, the command in the low one.
+    const LOCAL: u8 = 0x20;
+    const PROXY: u8 = 0x21;
+
+    // Address family in the high nibble, STREAM in the low one.
+    const UNSPEC: u8 = 0x00;
+    const INET: u8 = 0x11;
+    const INET6: u8 = 0x21;
+    const UNIX: u8 = 0x31;
+
+    let mut body = Vec::new();
+    let (command, family) = match addrs.map(|x| (&x.source, &x.destination)) {
+        None => (LOCAL, UNSPEC),
+        Some((SocketAddr::Tcp(src), SocketAddr::Tcp(dst))) => match (src.ip(), dst.ip()) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                body.extend(s.octets());
+                body.extend(d.octets());
+                body.extend(src.port().to_be_bytes());
+                body.extend(dst.port().to_be_bytes());
+                (PROXY, INET)
+            }
+            (IpAddr::V6(s), IpAddr::V6(d)) => {
+                body.extend(s.octets());
+                body.extend(d.octets());
+                body.extend(src.port().to_be_bytes());
+                body.extend(dst.port().to_be_bytes());
+                (PROXY, INET6)
+            }
+            _ => (PROXY, UNSPEC),
+        },
+        Some((SocketAddr::Unix(src), SocketAddr::Unix(dst))) => {
+            body.extend(sun_path(src));
+            body.extend(sun_path(dst));
+            (PROXY, UNIX)
+        }
+        Some(_) => (PROXY, UNSPEC),
+    };
+
+    let mut header = V2_SIGNATURE.to_vec();
+    header.push(command);
+    header.push(family);
+    // The body is at most `V2_UNIX_LEN` bytes long.
+    header.extend(u16::try_from(body.len()).unwrap_or(u16::MAX).to_be_bytes());
+    header.extend(body);
+    header
+}
+
+async fn read_v1<R>(reader: &mut R, mut line: Vec<u8>) -> Result<ProxyHeader>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    // The end of the line is not known in advance, and reading past it would
+    // consume proxied data.
+    while !line.ends_with(b"\r\n") {
+        if line.len() == V1_MAX_LEN {
+            return Err(invalid("PROXY v1 header is too long"));
+        }
+
+        let mut byte = ReadExactState::new(1);
+        byte.progress(reader).await?;
+        line.extend_from_slice(byte.filled());
+    }
+
+    let line = std::str::from_utf8(&line[V1_PREFIX.len()..line.len() - 2])
+        .map_err(|_| invalid("PROXY v1 header is not valid ASCII"))?;
+
+    parse_v1(line).map(|addrs| ProxyHeader {
+        version: ProxyVersion::V1,
+        addrs,
+    })
+}
+
+fn parse_v1(line: &str) -> Result<Option<ProxyAddrs>> {
+    let mut fields = line.split(' ');
+
+    let protocol = fields.next().unwrap_or_default();
+    if protocol == "UNKNOWN" {
+        return Ok(None);
+    }
+
+    let (Some(src), Some(dst), Some(sport), Some(dport), None) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        return Err(invalid("PROXY v1 header has the wrong number of fields"));
+    };
+
+    let (src, dst): (IpAddr, IpAddr) = match protocol {
+        "TCP4" => (
+            parse_field::<Ipv4Addr>(src)?.into(),
+            parse_field::<Ipv4Addr>(dst)?.into(),
+        ),
+        "TCP6" => (
+            parse_field::<Ipv6Addr>(src)?.into(),
+            parse_field::<Ipv6Addr>(dst)?.into(),
+        ),
+        _ => return Err(invalid("unknown protocol in PROXY v1 header")),
+    };
+
+    Ok(Some(ProxyAddrs {
+        source: std::net::SocketAddr::new(src, parse_field(sport)?).into(),
+        destination: std::net::SocketAddr::new(dst, parse_field(dport)?).into(),
+    }))
+}
+
+fn parse_field<T: std::str::FromStr>(field: &str) -> Result<T> {
+    field
+        .parse()
+        .map_err(|_| invalid(format!("invalid field in PROXY v1 header: {field}")))
+}
+
+async fn read_v2<R>(reader: &mut R, mut header: Vec<u8>) -> Result<ProxyHeader>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    let mut rest = ReadExactState::new(16 - header.len());
+    rest.progress(reader).await?;
+    header.extend_from_slice(rest.filled());
+
+    if header[..12] != V2_SIGNATURE[..] {
+        return Err(invalid("missing PROXY protocol header"));
+    }
+
+    let version = header[12] >> 4;
+    let command = header[12] & 0x0f;
+    let family = header[13] >> 4;
+    let len = usize::from(u16::from_be_bytes([header[14], header[15]]));
+
+    if version != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    let mut body = ReadExactState::new(len);
+    body.progress(reader).await?;
+    let body = body.into_inner();
+
+    let addrs = match command {
+        // LOCAL
+        0x0 => None,
+        // PROXY
+        0x1 => parse_v2_addrs(family, &body)?,
+        _ => return Err(invalid("unknown command in PROXY v2 header")),
+    };
+
+    Ok(ProxyHeader {
+        version: ProxyVersion::V2,
+        addrs,
+    })
+}
+
+fn parse_v2_addrs(family: u8, body: &[u8]) -> Result<Option<ProxyAddrs>> {
+    let short = || invalid("PROXY v2 address block is too short");
+
+    match family {
+        // AF_UNSPEC
+        0x0 => Ok(None),
+        // AF_INET
+        0x1 => {
+            let b = body.get(..V2_INET_LEN).ok_or_else(short)?;
+            let src = Ipv4Addr::from(octets::<4>(&b[0..]));
+            let dst = Ipv4Addr::from(octets::<4>(&b[4..]));
+            Ok(Some(inet_addrs(src.into(), dst.into(), &b[8..])))
+        }
+        // AF_INET6
+        0x2 => {
+            let b = body.get(..V2_INET6_LEN).ok_or_else(short)?;
+            let src = Ipv6Addr::from(octets::<16>(&b[0..]));
+            let dst = Ipv6Addr::from(octets::<16>(&b[16..]));
+            Ok(Some(inet_addrs(src.into(), dst.into(), &b[32..])))
+        }
+        // AF_UNIX
+        0x3 => {
+            let b = body.get(..V2_UNIX_LEN).ok_or_else(short)?;
+            Ok(Some(ProxyAddrs {
+                source: unix_addr(&b[..V2_UNIX_PATH_LEN])?,
+                destination: unix_addr(&b[V2_UNIX_PATH_LEN..])?,
+            }))
+        }
+        _ => Err(invalid("unknown address family in PROXY v2 header")),
+    }
+}
+
+fn octets<const N: usize>(b: &[u8]) -> [u8; N] {
+    let mut x = [0; N];
+    x.copy_from_slice(&b[..N]);
+    x
+}
+
+fn inet_addrs(src: IpAddr, dst: IpAddr, ports: &[u8]) -> ProxyAddrs {
+    let sport = u16::from_be_bytes([ports[0], ports[1]]);
+    let dport = u16::from_be_bytes([ports[2], ports[3]]);
+
+    ProxyAddrs {
+        source: std::net::SocketAddr::new(src, sport).into(),
+        destination: std::net::SocketAddr::new(dst, dport).into(),
+    }
+}
+
+/// Encode a unix address as the `sun_path` of a PROXY v2 header.
+fn sun_path(addr: &tokio::net::unix::SocketAddr) -> [u8; V2_UNIX_PATH_LEN] {
+    let addr = std::os::unix::net::SocketAddr::from(addr.clone());
+    let mut out = [0; V2_UNIX_PATH_LEN];
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(name) = addr.as_abstract_name() {
+        let len = name.len().min(V2_UNIX_PATH_LEN - 1);
+        out[1..=len].copy_from_slice(&name[..len]);
+        return out;
+    }
+
+    if let Some(path) = addr.as_pathname() {
+        let path = path.as_os_str().as_bytes();
+        let len = path.len().min(V2_UNIX_PATH_LEN - 1);
+        out[..len].copy_from_slice(&path[..len]);
+    }
+
+    out
+}
+
+/// Decode a `sun_path` as sent in a PROXY v2 header.
+fn unix_addr(sun_path: &[u8]) -> Result<SocketAddr> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let [0, name @ ..] = sun_path {
+        let end = name.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        if end != 0 {
+            return unix_addr_from_abstract_name(&name[..end]).map(SocketAddr::Unix);
+        }
+    }
+
+    let end = sun_path
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(sun_path.len());
+    if end == 0 {
+        // Client sockets are usually not bound to a path.
+        let unnamed = std::os::unix::net::UnixDatagram::unbound()?.local_addr()?;
+        return Ok(SocketAddr::Unix(unnamed.into()));
+    }
+
+    let path = OsStr::from_bytes(&sun_path[..end]);
+    unix_addr_from_pathname(Path::new(path)).map(SocketAddr::Unix)
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
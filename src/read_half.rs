@@ -1,20 +1,23 @@
 use std::fmt;
-use std::io::IoSliceMut;
+use std::io::{Error, IoSliceMut};
+use std::net::Shutdown;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-use pin_project::pin_project;
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
+use socket2::SockRef;
 use tokio::io::{AsyncRead, Interest, ReadBuf, Ready};
 
-use crate::SocketAddr;
-use crate::utils::Result;
+use crate::utils::{Result, fmt_connection, poll_peek_with, recv_peek, socket_cookie};
+use crate::{OwnedWriteHalf, PeerCred, SocketAddr, Stream};
 
 ///////////////////////////////////////////////////////////////////////////////
 
-#[pin_project(project = ReadHalfProj)]
 pub enum ReadHalf<'a> {
-    Tcp(#[pin] tokio::net::tcp::ReadHalf<'a>),
-    Unix(#[pin] tokio::net::unix::ReadHalf<'a>),
+    Tcp(tokio::net::tcp::ReadHalf<'a>),
+    Unix(tokio::net::unix::ReadHalf<'a>),
 }
 
 impl<'a> From<tokio::net::tcp::ReadHalf<'a>> for ReadHalf<'a> {
@@ -29,7 +32,7 @@ impl<'a> From<tokio::net::unix::ReadHalf<'a>> for ReadHalf<'a> {
     }
 }
 
-impl ReadHalf<'_> {
+impl<'a> ReadHalf<'a> {
     #[must_use]
     pub fn is_tcp(&self) -> bool {
         matches!(self, Self::Tcp(..))
@@ -39,9 +42,86 @@ impl ReadHalf<'_> {
     pub fn is_unix(&self) -> bool {
         matches!(self, Self::Unix(..))
     }
+
+    /// `"tcp"` or `"unix"`, the scheme of the socket's addresses.
+    #[must_use]
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Tcp(..) => "tcp",
+            Self::Unix(..) => "unix",
+        }
+    }
+
+    /// The underlying tokio half, if this is a TCP half.
+    ///
+    /// This is an escape hatch for what this type does not wrap. Prefer its
+    /// own methods, which work the same for both kinds of sockets.
+    #[must_use]
+    pub fn as_tcp(&self) -> Option<&tokio::net::tcp::ReadHalf<'a>> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_tcp_mut(&mut self) -> Option<&mut tokio::net::tcp::ReadHalf<'a>> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// unix half.
+    ///
+    /// See [`ReadHalf::as_tcp`].
+    pub fn into_tcp(self) -> Result<tokio::net::tcp::ReadHalf<'a>, Self> {
+        match self {
+            Self::Tcp(x) => Ok(x),
+            x => Err(x),
+        }
+    }
+
+    /// The underlying tokio half, if this is a unix half.
+    ///
+    /// See [`ReadHalf::as_tcp`].
+    #[must_use]
+    pub fn as_unix(&self) -> Option<&tokio::net::unix::ReadHalf<'a>> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_unix_mut(&mut self) -> Option<&mut tokio::net::unix::ReadHalf<'a>> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// TCP half.
+    ///
+    /// See [`ReadHalf::as_tcp`].
+    pub fn into_unix(self) -> Result<tokio::net::unix::ReadHalf<'a>, Self> {
+        match self {
+            Self::Unix(x) => Ok(x),
+            x => Err(x),
+        }
+    }
 }
 
 impl<'a> ReadHalf<'a> {
+    pub async fn async_io<R>(&self, interest: Interest, f: impl FnMut() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().async_io(interest, f).await,
+            Self::Unix(x) => x.as_ref().async_io(interest, f).await,
+        }
+    }
+
     pub fn local_addr(&self) -> Result<SocketAddr> {
         match self {
             Self::Tcp(x) => x.local_addr().map(Into::into),
@@ -49,6 +129,11 @@ impl<'a> ReadHalf<'a> {
         }
     }
 
+    pub async fn peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.async_io(Interest::READABLE, || recv_peek(self.as_fd(), &mut *buf))
+            .await
+    }
+
     pub fn peer_addr(&self) -> Result<SocketAddr> {
         match self {
             Self::Tcp(x) => x.peer_addr().map(Into::into),
@@ -56,6 +141,30 @@ impl<'a> ReadHalf<'a> {
         }
     }
 
+    /// See [`Stream::peer_cred`].
+    pub fn peer_cred(&self) -> Result<Option<PeerCred>> {
+        match self {
+            Self::Tcp(_) => Ok(None),
+            Self::Unix(x) => x.as_ref().peer_cred().map(|x| Some(x.into())),
+        }
+    }
+
+    pub fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<usize>> {
+        poll_peek_with(
+            cx,
+            buf,
+            |cx| self.poll_read_ready(cx),
+            |buf| self.try_peek(buf),
+        )
+    }
+
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Tcp(x) => x.as_ref().poll_read_ready(cx),
+            Self::Unix(x) => x.as_ref().poll_read_ready(cx),
+        }
+    }
+
     pub async fn readable(&self) -> Result<()> {
         match self {
             Self::Tcp(x) => x.readable().await,
@@ -70,6 +179,35 @@ impl<'a> ReadHalf<'a> {
         }
     }
 
+    /// The kernel's unique identifier for the socket (`SO_COOKIE`).
+    ///
+    /// Only supported on Linux.
+    pub fn socket_cookie(&self) -> Result<u64> {
+        match self {
+            Self::Tcp(x) => socket_cookie(x.as_ref().as_fd()),
+            Self::Unix(x) => socket_cookie(x.as_ref().as_fd()),
+        }
+    }
+
+    /// Get and clear the pending error of the socket (`SO_ERROR`).
+    ///
+    /// This affects the underlying socket, so it is visible to the other half
+    /// too.
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        SockRef::from(self).take_error()
+    }
+
+    pub fn try_io<R>(&self, interest: Interest, f: impl FnOnce() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().try_io(interest, f),
+            Self::Unix(x) => x.as_ref().try_io(interest, f),
+        }
+    }
+
+    pub fn try_peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.try_io(Interest::READABLE, || recv_peek(self.as_fd(), buf))
+    }
+
     pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
         match self {
             Self::Tcp(x) => x.try_read(buf),
@@ -83,11 +221,50 @@ impl<'a> ReadHalf<'a> {
             Self::Unix(x) => x.try_read_vectored(bufs),
         }
     }
+
+    /// See [`Stream::try_read_buf`].
+    #[cfg(feature = "bytes")]
+    pub fn try_read_buf<B: BufMut>(&self, buf: &mut B) -> Result<usize> {
+        match self {
+            Self::Tcp(x) => x.try_read_buf(buf),
+            Self::Unix(x) => x.try_read_buf(buf),
+        }
+    }
+
+    /// See [`Stream::read_buf`].
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: BufMut>(&self, buf: &mut B) -> Result<usize> {
+        loop {
+            self.readable().await?;
+
+            match self.try_read_buf(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                x => return x,
+            }
+        }
+    }
+}
+
+impl AsFd for ReadHalf<'_> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_fd(),
+            Self::Unix(x) => x.as_ref().as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for ReadHalf<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_raw_fd(),
+            Self::Unix(x) => x.as_ref().as_raw_fd(),
+        }
+    }
 }
 
 crate::macros::impl_async_read! {
     type: ReadHalf<'_>,
-    proj: ReadHalfProj,
 }
 
 impl fmt::Debug for ReadHalf<'_> {
@@ -99,12 +276,28 @@ impl fmt::Debug for ReadHalf<'_> {
     }
 }
 
+impl fmt::Display for ReadHalf<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+            Self::Unix(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+        }
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 
-#[pin_project(project = OwnedReadHalfProj)]
 pub enum OwnedReadHalf {
-    Tcp(#[pin] tokio::net::tcp::OwnedReadHalf),
-    Unix(#[pin] tokio::net::unix::OwnedReadHalf),
+    Tcp(tokio::net::tcp::OwnedReadHalf),
+    Unix(tokio::net::unix::OwnedReadHalf),
 }
 
 impl From<tokio::net::tcp::OwnedReadHalf> for OwnedReadHalf {
@@ -129,9 +322,86 @@ impl OwnedReadHalf {
     pub fn is_unix(&self) -> bool {
         matches!(self, Self::Unix(..))
     }
+
+    /// `"tcp"` or `"unix"`, the scheme of the socket's addresses.
+    #[must_use]
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Tcp(..) => "tcp",
+            Self::Unix(..) => "unix",
+        }
+    }
+
+    /// The underlying tokio half, if this is a TCP half.
+    ///
+    /// This is an escape hatch for what this type does not wrap. Prefer its
+    /// own methods, which work the same for both kinds of sockets.
+    #[must_use]
+    pub fn as_tcp(&self) -> Option<&tokio::net::tcp::OwnedReadHalf> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_tcp_mut(&mut self) -> Option<&mut tokio::net::tcp::OwnedReadHalf> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// unix half.
+    ///
+    /// See [`OwnedReadHalf::as_tcp`].
+    pub fn into_tcp(self) -> Result<tokio::net::tcp::OwnedReadHalf, Self> {
+        match self {
+            Self::Tcp(x) => Ok(x),
+            x => Err(x),
+        }
+    }
+
+    /// The underlying tokio half, if this is a unix half.
+    ///
+    /// See [`OwnedReadHalf::as_tcp`].
+    #[must_use]
+    pub fn as_unix(&self) -> Option<&tokio::net::unix::OwnedReadHalf> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_unix_mut(&mut self) -> Option<&mut tokio::net::unix::OwnedReadHalf> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// TCP half.
+    ///
+    /// See [`OwnedReadHalf::as_tcp`].
+    pub fn into_unix(self) -> Result<tokio::net::unix::OwnedReadHalf, Self> {
+        match self {
+            Self::Unix(x) => Ok(x),
+            x => Err(x),
+        }
+    }
 }
 
 impl OwnedReadHalf {
+    pub async fn async_io<R>(&self, interest: Interest, f: impl FnMut() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().async_io(interest, f).await,
+            Self::Unix(x) => x.as_ref().async_io(interest, f).await,
+        }
+    }
+
     pub fn local_addr(&self) -> Result<SocketAddr> {
         match self {
             Self::Tcp(x) => x.local_addr().map(Into::into),
@@ -139,6 +409,11 @@ impl OwnedReadHalf {
         }
     }
 
+    pub async fn peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.async_io(Interest::READABLE, || recv_peek(self.as_fd(), &mut *buf))
+            .await
+    }
+
     pub fn peer_addr(&self) -> Result<SocketAddr> {
         match self {
             Self::Tcp(x) => x.peer_addr().map(Into::into),
@@ -146,6 +421,30 @@ impl OwnedReadHalf {
         }
     }
 
+    /// See [`Stream::peer_cred`].
+    pub fn peer_cred(&self) -> Result<Option<PeerCred>> {
+        match self {
+            Self::Tcp(_) => Ok(None),
+            Self::Unix(x) => x.as_ref().peer_cred().map(|x| Some(x.into())),
+        }
+    }
+
+    pub fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<usize>> {
+        poll_peek_with(
+            cx,
+            buf,
+            |cx| self.poll_read_ready(cx),
+            |buf| self.try_peek(buf),
+        )
+    }
+
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Tcp(x) => x.as_ref().poll_read_ready(cx),
+            Self::Unix(x) => x.as_ref().poll_read_ready(cx),
+        }
+    }
+
     pub async fn readable(&self) -> Result<()> {
         match self {
             Self::Tcp(x) => x.readable().await,
@@ -160,6 +459,70 @@ impl OwnedReadHalf {
         }
     }
 
+    /// Put the stream back together.
+    ///
+    /// Fails if the halves did not come from the same [`Stream::into_split`]
+    /// call.
+    pub fn reunite(self, other: OwnedWriteHalf) -> Result<Stream, ReuniteError> {
+        match (self, other) {
+            (Self::Tcp(r), OwnedWriteHalf::Tcp(w)) => r
+                .reunite(w)
+                .map(Into::into)
+                .map_err(|e| ReuniteError::DifferentConnections(e.0.into(), e.1.into())),
+            (Self::Unix(r), OwnedWriteHalf::Unix(w)) => r
+                .reunite(w)
+                .map(Into::into)
+                .map_err(|e| ReuniteError::DifferentConnections(e.0.into(), e.1.into())),
+            (r, w) => Err(ReuniteError::DifferentKinds(r, w)),
+        }
+    }
+
+    /// Shut down the read half, write half or both halves of the connection.
+    ///
+    /// This affects the underlying socket, so it is visible to the other half
+    /// too. See [`Stream::shutdown_socket`].
+    pub fn shutdown_socket(&self, how: Shutdown) -> Result<()> {
+        SockRef::from(self).shutdown(how)
+    }
+
+    /// The kernel's unique identifier for the socket (`SO_COOKIE`).
+    ///
+    /// Only supported on Linux.
+    pub fn socket_cookie(&self) -> Result<u64> {
+        match self {
+            Self::Tcp(x) => socket_cookie(x.as_ref().as_fd()),
+            Self::Unix(x) => socket_cookie(x.as_ref().as_fd()),
+        }
+    }
+
+    /// The socket, for the options this crate does not wrap.
+    ///
+    /// See [`Stream::as_socket`]. Options apply to the whole connection, so
+    /// they are visible to the other half too.
+    #[must_use]
+    pub fn as_socket(&self) -> SockRef<'_> {
+        SockRef::from(self)
+    }
+
+    /// Get and clear the pending error of the socket (`SO_ERROR`).
+    ///
+    /// This affects the underlying socket, so it is visible to the other half
+    /// too.
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        SockRef::from(self).take_error()
+    }
+
+    pub fn try_io<R>(&self, interest: Interest, f: impl FnOnce() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().try_io(interest, f),
+            Self::Unix(x) => x.as_ref().try_io(interest, f),
+        }
+    }
+
+    pub fn try_peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.try_io(Interest::READABLE, || recv_peek(self.as_fd(), buf))
+    }
+
     pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
         match self {
             Self::Tcp(x) => x.try_read(buf),
@@ -173,11 +536,50 @@ impl OwnedReadHalf {
             Self::Unix(x) => x.try_read_vectored(bufs),
         }
     }
+
+    /// See [`Stream::try_read_buf`].
+    #[cfg(feature = "bytes")]
+    pub fn try_read_buf<B: BufMut>(&self, buf: &mut B) -> Result<usize> {
+        match self {
+            Self::Tcp(x) => x.try_read_buf(buf),
+            Self::Unix(x) => x.try_read_buf(buf),
+        }
+    }
+
+    /// See [`Stream::read_buf`].
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: BufMut>(&self, buf: &mut B) -> Result<usize> {
+        loop {
+            self.readable().await?;
+
+            match self.try_read_buf(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                x => return x,
+            }
+        }
+    }
+}
+
+impl AsFd for OwnedReadHalf {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_fd(),
+            Self::Unix(x) => x.as_ref().as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for OwnedReadHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_raw_fd(),
+            Self::Unix(x) => x.as_ref().as_raw_fd(),
+        }
+    }
 }
 
 crate::macros::impl_async_read! {
     type: OwnedReadHalf,
-    proj: OwnedReadHalfProj,
 }
 
 impl fmt::Debug for OwnedReadHalf {
@@ -188,3 +590,57 @@ impl fmt::Debug for OwnedReadHalf {
         }
     }
 }
+
+impl fmt::Display for OwnedReadHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+            Self::Unix(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Returned from [`OwnedReadHalf::reunite`] and [`OwnedWriteHalf::reunite`]
+/// when the halves cannot be put back together.
+///
+/// Both halves are given back.
+#[derive(Debug)]
+pub enum ReuniteError {
+    /// The halves belong to different connections.
+    DifferentConnections(OwnedReadHalf, OwnedWriteHalf),
+    /// One half is a TCP connection and the other a unix connection.
+    DifferentKinds(OwnedReadHalf, OwnedWriteHalf),
+}
+
+impl ReuniteError {
+    pub fn into_halves(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        match self {
+            Self::DifferentConnections(r, w) | Self::DifferentKinds(r, w) => (r, w),
+        }
+    }
+}
+
+impl fmt::Display for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DifferentConnections(..) => {
+                f.write_str("tried to reunite halves that are not from the same socket")
+            }
+            Self::DifferentKinds(..) => {
+                f.write_str("tried to reunite halves of a tcp and a unix socket")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReuniteError {}
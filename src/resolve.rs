@@ -0,0 +1,221 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+
+use crate::utils::{Result, unsupported};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The future returned by [`Resolve::resolve`].
+pub type Resolving<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<std::net::SocketAddr>>> + Send + 'a>>;
+
+/// The future returned by [`Resolve::resolve_srv`].
+pub type ResolvingSrv<'a> = Pin<Box<dyn Future<Output = Result<Vec<SrvTarget>>> + Send + 'a>>;
+
+/// A target of a DNS SRV record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvTarget {
+    /// Targets with a lower priority are tried first.
+    pub priority: u16,
+    /// Relative chance of being tried first among targets of equal priority.
+    pub weight: u16,
+    pub port: u16,
+    /// Hostname of the target. `"."` means the service is not available.
+    pub target: String,
+}
+
+/// Resolves hostnames to IP addresses.
+///
+/// Hostnames in `tcp://<host>:<port>` strings and `(host, port)` tuples are
+/// resolved with [`SystemResolver`] unless another resolver is set with
+/// [`ConnectOptions::resolver`](crate::ConnectOptions::resolver). IP literals
+/// never reach the resolver.
+///
+/// Errors are wrapped in one that names the hostname, so implementations do
+/// not have to.
+pub trait Resolve: fmt::Debug + Send + Sync {
+    /// Resolve `host` and give every address `port`.
+    ///
+    /// An empty list means the hostname has no addresses.
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> Resolving<'a>;
+
+    /// Look up the SRV records of `name`, like `_http._tcp.example.com`, for
+    /// `tcp+srv://<name>` addresses.
+    ///
+    /// The targets may be returned in any order. Their hostnames are resolved
+    /// with [`Resolve::resolve`] afterwards. The default implementation fails
+    /// with [`ErrorKind::Unsupported`].
+    fn resolve_srv<'a>(&'a self, name: &'a str) -> ResolvingSrv<'a> {
+        let _ = name;
+        Box::pin(async { Err(unsupported("SRV lookup by this resolver")) })
+    }
+}
+
+/// Resolves hostnames with the system resolver, through
+/// [`tokio::net::lookup_host`].
+///
+/// The system resolver cannot look up SRV records.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> Resolving<'a> {
+        Box::pin(async move {
+            tokio::net::lookup_host((host, port))
+                .await
+                .map(Iterator::collect)
+        })
+    }
+}
+
+/// Resolves hostnames and SRV records with [`hickory_resolver`].
+///
+/// Needed for `tcp+srv://` addresses, which the system resolver cannot look
+/// up.
+#[cfg(feature = "hickory")]
+#[derive(Debug, Clone)]
+pub struct HickoryResolver(hickory_resolver::TokioResolver);
+
+#[cfg(feature = "hickory")]
+impl HickoryResolver {
+    /// Create a resolver from the system configuration (`/etc/resolv.conf`).
+    pub fn new() -> Result<Self> {
+        let resolver = hickory_resolver::TokioResolver::builder_tokio()
+            .and_then(|x| x.build())
+            .map_err(Error::other)?;
+
+        Ok(Self(resolver))
+    }
+
+    #[must_use]
+    pub fn from_resolver(resolver: hickory_resolver::TokioResolver) -> Self {
+        Self(resolver)
+    }
+}
+
+#[cfg(feature = "hickory")]
+impl Resolve for HickoryResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> Resolving<'a> {
+        Box::pin(async move {
+            let ips = match self.0.lookup_ip(host).await {
+                Ok(x) => x,
+                Err(e) if e.is_no_records_found() => return Ok(Vec::new()),
+                Err(e) => return Err(Error::other(e)),
+            };
+
+            Ok(ips
+                .iter()
+                .map(|ip| std::net::SocketAddr::new(ip, port))
+                .collect())
+        })
+    }
+
+    fn resolve_srv<'a>(&'a self, name: &'a str) -> ResolvingSrv<'a> {
+        use hickory_resolver::proto::rr::RData;
+
+        Box::pin(async move {
+            let records = match self.0.srv_lookup(name).await {
+                Ok(x) => x,
+                Err(e) if e.is_no_records_found() => return Ok(Vec::new()),
+                Err(e) => return Err(Error::other(e)),
+            };
+
+            Ok(records
+                .answers()
+                .iter()
+                .filter_map(|x| match &x.data {
+                    RData::SRV(x) => Some(SrvTarget {
+                        priority: x.priority,
+                        weight: x.weight,
+                        port: x.port,
+                        target: x.target.to_utf8(),
+                    }),
+                    _ => None,
+                })
+                .collect())
+        })
+    }
+}
+
+/// Resolve the SRV records of `name` to addresses, in the order they should be
+/// tried (RFC 2782).
+///
+/// Targets whose hostname cannot be resolved are skipped, unless none can be.
+pub(crate) async fn resolve_srv(
+    resolver: &dyn Resolve,
+    name: &str,
+) -> Result<Vec<std::net::SocketAddr>> {
+    let targets = resolver
+        .resolve_srv(name)
+        .await
+        .map_err(|e| Error::new(e.kind(), format!("failed to resolve SRV {name}: {e}")))?;
+
+    if targets.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no SRV records for {name}"),
+        ));
+    }
+
+    if matches!(targets.as_slice(), [x] if x.target == ".") {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("service {name} is not available"),
+        ));
+    }
+
+    let mut addrs = Vec::new();
+    let mut last_error = None;
+
+    for x in order_srv(targets) {
+        match resolver.resolve(&x.target, x.port).await {
+            Ok(x) => addrs.extend(x),
+            Err(e) => last_error = Some((x, e)),
+        }
+    }
+
+    match last_error {
+        Some((x, e)) if addrs.is_empty() => Err(Error::new(
+            e.kind(),
+            format!("failed to resolve {}:{}: {e}", x.target, x.port),
+        )),
+        _ => Ok(addrs),
+    }
+}
+
+/// Sort `targets` by priority, and by a weighted random choice among equal
+/// priorities.
+fn order_srv(mut targets: Vec<SrvTarget>) -> Vec<SrvTarget> {
+    // Only needs to spread out clients, not be unpredictable.
+    let random = RandomState::new();
+
+    // Zero weights first, so they only get picked by a random value of 0.
+    targets.retain(|x| x.target != ".");
+    targets.sort_by_key(|x| (x.priority, x.weight != 0));
+
+    let mut ordered = Vec::with_capacity(targets.len());
+    while !targets.is_empty() {
+        let priority = targets[0].priority;
+        let end = targets.partition_point(|x| x.priority == priority);
+
+        let total: u64 = targets[..end].iter().map(|x| u64::from(x.weight)).sum();
+        let r = random.hash_one(ordered.len()) % (total + 1);
+
+        let mut sum = 0;
+        let i = targets[..end]
+            .iter()
+            .position(|x| {
+                sum += u64::from(x.weight);
+                sum >= r
+            })
+            .unwrap_or(0);
+
+        ordered.push(targets.remove(i));
+    }
+
+    ordered
+}
@@ -0,0 +1,158 @@
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::utils::Result;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Resumable equivalent of [`AsyncReadExt::read_exact`].
+///
+/// Bytes read so far are kept in the state, so dropping a
+/// [`ReadExactState::progress`] future (for example, because another branch of
+/// a `select!` completed) loses nothing. Calling it again continues where the
+/// previous call left off.
+///
+/// [`AsyncReadExt::read_exact`]: tokio::io::AsyncReadExt::read_exact
+#[derive(Debug, Clone)]
+pub struct ReadExactState {
+    buf: Vec<u8>,
+    filled: usize,
+}
+
+impl ReadExactState {
+    /// Prepare to read exactly `len` bytes.
+    pub fn new(len: usize) -> Self {
+        Self {
+            buf: vec![0; len],
+            filled: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn filled(&self) -> &[u8] {
+        &self.buf[..self.filled]
+    }
+
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.filled
+    }
+
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Get the bytes read so far.
+    pub fn into_inner(mut self) -> Vec<u8> {
+        self.buf.truncate(self.filled);
+        self.buf
+    }
+
+    /// Read into the buffer until it is full.
+    ///
+    /// Reaching end-of-file before that is an [`ErrorKind::UnexpectedEof`]
+    /// error. The bytes read before end-of-file stay available through
+    /// [`ReadExactState::filled`].
+    pub fn poll<R>(&mut self, reader: &mut R, cx: &mut Context<'_>) -> Poll<Result<()>>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+    {
+        while self.filled < self.buf.len() {
+            let mut buf = ReadBuf::new(&mut self.buf[self.filled..]);
+            ready!(Pin::new(&mut *reader).poll_read(cx, &mut buf))?;
+
+            let n = buf.filled().len();
+            if n == 0 {
+                return Poll::Ready(Err(ErrorKind::UnexpectedEof.into()));
+            }
+
+            self.filled += n;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Cancel-safe version of [`ReadExactState::poll`].
+    pub async fn progress<R>(&mut self, reader: &mut R) -> Result<()>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+    {
+        poll_fn(|cx| self.poll(reader, cx)).await
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Resumable equivalent of [`AsyncWriteExt::write_all`].
+///
+/// The number of bytes written so far is kept in the state, so dropping a
+/// [`WriteAllState::progress`] future neither loses nor repeats bytes. Calling
+/// it again continues where the previous call left off.
+///
+/// [`AsyncWriteExt::write_all`]: tokio::io::AsyncWriteExt::write_all
+#[derive(Debug, Clone)]
+pub struct WriteAllState<B> {
+    buf: B,
+    written: usize,
+}
+
+impl<B: AsRef<[u8]>> WriteAllState<B> {
+    /// Prepare to write all of `buf`.
+    pub fn new(buf: B) -> Self {
+        Self { buf, written: 0 }
+    }
+
+    #[must_use]
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    #[must_use]
+    pub fn remaining(&self) -> &[u8] {
+        &self.buf.as_ref()[self.written..]
+    }
+
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.remaining().is_empty()
+    }
+
+    pub fn into_inner(self) -> B {
+        self.buf
+    }
+
+    /// Write from the buffer until all of it has been written.
+    pub fn poll<W>(&mut self, writer: &mut W, cx: &mut Context<'_>) -> Poll<Result<()>>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        while !self.is_complete() {
+            let buf = &self.buf.as_ref()[self.written..];
+            let n = ready!(Pin::new(&mut *writer).poll_write(cx, buf))?;
+
+            if n == 0 {
+                return Poll::Ready(Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                )));
+            }
+
+            self.written += n;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Cancel-safe version of [`WriteAllState::poll`].
+    pub async fn progress<W>(&mut self, writer: &mut W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        poll_fn(|cx| self.poll(writer, cx)).await
+    }
+}
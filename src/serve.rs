@@ -0,0 +1,268 @@
+use std::fmt;
+use std::future::{Future, poll_fn};
+use std::io::Error;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use tokio::sync::{Semaphore, oneshot};
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::utils::Result;
+use crate::{AcceptErrorAction, AcceptOptions, Listener, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+const MIN_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ServeOptions {
+    /// Maximum number of connection handlers running at once.
+    ///
+    /// When the limit is reached, no new connections are accepted until a
+    /// handler finishes.
+    pub max_connections: Option<usize>,
+
+    /// Pass peer addresses to the handler in canonical form.
+    ///
+    /// See [`SocketAddr::to_canonical`].
+    pub canonical_peer_addrs: bool,
+
+    /// How long to wait for running handlers after accepting has stopped.
+    ///
+    /// Handlers still running after this are aborted. Without a timeout, the
+    /// accept loop waits for all of them.
+    pub drain_timeout: Option<Duration>,
+
+    /// Options applied to every accepted connection.
+    ///
+    /// A connection for which this fails is closed without calling the
+    /// handler, unless [`AcceptOptions::ignore_errors`] is set.
+    pub accept: AcceptOptions,
+}
+
+impl ServeOptions {
+    #[must_use]
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    #[must_use]
+    pub fn canonical_peer_addrs(mut self, canonical_peer_addrs: bool) -> Self {
+        self.canonical_peer_addrs = canonical_peer_addrs;
+        self
+    }
+
+    #[must_use]
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = Some(timeout);
+        self
+    }
+
+    #[must_use]
+    pub fn accept(mut self, accept: AcceptOptions) -> Self {
+        self.accept = accept;
+        self
+    }
+}
+
+/// Accept connections from `listener` and spawn `handler` for each one.
+///
+/// See [`serve_with`].
+pub fn serve<F, Fut>(listener: Listener, handler: F) -> ServeHandle
+where
+    F: Fn(Stream, SocketAddr) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    serve_with(listener, ServeOptions::default(), handler)
+}
+
+/// Accept connections from `listener` and spawn `handler` for each one.
+///
+/// The accept loop runs in its own task. Errors which only affect a single
+/// connection (`ECONNABORTED`, `ECONNRESET`, `EINTR`) are skipped. Running
+/// out of resources (`EMFILE`, `ENFILE`, `ENOBUFS`, `ENOMEM`) pauses
+/// accepting with an exponential backoff, instead of spinning. Any other error
+/// stops the loop and is returned from [`ServeHandle::shutdown`].
+///
+/// Each handler runs in its own task, so a handler that panics only ends its
+/// own connection.
+///
+/// Dropping the returned handle detaches the loop.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+pub fn serve_with<F, Fut>(listener: Listener, options: ServeOptions, handler: F) -> ServeHandle
+where
+    F: Fn(Stream, SocketAddr) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let task = tokio::spawn(accept_loop(listener, options, handler, rx));
+
+    ServeHandle { shutdown: tx, task }
+}
+
+/// Handle to an accept loop started with [`serve`].
+pub struct ServeHandle {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl ServeHandle {
+    /// Stop accepting connections and wait for all spawned handlers.
+    ///
+    /// See [`ServeOptions::drain_timeout`] to bound the wait.
+    pub async fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown.send(());
+
+        match self.task.await {
+            Ok(x) => x,
+            Err(e) => Err(Error::other(e)),
+        }
+    }
+
+    /// Keep serving until `signal` completes, then [shut down](Self::shutdown).
+    ///
+    /// Returns early if the accept loop stops on its own because of an error.
+    pub async fn shutdown_on(mut self, signal: impl Future) -> Result<()> {
+        let mut signal = std::pin::pin!(signal);
+
+        let stopped = poll_fn(|cx| {
+            if let Poll::Ready(x) = Pin::new(&mut self.task).poll(cx) {
+                return Poll::Ready(Some(x));
+            }
+
+            signal.as_mut().poll(cx).map(|_| None)
+        })
+        .await;
+
+        match stopped {
+            Some(Ok(x)) => x,
+            Some(Err(e)) => Err(Error::other(e)),
+            None => self.shutdown().await,
+        }
+    }
+}
+
+impl fmt::Debug for ServeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServeHandle").finish_non_exhaustive()
+    }
+}
+
+async fn accept_loop<F, Fut>(
+    listener: Listener,
+    options: ServeOptions,
+    handler: F,
+    mut shutdown: oneshot::Receiver<()>,
+) -> Result<()>
+where
+    F: Fn(Stream, SocketAddr) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let handler = Arc::new(handler);
+    let limit = options.max_connections.map(|x| Arc::new(Semaphore::new(x)));
+
+    let mut tasks = JoinSet::new();
+    let mut backoff = MIN_BACKOFF;
+    let mut detached = false;
+
+    let result = loop {
+        while tasks.try_join_next().is_some() {}
+
+        let permit = match &limit {
+            Some(limit) => {
+                let acquire = Arc::clone(limit).acquire_owned();
+                match until_shutdown(&mut shutdown, &mut detached, acquire).await {
+                    Some(x) => Some(x.expect("semaphore is never closed")),
+                    None => break Ok(()),
+                }
+            }
+            None => None,
+        };
+
+        let accept = listener.accept();
+        let Some(accepted) = until_shutdown(&mut shutdown, &mut detached, accept).await else {
+            break Ok(());
+        };
+
+        match accepted {
+            Ok((stream, addr)) => {
+                backoff = MIN_BACKOFF;
+
+                if options.accept.apply(&stream).is_err() {
+                    continue;
+                }
+
+                let addr = if options.canonical_peer_addrs {
+                    addr.to_canonical()
+                } else {
+                    addr
+                };
+
+                let handler = Arc::clone(&handler);
+                tasks.spawn(async move {
+                    handler(stream, addr).await;
+                    drop(permit);
+                });
+            }
+            Err(e) => match AcceptErrorAction::classify(&e) {
+                AcceptErrorAction::Retry => {}
+                AcceptErrorAction::Backoff => {
+                    let sleep = tokio::time::sleep(backoff);
+                    if until_shutdown(&mut shutdown, &mut detached, sleep)
+                        .await
+                        .is_none()
+                    {
+                        break Ok(());
+                    }
+
+                    backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+                }
+                AcceptErrorAction::Fail => break Err(e),
+            },
+        }
+    };
+
+    drop(listener);
+
+    let drain = async { while tasks.join_next().await.is_some() {} };
+    match options.drain_timeout {
+        Some(timeout) => {
+            if tokio::time::timeout(timeout, drain).await.is_err() {
+                tasks.shutdown().await;
+            }
+        }
+        None => drain.await,
+    }
+
+    result
+}
+
+/// Run `fut` to completion unless shutdown is requested first.
+async fn until_shutdown<T>(
+    shutdown: &mut oneshot::Receiver<()>,
+    detached: &mut bool,
+    fut: impl Future<Output = T>,
+) -> Option<T> {
+    let mut fut = std::pin::pin!(fut);
+
+    poll_fn(|cx| {
+        if !*detached {
+            match Pin::new(&mut *shutdown).poll(cx) {
+                Poll::Ready(Ok(())) => return Poll::Ready(None),
+                Poll::Ready(Err(_)) => *detached = true,
+                Poll::Pending => {}
+            }
+        }
+
+        fut.as_mut().poll(cx).map(Some)
+    })
+    .await
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::future::{Future, poll_fn};
+use std::pin::pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+
+use tokio::sync::Notify;
+
+use crate::utils::Result;
+use crate::{Listener, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+struct State {
+    current: Arc<Listener>,
+    /// Replaced listeners whose queued connections are still being accepted.
+    draining: Vec<Arc<Listener>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    swapped: Notify,
+}
+
+/// A handle to a [`Listener`] that can be replaced while connections are
+/// being accepted from it.
+///
+/// Clones share the listener. [`SharedListener::rebind`] swaps in a new one,
+/// for example one bound to a different address after reloading the
+/// configuration, and every [`SharedListener::accept`], including the ones
+/// already waiting, accepts from the new listener from then on. The old
+/// listener is closed once no accept is using it anymore, which happens right
+/// away, since the waiting ones are woken up.
+///
+/// Created by [`Listener::shared`].
+#[derive(Clone)]
+pub struct SharedListener {
+    shared: Arc<Shared>,
+}
+
+impl SharedListener {
+    pub fn new(listener: Listener) -> Self {
+        let state = State {
+            current: Arc::new(listener),
+            draining: Vec::new(),
+        };
+
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                swapped: Notify::new(),
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Replace the listener with `listener` and close the old one.
+    ///
+    /// Connections that were queued on the old listener but not accepted yet
+    /// are reset.
+    pub fn rebind(&self, listener: Listener) {
+        self.swap(listener, false);
+    }
+
+    /// Replace the listener with `listener`, but accept the connections that
+    /// are already queued on the old one before closing it.
+    ///
+    /// The queued connections are returned from [`SharedListener::accept`]
+    /// before any from the new listener. The old listener is closed as soon as
+    /// it has nothing left to accept.
+    pub fn rebind_draining(&self, listener: Listener) {
+        self.swap(listener, true);
+    }
+
+    fn swap(&self, listener: Listener, drain: bool) {
+        {
+            let mut state = self.state();
+            let old = std::mem::replace(&mut state.current, Arc::new(listener));
+
+            if drain {
+                state.draining.push(old);
+            }
+        }
+
+        self.shared.swapped.notify_waiters();
+    }
+
+    /// Accept a connection from the current listener.
+    pub async fn accept(&self) -> Result<(Stream, SocketAddr)> {
+        loop {
+            let mut swapped = pin!(self.shared.swapped.notified());
+            // Do not miss a swap that happens after looking at the state.
+            swapped.as_mut().enable();
+
+            let current = self.state().current.clone();
+
+            let accepted = poll_fn(|cx| {
+                if let Poll::Ready(x) = self.poll_drain(cx) {
+                    return Poll::Ready(Some(x));
+                }
+
+                if swapped.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+
+                current.poll_accept(cx).map(Some)
+            })
+            .await;
+
+            if let Some(x) = accepted {
+                return x;
+            }
+        }
+    }
+
+    /// Accept a connection that is still queued on a replaced listener,
+    /// forgetting the ones that have none left.
+    fn poll_drain(&self, cx: &mut Context<'_>) -> Poll<Result<(Stream, SocketAddr)>> {
+        let draining = self.state().draining.clone();
+
+        for listener in &draining {
+            if let Poll::Ready(Ok(x)) = listener.poll_accept(cx) {
+                return Poll::Ready(Ok(x));
+            }
+
+            self.state().draining.retain(|x| !Arc::ptr_eq(x, listener));
+        }
+
+        Poll::Pending
+    }
+
+    /// The address of the current listener.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.state().current.local_addr()
+    }
+}
+
+impl fmt::Debug for SharedListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+
+        f.debug_struct("SharedListener")
+            .field("listener", &state.current)
+            .field("draining", &state.draining.len())
+            .finish()
+    }
+}
@@ -0,0 +1,369 @@
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+
+use rustix::io::Errno;
+use socket2::{Domain, Protocol, SockRef, Type};
+
+#[cfg(target_os = "linux")]
+use crate::utils::is_mptcp;
+use crate::utils::{Result, domain_of, to_bind_sock_addr, to_sock_addr, unsupported};
+use crate::{Listener, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A socket that has not yet been connected or put into listening mode.
+///
+/// This is the equivalent of [`tokio::net::TcpSocket`] for any supported
+/// address family. It allows configuring the socket before calling
+/// [`Socket::connect`] or [`Socket::listen`].
+#[derive(Debug)]
+pub struct Socket {
+    inner: socket2::Socket,
+    domain: Domain,
+}
+
+impl Socket {
+    /// Create a socket of the right family to connect to or bind `addr`.
+    pub fn new_for(addr: &SocketAddr) -> Result<Self> {
+        Self::new(domain_of(addr))
+    }
+
+    pub fn new_v4() -> Result<Self> {
+        Self::new(Domain::IPV4)
+    }
+
+    pub fn new_v6() -> Result<Self> {
+        Self::new(Domain::IPV6)
+    }
+
+    pub fn new_unix() -> Result<Self> {
+        Self::new(Domain::UNIX)
+    }
+
+    /// Create a Multipath TCP socket (`IPPROTO_MPTCP`) of the right family to
+    /// connect to or bind `addr`.
+    ///
+    /// If the kernel does not support MPTCP or has it disabled, this creates
+    /// a plain TCP socket instead. Use [`Socket::is_mptcp`] to tell them
+    /// apart. Unix addresses fail with [`ErrorKind::InvalidInput`].
+    #[cfg(target_os = "linux")]
+    pub fn new_mptcp_for(addr: &SocketAddr) -> Result<Self> {
+        let domain = domain_of(addr);
+        if domain == Domain::UNIX {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "MPTCP is not supported for unix sockets",
+            ));
+        }
+
+        let result = Self::with_protocol(domain, Some(Protocol::MPTCP));
+
+        let errno = result.as_ref().err().and_then(Error::raw_os_error);
+        if matches!(
+            errno.map(Errno::from_raw_os_error),
+            Some(Errno::PROTONOSUPPORT | Errno::NOPROTOOPT)
+        ) {
+            return Self::new(domain);
+        }
+
+        result
+    }
+
+    fn new(domain: Domain) -> Result<Self> {
+        Self::with_protocol(domain, None)
+    }
+
+    fn with_protocol(domain: Domain, protocol: Option<Protocol>) -> Result<Self> {
+        let inner = socket2::Socket::new(domain, Type::STREAM, protocol)?;
+        inner.set_nonblocking(true)?;
+
+        Ok(Self { inner, domain })
+    }
+
+    #[must_use]
+    pub fn is_tcp(&self) -> bool {
+        !self.is_unix()
+    }
+
+    #[must_use]
+    pub fn is_unix(&self) -> bool {
+        self.domain == Domain::UNIX
+    }
+
+    /// Whether this is a Multipath TCP socket.
+    ///
+    /// See [`Socket::new_mptcp_for`].
+    #[cfg(target_os = "linux")]
+    pub fn is_mptcp(&self) -> Result<bool> {
+        is_mptcp(self.as_fd())
+    }
+
+    /// Borrow the socket to set options not otherwise exposed by this crate.
+    ///
+    /// The socket must be left in non-blocking mode.
+    pub fn socket_ref(&self) -> SockRef<'_> {
+        SockRef::from(&self.inner)
+    }
+
+    fn check_tcp(&self, what: &str) -> Result<()> {
+        if self.is_unix() {
+            return Err(unsupported(&format!("{what} on unix sockets")));
+        }
+
+        Ok(())
+    }
+
+    /// Only send and receive traffic through the interface named `device`
+    /// (`SO_BINDTODEVICE`), or remove the restriction with `None`.
+    ///
+    /// Only supported for TCP sockets on Linux and Android.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_device(&self, device: Option<&str>) -> Result<()> {
+        self.check_tcp("SO_BINDTODEVICE")?;
+        self.inner.bind_device(device.map(str::as_bytes))
+    }
+
+    /// Set the mark of outgoing packets, for policy routing and filtering
+    /// (`SO_MARK`).
+    ///
+    /// This usually requires `CAP_NET_ADMIN`. Only supported for TCP sockets
+    /// on Linux and Android.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_mark(&self, mark: u32) -> Result<()> {
+        self.check_tcp("SO_MARK")?;
+        self.inner.set_mark(mark)
+    }
+
+    /// Accept connections to and bind addresses that are not local, for
+    /// transparent proxying with `TPROXY` (`IP_TRANSPARENT`).
+    ///
+    /// This requires `CAP_NET_ADMIN`. Only supported for TCP sockets on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn set_transparent(&self, transparent: bool) -> Result<()> {
+        self.check_tcp("IP_TRANSPARENT")?;
+        self.inner.set_ip_transparent_v4(transparent)
+    }
+
+    /// Send the first data written together with the connection request,
+    /// using TCP Fast Open (`TCP_FASTOPEN_CONNECT`).
+    ///
+    /// Connecting then completes right away if a Fast Open cookie for the
+    /// server is cached, and the handshake happens with the first write. The
+    /// server must have Fast Open enabled, otherwise the connection falls back
+    /// to a normal handshake. Only supported for TCP sockets on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn set_fastopen_connect(&self, fastopen: bool) -> Result<()> {
+        self.check_tcp("TCP_FASTOPEN_CONNECT")?;
+        nix::sys::socket::setsockopt(
+            &self.inner,
+            nix::sys::socket::sockopt::TcpFastOpenConnect,
+            &fastopen,
+        )?;
+        Ok(())
+    }
+
+    /// Choose which socket in the `SO_REUSEPORT` group of this socket gets
+    /// each new connection with a classic BPF `program`
+    /// (`SO_ATTACH_REUSEPORT_CBPF`).
+    ///
+    /// The program returns the index of the socket in the group, in the order
+    /// the sockets were bound. If it returns an index that is out of range,
+    /// the kernel falls back to its hash. Only supported for TCP sockets on
+    /// Linux.
+    #[cfg(target_os = "linux")]
+    pub fn attach_reuseport_cbpf(&self, program: &[SockFilter]) -> Result<()> {
+        use nix::libc::{sock_filter, sock_fprog};
+
+        self.check_tcp("SO_ATTACH_REUSEPORT_CBPF")?;
+
+        let mut filter: Vec<sock_filter> = program
+            .iter()
+            .map(|x| sock_filter {
+                code: x.code,
+                jt: x.jt,
+                jf: x.jf,
+                k: x.k,
+            })
+            .collect();
+
+        let len = u16::try_from(filter.len())
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "BPF program is too long"))?;
+
+        let program = sock_fprog {
+            len,
+            filter: filter.as_mut_ptr(),
+        };
+        nix::sys::socket::setsockopt(
+            &self.inner,
+            nix::sys::socket::sockopt::AttachReusePortCbpf,
+            &program,
+        )?;
+        Ok(())
+    }
+
+    /// Set the type-of-service field of outgoing packets (`IP_TOS` or
+    /// `IPV6_TCLASS`, depending on the family).
+    pub fn set_tos(&self, tos: u32) -> Result<()> {
+        self.check_tcp("IP_TOS")?;
+
+        if self.domain == Domain::IPV6 {
+            self.inner.set_tclass_v6(tos)
+        } else {
+            self.inner.set_tos_v4(tos)
+        }
+    }
+
+    /// Allow binding to an address that still has connections in `TIME_WAIT`
+    /// (`SO_REUSEADDR`).
+    ///
+    /// Only supported for TCP sockets.
+    pub fn set_reuse_address(&self, reuse: bool) -> Result<()> {
+        self.check_tcp("SO_REUSEADDR")?;
+        self.inner.set_reuse_address(reuse)
+    }
+
+    /// Allow multiple sockets to bind to the same address (`SO_REUSEPORT`).
+    ///
+    /// Only supported for TCP sockets.
+    pub fn set_reuse_port(&self, reuse: bool) -> Result<()> {
+        self.check_tcp("SO_REUSEPORT")?;
+        self.inner.set_reuse_port(reuse)
+    }
+
+    /// Disable Nagle's algorithm (`TCP_NODELAY`).
+    ///
+    /// Only supported for TCP sockets. See [`Stream::set_nodelay`].
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        self.check_tcp("TCP_NODELAY")?;
+        self.inner.set_tcp_nodelay(nodelay)
+    }
+
+    /// Set the size of the kernel send buffer (`SO_SNDBUF`).
+    ///
+    /// Setting this before connecting or listening lets it take part in TCP
+    /// window scaling negotiation.
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<()> {
+        self.inner.set_send_buffer_size(size)
+    }
+
+    /// Set the size of the kernel receive buffer (`SO_RCVBUF`).
+    ///
+    /// See [`Socket::set_send_buffer_size`].
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<()> {
+        self.inner.set_recv_buffer_size(size)
+    }
+
+    fn check_family(&self, addr: &SocketAddr) -> Result<()> {
+        if domain_of(addr) == self.domain {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "address family does not match the socket",
+            ))
+        }
+    }
+
+    pub fn bind(&self, addr: &SocketAddr) -> Result<()> {
+        self.check_family(addr)?;
+        self.inner.bind(&to_bind_sock_addr(addr)?)
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        if self.is_unix() {
+            // Only the standard library can represent unnamed addresses, so
+            // ask it through a duplicate of the descriptor.
+            let fd = self.inner.as_fd().try_clone_to_owned()?;
+            let x = std::os::unix::net::UnixStream::from(fd);
+            return x.local_addr().map(Into::into).map(SocketAddr::Unix);
+        }
+
+        self.inner
+            .local_addr()?
+            .as_socket()
+            .map(SocketAddr::Tcp)
+            .ok_or_else(|| Error::other("socket has a non-internet local address"))
+    }
+
+    /// Connect the socket to `addr`.
+    pub async fn connect(self, addr: &SocketAddr) -> Result<Stream> {
+        self.check_family(addr)?;
+
+        match addr {
+            SocketAddr::Tcp(x) => {
+                let socket = std::net::TcpStream::from(self.inner);
+                let socket = tokio::net::TcpSocket::from_std_stream(socket);
+                socket.connect(*x).await.map(Into::into)
+            }
+            SocketAddr::Unix(_) => {
+                let in_progress = match self.inner.connect(&to_sock_addr(addr)?) {
+                    Ok(()) => false,
+                    Err(e) if e.raw_os_error() == Some(Errno::INPROGRESS.raw_os_error()) => true,
+                    Err(e) => return Err(e),
+                };
+
+                let socket = std::os::unix::net::UnixStream::from(OwnedFd::from(self.inner));
+                let stream = tokio::net::UnixStream::from_std(socket)?;
+
+                if in_progress {
+                    stream.writable().await?;
+
+                    if let Some(e) = stream.take_error()? {
+                        return Err(e);
+                    }
+                }
+
+                Ok(stream.into())
+            }
+        }
+    }
+
+    /// Put the socket into listening mode.
+    pub fn listen(self, backlog: u32) -> Result<Listener> {
+        if self.is_unix() {
+            let backlog = i32::try_from(backlog).unwrap_or(i32::MAX);
+            self.inner.listen(backlog)?;
+
+            let socket = std::os::unix::net::UnixListener::from(OwnedFd::from(self.inner));
+            return tokio::net::UnixListener::from_std(socket).map(Into::into);
+        }
+
+        let socket = std::net::TcpStream::from(self.inner);
+        let socket = tokio::net::TcpSocket::from_std_stream(socket);
+        socket.listen(backlog).map(Into::into)
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// An instruction of a classic BPF program, like `struct sock_filter`.
+///
+/// See [`Socket::attach_reuseport_cbpf`].
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+#[cfg(target_os = "linux")]
+impl SockFilter {
+    #[must_use]
+    pub const fn new(code: u16, jt: u8, jf: u8, k: u32) -> Self {
+        Self { code, jt, jf, k }
+    }
+}
@@ -1,8 +1,13 @@
-use std::ffi::OsStr;
-use std::fmt;
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Write as _};
 use std::io::Error;
 use std::iter;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
 use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::pin::Pin;
 use std::str::FromStr;
 use std::vec;
 
@@ -12,6 +17,7 @@ use std::os::android::net::SocketAddrExt;
 use std::os::linux::net::SocketAddrExt;
 
 use crate::utils::Result;
+use crate::{Resolve, SystemResolver};
 
 ///////////////////////////////////////////////////////////////////////////////
 
@@ -34,178 +40,2240 @@ impl From<tokio::net::unix::SocketAddr> for SocketAddr {
 }
 
 impl SocketAddr {
+    /// Create a TCP address.
+    pub fn tcp(ip: impl Into<IpAddr>, port: u16) -> Self {
+        Self::Tcp(std::net::SocketAddr::new(ip.into(), port))
+    }
+
+    /// Create a unix address with a path.
+    ///
+    /// The path is taken as is. A leading `@` is part of the path and never
+    /// makes this an abstract address, see [`SocketAddr::unix_abstract`] for
+    /// those.
+    ///
+    /// Fails if the path is empty, contains a NUL byte or does not fit in
+    /// `sun_path`.
+    pub fn unix(path: impl AsRef<Path>) -> Result<Self> {
+        unix_addr_from_pathname(path.as_ref()).map(Self::Unix)
+    }
+
+    /// Create a unix address in the abstract namespace.
+    ///
+    /// Fails if the name is empty or does not fit in `sun_path`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn unix_abstract(name: impl AsRef<[u8]>) -> Result<Self> {
+        unix_addr_from_abstract_name(name.as_ref()).map(Self::Unix)
+    }
+
+    /// The unnamed unix address.
+    ///
+    /// Binding a listener or datagram socket to it makes the kernel pick an
+    /// unused name in the abstract namespace (autobind). Use `local_addr` on
+    /// the bound socket to get the name.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn unix_autobind() -> Result<Self> {
+        // Only the standard library can represent unnamed addresses, so take
+        // one from an unbound socket.
+        let x = std::os::unix::net::UnixDatagram::unbound()?.local_addr()?;
+        Ok(Self::Unix(x.into()))
+    }
+
     #[must_use]
     pub fn is_tcp(&self) -> bool {
         matches!(self, Self::Tcp(..))
     }
 
-    #[must_use]
-    pub fn is_unix(&self) -> bool {
-        matches!(self, Self::Unix(..))
+    #[must_use]
+    pub fn is_unix(&self) -> bool {
+        matches!(self, Self::Unix(..))
+    }
+
+    /// `"tcp"` or `"unix"`, the scheme [`Display`](fmt::Display) uses for the
+    /// address.
+    #[must_use]
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Tcp(..) => "tcp",
+            Self::Unix(..) => "unix",
+        }
+    }
+
+    #[must_use]
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, Self::Tcp(std::net::SocketAddr::V4(..)))
+    }
+
+    #[must_use]
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, Self::Tcp(std::net::SocketAddr::V6(..)))
+    }
+
+    /// Whether this is a unix address without a path or abstract name.
+    #[must_use]
+    pub fn is_unnamed(&self) -> bool {
+        matches!(self, Self::Unix(x) if x.is_unnamed())
+    }
+
+    /// The IP address of a TCP address.
+    #[must_use]
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Self::Tcp(x) => Some(x.ip()),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// The port of a TCP address.
+    #[must_use]
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Self::Tcp(x) => Some(x.port()),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Change the port of a TCP address. Unix addresses are returned
+    /// unchanged.
+    #[must_use]
+    pub fn with_port(mut self, port: u16) -> Self {
+        if let Self::Tcp(x) = &mut self {
+            x.set_port(port);
+        }
+
+        self
+    }
+
+    /// The path of a unix address.
+    #[must_use]
+    pub fn as_pathname(&self) -> Option<&Path> {
+        match self {
+            Self::Tcp(_) => None,
+            Self::Unix(x) => x.as_pathname(),
+        }
+    }
+
+    /// The name of a unix address in the abstract namespace, without the
+    /// leading NUL byte.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[must_use]
+    pub fn as_abstract_name(&self) -> Option<&[u8]> {
+        match self {
+            Self::Tcp(_) => None,
+            Self::Unix(x) => x.as_abstract_name(),
+        }
+    }
+
+    /// Convert IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) to plain IPv4.
+    ///
+    /// Dual-stack listeners report IPv4 peers in the mapped form, so this gives
+    /// the same address for a peer no matter which way it connected. All other
+    /// addresses are returned unchanged.
+    ///
+    /// See [`IpAddr::to_canonical`](std::net::IpAddr::to_canonical).
+    #[must_use]
+    pub fn to_canonical(&self) -> Self {
+        match self {
+            Self::Tcp(x) => Self::Tcp(std::net::SocketAddr::new(x.ip().to_canonical(), x.port())),
+            Self::Unix(_) => self.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.key() {
+            Key::Tcp(x) => write!(f, "tcp://{x}"),
+            Key::Path(path) => {
+                let path = path.as_os_str().as_bytes();
+
+                // A leading `@` would parse as an abstract name.
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                if let Some(rest) = path.strip_prefix(b"@") {
+                    return write!(f, "unix://%40{}", Escaped(rest));
+                }
+
+                write!(f, "unix://{}", Escaped(path))
+            }
+            Key::Abstract(name) => write!(f, "unix://@{}", Escaped(name)),
+            Key::Unnamed => f.write_str("(unnamed unix socket)"),
+        }
+    }
+}
+
+impl fmt::Display for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// What addresses are compared and hashed by.
+///
+/// The variant order is the order of [`Ord`] for [`SocketAddr`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Key<'a> {
+    Tcp(&'a std::net::SocketAddr),
+    Path(&'a Path),
+    Abstract(&'a [u8]),
+    Unnamed,
+}
+
+impl SocketAddr {
+    fn key(&self) -> Key<'_> {
+        if let Self::Tcp(x) = self {
+            return Key::Tcp(x);
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(name) = self.as_abstract_name() {
+            return Key::Abstract(name);
+        }
+
+        match self.as_pathname() {
+            Some(path) => Key::Path(path),
+            None => Key::Unnamed,
+        }
+    }
+}
+
+/// TCP addresses are equal if [`std::net::SocketAddr`] says so. Unix addresses
+/// are equal if they have the same path or the same abstract name, compared
+/// byte for byte without normalizing paths. All unnamed unix addresses are
+/// equal to each other. A TCP address is never equal to a unix address.
+impl PartialEq for SocketAddr {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SocketAddr {}
+
+impl PartialOrd for SocketAddr {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// TCP addresses sort first, ordered like [`std::net::SocketAddr`], then unix
+/// paths, then abstract names and last unnamed unix addresses.
+impl Ord for SocketAddr {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl std::hash::Hash for SocketAddr {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// Writes a unix path or abstract name so that [`FromStr`] reads back the same
+/// bytes.
+///
+/// `%`, whitespace, control characters and bytes which are not valid UTF-8 are
+/// percent-encoded. Everything else is written as is.
+pub(crate) struct Escaped<'a>(pub(crate) &'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                if c == '%' || c.is_whitespace() || c.is_control() {
+                    for b in c.encode_utf8(&mut [0; 4]).bytes() {
+                        write!(f, "%{b:02X}")?;
+                    }
+                } else {
+                    f.write_char(c)?;
+                }
+            }
+
+            for b in chunk.invalid() {
+                write!(f, "%{b:02X}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode the percent-encoded bytes written by [`Escaped`].
+///
+/// Any `%` must start an escape.
+pub(crate) fn unescape(s: &[u8]) -> Result<Vec<u8>> {
+    let hex = |x: Option<u8>| x.and_then(|x| char::from(x).to_digit(16));
+
+    let mut bytes = s.iter().copied();
+    let mut out = Vec::with_capacity(s.len());
+
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            out.push(b);
+            continue;
+        }
+
+        match (hex(bytes.next()), hex(bytes.next())) {
+            (Some(hi), Some(lo)) => out.push((hi << 4 | lo) as u8),
+            _ => return Err(AddrParseError::InvalidEscape.into()),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Size of `sun_path` in `struct sockaddr_un`.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "solaris",
+    target_os = "illumos"
+))]
+const SUN_PATH_LEN: usize = 108;
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "solaris",
+    target_os = "illumos"
+)))]
+const SUN_PATH_LEN: usize = 104;
+
+/// The longest unix socket path in bytes, which is the size of `sun_path`
+/// minus the terminating NUL.
+///
+/// Constructing a [`SocketAddr`] with a longer path, whether by parsing or
+/// with [`SocketAddr::unix`], fails with [`AddrParseError::PathTooLong`].
+pub const MAX_UNIX_PATH_LEN: usize = SUN_PATH_LEN - 1;
+
+/// The longest name in the abstract namespace in bytes, which is the size of
+/// `sun_path` minus the leading NUL.
+///
+/// Longer names fail with [`AddrParseError::AbstractNameTooLong`].
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const MAX_ABSTRACT_NAME_LEN: usize = SUN_PATH_LEN - 1;
+
+/// The reason a socket address could not be parsed.
+///
+/// This is returned as the inner error of an [`Error`] of kind
+/// [`ErrorKind::InvalidInput`](std::io::ErrorKind::InvalidInput).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AddrParseError {
+    /// The scheme is missing or not one of the supported ones.
+    InvalidScheme,
+    /// The scheme of a [`Url`](url::Url) is not one of the supported ones.
+    #[cfg(feature = "url")]
+    UnsupportedScheme(String),
+    /// [`SocketAddr::parse_lenient`] could not tell what kind of address
+    /// this is.
+    MissingScheme,
+    /// The address contains a NUL byte.
+    NulByte,
+    /// A `%` in a unix address is not followed by two hex digits.
+    InvalidEscape,
+    /// The TCP address is not a valid `ip:port` pair.
+    InvalidTcpAddr(std::net::AddrParseError),
+    /// The IP address is not of the family required by a `tcp4://` or
+    /// `tcp6://` scheme.
+    WrongFamily,
+    /// The unix socket path is empty.
+    EmptyPath,
+    /// The unix socket path does not fit in `sun_path`.
+    PathTooLong { len: usize, max: usize },
+    /// The abstract socket name is empty.
+    EmptyAbstractName,
+    /// The abstract socket name does not fit in `sun_path`.
+    AbstractNameTooLong { len: usize, max: usize },
+    /// The TCP address has no host.
+    MissingHost,
+    /// The TCP address has no port.
+    MissingPort,
+    /// The URL has a component that is not part of a socket address, like a
+    /// query or userinfo.
+    UnexpectedComponent(&'static str),
+    /// The zone of an IPv6 address names a network interface that does not
+    /// exist.
+    UnknownInterface(String),
+    /// The port range is malformed or its start is after its end.
+    InvalidPortRange,
+    /// An address list has an empty entry, like in `a,,b` or `a,`.
+    EmptyListEntry,
+    /// The prefix length of a CIDR prefix is not a number or too long for
+    /// the IP address.
+    InvalidPrefixLen,
+    /// An address filter rule does not start with `allow` or `deny`.
+    InvalidFilterRule,
+}
+
+impl fmt::Display for AddrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidScheme => f.write_str("invalid scheme"),
+            #[cfg(feature = "url")]
+            Self::UnsupportedScheme(x) => write!(f, "unsupported scheme `{x}`"),
+            Self::MissingScheme => {
+                f.write_str("not a recognizable address, add a tcp:// or unix:// scheme")
+            }
+            Self::NulByte => f.write_str("address contains a NUL byte"),
+            Self::InvalidEscape => f.write_str("invalid percent-encoding in unix address"),
+            Self::InvalidTcpAddr(e) => write!(f, "invalid tcp address: {e}"),
+            Self::WrongFamily => f.write_str("ip address is not of the requested family"),
+            Self::EmptyPath => f.write_str("unix socket path is empty"),
+            Self::PathTooLong { len, max } => write!(
+                f,
+                "unix socket path is {len} bytes long, but the limit is {max} bytes"
+            ),
+            Self::EmptyAbstractName => f.write_str("abstract socket name is empty"),
+            Self::AbstractNameTooLong { len, max } => write!(
+                f,
+                "abstract socket name is {len} bytes long, but the limit is {max} bytes"
+            ),
+            Self::MissingHost => f.write_str("tcp address has no host"),
+            Self::MissingPort => f.write_str("tcp address has no port"),
+            Self::UnexpectedComponent(x) => write!(f, "unexpected {x} in socket address url"),
+            Self::UnknownInterface(x) => write!(f, "unknown network interface {x}"),
+            Self::InvalidPortRange => f.write_str("invalid port range"),
+            Self::EmptyListEntry => f.write_str("address list has an empty entry"),
+            Self::InvalidPrefixLen => f.write_str("invalid prefix length"),
+            Self::InvalidFilterRule => {
+                f.write_str("address filter rule must start with `allow` or `deny`")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AddrParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidTcpAddr(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<AddrParseError> for Error {
+    fn from(e: AddrParseError) -> Self {
+        Error::new(std::io::ErrorKind::InvalidInput, e)
+    }
+}
+
+pub(crate) fn unix_addr_from_pathname(path: &Path) -> Result<tokio::net::unix::SocketAddr> {
+    let bytes = path.as_os_str().as_bytes();
+
+    if bytes.is_empty() {
+        return Err(AddrParseError::EmptyPath.into());
+    }
+
+    if bytes.contains(&0) {
+        return Err(AddrParseError::NulByte.into());
+    }
+
+    let max = MAX_UNIX_PATH_LEN;
+    if bytes.len() > max {
+        let len = bytes.len();
+        return Err(AddrParseError::PathTooLong { len, max }.into());
+    }
+
+    std::os::unix::net::SocketAddr::from_pathname(path)
+        .map(Into::into)
+        .map_err(Error::other)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn unix_addr_from_abstract_name(name: &[u8]) -> Result<tokio::net::unix::SocketAddr> {
+    if name.is_empty() {
+        return Err(AddrParseError::EmptyAbstractName.into());
+    }
+
+    let max = MAX_ABSTRACT_NAME_LEN;
+    if name.len() > max {
+        let len = name.len();
+        return Err(AddrParseError::AbstractNameTooLong { len, max }.into());
+    }
+
+    std::os::unix::net::SocketAddr::from_abstract_name(name)
+        .map(Into::into)
+        .map_err(Error::other)
+}
+
+/// Parses addresses of the form `tcp://<ip>:<port>` and `unix://<path>`.
+///
+/// On Linux and Android, `unix://@<name>` is an address in the abstract
+/// namespace.
+///
+/// The spellings used by gRPC, Docker and similar tools are accepted as well:
+///
+/// - `unix:///<path>` is the absolute path `/<path>`. This is just
+///   `unix://<path>` with a path starting with `/`, and is what
+///   [`Display`](fmt::Display) produces for absolute paths. Relative paths
+///   are displayed as `unix://<path>`.
+/// - `unix:<path>` is a path, absolute or relative.
+/// - `unix-abstract:<name>` is a name in the abstract namespace. On other
+///   platforms it is an error of kind
+///   [`ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported).
+///
+/// `tcp4://<ip>:<port>` and `tcp6://<ip>:<port>` only accept IPv4 and IPv6
+/// addresses respectively. The family of an IP address is part of the address
+/// itself, so these parse to [`SocketAddr::Tcp`] and are displayed as
+/// `tcp://`, which parses back to the same address.
+///
+/// IPv6 addresses may have a zone, like `tcp://[fe80::1%eth0]:9000`. The zone
+/// is either the index or, on Linux and Android, the name of a network
+/// interface, and becomes the scope ID of the address. Naming an interface
+/// that does not exist is an [`AddrParseError::UnknownInterface`] error.
+/// [`Display`](fmt::Display) always writes the index.
+///
+/// Schemes are matched case-insensitively, so `TCP://` and `Unix://` are
+/// accepted. [`Display`](fmt::Display) always produces lowercase schemes.
+///
+/// An `@` is only special right after `unix://` or `unix:`, and only on Linux
+/// and Android, where it starts an abstract name. Anywhere else, like in
+/// `unix:///tmp/@app.sock`, and on other platforms, it is part of the path. A
+/// relative path that starts with `@` is written with the `@` escaped as
+/// `%40`, like `unix://%40app.sock`.
+///
+/// Unix paths and abstract names are percent-decoded, so `%20` is a space and
+/// `%25` is a literal `%`. A `%` that does not start an escape is an error.
+/// [`Display`](fmt::Display) escapes `%`, whitespace, control characters and
+/// bytes that are not valid UTF-8, as well as a leading `@` of a path, so every
+/// named address it prints parses back to the same address. Abstract names may
+/// contain escaped NUL bytes, paths may not.
+impl FromStr for SocketAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('\0') {
+            return Err(AddrParseError::NulByte.into());
+        }
+
+        let Some((scheme, rest)) = s.split_once(':') else {
+            return Err(AddrParseError::InvalidScheme.into());
+        };
+
+        if scheme.eq_ignore_ascii_case("unix-abstract") {
+            return parse_unix_abstract(rest.as_bytes());
+        }
+
+        let Some(rest) = rest.strip_prefix("//") else {
+            if scheme.eq_ignore_ascii_case("unix") {
+                return parse_unix(rest.as_bytes());
+            }
+
+            return Err(AddrParseError::InvalidScheme.into());
+        };
+
+        if let Some(family) = Family::from_scheme(scheme) {
+            let addr = parse_tcp_addr(rest)?;
+
+            if !family.matches(&addr) {
+                return Err(AddrParseError::WrongFamily.into());
+            }
+
+            Ok(SocketAddr::Tcp(addr))
+        } else if scheme.eq_ignore_ascii_case("unix") {
+            parse_unix(rest.as_bytes())
+        } else {
+            Err(AddrParseError::InvalidScheme.into())
+        }
+    }
+}
+
+/// Parse `<ip>:<port>`, where an IPv6 address may have a `%<zone>` suffix
+/// with the name or index of a network interface.
+fn parse_tcp_addr(s: &str) -> Result<std::net::SocketAddr> {
+    let zoned = s
+        .strip_prefix('[')
+        .and_then(|x| x.split_once(']'))
+        .and_then(|(host, port)| Some((host.split_once('%')?, port)));
+
+    // The standard library handles numeric zones.
+    let Some(((ip, zone), port)) = zoned.filter(|((_, zone), _)| is_interface_name(zone)) else {
+        return Ok(s.parse().map_err(AddrParseError::InvalidTcpAddr)?);
+    };
+
+    let mut addr: std::net::SocketAddrV6 = format!("[{ip}]{port}")
+        .parse()
+        .map_err(AddrParseError::InvalidTcpAddr)?;
+
+    addr.set_scope_id(interface_index(zone)?);
+    Ok(addr.into())
+}
+
+fn is_interface_name(zone: &str) -> bool {
+    !zone.is_empty() && !zone.bytes().all(|x| x.is_ascii_digit())
+}
+
+/// Get the index of the network interface called `name`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn interface_index(name: &str) -> Result<u32> {
+    use rustix::net::{AddressFamily, SocketType};
+
+    let socket = rustix::net::socket(AddressFamily::INET, SocketType::DGRAM, None)?;
+    rustix::net::netdevice::name_to_index(&socket, name)
+        .map_err(|_| AddrParseError::UnknownInterface(name.to_owned()).into())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn interface_index(name: &str) -> Result<u32> {
+    let _ = name;
+    Err(crate::utils::unsupported("interface names in IPv6 zones"))
+}
+
+/// Parse what follows `unix://` or `unix:`.
+fn parse_unix(s: &[u8]) -> Result<SocketAddr> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(name) = s.strip_prefix(b"@") {
+        return parse_unix_abstract(name);
+    }
+
+    let path = unescape(s)?;
+    unix_addr_from_pathname(Path::new(OsStr::from_bytes(&path))).map(SocketAddr::Unix)
+}
+
+/// Parse what follows `unix-abstract:`.
+fn parse_unix_abstract(s: &[u8]) -> Result<SocketAddr> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return unix_addr_from_abstract_name(&unescape(s)?).map(SocketAddr::Unix);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = s;
+        Err(crate::utils::unsupported("the abstract namespace"))
+    }
+}
+
+/// Whether `s` starts with one of the unix schemes that are not followed by
+/// `//`.
+fn has_short_unix_scheme(s: &str) -> bool {
+    s.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.eq_ignore_ascii_case("unix") || scheme.eq_ignore_ascii_case("unix-abstract")
+    })
+}
+
+impl SocketAddr {
+    /// Parse an address typed by a person, where the scheme may be left out.
+    ///
+    /// Strings with a scheme, including `unix:<path>` and
+    /// `unix-abstract:<name>`, are parsed like [`FromStr`] does. Otherwise:
+    ///
+    /// - `/path`, `./path` and `../path` are unix socket paths,
+    /// - `@name` is a name in the abstract namespace (Linux and Android only;
+    ///   write `./@name` for a path),
+    /// - `<ip>:<port>` is a TCP address.
+    ///
+    /// Paths and names are taken as they are, without percent-decoding.
+    /// Anything else is an [`AddrParseError::MissingScheme`] error. In
+    /// particular, `app.sock` is not taken as a relative path, because it
+    /// could just as well be a misspelled hostname, and `localhost:80` is not
+    /// resolved. Windows paths like `C:\app.sock` are rejected too.
+    ///
+    /// Use [`FromStr`] for addresses in configuration files, where leaving the
+    /// scheme out is more likely a mistake.
+    pub fn parse_lenient(s: &str) -> Result<Self> {
+        if s.contains("://") || has_short_unix_scheme(s) {
+            return s.parse();
+        }
+
+        if s.contains('\0') {
+            return Err(AddrParseError::NulByte.into());
+        }
+
+        if s.starts_with('/') || s.starts_with("./") || s.starts_with("../") {
+            return Self::unix(s);
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(name) = s.strip_prefix('@') {
+            return Self::unix_abstract(name);
+        }
+
+        match s.parse() {
+            Ok(addr) => Ok(Self::Tcp(addr)),
+            Err(_) => Err(AddrParseError::MissingScheme.into()),
+        }
+    }
+}
+
+impl TryFrom<&str> for SocketAddr {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<String> for SocketAddr {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Parses the same forms as [`FromStr`], but keeps bytes that are not valid
+/// UTF-8, as found in command line arguments and environment variables.
+///
+/// Those bytes are taken as is in unix paths and abstract names, next to
+/// percent-encoded ones. Anywhere else, they make the address invalid.
+/// [`Display`](fmt::Display) percent-encodes them, so the result can be
+/// written out as UTF-8 and parses back to the same address.
+impl TryFrom<&OsStr> for SocketAddr {
+    type Error = Error;
+
+    fn try_from(s: &OsStr) -> Result<Self, Self::Error> {
+        if let Some(s) = s.to_str() {
+            return s.parse();
+        }
+
+        let bytes = s.as_bytes();
+        if bytes.contains(&0) {
+            return Err(AddrParseError::NulByte.into());
+        }
+
+        let Some(i) = bytes.iter().position(|&x| x == b':') else {
+            return Err(AddrParseError::InvalidScheme.into());
+        };
+
+        let (scheme, rest) = (&bytes[..i], &bytes[i + 1..]);
+
+        if scheme.eq_ignore_ascii_case(b"unix-abstract") {
+            parse_unix_abstract(rest)
+        } else if scheme.eq_ignore_ascii_case(b"unix") {
+            parse_unix(rest.strip_prefix(b"//").unwrap_or(rest))
+        } else {
+            // Fails, but with the error for the scheme.
+            s.to_string_lossy().parse()
+        }
+    }
+}
+
+impl TryFrom<OsString> for SocketAddr {
+    type Error = Error;
+
+    fn try_from(s: OsString) -> Result<Self, Self::Error> {
+        Self::try_from(s.as_os_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+
+    use serde::de::{EnumAccess, Error, SeqAccess, Unexpected, VariantAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    const NAME: &str = "SocketAddr";
+    const VARIANTS: &[&str] = &["Tcp", "Unix", "Abstract"];
+
+    /// Human-readable formats get the same string as [`Display`](fmt::Display),
+    /// which [`FromStr`] parses back to the same address.
+    ///
+    /// Other formats get an enum with the variants `Tcp`, holding the
+    /// [`std::net::SocketAddr`], and `Unix` and `Abstract`, holding the raw
+    /// bytes of the path or name. This keeps every named address intact.
+    ///
+    /// Unnamed unix addresses cannot be serialized in either form.
+    impl Serialize for SocketAddr {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            use serde::ser::Error;
+
+            if serializer.is_human_readable() {
+                return serialize_str(self, serializer);
+            }
+
+            let x = match self {
+                Self::Tcp(x) => return serializer.serialize_newtype_variant(NAME, 0, "Tcp", x),
+                Self::Unix(x) => std::os::unix::net::SocketAddr::from(x.clone()),
+            };
+
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            if let Some(name) = x.as_abstract_name() {
+                return serializer.serialize_newtype_variant(NAME, 2, "Abstract", &Bytes(name));
+            }
+
+            match x.as_pathname() {
+                Some(path) => {
+                    let path = Bytes(path.as_os_str().as_bytes());
+                    serializer.serialize_newtype_variant(NAME, 1, "Unix", &path)
+                }
+                None => Err(S::Error::custom("cannot serialize an unnamed unix address")),
+            }
+        }
+    }
+
+    fn serialize_str<S>(addr: &SocketAddr, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        use serde::ser::Error;
+
+        if addr.is_unnamed() {
+            return Err(S::Error::custom("cannot serialize an unnamed unix address"));
+        }
+
+        serializer.collect_str(addr)
+    }
+
+    /// Accepts the form [`Serialize`] produces for the format.
+    ///
+    /// Strings written by versions which only had the string form still load.
+    /// In human-readable formats they are the current form. Binary formats
+    /// which describe themselves present them as an unknown variant, and are
+    /// parsed from its name. Formats like bincode, which do not, read the
+    /// length of the string in place of the variant index, so as long as both
+    /// are encoded the same way, which is the case with varints, any index
+    /// past the known variants is taken as the length of a string.
+    impl<'de> Deserialize<'de> for SocketAddr {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            struct SocketAddrVisitor;
+
+            impl<'de> Visitor<'de> for SocketAddrVisitor {
+                type Value = SocketAddr;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a socket address")
+                }
+
+                fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                where
+                    E: Error,
+                {
+                    v.parse().map_err(Error::custom)
+                }
+
+                fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+                where
+                    E: Error,
+                {
+                    v.parse().map_err(Error::custom)
+                }
+
+                fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+                where
+                    A: EnumAccess<'de>,
+                {
+                    let (kind, variant) = data.variant()?;
+
+                    let addr = match kind {
+                        Kind::Tcp => return variant.newtype_variant().map(SocketAddr::Tcp),
+                        Kind::Str(x) => {
+                            variant.unit_variant()?;
+                            return self.visit_string(x);
+                        }
+                        Kind::StrLen(len) => {
+                            return variant.tuple_variant(len, LegacyStrVisitor(len));
+                        }
+                        Kind::Unix => {
+                            let ByteBuf(path) = variant.newtype_variant()?;
+                            unix_addr_from_pathname(Path::new(OsStr::from_bytes(&path)))
+                        }
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        Kind::Abstract => {
+                            let ByteBuf(name) = variant.newtype_variant()?;
+                            unix_addr_from_abstract_name(&name)
+                        }
+                        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                        Kind::Abstract => {
+                            return Err(Error::custom(
+                                "abstract unix addresses are not supported on this platform",
+                            ));
+                        }
+                    };
+
+                    addr.map(SocketAddr::Unix).map_err(Error::custom)
+                }
+            }
+
+            if deserializer.is_human_readable() {
+                deserializer.deserialize_str(SocketAddrVisitor)
+            } else {
+                deserializer.deserialize_enum(NAME, VARIANTS, SocketAddrVisitor)
+            }
+        }
+    }
+
+    /// Variant of the non-human-readable form.
+    enum Kind {
+        Tcp,
+        Unix,
+        Abstract,
+        /// An old string, in place of the variant name.
+        Str(String),
+        /// The length of an old string, in place of the variant index.
+        StrLen(usize),
+    }
+
+    impl<'de> Deserialize<'de> for Kind {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            struct KindVisitor;
+
+            impl Visitor<'_> for KindVisitor {
+                type Value = Kind;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a socket address variant")
+                }
+
+                fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+                where
+                    E: Error,
+                {
+                    match v {
+                        0 => Ok(Kind::Tcp),
+                        1 => Ok(Kind::Unix),
+                        2 => Ok(Kind::Abstract),
+                        _ => match usize::try_from(v) {
+                            Ok(len) => Ok(Kind::StrLen(len)),
+                            Err(_) => Err(E::invalid_value(Unexpected::Unsigned(v), &self)),
+                        },
+                    }
+                }
+
+                fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                where
+                    E: Error,
+                {
+                    match v {
+                        "Tcp" => Ok(Kind::Tcp),
+                        "Unix" => Ok(Kind::Unix),
+                        "Abstract" => Ok(Kind::Abstract),
+                        _ => Ok(Kind::Str(v.to_owned())),
+                    }
+                }
+            }
+
+            deserializer.deserialize_identifier(KindVisitor)
+        }
+    }
+
+    /// Reads the bytes of an old string whose length was read as the variant
+    /// index.
+    struct LegacyStrVisitor(usize);
+
+    impl<'de> Visitor<'de> for LegacyStrVisitor {
+        type Value = SocketAddr;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a socket address string of {} bytes", self.0)
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut v = Vec::with_capacity(self.0.min(SUN_PATH_LEN));
+            while let Some(x) = seq.next_element::<u8>()? {
+                v.push(x);
+            }
+
+            let s = String::from_utf8(v)
+                .map_err(|e| A::Error::invalid_value(Unexpected::Bytes(e.as_bytes()), &self))?;
+            s.parse().map_err(A::Error::custom)
+        }
+    }
+
+    struct Bytes<'a>(&'a [u8]);
+
+    impl Serialize for Bytes<'_> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    struct ByteBuf(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for ByteBuf {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            struct ByteBufVisitor;
+
+            impl<'de> Visitor<'de> for ByteBufVisitor {
+                type Value = ByteBuf;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a byte array")
+                }
+
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+                where
+                    E: Error,
+                {
+                    Ok(ByteBuf(v.to_vec()))
+                }
+
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+                where
+                    E: Error,
+                {
+                    Ok(ByteBuf(v))
+                }
+
+                fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                where
+                    A: SeqAccess<'de>,
+                {
+                    let mut v = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(SUN_PATH_LEN));
+                    while let Some(x) = seq.next_element()? {
+                        v.push(x);
+                    }
+
+                    Ok(ByteBuf(v))
+                }
+            }
+
+            deserializer.deserialize_byte_buf(ByteBufVisitor)
+        }
+    }
+}
+
+#[cfg(feature = "url")]
+mod url_impl {
+    use super::*;
+
+    use std::ffi::OsString;
+    use std::net::IpAddr;
+    use std::os::unix::ffi::OsStringExt;
+
+    use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, percent_encode};
+    use url::{Host, Url};
+
+    const PATH: &AsciiSet = &CONTROLS
+        .add(b' ')
+        .add(b'"')
+        .add(b'#')
+        .add(b'%')
+        .add(b'<')
+        .add(b'>')
+        .add(b'?')
+        .add(b'[')
+        .add(b'\\')
+        .add(b']')
+        .add(b'^')
+        .add(b'`')
+        .add(b'{')
+        .add(b'|')
+        .add(b'}');
+
+    fn decoded_path(url: &Url) -> Result<Vec<u8>> {
+        if url.host_str().is_some_and(|x| !x.is_empty()) {
+            return Err(AddrParseError::UnexpectedComponent("host").into());
+        }
+
+        Ok(percent_decode_str(url.path()).collect())
+    }
+
+    impl TryFrom<&Url> for SocketAddr {
+        type Error = Error;
+
+        /// Convert a [`Url`] to a socket address.
+        ///
+        /// Supported schemes are:
+        ///
+        /// - `tcp://<ip>:<port>`, `tcp4://<ip>:<port>` and `tcp6://<ip>:<port>`
+        /// - `unix://<path>` or `unix:<relative path>`, where the path is
+        ///   percent-decoded
+        /// - `unix+abstract:<name>` or `unix-abstract:<name>`, where the name
+        ///   is percent-decoded (Linux and Android only)
+        ///
+        /// Other schemes fail with [`AddrParseError::UnsupportedScheme`].
+        /// Userinfo, queries and fragments are rejected. Errors are of kind
+        /// [`ErrorKind::InvalidInput`](std::io::ErrorKind::InvalidInput) with an
+        /// [`AddrParseError`] inside, like those of [`FromStr`].
+        fn try_from(url: &Url) -> Result<Self, Self::Error> {
+            if !url.username().is_empty() || url.password().is_some() {
+                return Err(AddrParseError::UnexpectedComponent("userinfo").into());
+            }
+
+            if url.query().is_some() {
+                return Err(AddrParseError::UnexpectedComponent("query").into());
+            }
+
+            if url.fragment().is_some() {
+                return Err(AddrParseError::UnexpectedComponent("fragment").into());
+            }
+
+            match url.scheme() {
+                scheme @ ("tcp" | "tcp4" | "tcp6") => {
+                    let ip = match url.host() {
+                        Some(Host::Ipv4(x)) => IpAddr::V4(x),
+                        Some(Host::Ipv6(x)) => IpAddr::V6(x),
+                        Some(Host::Domain(x)) => {
+                            x.parse().map_err(AddrParseError::InvalidTcpAddr)?
+                        }
+                        None => return Err(AddrParseError::MissingHost.into()),
+                    };
+
+                    let port = url.port().ok_or(AddrParseError::MissingPort)?;
+
+                    if !matches!(url.path(), "" | "/") {
+                        return Err(AddrParseError::UnexpectedComponent("path").into());
+                    }
+
+                    let addr = std::net::SocketAddr::new(ip, port);
+                    if Family::from_scheme(scheme).is_some_and(|x| !x.matches(&addr)) {
+                        return Err(AddrParseError::WrongFamily.into());
+                    }
+
+                    Ok(SocketAddr::Tcp(addr))
+                }
+                "unix" => {
+                    let path = OsString::from_vec(decoded_path(url)?);
+                    unix_addr_from_pathname(Path::new(&path)).map(SocketAddr::Unix)
+                }
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                "unix+abstract" | "unix-abstract" => {
+                    let name = decoded_path(url)?;
+                    unix_addr_from_abstract_name(&name).map(SocketAddr::Unix)
+                }
+                x => Err(AddrParseError::UnsupportedScheme(x.to_owned()).into()),
+            }
+        }
+    }
+
+    impl TryFrom<Url> for SocketAddr {
+        type Error = Error;
+
+        fn try_from(url: Url) -> Result<Self, Self::Error> {
+            Self::try_from(&url)
+        }
+    }
+
+    impl SocketAddr {
+        /// Convert the address to its canonical [`Url`] form.
+        ///
+        /// This is the inverse of the [`TryFrom<&Url>`] conversion. Unnamed
+        /// unix addresses cannot be represented and return an error.
+        pub fn to_url(&self) -> Result<Url> {
+            let s = match self {
+                Self::Tcp(x) => format!("tcp://{x}"),
+                Self::Unix(x) => {
+                    let x = std::os::unix::net::SocketAddr::from(x.clone());
+
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    if let Some(name) = x.as_abstract_name() {
+                        let name = percent_encode(name, PATH);
+                        return Url::parse(&format!("unix+abstract:{name}")).map_err(Error::other);
+                    }
+
+                    let Some(path) = x.as_pathname() else {
+                        return Err(Error::other("cannot convert an unnamed address to a url"));
+                    };
+
+                    let encoded = percent_encode(path.as_os_str().as_bytes(), PATH);
+                    if path.is_absolute() {
+                        format!("unix://{encoded}")
+                    } else {
+                        format!("unix:{encoded}")
+                    }
+                }
+            };
+
+            Url::parse(&s).map_err(Error::other)
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impl {
+    use super::*;
+
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV6};
+
+    use arbitrary::{Arbitrary, Unstructured};
+
+    /// Generates IPv4 and IPv6 addresses, the latter with scope IDs, unix
+    /// paths, abstract names on Linux and Android, and unnamed unix addresses.
+    ///
+    /// Every generated address can be constructed, so paths never contain NUL
+    /// bytes and paths and names always fit in `sun_path`. IPv6 addresses have
+    /// no flow info, which [`Display`](fmt::Display) does not write, so every
+    /// named address parses back to itself with [`FromStr`].
+    impl<'a> Arbitrary<'a> for SocketAddr {
+        fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+            match u.int_in_range(0..=4)? {
+                0 => {
+                    let ip = Ipv4Addr::from(u.arbitrary::<[u8; 4]>()?);
+                    Ok(Self::tcp(ip, u.arbitrary()?))
+                }
+                1 => {
+                    let ip = Ipv6Addr::from(u.arbitrary::<[u8; 16]>()?);
+                    let x = SocketAddrV6::new(ip, u.arbitrary()?, 0, u.arbitrary()?);
+                    Ok(Self::Tcp(x.into()))
+                }
+                2 => {
+                    let len = u.int_in_range(1..=MAX_UNIX_PATH_LEN)?;
+                    let path = (0..len)
+                        .map(|_| u.int_in_range(1..=u8::MAX))
+                        .collect::<arbitrary::Result<Vec<u8>>>()?;
+
+                    Self::unix(OsStr::from_bytes(&path))
+                        .map_err(|_| arbitrary::Error::IncorrectFormat)
+                }
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                3 => {
+                    let len = u.int_in_range(1..=MAX_ABSTRACT_NAME_LEN)?;
+                    let name = (0..len)
+                        .map(|_| u.arbitrary())
+                        .collect::<arbitrary::Result<Vec<u8>>>()?;
+
+                    Self::unix_abstract(name).map_err(|_| arbitrary::Error::IncorrectFormat)
+                }
+                _ => std::os::unix::net::UnixDatagram::unbound()
+                    .and_then(|x| x.local_addr())
+                    .map(|x| Self::Unix(x.into()))
+                    .map_err(|_| arbitrary::Error::IncorrectFormat),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "schemars")]
+mod schemars_impl {
+    use super::*;
+
+    use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+
+    /// Matches what [`FromStr`] accepts. Schemes are case-insensitive.
+    const PATTERN: &str = concat!(
+        r"^([Tt][Cc][Pp][46]?://(\[[0-9A-Fa-f:.]+(%[^\]]+)?\]|[0-9.]+):[0-9]+",
+        r"|[Uu][Nn][Ii][Xx](-[Aa][Bb][Ss][Tt][Rr][Aa][Cc][Tt])?:.+)$",
+    );
+
+    /// Describes the string that human-readable formats use, see the
+    /// [`Serialize`](serde::Serialize) implementation.
+    impl JsonSchema for SocketAddr {
+        fn schema_name() -> Cow<'static, str> {
+            "SocketAddr".into()
+        }
+
+        fn schema_id() -> Cow<'static, str> {
+            concat!(module_path!(), "::SocketAddr").into()
+        }
+
+        fn json_schema(_: &mut SchemaGenerator) -> Schema {
+            json_schema!({
+                "type": "string",
+                "description": "A socket address: `tcp://<ip>:<port>` (IPv6 addresses in \
+                    brackets), `unix://<path>` or `unix://@<name>` for a name in the abstract \
+                    namespace. Unix paths and names are percent-decoded.",
+                "pattern": PATTERN,
+                "examples": [
+                    "tcp://127.0.0.1:8080",
+                    "tcp://[::1]:8080",
+                    "unix:///run/app.sock",
+                    "unix://@app",
+                ],
+            })
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Types that can be converted to one or more [`SocketAddr`]s.
+///
+/// [`to_socket_addrs`] never blocks on a lookup, so it fails for hostnames,
+/// like in `tcp://localhost:80`. Only the async methods resolve them:
+/// [`to_socket_addrs_async`] on tokio's blocking thread pool, and
+/// [`to_socket_addrs_with`] with a [`Resolve`] implementation. The async
+/// functions of this crate that take addresses, like [`Listener::bind`] and
+/// [`lookup`], use the async methods. [`Stream::connect`] and
+/// [`Stream::connect_with`] use [`to_socket_addrs_lazy`], the latter to
+/// resolve through [`ConnectOptions::resolver`]. The exception is [`Tcp`],
+/// whose [`to_socket_addrs`] resolves like the [`std::net::ToSocketAddrs`] it
+/// wraps.
+///
+/// The async methods return all addresses at once, since a hostname can
+/// resolve to any number of them.
+///
+/// Only [`to_socket_addrs`] has to be implemented. The async methods have
+/// defaults that convert without resolving, and the futures they return are
+/// [`Send`], so the futures of functions like [`Stream::connect`] can be
+/// spawned for any address type that is [`Send`] and [`Sync`]. The
+/// [`Iter`](ToSocketAddrs::Iter) of an implementation does not have to be.
+///
+/// [`to_socket_addrs`]: ToSocketAddrs::to_socket_addrs
+/// [`to_socket_addrs_async`]: ToSocketAddrs::to_socket_addrs_async
+/// [`to_socket_addrs_with`]: ToSocketAddrs::to_socket_addrs_with
+/// [`to_socket_addrs_lazy`]: ToSocketAddrs::to_socket_addrs_lazy
+/// [`Stream::connect_with`]: crate::Stream::connect_with
+/// [`ConnectOptions::resolver`]: crate::ConnectOptions::resolver
+/// [`Stream::connect`]: crate::Stream::connect
+/// [`Listener::bind`]: crate::Listener::bind
+pub trait ToSocketAddrs {
+    type Iter: Iterator<Item = SocketAddr>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter>;
+
+    /// Like [`ToSocketAddrs::to_socket_addrs`], but also resolve hostnames,
+    /// without blocking the current thread.
+    ///
+    /// The default implementation calls [`ToSocketAddrs::to_socket_addrs`],
+    /// which is enough for types that never need to resolve hostnames.
+    fn to_socket_addrs_async(
+        &self,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        let addrs = self.to_socket_addrs().map(collect);
+        std::future::ready(addrs)
+    }
+
+    /// Like [`ToSocketAddrs::to_socket_addrs_async`], but resolve hostnames
+    /// with `resolver`.
+    ///
+    /// The default implementation calls
+    /// [`ToSocketAddrs::to_socket_addrs_async`] and ignores `resolver`, which
+    /// is enough for types that never need to resolve hostnames.
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        let _ = resolver;
+        self.to_socket_addrs_async()
+    }
+
+    /// Like [`ToSocketAddrs::to_socket_addrs_with`], but resolve the
+    /// addresses in batches, each only once the addresses of the previous ones
+    /// have been used up.
+    ///
+    /// [`Stream::connect`] and [`Stream::connect_with`] use this, so batches
+    /// after the one with the address that connects are never resolved.
+    /// Batches that fail to resolve are skipped and reported in [`AddrErrors`]
+    /// if no address connects. The default implementation is a single batch
+    /// from [`ToSocketAddrs::to_socket_addrs_with`].
+    ///
+    /// [`Stream::connect`]: crate::Stream::connect
+    /// [`Stream::connect_with`]: crate::Stream::connect_with
+    fn to_socket_addrs_lazy<'a>(&'a self, resolver: &'a dyn Resolve) -> LazyAddrs<'a> {
+        let addrs = self.to_socket_addrs_with(resolver);
+        LazyAddrs::new([async move { addrs.await.map(Iterator::collect) }])
+    }
+}
+
+impl<T> ToSocketAddrs for &T
+where
+    T: ToSocketAddrs + ?Sized,
+{
+    type Iter = T::Iter;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        T::to_socket_addrs(self)
+    }
+
+    fn to_socket_addrs_async(
+        &self,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        T::to_socket_addrs_async(self)
+    }
+
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        T::to_socket_addrs_with(self, resolver)
+    }
+
+    fn to_socket_addrs_lazy<'a>(&'a self, resolver: &'a dyn Resolve) -> LazyAddrs<'a> {
+        T::to_socket_addrs_lazy(self, resolver)
+    }
+}
+
+fn collect(addrs: impl Iterator<Item = SocketAddr>) -> vec::IntoIter<SocketAddr> {
+    addrs.collect::<Vec<_>>().into_iter()
+}
+
+/// The future of one batch of [`LazyAddrs`].
+type Batch<'a> = Pin<Box<dyn Future<Output = Result<Vec<SocketAddr>>> + Send + 'a>>;
+
+/// Addresses that are resolved in batches, as they are needed.
+///
+/// Returned by [`ToSocketAddrs::to_socket_addrs_lazy`].
+pub struct LazyAddrs<'a> {
+    batches: Box<dyn Iterator<Item = Batch<'a>> + Send + 'a>,
+}
+
+impl<'a> LazyAddrs<'a> {
+    /// One batch for every future of `batches`, resolved in order.
+    pub fn new<I, F>(batches: I) -> Self
+    where
+        I: IntoIterator<Item = F>,
+        I::IntoIter: Send + 'a,
+        F: Future<Output = Result<Vec<SocketAddr>>> + Send + 'a,
+    {
+        Self {
+            batches: Box::new(batches.into_iter().map(|x| Box::pin(x) as Batch<'a>)),
+        }
+    }
+
+    /// Resolve the next batch, or get [`None`] if there are none left.
+    pub async fn next_batch(&mut self) -> Option<Result<Vec<SocketAddr>>> {
+        Some(self.batches.next()?.await)
+    }
+
+    /// Call `f` on every address in order until it succeeds.
+    ///
+    /// If it never does, the failures and the batches that could not be
+    /// resolved are returned as [`AddrErrors`].
+    pub(crate) async fn try_each<T, F, Fut>(mut self, mut f: F) -> Result<T>
+    where
+        F: FnMut(SocketAddr) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut errors = Vec::new();
+        let mut unresolved = Vec::new();
+
+        while let Some(batch) = self.next_batch().await {
+            let batch = match batch {
+                Ok(x) => x,
+                Err(e) => {
+                    unresolved.push(e);
+                    continue;
+                }
+            };
+
+            for addr in batch {
+                match f(addr.clone()).await {
+                    Ok(x) => return Ok(x),
+                    Err(e) => errors.push((addr, e)),
+                }
+            }
+        }
+
+        Err(AddrErrors::into_error_with(errors, unresolved))
+    }
+}
+
+impl fmt::Debug for LazyAddrs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyAddrs").finish_non_exhaustive()
+    }
+}
+
+/// Resolve `addr` to the list of addresses [`Stream::connect`] would try, in
+/// order, without connecting.
+///
+/// [`Stream::connect`]: crate::Stream::connect
+pub async fn lookup<A: ToSocketAddrs>(addr: A) -> Result<Vec<SocketAddr>> {
+    addr.to_socket_addrs_async().await.map(Iterator::collect)
+}
+
+/// The failures of all addresses that were tried.
+///
+/// When connecting or binding fails, the returned [`Error`] wraps an
+/// `AddrErrors` and has the kind of the last failure. It can be reached with
+/// [`Error::get_ref`] or [`Error::into_inner`] and a downcast. This is also
+/// the case if only one address was tried, so the address is never lost.
+///
+/// Addresses that could not be resolved, like a hostname of a [`FromIter`] or
+/// an [`AddrList`] that failed to resolve, count as failures too.
+#[derive(Debug)]
+pub struct AddrErrors {
+    errors: Vec<(SocketAddr, Error)>,
+    unresolved: Vec<Error>,
+}
+
+impl AddrErrors {
+    /// Each address with the error it failed with, in the order they failed.
+    #[must_use]
+    pub fn errors(&self) -> &[(SocketAddr, Error)] {
+        &self.errors
+    }
+
+    #[must_use]
+    pub fn into_errors(self) -> Vec<(SocketAddr, Error)> {
+        self.errors
+    }
+
+    /// The errors of addresses that could not be resolved, in order.
+    #[must_use]
+    pub fn unresolved(&self) -> &[Error] {
+        &self.unresolved
+    }
+
+    pub(crate) fn into_error(errors: Vec<(SocketAddr, Error)>) -> Error {
+        Self::into_error_with(errors, Vec::new())
+    }
+
+    pub(crate) fn into_error_with(
+        errors: Vec<(SocketAddr, Error)>,
+        unresolved: Vec<Error>,
+    ) -> Error {
+        let kind = match (errors.last(), unresolved.last()) {
+            (Some((_, e)), _) | (None, Some(e)) => e.kind(),
+            (None, None) => {
+                return Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "could not resolve to any addresses",
+                );
+            }
+        };
+
+        Error::new(kind, Self { errors, unresolved })
+    }
+}
+
+impl fmt::Display for AddrErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let ([(addr, e)], []) = (&*self.errors, &*self.unresolved) {
+            return write!(f, "{addr}: {e}");
+        }
+
+        write!(f, "all {} addresses failed", self.errors.len())?;
+
+        if !self.unresolved.is_empty() {
+            write!(f, " and {} could not be resolved", self.unresolved.len())?;
+        }
+
+        for (addr, e) in &self.errors {
+            write!(f, "; {addr}: {e}")?;
+        }
+
+        for e in &self.unresolved {
+            write!(f, "; {e}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for AddrErrors {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.errors.last() {
+            Some((_, e)) => Some(e),
+            None => self.unresolved.last().map(|e| e as _),
+        }
+    }
+}
+
+/// Adapter to pass any iterable of addresses where [`ToSocketAddrs`] is
+/// expected.
+///
+/// The elements are resolved lazily, one at a time, so an element is only
+/// resolved if all addresses of the previous ones have been consumed. With
+/// [`Stream::connect`](crate::Stream::connect) and
+/// [`Stream::connect_with`](crate::Stream::connect_with), which resolve
+/// through [`ToSocketAddrs::to_socket_addrs_lazy`], this means that elements
+/// after the first one that connects successfully are never resolved. Elements
+/// that fail to resolve are reported in [`AddrErrors`] if no address connects.
+///
+/// [`ToSocketAddrs::to_socket_addrs_async`] and
+/// [`ToSocketAddrs::to_socket_addrs_with`] resolve all elements, and only fail
+/// if none of them could be resolved. [`ToSocketAddrs::to_socket_addrs`] is
+/// lazy too, but skips elements that fail to convert, like hostnames.
+#[derive(Debug, Clone)]
+pub struct FromIter<I>(pub I);
+
+impl<I> ToSocketAddrs for FromIter<I>
+where
+    I: IntoIterator + Clone + Sync,
+    I::IntoIter: Send,
+    I::Item: ToSocketAddrs + Send + Sync,
+{
+    type Iter = FromIterAddrs<I::IntoIter>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Ok(FromIterAddrs {
+            items: self.0.clone().into_iter(),
+            current: None,
+        })
+    }
+
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        self.to_socket_addrs_with(&SystemResolver).await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        let mut addrs = self.to_socket_addrs_lazy(resolver);
+        let mut resolved = Vec::new();
+        let mut unresolved = Vec::new();
+
+        while let Some(batch) = addrs.next_batch().await {
+            match batch {
+                Ok(x) => resolved.extend(x),
+                Err(e) => unresolved.push(e),
+            }
+        }
+
+        if resolved.is_empty() {
+            return Err(AddrErrors::into_error_with(Vec::new(), unresolved));
+        }
+
+        Ok(resolved.into_iter())
+    }
+
+    fn to_socket_addrs_lazy<'a>(&'a self, resolver: &'a dyn Resolve) -> LazyAddrs<'a> {
+        LazyAddrs::new(self.0.clone().into_iter().map(move |item| async move {
+            item.to_socket_addrs_with(resolver)
+                .await
+                .map(Iterator::collect)
+        }))
+    }
+}
+/// The iterator returned by [`FromIter`].
+pub struct FromIterAddrs<I>
+where
+    I: Iterator,
+    I::Item: ToSocketAddrs,
+{
+    items: I,
+    current: Option<<I::Item as ToSocketAddrs>::Iter>,
+}
+
+impl<I> Iterator for FromIterAddrs<I>
+where
+    I: Iterator,
+    I::Item: ToSocketAddrs,
+{
+    type Item = SocketAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(addr) = self.current.as_mut().and_then(Iterator::next) {
+                return Some(addr);
+            }
+
+            let item = self.items.next()?;
+            self.current = item.to_socket_addrs().ok();
+        }
+    }
+}
+
+impl<I> fmt::Debug for FromIterAddrs<I>
+where
+    I: Iterator,
+    I::Item: ToSocketAddrs,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FromIterAddrs").finish_non_exhaustive()
+    }
+}
+
+/// Adapter to pass anything that implements [`std::net::ToSocketAddrs`] where
+/// [`ToSocketAddrs`] is expected, like `Tcp("localhost:8080")`.
+///
+/// The resolved addresses are TCP addresses. Resolution goes through the
+/// standard library, so [`ToSocketAddrs::to_socket_addrs`] may block the
+/// current thread on a hostname. [`ToSocketAddrs::to_socket_addrs_async`] runs
+/// it on tokio's blocking thread pool with a clone of the value, and
+/// [`ToSocketAddrs::to_socket_addrs_with`] ignores the resolver. Prefer
+/// `tcp://<host>:<port>` or a `(host, port)` tuple to resolve through a
+/// [`Resolve`] implementation.
+#[derive(Debug, Clone, Copy)]
+pub struct Tcp<T>(pub T);
+
+impl<T> Tcp<T>
+where
+    T: std::net::ToSocketAddrs,
+{
+    fn resolve(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        let addrs: Vec<_> = std::net::ToSocketAddrs::to_socket_addrs(&self.0)?
+            .map(SocketAddr::Tcp)
+            .collect();
+
+        Ok(addrs.into_iter())
+    }
+}
+
+impl<T> ToSocketAddrs for Tcp<T>
+where
+    T: std::net::ToSocketAddrs + Clone + Send + 'static,
+{
+    type Iter = vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        self.resolve()
+    }
+
+    fn to_socket_addrs_async(
+        &self,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        let this = Tcp(self.0.clone());
+        async move { tokio::task::spawn_blocking(move || this.resolve()).await? }
+    }
+}
+
+/// A range of TCP ports on one IP address, like `tcp://127.0.0.1:8000-8100`.
+///
+/// As [`ToSocketAddrs`], this is every port of the range in order, produced
+/// lazily, so [`Listener::bind`](crate::Listener::bind) binds the first free
+/// one. Use [`Listener::bind_range`](crate::Listener::bind_range) for an error
+/// that names the range when all of them are taken.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PortRange {
+    ip: IpAddr,
+    ports: RangeInclusive<u16>,
+}
+
+impl PortRange {
+    /// The `ports` of `ip`, including both ends.
+    ///
+    /// Fails with [`AddrParseError::InvalidPortRange`] if the range is
+    /// reversed.
+    pub fn new(ip: impl Into<IpAddr>, ports: RangeInclusive<u16>) -> Result<Self> {
+        if ports.start() > ports.end() {
+            return Err(AddrParseError::InvalidPortRange.into());
+        }
+
+        Ok(Self {
+            ip: ip.into(),
+            ports,
+        })
+    }
+
+    #[must_use]
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    #[must_use]
+    pub fn ports(&self) -> RangeInclusive<u16> {
+        self.ports.clone()
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, end) = (self.ports.start(), self.ports.end());
+
+        match self.ip {
+            IpAddr::V4(ip) => write!(f, "tcp://{ip}:{start}-{end}"),
+            IpAddr::V6(ip) => write!(f, "tcp://[{ip}]:{start}-{end}"),
+        }
+    }
+}
+
+/// Parses `tcp://<ip>:<start>-<end>`, where IPv6 addresses are enclosed in
+/// brackets. Like with [`SocketAddr`], `tcp4://` and `tcp6://` only accept
+/// IPv4 and IPv6 addresses respectively. Both ends of the range are included.
+impl FromStr for PortRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((family, rest)) = s
+            .split_once("://")
+            .and_then(|(scheme, rest)| Some((Family::from_scheme(scheme)?, rest)))
+        else {
+            return Err(AddrParseError::InvalidScheme.into());
+        };
+
+        let (host, ports) = rest.rsplit_once(':').ok_or(AddrParseError::MissingPort)?;
+
+        let host = host
+            .strip_prefix('[')
+            .and_then(|x| x.strip_suffix(']'))
+            .unwrap_or(host);
+        let ip: IpAddr = host.parse().map_err(AddrParseError::InvalidTcpAddr)?;
+
+        if !family.matches(&std::net::SocketAddr::new(ip, 0)) {
+            return Err(AddrParseError::WrongFamily.into());
+        }
+
+        let (start, end) = ports
+            .split_once('-')
+            .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+            .ok_or(AddrParseError::InvalidPortRange)?;
+
+        Self::new(ip, start..=end)
+    }
+}
+
+impl ToSocketAddrs for PortRange {
+    type Iter = PortRangeAddrs;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Ok(PortRangeAddrs {
+            ip: self.ip,
+            ports: self.ports.clone(),
+        })
     }
 }
 
-impl fmt::Debug for SocketAddr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Tcp(x) => write!(f, "tcp://{x}"),
-            Self::Unix(x) => {
-                let x = std::os::unix::net::SocketAddr::from(x.clone());
+/// The iterator returned by [`PortRange`].
+#[derive(Debug, Clone)]
+pub struct PortRangeAddrs {
+    ip: IpAddr,
+    ports: RangeInclusive<u16>,
+}
 
-                if x.is_unnamed() {
-                    return f.write_str("(unnamed unix socket)");
-                }
+impl Iterator for PortRangeAddrs {
+    type Item = SocketAddr;
 
-                f.write_str("unix://")?;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ports.next().map(|port| SocketAddr::tcp(self.ip, port))
+    }
 
-                #[cfg(any(target_os = "linux", target_os = "android"))]
-                if let Some(p) = x.as_abstract_name() {
-                    let name = OsStr::from_bytes(p);
-                    return write!(f, "@{}", name.display());
-                }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ports.size_hint()
+    }
+}
+
+impl ExactSizeIterator for PortRangeAddrs {}
+
+/// A list of addresses to try in order, like
+/// `unix:///run/app.sock,tcp://127.0.0.1:9000`.
+///
+/// Entries are separated by commas or whitespace. Every comma-separated part
+/// must contain at least one entry, so `a,,b` and a trailing comma are
+/// [`AddrParseError::EmptyListEntry`] errors. Each entry is anything
+/// [`ToSocketAddrs`] accepts for strings, including `tcp://<host>:<port>`, and
+/// is checked when the list is parsed. A comma in a unix path must be written
+/// as `%2C`.
+///
+/// As [`ToSocketAddrs`], the addresses of all entries are returned in order.
+/// Entries that fail to resolve are skipped, so a later one can still be
+/// connected to. [`Stream::connect`](crate::Stream::connect) and
+/// [`Stream::connect_with`](crate::Stream::connect_with) resolve each entry
+/// only when the previous ones failed, and report the entries that failed to
+/// resolve in [`AddrErrors`] if no address connects. If no entry resolves,
+/// resolving fails with the errors of all entries in an [`AddrErrors`].
+///
+/// [`Display`](fmt::Display) writes the entries as they were given, separated
+/// by commas, which parses back to the same list.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AddrList {
+    entries: Vec<String>,
+}
 
-                let path = x
-                    .as_pathname()
-                    .expect("path should be Some because x is named");
+impl AddrList {
+    /// The entries in order, as they were given.
+    #[must_use]
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    fn merge(
+        results: impl IntoIterator<Item = Result<vec::IntoIter<SocketAddr>>>,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        let mut addrs = Vec::new();
+        let mut unresolved = Vec::new();
 
-                write!(f, "{}", path.display())
+        for result in results {
+            match result {
+                Ok(x) => addrs.extend(x),
+                Err(e) => unresolved.push(e),
             }
         }
+
+        if addrs.is_empty() && !unresolved.is_empty() {
+            return Err(AddrErrors::into_error_with(Vec::new(), unresolved));
+        }
+
+        Ok(addrs.into_iter())
     }
 }
 
-impl fmt::Display for SocketAddr {
+impl fmt::Display for AddrList {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i != 0 {
+                f.write_char(',')?;
+            }
+
+            f.write_str(entry)?;
+        }
+
+        Ok(())
     }
 }
 
-impl FromStr for SocketAddr {
+impl FromStr for AddrList {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some(x) = s.strip_prefix("tcp://") {
-            x.parse().map(SocketAddr::Tcp).map_err(Error::other)
-        } else if let Some(x) = s.strip_prefix("unix://") {
-            fn parse_unix_addr(x: &str) -> Result<tokio::net::unix::SocketAddr> {
-                #[cfg(any(target_os = "linux", target_os = "android"))]
-                if let Some(x) = x.strip_prefix('@') {
-                    return std::os::unix::net::SocketAddr::from_abstract_name(x.as_bytes())
-                        .map(Into::into)
-                        .map_err(Error::other);
-                }
+        let mut entries = Vec::new();
 
-                std::os::unix::net::SocketAddr::from_pathname(x)
-                    .map(Into::into)
-                    .map_err(Error::other)
+        for part in s.split(',') {
+            if part.trim().is_empty() {
+                return Err(AddrParseError::EmptyListEntry.into());
             }
 
-            parse_unix_addr(x).map(SocketAddr::Unix)
-        } else {
-            Err(Error::other("invalid scheme"))
+            for entry in part.split_whitespace() {
+                // Hostnames are only resolved when connecting.
+                let _ = parse_or_hostname(entry)?;
+                entries.push(entry.to_owned());
+            }
         }
+
+        Ok(Self { entries })
     }
 }
 
-impl TryFrom<&str> for SocketAddr {
-    type Error = Error;
+impl ToSocketAddrs for AddrList {
+    type Iter = vec::IntoIter<SocketAddr>;
 
-    fn try_from(s: &str) -> Result<Self, Self::Error> {
-        s.parse()
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Self::merge(
+            self.entries
+                .iter()
+                .map(|x| x.to_socket_addrs().map(collect)),
+        )
     }
-}
 
-impl TryFrom<String> for SocketAddr {
-    type Error = Error;
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        self.to_socket_addrs_with(&SystemResolver).await
+    }
 
-    fn try_from(s: String) -> Result<Self, Self::Error> {
-        s.parse()
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        let mut results = Vec::new();
+
+        for entry in &self.entries {
+            results.push(entry.to_socket_addrs_with(resolver).await);
+        }
+
+        Self::merge(results)
+    }
+
+    fn to_socket_addrs_lazy<'a>(&'a self, resolver: &'a dyn Resolve) -> LazyAddrs<'a> {
+        LazyAddrs::new(self.entries.iter().map(move |entry| async move {
+            entry
+                .to_socket_addrs_with(resolver)
+                .await
+                .map(Iterator::collect)
+        }))
     }
 }
 
-#[cfg(feature = "serde")]
-mod serde_impl {
-    use super::*;
+///////////////////////////////////////////////////////////////////////////////
 
-    use serde::de::{Error, Visitor};
-    use serde::{Deserialize, Deserializer};
+impl ToSocketAddrs for SocketAddr {
+    type Iter = iter::Once<SocketAddr>;
 
-    impl<'de> Deserialize<'de> for SocketAddr {
-        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-        where
-            D: Deserializer<'de>,
-        {
-            struct SocketAddrVisitor;
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Ok(iter::once(self.clone()))
+    }
+}
 
-            impl<'de> Visitor<'de> for SocketAddrVisitor {
-                type Value = SocketAddr;
+/// Accepts everything [`SocketAddr::from_str`] does, plus
+/// `tcp://<host>:<port>` where `<host>` is a hostname. The `tcp4://` and
+/// `tcp6://` schemes work with hostnames too.
+///
+/// Hostnames and `tcp+srv://` names are only resolved by the async methods.
+/// Every resolved address of a hostname is returned, in the order the resolver
+/// returned them. With `tcp4://` and `tcp6://`, addresses of the other family
+/// are left out, and it is an error if none remain.
+impl ToSocketAddrs for str {
+    type Iter = iter::Once<SocketAddr>;
 
-                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                    f.write_str("a socket address")
-                }
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        if srv_name(self).is_some() {
+            return Err(needs_lookup(self));
+        }
 
-                fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-                where
-                    E: Error,
-                {
-                    v.parse().map_err(Error::custom)
-                }
+        match parse_or_hostname(self)? {
+            Ok(addr) => Ok(iter::once(addr)),
+            Err(_) => Err(needs_lookup(self)),
+        }
+    }
 
-                fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
-                where
-                    E: Error,
-                {
-                    v.parse().map_err(Error::custom)
-                }
-            }
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        self.to_socket_addrs_with(&SystemResolver).await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        if let Some(name) = srv_name(self) {
+            let addrs = crate::resolve::resolve_srv(resolver, name).await?;
+            return Ok(addrs
+                .into_iter()
+                .map(SocketAddr::Tcp)
+                .collect::<Vec<_>>()
+                .into_iter());
+        }
 
-            deserializer.deserialize_str(SocketAddrVisitor)
+        match parse_or_hostname(self)? {
+            Ok(addr) => Ok(vec![addr].into_iter()),
+            Err((family, host, port)) => {
+                let addrs = resolver.resolve(host, port).await;
+                resolve(family, host, port, addrs.map(Vec::into_iter))
+            }
         }
     }
 }
 
-///////////////////////////////////////////////////////////////////////////////
+impl ToSocketAddrs for String {
+    type Iter = iter::Once<SocketAddr>;
 
-pub trait ToSocketAddrs {
-    type Iter: Iterator<Item = SocketAddr>;
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        self.as_str().to_socket_addrs()
+    }
 
-    fn to_socket_addrs(&self) -> Result<Self::Iter>;
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        self.as_str().to_socket_addrs_async().await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        self.as_str().to_socket_addrs_with(resolver).await
+    }
 }
 
-impl<T> ToSocketAddrs for &T
-where
-    T: ToSocketAddrs + ?Sized,
-{
-    type Iter = T::Iter;
+impl ToSocketAddrs for Cow<'_, str> {
+    type Iter = iter::Once<SocketAddr>;
 
     fn to_socket_addrs(&self) -> Result<Self::Iter> {
-        T::to_socket_addrs(self)
+        (**self).to_socket_addrs()
+    }
+
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        (**self).to_socket_addrs_async().await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        (**self).to_socket_addrs_with(resolver).await
     }
 }
 
-///////////////////////////////////////////////////////////////////////////////
+/// Like [`str`] if the string is valid UTF-8. Otherwise, the address is
+/// parsed with [`SocketAddr::try_from`], which keeps the bytes of unix paths
+/// and abstract names.
+impl ToSocketAddrs for OsStr {
+    type Iter = iter::Once<SocketAddr>;
 
-impl ToSocketAddrs for SocketAddr {
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        match self.to_str() {
+            Some(x) => x.to_socket_addrs(),
+            None => SocketAddr::try_from(self).map(iter::once),
+        }
+    }
+
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        self.to_socket_addrs_with(&SystemResolver).await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        match self.to_str() {
+            Some(x) => x.to_socket_addrs_with(resolver).await,
+            None => SocketAddr::try_from(self).map(|x| vec![x].into_iter()),
+        }
+    }
+}
+
+impl ToSocketAddrs for OsString {
     type Iter = iter::Once<SocketAddr>;
 
     fn to_socket_addrs(&self) -> Result<Self::Iter> {
-        Ok(iter::once(self.clone()))
+        self.as_os_str().to_socket_addrs()
+    }
+
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        self.as_os_str().to_socket_addrs_async().await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        self.as_os_str().to_socket_addrs_with(resolver).await
     }
 }
 
-impl ToSocketAddrs for str {
+/// Resolves `host` to TCP addresses, like `tcp://<host>:<port>`. As with
+/// [`str`], hostnames are only resolved by the async methods.
+impl ToSocketAddrs for (&str, u16) {
     type Iter = iter::Once<SocketAddr>;
 
     fn to_socket_addrs(&self) -> Result<Self::Iter> {
-        self.parse().map(iter::once)
+        let (host, port) = *self;
+
+        match host.parse::<IpAddr>() {
+            Ok(ip) => Ok(iter::once(SocketAddr::tcp(ip, port))),
+            Err(_) => Err(needs_lookup(&format!("{host}:{port}"))),
+        }
+    }
+
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        self.to_socket_addrs_with(&SystemResolver).await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        let (host, port) = *self;
+
+        // IP literals never reach the resolver.
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::tcp(ip, port)].into_iter());
+        }
+
+        let addrs = resolver.resolve(host, port).await;
+        resolve(Family::Any, host, port, addrs.map(Vec::into_iter))
     }
 }
 
-impl ToSocketAddrs for String {
+/// Resolves `host` to TCP addresses, like `tcp://<host>:<port>`.
+impl ToSocketAddrs for (String, u16) {
     type Iter = iter::Once<SocketAddr>;
 
     fn to_socket_addrs(&self) -> Result<Self::Iter> {
-        self.parse().map(iter::once)
+        (self.0.as_str(), self.1).to_socket_addrs()
+    }
+
+    async fn to_socket_addrs_async(&self) -> Result<vec::IntoIter<SocketAddr>> {
+        (self.0.as_str(), self.1).to_socket_addrs_async().await
+    }
+
+    async fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> Result<vec::IntoIter<SocketAddr>> {
+        (self.0.as_str(), self.1)
+            .to_socket_addrs_with(resolver)
+            .await
+    }
+}
+
+/// The address family required by a `tcp://`, `tcp4://` or `tcp6://` scheme.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Family {
+    Any,
+    V4,
+    V6,
+}
+
+impl Family {
+    fn from_scheme(scheme: &str) -> Option<Self> {
+        if scheme.eq_ignore_ascii_case("tcp") {
+            Some(Self::Any)
+        } else if scheme.eq_ignore_ascii_case("tcp4") {
+            Some(Self::V4)
+        } else if scheme.eq_ignore_ascii_case("tcp6") {
+            Some(Self::V6)
+        } else {
+            None
+        }
+    }
+
+    fn matches(self, addr: &std::net::SocketAddr) -> bool {
+        match self {
+            Self::Any => true,
+            Self::V4 => addr.is_ipv4(),
+            Self::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// Parse `s` as an address, or get the `<host>:<port>` part if it is a TCP
+/// address with a hostname.
+fn parse_or_hostname(s: &str) -> Result<Result<SocketAddr, (Family, &str, u16)>> {
+    match s.parse() {
+        Ok(addr) => Ok(Ok(addr)),
+        Err(e) => tcp_hostname(s).map(Err).ok_or(e),
+    }
+}
+
+fn resolve<I>(
+    family: Family,
+    host: &str,
+    port: u16,
+    addrs: Result<I>,
+) -> Result<vec::IntoIter<SocketAddr>>
+where
+    I: Iterator<Item = std::net::SocketAddr>,
+{
+    let addrs = addrs
+        .map_err(|e| Error::new(e.kind(), format!("failed to resolve {host}:{port}: {e}")))?
+        .collect::<Vec<_>>();
+
+    let any = !addrs.is_empty();
+    let addrs: Vec<_> = addrs
+        .into_iter()
+        .filter(|addr| family.matches(addr))
+        .map(SocketAddr::Tcp)
+        .collect();
+
+    if any && addrs.is_empty() {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("no addresses of requested family for {host}:{port}"),
+        ));
+    }
+
+    Ok(addrs.into_iter())
+}
+
+/// The error of [`ToSocketAddrs::to_socket_addrs`] for an address that needs
+/// a lookup.
+fn needs_lookup(name: &str) -> Error {
+    Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{name} needs a lookup, which only to_socket_addrs_async does"),
+    )
+}
+
+/// Get the name of a `tcp+srv://<name>` address.
+fn srv_name(s: &str) -> Option<&str> {
+    let (scheme, name) = s.split_once("://")?;
+    scheme.eq_ignore_ascii_case("tcp+srv").then_some(name)
+}
+
+/// Get the host and port of a TCP address if the host looks like a hostname
+/// and not a malformed IP literal.
+pub(crate) fn tcp_hostname(s: &str) -> Option<(Family, &str, u16)> {
+    let (scheme, rest) = s.split_once("://")?;
+    let family = Family::from_scheme(scheme)?;
+
+    let (host, port) = rest.rsplit_once(':')?;
+    let port = port.parse::<u16>().ok()?;
+    if host.is_empty() || host.contains([':', '[', ']']) {
+        return None;
+    }
+
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return None;
+    }
+
+    Some((family, host, port))
+}
+
+/// Get the addresses of all `items`, in order, from the futures `f` returns.
+///
+/// The futures are created up front, so the returned future does not hold a
+/// reference to `items` and is [`Send`] without `T: Sync`.
+fn concat<'a, T, F>(
+    items: &'a [T],
+    f: impl FnMut(&'a T) -> F,
+) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send
+where
+    F: Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send,
+{
+    let futures: Vec<_> = items.iter().map(f).collect();
+
+    async move {
+        let mut addrs = Vec::new();
+
+        for future in futures {
+            addrs.extend(future.await?);
+        }
+
+        Ok(addrs.into_iter())
     }
 }
 
@@ -224,6 +2292,67 @@ where
 
         Ok(addrs.into_iter())
     }
+
+    fn to_socket_addrs_async(
+        &self,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        concat(self, T::to_socket_addrs_async)
+    }
+
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        concat(self, |x| x.to_socket_addrs_with(resolver))
+    }
+}
+
+impl<T> ToSocketAddrs for Vec<T>
+where
+    T: ToSocketAddrs,
+{
+    type Iter = vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        self.as_slice().to_socket_addrs()
+    }
+
+    fn to_socket_addrs_async(
+        &self,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        concat(self, T::to_socket_addrs_async)
+    }
+
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        concat(self, |x| x.to_socket_addrs_with(resolver))
+    }
+}
+
+impl<T, const N: usize> ToSocketAddrs for [T; N]
+where
+    T: ToSocketAddrs,
+{
+    type Iter = vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        self.as_slice().to_socket_addrs()
+    }
+
+    fn to_socket_addrs_async(
+        &self,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        concat(self, T::to_socket_addrs_async)
+    }
+
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &dyn Resolve,
+    ) -> impl Future<Output = Result<vec::IntoIter<SocketAddr>>> + Send {
+        concat(self, |x| x.to_socket_addrs_with(resolver))
+    }
 }
 
 impl ToSocketAddrs for std::net::SocketAddr {
@@ -280,23 +2409,48 @@ impl ToSocketAddrs for (std::net::Ipv6Addr, u16) {
     }
 }
 
+/// The path of a unix socket, taken as is, with one exception: on Linux and
+/// Android, a path whose first component is exactly `@`, like `@/name`, is the
+/// abstract name `name`. Paths that merely start with `@`, like `@app.sock`,
+/// are paths. Write `./@/name` for a path in a directory called `@`, or use
+/// [`SocketAddr::unix`], which never interprets the path.
 impl ToSocketAddrs for std::path::Path {
     type Iter = iter::Once<SocketAddr>;
 
     fn to_socket_addrs(&self) -> Result<Self::Iter> {
         #[cfg(any(target_os = "linux", target_os = "android"))]
         if let Ok(path) = self.strip_prefix("@") {
-            return std::os::unix::net::SocketAddr::from_abstract_name(path.as_os_str().as_bytes())
-                .map(Into::into)
+            return unix_addr_from_abstract_name(path.as_os_str().as_bytes())
                 .map(SocketAddr::Unix)
-                .map(iter::once)
-                .map_err(Error::other);
+                .map(iter::once);
         }
 
-        std::os::unix::net::SocketAddr::from_pathname(self)
-            .map(Into::into)
+        unix_addr_from_pathname(self)
             .map(SocketAddr::Unix)
             .map(iter::once)
-            .map_err(Error::other)
+    }
+}
+
+impl ToSocketAddrs for std::path::PathBuf {
+    type Iter = iter::Once<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        self.as_path().to_socket_addrs()
+    }
+}
+
+impl ToSocketAddrs for tokio::net::unix::SocketAddr {
+    type Iter = iter::Once<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Ok(iter::once(SocketAddr::Unix(self.clone())))
+    }
+}
+
+impl ToSocketAddrs for std::os::unix::net::SocketAddr {
+    type Iter = iter::Once<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> Result<Self::Iter> {
+        Ok(iter::once(SocketAddr::Unix(self.clone().into())))
     }
 }
@@ -1,22 +1,447 @@
+use std::collections::hash_map::RandomState;
 use std::fmt;
-use std::io::{Error, IoSlice, IoSliceMut};
-use std::os::fd::{AsFd, AsRawFd};
+use std::fs::File;
+use std::future::poll_fn;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Error, ErrorKind, IoSlice, IoSliceMut};
+use std::mem::MaybeUninit;
+use std::net::{Ipv4Addr, Shutdown};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::fs::FileExt;
 use std::os::unix::prelude::{BorrowedFd, RawFd};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use pin_project::pin_project;
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
+use rustix::io::Errno;
+use rustix::net::{
+    RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, ReturnFlags, SendAncillaryBuffer,
+    SendAncillaryMessage, SendFlags,
+};
+use socket2::{Domain, SockRef, TcpKeepalive};
 use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf, Ready};
+use tokio::time::{Instant, sleep};
 
-use crate::utils::{Result, into2, unix_addr_to_path};
-use crate::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, SocketAddr, ToSocketAddrs, WriteHalf};
+use crate::proxy::Host;
+use crate::trace;
+#[cfg(target_os = "linux")]
+use crate::utils::is_mptcp;
+use crate::utils::{
+    Result, check_socket, drain_until_closed, fmt_connection, into2, poll_peek_with, recv_peek,
+    socket_cookie, unix_addr_to_path, unsupported,
+};
+use crate::{
+    AddrErrors, BufferedStream, Limit, Limiter, MeteredStream, OwnedReadHalf, OwnedWriteHalf,
+    Proxy, ProxyHeader, ReadHalf, Resolve, Socket, SocketAddr, SystemResolver, ThrottledStream,
+    TimedStream, ToSocketAddrs, WriteHalf,
+};
+#[cfg(feature = "test-util")]
+use crate::{FaultPlan, FaultyStream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// How [`Stream::connect_with`] creates its socket.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ConnectOptions {
+    /// Interface to send and receive through (`SO_BINDTODEVICE`).
+    ///
+    /// See [`Socket::bind_device`].
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub device: Option<String>,
+
+    /// Mark of outgoing packets (`SO_MARK`).
+    ///
+    /// See [`Socket::set_mark`].
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub mark: Option<u32>,
+
+    /// Type-of-service field of outgoing packets (`IP_TOS`).
+    ///
+    /// See [`Socket::set_tos`].
+    pub tos: Option<u32>,
+
+    /// Connect with Multipath TCP, falling back to plain TCP if the kernel
+    /// does not support it.
+    ///
+    /// See [`Socket::new_mptcp_for`]. Connecting to a unix address fails with
+    /// [`ErrorKind::InvalidInput`] if this is set.
+    #[cfg(target_os = "linux")]
+    pub mptcp: bool,
+
+    /// Connect with TCP Fast Open (`TCP_FASTOPEN_CONNECT`).
+    ///
+    /// See [`Socket::set_fastopen_connect`]. Connecting to a unix address
+    /// fails with [`ErrorKind::Unsupported`] if this is set.
+    #[cfg(target_os = "linux")]
+    pub fastopen: bool,
+
+    /// Address to bind the socket to before connecting.
+    ///
+    /// For TCP, this picks the source IP address and, unless the port is 0,
+    /// the source port. A unix socket bound to a path or abstract name is
+    /// seen by the peer as connecting from that address. The address must be
+    /// of the same family as the one connected to, or connecting to it fails
+    /// with [`ErrorKind::InvalidInput`] before anything is sent.
+    pub local_addr: Option<SocketAddr>,
+
+    /// Proxy to tunnel the connection through.
+    ///
+    /// The socket options above apply to the connection to the proxy. Only
+    /// TCP addresses can be reached through a proxy.
+    pub proxy: Option<Proxy>,
+
+    /// Resolver for hostnames in the address connected to.
+    ///
+    /// It is asked again on every connect, so retries see fresh records
+    /// unless the resolver caches them itself. `None` uses
+    /// [`SystemResolver`]. Hostnames sent to a proxy by
+    /// [`Stream::connect_host_with`] are resolved by the proxy instead.
+    pub resolver: Option<Arc<dyn Resolve>>,
+
+    /// Order in which the addresses are tried.
+    pub strategy: ConnectStrategy,
+
+    /// Order of the addresses that [`ConnectOptions::strategy`] ranks the
+    /// same.
+    ///
+    /// The strategy sorts first, then each group of addresses it considers
+    /// equal is put in this order on its own. With
+    /// [`ConnectStrategy::PreferUnix`] and [`ConnectStrategy::PreferTcp`],
+    /// unix and TCP addresses are the two groups, so
+    /// [`CandidateOrder::Shuffle`] spreads connections over the preferred
+    /// addresses but never tries a TCP address before a unix one. The other
+    /// strategies treat all addresses as one group, and the order is applied
+    /// after theirs.
+    pub candidate_order: CandidateOrder,
+}
+
+impl ConnectOptions {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[must_use]
+    pub fn device(mut self, device: impl Into<String>) -> Self {
+        self.device = Some(device.into());
+        self
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[must_use]
+    pub fn mark(mut self, mark: u32) -> Self {
+        self.mark = Some(mark);
+        self
+    }
+
+    #[must_use]
+    pub fn tos(mut self, tos: u32) -> Self {
+        self.tos = Some(tos);
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn mptcp(mut self, mptcp: bool) -> Self {
+        self.mptcp = mptcp;
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn fastopen(mut self, fastopen: bool) -> Self {
+        self.fastopen = fastopen;
+        self
+    }
+
+    #[must_use]
+    pub fn local_addr(mut self, addr: SocketAddr) -> Self {
+        self.local_addr = Some(addr);
+        self
+    }
+
+    #[must_use]
+    pub fn proxy(mut self, proxy: impl Into<Proxy>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    #[must_use]
+    pub fn resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    #[must_use]
+    pub fn strategy(mut self, strategy: ConnectStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    #[must_use]
+    pub fn candidate_order(mut self, order: CandidateOrder) -> Self {
+        self.candidate_order = order;
+        self
+    }
+
+    /// Put `addrs` in the order they are tried, see
+    /// [`ConnectOptions::candidate_order`].
+    fn order(&self, addrs: &mut [SocketAddr]) {
+        self.strategy.apply(addrs);
+
+        let start = match &self.candidate_order {
+            CandidateOrder::AsResolved => return,
+            CandidateOrder::Shuffle => 0,
+            CandidateOrder::RoundRobin(x) => x.next(),
+        };
+
+        let rank = |x: &SocketAddr| match self.strategy {
+            ConnectStrategy::PreferUnix | ConnectStrategy::PreferTcp => x.is_unix(),
+            _ => false,
+        };
+
+        for group in addrs.chunk_by_mut(|a, b| rank(a) == rank(b)) {
+            match &self.candidate_order {
+                CandidateOrder::AsResolved => {}
+                CandidateOrder::Shuffle => shuffle(group),
+                CandidateOrder::RoundRobin(_) => group.rotate_left(start % group.len()),
+            }
+        }
+    }
+
+    fn apply(&self, socket: &Socket) -> Result<()> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            if let Some(device) = &self.device {
+                socket.bind_device(Some(device))?;
+            }
+
+            if let Some(mark) = self.mark {
+                socket.set_mark(mark)?;
+            }
+        }
+
+        if let Some(tos) = self.tos {
+            socket.set_tos(tos)?;
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.fastopen {
+            socket.set_fastopen_connect(true)?;
+        }
+
+        if let Some(addr) = &self.local_addr {
+            socket.bind(addr)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Order in which [`Stream::connect_with`] tries the addresses it resolved.
+///
+/// The first address that accepts the connection is used, so the order
+/// decides which address gets the connection when several are up. For
+/// addresses that are resolved in batches, like the elements of a
+/// [`FromIter`](crate::FromIter), each batch is ordered on its own.
+#[derive(Debug, Clone, Default)]
+pub enum ConnectStrategy {
+    /// Try the addresses in the order they were resolved.
+    #[default]
+    InOrder,
+    /// Try the addresses in a random order.
+    Shuffle,
+    /// Start at the next address for every connect sharing the handle, so
+    /// connections are spread over all addresses.
+    RoundRobin(RoundRobin),
+    /// Try unix addresses before TCP addresses, otherwise in order.
+    PreferUnix,
+    /// Try TCP addresses before unix addresses, otherwise in order.
+    PreferTcp,
+}
+
+impl ConnectStrategy {
+    fn apply(&self, addrs: &mut [SocketAddr]) {
+        match self {
+            Self::InOrder => {}
+            Self::Shuffle => shuffle(addrs),
+            Self::RoundRobin(x) => {
+                if !addrs.is_empty() {
+                    addrs.rotate_left(x.next() % addrs.len());
+                }
+            }
+            Self::PreferUnix => addrs.sort_by_key(SocketAddr::is_tcp),
+            Self::PreferTcp => addrs.sort_by_key(SocketAddr::is_unix),
+        }
+    }
+}
+
+/// Order of the addresses that [`ConnectStrategy`] ranks the same, see
+/// [`ConnectOptions::candidate_order`].
+#[derive(Debug, Clone, Default)]
+pub enum CandidateOrder {
+    /// Keep the order of the strategy.
+    #[default]
+    AsResolved,
+    /// Shuffle each group on every connect.
+    Shuffle,
+    /// Rotate each group by one more position for every connect sharing the
+    /// handle.
+    RoundRobin(RoundRobin),
+}
+
+fn shuffle(addrs: &mut [SocketAddr]) {
+    // Only needs to spread out clients, not be unpredictable.
+    let random = RandomState::new();
+    for i in (1..addrs.len()).rev() {
+        let x = random.hash_one(i) as usize;
+        addrs.swap(i, x % (i + 1));
+    }
+}
+
+/// Shared position of [`ConnectStrategy::RoundRobin`] and
+/// [`CandidateOrder::RoundRobin`].
+///
+/// Clones share the position. Use one handle per endpoint, since connects to
+/// different endpoints would otherwise move each other's position.
+#[derive(Debug, Clone, Default)]
+pub struct RoundRobin(Arc<AtomicUsize>);
+
+impl RoundRobin {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next(&self) -> usize {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// Settings of [`Stream::connect_happy`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct HappyEyeballsConfig {
+    /// How long an attempt may be pending before the next one is started
+    /// alongside it ("Connection Attempt Delay" in RFC 8305).
+    ///
+    /// Defaults to 250ms.
+    pub attempt_delay: Duration,
+}
+
+impl Default for HappyEyeballsConfig {
+    fn default() -> Self {
+        Self {
+            attempt_delay: Duration::from_millis(250),
+        }
+    }
+}
+
+impl HappyEyeballsConfig {
+    #[must_use]
+    pub fn attempt_delay(mut self, attempt_delay: Duration) -> Self {
+        self.attempt_delay = attempt_delay;
+        self
+    }
+}
+
+/// When [`Stream::connect_with_retry`] tries again.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RetryPolicy {
+    /// Delay after the first failed round. Defaults to 100ms.
+    pub initial_delay: Duration,
+
+    /// Upper bound of the delay. Defaults to 10s.
+    pub max_delay: Duration,
+
+    /// Factor the delay grows by after every failed round. Defaults to 2.
+    pub multiplier: f64,
+
+    /// Fraction of each delay that is randomized, from 0 to 1. A delay `d` is
+    /// shortened to somewhere between `d * (1 - jitter)` and `d`. Defaults to
+    /// 0.2.
+    pub jitter: f64,
+
+    /// Give up after this many rounds. Defaults to 10.
+    pub max_attempts: Option<u32>,
+
+    /// Give up once this much time has passed since the first round, instead
+    /// of sleeping past it. Defaults to no limit.
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: Some(10),
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    #[must_use]
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    #[must_use]
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    #[must_use]
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    #[must_use]
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    #[must_use]
+    pub fn max_attempts(mut self, max_attempts: Option<u32>) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    #[must_use]
+    pub fn deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    fn next_delay(&self, delay: Duration) -> Duration {
+        Duration::try_from_secs_f64(delay.as_secs_f64() * self.multiplier)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        // Only needs to spread out clients, not be unpredictable.
+        let random = RandomState::new().build_hasher().finish() >> 11;
+        let fraction = random as f64 / (1u64 << 53) as f64;
+
+        delay.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * fraction)
+    }
+}
 
 ///////////////////////////////////////////////////////////////////////////////
 
-#[pin_project(project = StreamProj)]
 pub enum Stream {
-    Tcp(#[pin] tokio::net::TcpStream),
-    Unix(#[pin] tokio::net::UnixStream),
+    Tcp(tokio::net::TcpStream),
+    Unix(tokio::net::UnixStream),
 }
 
 impl From<tokio::net::TcpStream> for Stream {
@@ -31,6 +456,28 @@ impl From<tokio::net::UnixStream> for Stream {
     }
 }
 
+/// Wrap an inherited socket, detecting whether it is a TCP or unix socket.
+///
+/// Fails with [`ErrorKind::InvalidInput`] if the descriptor is not a connected
+/// stream socket of a supported family.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+impl TryFrom<OwnedFd> for Stream {
+    type Error = Error;
+
+    fn try_from(fd: OwnedFd) -> Result<Self> {
+        let stream = if check_socket(fd.as_fd(), false)? == Domain::UNIX {
+            StdStream::Unix(fd.into())
+        } else {
+            StdStream::Tcp(fd.into())
+        };
+
+        Self::from_std(stream)
+    }
+}
+
 impl Stream {
     #[must_use]
     pub fn is_tcp(&self) -> bool {
@@ -41,6 +488,76 @@ impl Stream {
     pub fn is_unix(&self) -> bool {
         matches!(self, Self::Unix(..))
     }
+
+    /// `"tcp"` or `"unix"`, the scheme of the socket's addresses.
+    #[must_use]
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Tcp(..) => "tcp",
+            Self::Unix(..) => "unix",
+        }
+    }
+
+    /// The underlying tokio stream, if this is a TCP stream.
+    ///
+    /// This is an escape hatch for what this type does not wrap. Prefer its
+    /// own methods, which work the same for both kinds of sockets.
+    #[must_use]
+    pub fn as_tcp(&self) -> Option<&tokio::net::TcpStream> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_tcp_mut(&mut self) -> Option<&mut tokio::net::TcpStream> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio stream, or get `self` back if this is a
+    /// unix stream.
+    ///
+    /// See [`Stream::as_tcp`].
+    pub fn into_tcp(self) -> Result<tokio::net::TcpStream, Self> {
+        match self {
+            Self::Tcp(x) => Ok(x),
+            x => Err(x),
+        }
+    }
+
+    /// The underlying tokio stream, if this is a unix stream.
+    ///
+    /// See [`Stream::as_tcp`].
+    #[must_use]
+    pub fn as_unix(&self) -> Option<&tokio::net::UnixStream> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_unix_mut(&mut self) -> Option<&mut tokio::net::UnixStream> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio stream, or get `self` back if this is a
+    /// TCP stream.
+    ///
+    /// See [`Stream::as_tcp`].
+    pub fn into_unix(self) -> Result<tokio::net::UnixStream, Self> {
+        match self {
+            Self::Unix(x) => Ok(x),
+            x => Err(x),
+        }
+    }
 }
 
 impl Stream {
@@ -51,33 +568,462 @@ impl Stream {
         }
     }
 
+    /// Connect to the first address of `addr` that accepts the connection.
+    ///
+    /// The addresses are tried in turn. If none of them connects, the error
+    /// wraps an [`AddrErrors`] with the failure of each. Use
+    /// [`Stream::connect_full`] to find out which address was connected to.
     pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
-        let addrs = addr.to_socket_addrs()?;
+        Self::connect_full(addr).await.map(|(x, _)| x)
+    }
+
+    /// Like [`Stream::connect`], but also returns the address of `addr` that
+    /// the connection was established to.
+    ///
+    /// Unlike [`Stream::peer_addr`], this is the address as it was resolved,
+    /// so it is known even when the peer is reached through a proxy or its
+    /// address is rewritten on the way.
+    pub async fn connect_full<A: ToSocketAddrs>(addr: A) -> Result<(Self, SocketAddr)> {
+        addr.to_socket_addrs_lazy(&SystemResolver)
+            .try_each(|addr| async move { Ok((Self::_connect(addr.clone()).await?, addr)) })
+            .await
+    }
+
+    /// Like [`Stream::connect`], but race the addresses as described by Happy
+    /// Eyeballs (RFC 8305).
+    ///
+    /// The addresses are reordered so that address families alternate,
+    /// starting with the family of the first address. Unix addresses count as
+    /// a family of their own. Whenever an attempt has been pending for
+    /// [`HappyEyeballsConfig::attempt_delay`], or has failed, the next one is
+    /// started without giving up on the earlier ones. The first connection to
+    /// be established is returned and the other attempts are aborted.
+    ///
+    /// The attempts are made like [`Stream::connect`] makes them, so
+    /// [`ConnectOptions`] do not apply: there is no proxy, local address,
+    /// resolver or socket option. Use [`Stream::connect_with`] for those,
+    /// which tries the addresses one at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with the time driver
+    /// enabled.
+    pub async fn connect_happy<A: ToSocketAddrs>(
+        addr: A,
+        config: &HappyEyeballsConfig,
+    ) -> Result<Self> {
+        Self::connect_happy_full(addr, config).await.map(|(x, _)| x)
+    }
+
+    /// Like [`Stream::connect_happy`], but also returns the address of the
+    /// attempt that won, like [`Stream::connect_full`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with the time driver
+    /// enabled.
+    pub async fn connect_happy_full<A: ToSocketAddrs>(
+        addr: A,
+        config: &HappyEyeballsConfig,
+    ) -> Result<(Self, SocketAddr)> {
+        type Attempt = Pin<Box<dyn Future<Output = Result<Stream>> + Send>>;
+
+        let mut queue = interleave_families(addr.to_socket_addrs_async().await?.collect());
+        let mut attempts: Vec<(SocketAddr, Attempt)> = Vec::new();
+        let mut timer = Box::pin(sleep(config.attempt_delay));
+        let mut start_next = true;
+        let mut errors = Vec::new();
+
+        poll_fn(|cx| {
+            loop {
+                if start_next {
+                    start_next = false;
+
+                    if let Some(addr) = queue.next() {
+                        attempts.push((addr.clone(), Box::pin(Self::_connect(addr))));
+                        timer.as_mut().reset(Instant::now() + config.attempt_delay);
+                    }
+                }
+
+                let mut i = 0;
+                while i < attempts.len() {
+                    match attempts[i].1.as_mut().poll(cx) {
+                        Poll::Ready(Ok(x)) => {
+                            return Poll::Ready(Ok((x, attempts.swap_remove(i).0)));
+                        }
+                        Poll::Ready(Err(e)) => {
+                            let (addr, _) = attempts.remove(i);
+                            errors.push((addr, e));
+                            start_next = true;
+                        }
+                        Poll::Pending => i += 1,
+                    }
+                }
+
+                if start_next {
+                    continue;
+                }
+
+                if attempts.is_empty() {
+                    let errors = std::mem::take(&mut errors);
+                    return Poll::Ready(Err(AddrErrors::into_error(errors)));
+                }
+
+                if queue.len() != 0 && timer.as_mut().poll(cx).is_ready() {
+                    start_next = true;
+                    continue;
+                }
 
-        let mut last_err = None;
-        for addr in addrs {
-            match Self::_connect(addr).await {
-                Ok(x) => return Ok(x),
-                Err(e) => last_err = Some(e),
+                return Poll::Pending;
+            }
+        })
+        .await
+    }
+
+    /// Like [`Stream::connect`], but give up on an address if connecting to it
+    /// takes longer than `per_attempt` and move on to the next one.
+    ///
+    /// Fails with [`ErrorKind::TimedOut`] if the last address timed out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with the time driver
+    /// enabled.
+    pub async fn connect_timeout<A: ToSocketAddrs>(addr: A, per_attempt: Duration) -> Result<Self> {
+        addr.to_socket_addrs_lazy(&SystemResolver)
+            .try_each(|addr| async move {
+                match tokio::time::timeout(per_attempt, Self::_connect(addr)).await {
+                    Ok(x) => x,
+                    Err(_) => Err(Error::new(
+                        ErrorKind::TimedOut,
+                        "connection attempt timed out",
+                    )),
+                }
+            })
+            .await
+    }
+
+    /// Like [`Stream::connect`], but send `header` before anything else, as a
+    /// proxy forwarding a connection to a server that expects the PROXY
+    /// protocol would.
+    pub async fn connect_with_proxy_header<A: ToSocketAddrs>(
+        addr: A,
+        header: &ProxyHeader,
+    ) -> Result<Self> {
+        let mut stream = Self::connect(addr).await?;
+        header.write_to(&mut stream).await?;
+        Ok(stream)
+    }
+
+    /// Like [`Stream::connect`], but create the socket according to `options`.
+    ///
+    /// With a [proxy](ConnectOptions::proxy), `addr` is resolved locally and
+    /// the proxy is asked to connect to each address in turn. Use
+    /// [`Stream::connect_host_with`] to let the proxy resolve hostnames.
+    pub async fn connect_with<A: ToSocketAddrs>(addr: A, options: &ConnectOptions) -> Result<Self> {
+        Self::connect_with_full(addr, options).await.map(|(x, _)| x)
+    }
+
+    /// Like [`Stream::connect_with`], but also returns the address of `addr`
+    /// that the connection was established to, like
+    /// [`Stream::connect_full`].
+    ///
+    /// With a proxy, this is the address the proxy was asked to connect to,
+    /// while [`Stream::peer_addr`] is the address of the proxy.
+    pub async fn connect_with_full<A: ToSocketAddrs>(
+        addr: A,
+        options: &ConnectOptions,
+    ) -> Result<(Self, SocketAddr)> {
+        let resolver = options.resolver.as_deref().unwrap_or(&SystemResolver);
+        let mut addrs = addr.to_socket_addrs_lazy(resolver);
+
+        let mut errors = Vec::new();
+        let mut unresolved = Vec::new();
+        while let Some(batch) = addrs.next_batch().await {
+            let mut batch = match batch {
+                Ok(x) => x,
+                Err(e) => {
+                    unresolved.push(e);
+                    continue;
+                }
+            };
+
+            options.order(&mut batch);
+
+            for addr in batch {
+                let result = match (&options.proxy, &addr) {
+                    (Some(proxy), SocketAddr::Tcp(x)) => {
+                        Self::_connect_proxied(proxy, Host::Ip(x.ip()), x.port(), options).await
+                    }
+                    (Some(_), SocketAddr::Unix(_)) => Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "cannot connect to a unix socket through a proxy",
+                    )),
+                    (None, _) => Self::_connect_with(addr.clone(), options).await,
+                };
+
+                match result {
+                    Ok(x) => return Ok((x, addr)),
+                    Err(e) => errors.push((addr, e)),
+                }
             }
         }
 
-        let Some(last_err) = last_err else {
-            unreachable!()
-        };
+        Err(AddrErrors::into_error_with(errors, unresolved))
+    }
+
+    /// Connect to `host` on TCP `port` according to `options`.
+    ///
+    /// `host` is a hostname or an IP address. IPv6 addresses may be enclosed
+    /// in brackets. With a [proxy](ConnectOptions::proxy), hostnames are sent
+    /// to the proxy as-is and resolved there. Without one, this is the same
+    /// as [`Stream::connect_with`] on `(host, port)`.
+    pub async fn connect_host_with(
+        host: &str,
+        port: u16,
+        options: &ConnectOptions,
+    ) -> Result<Self> {
+        match &options.proxy {
+            Some(proxy) => Self::_connect_proxied(proxy, Host::new(host), port, options).await,
+            None => {
+                let host = host
+                    .strip_prefix('[')
+                    .and_then(|x| x.strip_suffix(']'))
+                    .unwrap_or(host);
 
-        Err(last_err)
+                Self::connect_with((host, port), options).await
+            }
+        }
+    }
+
+    async fn _connect_proxied(
+        proxy: &Proxy,
+        host: Host<'_>,
+        port: u16,
+        options: &ConnectOptions,
+    ) -> Result<Self> {
+        let mut stream = Self::_connect_with(proxy.addr().clone(), options).await?;
+        proxy.handshake(&mut stream, host, port).await?;
+        Ok(stream)
+    }
+
+    /// Like [`Stream::connect`], but try again according to `policy` while
+    /// the failures look temporary.
+    ///
+    /// Addresses are resolved again for every round, so DNS changes are picked
+    /// up. Refused, reset, timed out and unreachable connections, missing unix
+    /// sockets and failed hostname lookups are retried. Any other error, like
+    /// an invalid address or [`ErrorKind::PermissionDenied`], is returned
+    /// immediately. When the policy gives up, the last error is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with the time driver
+    /// enabled.
+    pub async fn connect_with_retry<A: ToSocketAddrs>(
+        addr: A,
+        policy: &RetryPolicy,
+    ) -> Result<Self> {
+        let deadline = policy.deadline.map(|x| Instant::now() + x);
+        let mut delay = policy.initial_delay.min(policy.max_delay);
+        let mut attempts = 0u32;
+
+        loop {
+            attempts = attempts.saturating_add(1);
+
+            let e = match addr.to_socket_addrs_async().await {
+                Ok(addrs) => {
+                    let addrs: Vec<SocketAddr> = addrs.collect();
+                    match Self::connect(addrs.as_slice()).await {
+                        Ok(x) => return Ok(x),
+                        Err(e) if is_retryable(&e) => e,
+                        Err(e) => return Err(e),
+                    }
+                }
+                // Anything but a malformed address is a failed lookup.
+                Err(e) if e.kind() != ErrorKind::InvalidInput => e,
+                Err(e) => return Err(e),
+            };
+
+            if policy.max_attempts.is_some_and(|max| attempts >= max) {
+                return Err(e);
+            }
+
+            let wake = Instant::now() + policy.jittered(delay);
+            if deadline.is_some_and(|deadline| wake >= deadline) {
+                return Err(e);
+            }
+
+            tokio::time::sleep_until(wake).await;
+            delay = policy.next_delay(delay);
+        }
+    }
+
+    async fn _connect_with(addr: SocketAddr, options: &ConnectOptions) -> Result<Self> {
+        trace::connect(addr, |addr| async move {
+            #[cfg(target_os = "linux")]
+            let socket = if options.mptcp {
+                Socket::new_mptcp_for(&addr)?
+            } else {
+                Socket::new_for(&addr)?
+            };
+            #[cfg(not(target_os = "linux"))]
+            let socket = Socket::new_for(&addr)?;
+
+            options.apply(&socket)?;
+            socket.connect(&addr).await
+        })
+        .await
     }
 
     async fn _connect(addr: SocketAddr) -> Result<Self> {
-        match addr {
-            SocketAddr::Tcp(x) => tokio::net::TcpStream::connect(x).await.map(Into::into),
-            SocketAddr::Unix(x) => {
-                assert!(!x.is_unnamed(), "cannot connect to an unnamed address");
-                let x = x.into();
-                tokio::net::UnixStream::connect(unix_addr_to_path(&x))
-                    .await
-                    .map(Into::into)
+        trace::connect(addr, |addr| async move {
+            match addr {
+                SocketAddr::Tcp(x) => tokio::net::TcpStream::connect(x).await.map(Into::into),
+                SocketAddr::Unix(x) => {
+                    if x.is_unnamed() {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            "cannot connect to an unnamed unix address",
+                        ));
+                    }
+
+                    let x = x.into();
+                    tokio::net::UnixStream::connect(unix_addr_to_path(&x))
+                        .await
+                        .map(Into::into)
+                }
+            }
+        })
+        .await
+    }
+
+    /// Whether the connection uses Multipath TCP.
+    ///
+    /// Connections accepted from an MPTCP listener are only MPTCP if the peer
+    /// supports it too. Connecting with [`ConnectOptions::mptcp`] gives an
+    /// MPTCP socket whenever the local kernel supports it, and it silently
+    /// falls back to plain TCP if the peer does not, without this changing.
+    #[cfg(target_os = "linux")]
+    pub fn is_mptcp(&self) -> Result<bool> {
+        is_mptcp(self.as_fd())
+    }
+
+    /// The number of bytes that can be read without waiting (`FIONREAD`).
+    pub fn bytes_readable(&self) -> Result<usize> {
+        let n = rustix::io::ioctl_fionread(self)?;
+        usize::try_from(n).map_err(Error::other)
+    }
+
+    /// Wait until the peer has closed the connection or it has been reset.
+    ///
+    /// Nothing is read, so data the peer sent before closing is still there
+    /// for the next read. Data that arrives while waiting does not end the
+    /// wait.
+    ///
+    /// Data that has not been read keeps the socket readable, so this waits
+    /// for the next readiness event instead. A task reading from the stream at
+    /// the same time may then not be woken until more data arrives, so only
+    /// wait for this on connections nobody is reading from, like idle ones.
+    pub async fn closed(&self) -> Result<()> {
+        loop {
+            let ready = self.ready(Interest::READABLE).await?;
+            if ready.is_read_closed() {
+                return Ok(());
+            }
+
+            let _ = self.try_io(Interest::READABLE, || {
+                Err::<(), _>(ErrorKind::WouldBlock.into())
+            });
+        }
+    }
+
+    /// Close the connection without losing data or making it reset.
+    ///
+    /// This shuts down the write direction, so the peer reads everything
+    /// written so far followed by end of file. Then it reads and discards
+    /// whatever the peer still sends, until the peer closes its side too.
+    /// Closing a socket with unread data makes the kernel reset the
+    /// connection, which can make the peer lose data it has not read yet, so
+    /// this waits for that instead.
+    ///
+    /// Writes go straight to the socket, so there is nothing to flush. Flush
+    /// wrappers like [`BufferedStream`] before taking the stream out of them.
+    ///
+    /// Fails with [`ErrorKind::TimedOut`] if the peer does not close the
+    /// connection within `timeout`. The connection is closed either way.
+    pub async fn close_graceful(self, timeout: Duration) -> Result<()> {
+        self.close_graceful_with(timeout, |_| {}).await
+    }
+
+    /// Like [`Stream::close_graceful`], but pass what the peer still sends to
+    /// `on_data` instead of discarding it.
+    pub async fn close_graceful_with(
+        mut self,
+        timeout: Duration,
+        on_data: impl FnMut(&[u8]),
+    ) -> Result<()> {
+        self.shutdown_socket(Shutdown::Write)?;
+        drain_until_closed(&mut self, timeout, on_data).await
+    }
+
+    /// Adopt the connection on standard input, as passed by inetd or by
+    /// systemd with `Accept=yes`.
+    ///
+    /// The descriptor is duplicated, so standard input itself stays open. Fails
+    /// with [`ErrorKind::InvalidInput`] if standard input is not a connected
+    /// TCP or unix socket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn from_stdio() -> Result<Self> {
+        let fd = std::io::stdin().as_fd().try_clone_to_owned()?;
+
+        Self::try_from(fd).map_err(|e| {
+            if e.raw_os_error() == Some(Errno::NOTSOCK.raw_os_error()) {
+                Error::new(ErrorKind::InvalidInput, "standard input is not a socket")
+            } else {
+                e
+            }
+        })
+    }
+
+    /// Register a standard library stream with the current tokio runtime.
+    ///
+    /// The socket is switched to non-blocking mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn from_std(stream: StdStream) -> Result<Self> {
+        match stream {
+            StdStream::Tcp(x) => {
+                x.set_nonblocking(true)?;
+                tokio::net::TcpStream::from_std(x).map(Into::into)
+            }
+            StdStream::Unix(x) => {
+                x.set_nonblocking(true)?;
+                tokio::net::UnixStream::from_std(x).map(Into::into)
+            }
+        }
+    }
+
+    /// Deregister the stream from the runtime and turn it into a standard
+    /// library stream.
+    ///
+    /// The socket is switched back to blocking mode.
+    pub fn into_std(self) -> Result<StdStream> {
+        match self {
+            Self::Tcp(x) => {
+                let x = x.into_std()?;
+                x.set_nonblocking(false)?;
+                Ok(x.into())
+            }
+            Self::Unix(x) => {
+                let x = x.into_std()?;
+                x.set_nonblocking(false)?;
+                Ok(x.into())
             }
         }
     }
@@ -89,6 +1035,79 @@ impl Stream {
         }
     }
 
+    /// Check without blocking whether the connection can still be read from.
+    ///
+    /// This is `false` if the connection has been reset, or if the peer has
+    /// closed it and all data it sent has been read. A peer that sent data and
+    /// then closed the connection still counts as open until that data has
+    /// been read, since reads still return it. Nothing is read from the
+    /// connection, but a pending socket error (`SO_ERROR`) is cleared.
+    ///
+    /// That the connection is open does not mean the peer is still there: a
+    /// peer that vanished without closing the connection is only noticed
+    /// once sending to it fails.
+    pub fn is_open(&self) -> Result<bool> {
+        if self.take_error()?.is_some() {
+            return Ok(false);
+        }
+
+        // Peeking directly leaves the readiness tracked by tokio alone.
+        match recv_peek(self.as_fd(), &mut [0]) {
+            Ok(n) => Ok(n != 0),
+            Err(e) => match e.kind() {
+                ErrorKind::WouldBlock => Ok(true),
+                ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::BrokenPipe
+                | ErrorKind::TimedOut => Ok(false),
+                _ => Err(e),
+            },
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        match self {
+            Self::Tcp(x) => x.local_addr().map(Into::into),
+            Self::Unix(x) => x.local_addr().map(Into::into),
+        }
+    }
+
+    /// Count the bytes read from and written to this stream.
+    ///
+    /// See [`MeteredStream`].
+    pub fn metered(self) -> MeteredStream {
+        MeteredStream::new(self)
+    }
+
+    /// Create a pair of connected unix streams (`socketpair`).
+    ///
+    /// This is useful for tests. Both ends are unnamed, so their
+    /// [`Stream::local_addr`] and [`Stream::peer_addr`] are unnamed unix
+    /// addresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn pair() -> Result<(Self, Self)> {
+        tokio::net::UnixStream::pair().map(into2)
+    }
+
+    /// Create a pair of TCP streams connected over the loopback interface.
+    ///
+    /// Like [`Stream::pair`], but for when TCP-specific behavior matters. The
+    /// listener used to set up the connection is closed before returning.
+    pub async fn pair_tcp() -> Result<(Self, Self)> {
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let a = tokio::net::TcpStream::connect(listener.local_addr()?).await?;
+        let (b, _) = listener.accept().await?;
+        Ok((a.into(), b.into()))
+    }
+
+    pub async fn peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.async_io(Interest::READABLE, || recv_peek(self.as_fd(), &mut *buf))
+            .await
+    }
+
     pub fn peer_addr(&self) -> Result<SocketAddr> {
         match self {
             Self::Tcp(x) => x.peer_addr().map(Into::into),
@@ -96,10 +1115,96 @@ impl Stream {
         }
     }
 
+    /// The credentials of the peer process (`SO_PEERCRED`).
+    ///
+    /// These are captured when the connection is established. TCP connections
+    /// have no such information, so this is `None` for them.
+    pub fn peer_cred(&self) -> Result<Option<PeerCred>> {
+        match self {
+            Self::Tcp(_) => Ok(None),
+            Self::Unix(x) => x.peer_cred().map(|x| Some(x.into())),
+        }
+    }
+
+    /// A pidfd referring to the peer process of a unix connection
+    /// (`SO_PEERPIDFD`).
+    ///
+    /// The PID in [`Stream::peer_cred`] can be reused by another process once
+    /// the peer exits, the pidfd keeps referring to the same process. This is
+    /// `None` for TCP connections.
+    ///
+    /// Only supported on Linux 6.5 and later. Older kernels and other
+    /// platforms fail with [`ErrorKind::Unsupported`].
+    pub fn peer_pidfd(&self) -> Result<Option<OwnedFd>> {
+        match self {
+            Self::Tcp(_) => Ok(None),
+            #[cfg(target_os = "linux")]
+            Self::Unix(x) => {
+                use nix::errno::Errno;
+                use nix::sys::socket::{getsockopt, sockopt::PeerPidfd};
+
+                match getsockopt(x, PeerPidfd) {
+                    Ok(x) => Ok(Some(x)),
+                    Err(Errno::ENOPROTOOPT) => Err(unsupported("SO_PEERPIDFD")),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            #[cfg(not(target_os = "linux"))]
+            Self::Unix(_) => Err(unsupported("SO_PEERPIDFD")),
+        }
+    }
+
+    /// The address the peer originally connected to, before it was redirected
+    /// to this socket with netfilter `REDIRECT` or `DNAT` (`SO_ORIGINAL_DST`,
+    /// or `IP6T_SO_ORIGINAL_DST` for IPv6).
+    ///
+    /// This is `None` for unix connections and for TCP connections that were
+    /// not redirected. Connections intercepted with `TPROXY` keep their
+    /// destination, so [`Stream::local_addr`] already is the original one.
+    ///
+    /// Only supported on Linux and Android.
+    pub fn original_dst(&self) -> Result<Option<std::net::SocketAddr>> {
+        match self {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Tcp(x) => {
+                use rustix::net::sockopt::{ip_original_dst, ipv6_original_dst};
+
+                let ipv4 = match x.local_addr()? {
+                    std::net::SocketAddr::V4(_) => true,
+                    std::net::SocketAddr::V6(x) => x.ip().to_ipv4_mapped().is_some(),
+                };
+
+                let addr = if ipv4 {
+                    ip_original_dst(x).map(Into::into)
+                } else {
+                    ipv6_original_dst(x).map(Into::into)
+                };
+
+                match addr {
+                    Ok(x) => Ok(Some(x)),
+                    Err(Errno::NOENT) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Self::Tcp(_) => Err(unsupported("SO_ORIGINAL_DST on this platform")),
+            Self::Unix(_) => Ok(None),
+        }
+    }
+
+    pub fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<usize>> {
+        poll_peek_with(
+            cx,
+            buf,
+            |cx| self.poll_read_ready(cx),
+            |buf| self.try_peek(buf),
+        )
+    }
+
     pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
         match self {
             Self::Tcp(x) => x.poll_read_ready(cx),
-            Self::Unix(x) => x.poll_write_ready(cx),
+            Self::Unix(x) => x.poll_read_ready(cx),
         }
     }
 
@@ -124,6 +1229,26 @@ impl Stream {
         }
     }
 
+    /// Shut down the read half, write half or both halves of the connection.
+    ///
+    /// Unlike [`AsyncWriteExt::shutdown`], this can also shut down reading and
+    /// does not need a mutable reference.
+    ///
+    /// [`AsyncWriteExt::shutdown`]: tokio::io::AsyncWriteExt::shutdown
+    pub fn shutdown_socket(&self, how: Shutdown) -> Result<()> {
+        SockRef::from(self).shutdown(how)
+    }
+
+    /// The kernel's unique identifier for this socket (`SO_COOKIE`).
+    ///
+    /// Only supported on Linux.
+    pub fn socket_cookie(&self) -> Result<u64> {
+        match self {
+            Self::Tcp(x) => socket_cookie(x.as_fd()),
+            Self::Unix(x) => socket_cookie(x.as_fd()),
+        }
+    }
+
     pub fn split<'a>(&'a mut self) -> (ReadHalf<'a>, WriteHalf<'a>) {
         match self {
             Self::Tcp(x) => into2(x.split()),
@@ -131,6 +1256,20 @@ impl Stream {
         }
     }
 
+    /// The socket, for the options this crate does not wrap, like
+    /// `SO_PRIORITY`.
+    ///
+    /// Getting and setting socket options through it is fine. Reading,
+    /// writing, shutting down or changing the blocking mode is not: tokio
+    /// tracks the readiness of the socket and expects it to stay non-blocking,
+    /// so blocking calls stall the runtime and reads or writes behind its back
+    /// can leave tasks waiting for readiness that already passed. Use the
+    /// methods of the stream for that.
+    #[must_use]
+    pub fn as_socket(&self) -> SockRef<'_> {
+        SockRef::from(self)
+    }
+
     pub fn take_error(&self) -> Result<Option<Error>> {
         match self {
             Self::Tcp(x) => x.take_error(),
@@ -145,6 +1284,10 @@ impl Stream {
         }
     }
 
+    pub fn try_peek(&self, buf: &mut [u8]) -> Result<usize> {
+        self.try_io(Interest::READABLE, || recv_peek(self.as_fd(), buf))
+    }
+
     pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
         match self {
             Self::Tcp(x) => x.try_read(buf),
@@ -159,6 +1302,32 @@ impl Stream {
         }
     }
 
+    /// Like [`Stream::try_read`], but read into the spare capacity of `buf`
+    /// without initializing it first, and advance `buf` past the bytes read.
+    #[cfg(feature = "bytes")]
+    pub fn try_read_buf<B: BufMut>(&self, buf: &mut B) -> Result<usize> {
+        match self {
+            Self::Tcp(x) => x.try_read_buf(buf),
+            Self::Unix(x) => x.try_read_buf(buf),
+        }
+    }
+
+    /// Like [`Stream::try_read_buf`], but wait until data is available.
+    ///
+    /// This is the same as `AsyncReadExt::read_buf`, without needing a
+    /// mutable reference.
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: BufMut>(&self, buf: &mut B) -> Result<usize> {
+        loop {
+            self.readable().await?;
+
+            match self.try_read_buf(buf) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+                x => return x,
+            }
+        }
+    }
+
     pub fn try_write(&self, buf: &[u8]) -> Result<usize> {
         match self {
             Self::Tcp(x) => x.try_write(buf),
@@ -179,6 +1348,616 @@ impl Stream {
             Self::Unix(x) => x.writable().await,
         }
     }
+
+    /// Buffer reads and writes, keeping the address methods of the stream.
+    ///
+    /// See [`BufferedStream`].
+    ///
+    /// # Panics
+    ///
+    /// If either capacity is zero.
+    pub fn buffered(self, read_capacity: usize, write_capacity: usize) -> BufferedStream {
+        BufferedStream::new(self, read_capacity, write_capacity)
+    }
+
+    /// Inject the faults of `plan` into reads and writes.
+    ///
+    /// See [`FaultyStream`].
+    #[cfg(feature = "test-util")]
+    pub fn with_faults(self, plan: FaultPlan) -> FaultyStream {
+        FaultyStream::new(self, plan)
+    }
+
+    /// Limit the bandwidth of reads and writes. `None` leaves a direction
+    /// unlimited, until a limit is set through its [`Limiter`].
+    ///
+    /// Use [`ThrottledStream::new`] to share limiters between connections.
+    /// See [`ThrottledStream`].
+    pub fn throttled(self, read: Option<Limit>, write: Option<Limit>) -> ThrottledStream {
+        ThrottledStream::new(self, Limiter::new(read), Limiter::new(write))
+    }
+
+    /// Fail reads and writes that make no progress for the given time.
+    ///
+    /// See [`TimedStream`].
+    pub fn with_timeouts(self, read: Option<Duration>, write: Option<Duration>) -> TimedStream {
+        TimedStream::new(self, read, write)
+    }
+}
+
+fn is_retryable(e: &Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::TimedOut
+            | ErrorKind::NotFound
+            | ErrorKind::AddrNotAvailable
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::NetworkDown
+            | ErrorKind::Interrupted
+    )
+}
+
+/// Reorder `addrs` so that address families alternate, keeping the relative
+/// order within each family. Families take turns in the order they first
+/// appear.
+fn interleave_families(addrs: Vec<SocketAddr>) -> std::vec::IntoIter<SocketAddr> {
+    fn family(addr: &SocketAddr) -> u8 {
+        match addr {
+            SocketAddr::Tcp(std::net::SocketAddr::V4(_)) => 0,
+            SocketAddr::Tcp(std::net::SocketAddr::V6(_)) => 1,
+            SocketAddr::Unix(_) => 2,
+        }
+    }
+
+    let mut groups: Vec<(u8, std::collections::VecDeque<SocketAddr>)> = Vec::new();
+    for addr in addrs {
+        let f = family(&addr);
+        match groups.iter_mut().find(|(x, _)| *x == f) {
+            Some((_, group)) => group.push_back(addr),
+            None => groups.push((f, [addr].into())),
+        }
+    }
+
+    let mut out = Vec::new();
+    while !groups.is_empty() {
+        groups.retain_mut(|(_, group)| {
+            out.extend(group.pop_front());
+            !group.is_empty()
+        });
+    }
+
+    out.into_iter()
+}
+
+/// Credentials of the process on the other end of a unix socket.
+///
+/// See [`Stream::peer_cred`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCred {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not every platform reports the process ID.
+    pub pid: Option<i32>,
+}
+
+impl From<tokio::net::unix::UCred> for PeerCred {
+    fn from(x: tokio::net::unix::UCred) -> Self {
+        Self {
+            uid: x.uid(),
+            gid: x.gid(),
+            pid: x.pid(),
+        }
+    }
+}
+
+/// TCP keepalive settings.
+///
+/// See [`Stream::set_keepalive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// How long the connection must be idle before the first probe is sent
+    /// (`TCP_KEEPIDLE`).
+    pub time: Duration,
+
+    /// Time between probes (`TCP_KEEPINTVL`).
+    pub interval: Duration,
+
+    /// How many unanswered probes are sent before the connection is dropped
+    /// (`TCP_KEEPCNT`).
+    pub retries: u32,
+}
+
+impl Stream {
+    /// Whether Nagle's algorithm is disabled (`TCP_NODELAY`).
+    ///
+    /// Unix sockets never delay writes, so this is always `true` for them.
+    pub fn nodelay(&self) -> Result<bool> {
+        match self {
+            Self::Tcp(x) => x.nodelay(),
+            Self::Unix(_) => Ok(true),
+        }
+    }
+
+    /// Disable or enable Nagle's algorithm (`TCP_NODELAY`).
+    ///
+    /// This does nothing for unix sockets. See [`Stream::nodelay`].
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        match self {
+            Self::Tcp(x) => x.set_nodelay(nodelay),
+            Self::Unix(_) => Ok(()),
+        }
+    }
+
+    /// Whether partial segments are held back (`TCP_CORK`).
+    ///
+    /// Only supported for TCP sockets on Linux and Android. Otherwise this is
+    /// an [`ErrorKind::Unsupported`] error.
+    pub fn cork(&self) -> Result<bool> {
+        match self {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Tcp(x) => SockRef::from(x).tcp_cork(),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Self::Tcp(_) => Err(unsupported("TCP_CORK on this platform")),
+            Self::Unix(_) => Err(unsupported("cork on unix sockets")),
+        }
+    }
+
+    /// Hold back partial segments until the stream is uncorked (`TCP_CORK`).
+    ///
+    /// While corked, only full segments are sent, so a small header written
+    /// before a [`Stream::send_file`] goes out together with the body.
+    /// Uncorking sends whatever is left right away. The kernel also sends
+    /// held back data after 200ms.
+    ///
+    /// Corking takes precedence over [`Stream::set_nodelay`] while it is set.
+    /// With `TCP_NODELAY` enabled, uncorking sends the remaining data
+    /// immediately; without it, Nagle's algorithm applies to the remainder.
+    ///
+    /// See [`Stream::cork`] for platform support.
+    pub fn set_cork(&self, cork: bool) -> Result<()> {
+        match self {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Tcp(x) => SockRef::from(x).set_tcp_cork(cork),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Self::Tcp(_) => {
+                let _ = cork;
+                Err(unsupported("TCP_CORK on this platform"))
+            }
+            Self::Unix(_) => Err(unsupported("cork on unix sockets")),
+        }
+    }
+
+    /// Switch to or from sending ACKs immediately (`TCP_QUICKACK`).
+    ///
+    /// This is not sticky: the kernel switches modes on its own as the
+    /// connection goes on, so it usually has to be enabled again after every
+    /// read to keep delayed ACKs from stalling a request/response exchange.
+    /// It is independent of [`Stream::set_nodelay`], which only affects
+    /// sending data.
+    ///
+    /// See [`Stream::cork`] for platform support.
+    pub fn set_quickack(&self, quickack: bool) -> Result<()> {
+        match self {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Tcp(x) => SockRef::from(x).set_tcp_quickack(quickack),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Self::Tcp(_) => {
+                let _ = quickack;
+                Err(unsupported("TCP_QUICKACK on this platform"))
+            }
+            Self::Unix(_) => Err(unsupported("quickack on unix sockets")),
+        }
+    }
+
+    /// The keepalive settings of the connection (`SO_KEEPALIVE`), as reported
+    /// by the kernel.
+    ///
+    /// This is `None` if keepalive is disabled. Unix sockets have no
+    /// keepalive, so this is an [`ErrorKind::Unsupported`] error for them.
+    pub fn keepalive(&self) -> Result<Option<KeepaliveConfig>> {
+        let Self::Tcp(x) = self else {
+            return Err(unsupported("keepalive on unix sockets"));
+        };
+
+        let x = SockRef::from(x);
+        if !x.keepalive()? {
+            return Ok(None);
+        }
+
+        Ok(Some(KeepaliveConfig {
+            time: x.tcp_keepalive_time()?,
+            interval: x.tcp_keepalive_interval()?,
+            retries: x.tcp_keepalive_retries()?,
+        }))
+    }
+
+    /// Enable keepalive with the given settings, or disable it with `None`.
+    ///
+    /// See [`Stream::keepalive`].
+    pub fn set_keepalive(&self, keepalive: Option<KeepaliveConfig>) -> Result<()> {
+        let Self::Tcp(x) = self else {
+            return Err(unsupported("keepalive on unix sockets"));
+        };
+
+        let x = SockRef::from(x);
+        match keepalive {
+            Some(k) => x.set_tcp_keepalive(
+                &TcpKeepalive::new()
+                    .with_time(k.time)
+                    .with_interval(k.interval)
+                    .with_retries(k.retries),
+            ),
+            None => x.set_keepalive(false),
+        }
+    }
+
+    /// The time-to-live of outgoing IP packets (`IP_TTL`).
+    ///
+    /// Unix sockets send no IP packets and have no time-to-live, so like the
+    /// IPv6 hop limit, this is an [`ErrorKind::Unsupported`] error for them.
+    pub fn ttl(&self) -> Result<u32> {
+        match self {
+            Self::Tcp(x) => x.ttl(),
+            Self::Unix(_) => Err(unsupported("time-to-live on unix sockets")),
+        }
+    }
+
+    /// Set the time-to-live of outgoing IP packets (`IP_TTL`).
+    ///
+    /// See [`Stream::ttl`].
+    pub fn set_ttl(&self, ttl: u32) -> Result<()> {
+        match self {
+            Self::Tcp(x) => x.set_ttl(ttl),
+            Self::Unix(_) => Err(unsupported("time-to-live on unix sockets")),
+        }
+    }
+
+    /// The hop limit of outgoing IPv6 packets (`IPV6_UNICAST_HOPS`).
+    ///
+    /// This is the IPv6 counterpart of [`Stream::ttl`]. It fails for IPv4
+    /// sockets, and is an [`ErrorKind::Unsupported`] error for unix sockets.
+    pub fn unicast_hops_v6(&self) -> Result<u32> {
+        match self {
+            Self::Tcp(x) => SockRef::from(x).unicast_hops_v6(),
+            Self::Unix(_) => Err(unsupported("hop limit on unix sockets")),
+        }
+    }
+
+    /// Set the hop limit of outgoing IPv6 packets (`IPV6_UNICAST_HOPS`).
+    ///
+    /// See [`Stream::unicast_hops_v6`].
+    pub fn set_unicast_hops_v6(&self, hops: u32) -> Result<()> {
+        match self {
+            Self::Tcp(x) => SockRef::from(x).set_unicast_hops_v6(hops),
+            Self::Unix(_) => Err(unsupported("hop limit on unix sockets")),
+        }
+    }
+
+    /// The size of the kernel send buffer (`SO_SNDBUF`).
+    ///
+    /// On Linux, this is double the value that was set, because the kernel
+    /// reserves the extra space for bookkeeping.
+    pub fn send_buffer_size(&self) -> Result<usize> {
+        SockRef::from(self).send_buffer_size()
+    }
+
+    /// Set the size of the kernel send buffer (`SO_SNDBUF`).
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<()> {
+        SockRef::from(self).set_send_buffer_size(size)
+    }
+
+    /// The size of the kernel receive buffer (`SO_RCVBUF`).
+    ///
+    /// On Linux, this is double the value that was set, because the kernel
+    /// reserves the extra space for bookkeeping.
+    pub fn recv_buffer_size(&self) -> Result<usize> {
+        SockRef::from(self).recv_buffer_size()
+    }
+
+    /// Set the size of the kernel receive buffer (`SO_RCVBUF`).
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<()> {
+        SockRef::from(self).set_recv_buffer_size(size)
+    }
+
+    /// How long closing the socket waits for unsent data (`SO_LINGER`).
+    ///
+    /// `None` means that closing returns immediately and the data is sent in
+    /// the background, which is the default. Unix sockets accept the option but
+    /// it has no effect on them.
+    pub fn linger(&self) -> Result<Option<Duration>> {
+        SockRef::from(self).linger()
+    }
+
+    /// Set how long closing the socket waits for unsent data (`SO_LINGER`).
+    ///
+    /// A zero timeout aborts TCP connections on close, sending a reset to the
+    /// peer instead of the normal shutdown. See [`Stream::linger`].
+    pub fn set_linger(&self, linger: Option<Duration>) -> Result<()> {
+        SockRef::from(self).set_linger(linger)
+    }
+
+    /// Whether urgent data is placed inline with the normal data stream
+    /// (`SO_OOBINLINE`).
+    pub fn oob_inline(&self) -> Result<bool> {
+        match self {
+            Self::Tcp(x) => Ok(rustix::net::sockopt::socket_oobinline(x)?),
+            Self::Unix(_) => Err(unsupported("out-of-band data on unix sockets")),
+        }
+    }
+
+    pub fn set_oob_inline(&self, oob_inline: bool) -> Result<()> {
+        match self {
+            Self::Tcp(x) => Ok(rustix::net::sockopt::set_socket_oobinline(x, oob_inline)?),
+            Self::Unix(_) => Err(unsupported("out-of-band data on unix sockets")),
+        }
+    }
+
+    /// Send `byte` as TCP urgent data (`MSG_OOB`).
+    pub async fn send_oob(&self, byte: u8) -> Result<()> {
+        match self {
+            Self::Tcp(x) => {
+                x.async_io(Interest::WRITABLE, || {
+                    rustix::net::send(x, &[byte], SendFlags::OOB).map_err(Into::into)
+                })
+                .await?;
+
+                Ok(())
+            }
+            Self::Unix(_) => Err(unsupported("out-of-band data on unix sockets")),
+        }
+    }
+
+    /// Receive pending TCP urgent data (`MSG_OOB`).
+    ///
+    /// This waits until urgent data is available. It fails with
+    /// [`ErrorKind::InvalidInput`] if [`Stream::set_oob_inline`] is enabled,
+    /// because the urgent byte is then delivered through the normal read path.
+    ///
+    /// On Linux and Android, this waits for the priority band (`POLLPRI`),
+    /// which does not interfere with concurrent reads. Elsewhere, the socket
+    /// is checked for urgent data whenever it is readable, and every 10ms
+    /// while it stays readable without any.
+    ///
+    /// [`ErrorKind::InvalidInput`]: std::io::ErrorKind::InvalidInput
+    pub async fn recv_oob(&self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Tcp(x) => {
+                if rustix::net::sockopt::socket_oobinline(x)? {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "urgent data is delivered inline",
+                    ));
+                }
+
+                recv_oob(x, buf).await
+            }
+            Self::Unix(_) => Err(unsupported("out-of-band data on unix sockets")),
+        }
+    }
+
+    /// Send `data` along with the file descriptors `fds` (`SCM_RIGHTS`).
+    ///
+    /// The descriptors arrive with the first byte of `data`, so it must not be
+    /// empty if `fds` is not. Only supported for unix sockets.
+    pub async fn send_fds(&self, data: &[u8], fds: &[BorrowedFd<'_>]) -> Result<usize> {
+        let Self::Unix(x) = self else {
+            return Err(unsupported("file descriptor passing on tcp sockets"));
+        };
+
+        if data.is_empty() && !fds.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "file descriptors must be sent with at least one byte of data",
+            ));
+        }
+
+        x.async_io(Interest::WRITABLE, || {
+            let mut space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(fds.len()))];
+            let mut control = SendAncillaryBuffer::new(&mut space);
+
+            if !fds.is_empty() {
+                let pushed = control.push(SendAncillaryMessage::ScmRights(fds));
+                debug_assert!(pushed, "control buffer should fit all descriptors");
+            }
+
+            let iov = [IoSlice::new(data)];
+            Ok(rustix::net::sendmsg(
+                x,
+                &iov,
+                &mut control,
+                SendFlags::empty(),
+            )?)
+        })
+        .await
+    }
+
+    /// Receive data into `buf` along with up to `max_fds` file descriptors
+    /// (`SCM_RIGHTS`).
+    ///
+    /// If the peer sent more than `max_fds` descriptors with this data, the
+    /// kernel discards the rest. This is reported as an
+    /// [`ErrorKind::InvalidData`] error, after the data has been consumed and
+    /// any descriptors that did arrive have been closed. Only supported for unix
+    /// sockets.
+    pub async fn recv_fds(&self, buf: &mut [u8], max_fds: usize) -> Result<(usize, Vec<OwnedFd>)> {
+        let Self::Unix(x) = self else {
+            return Err(unsupported("file descriptor passing on tcp sockets"));
+        };
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let flags = RecvFlags::CMSG_CLOEXEC;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let flags = RecvFlags::empty();
+
+        x.async_io(Interest::READABLE, || {
+            let mut space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(max_fds))];
+            let mut control = RecvAncillaryBuffer::new(&mut space);
+
+            let mut iov = [IoSliceMut::new(&mut *buf)];
+            let msg = rustix::net::recvmsg(x, &mut iov, &mut control, flags)?;
+
+            let mut fds = Vec::new();
+            for message in control.drain() {
+                if let RecvAncillaryMessage::ScmRights(x) = message {
+                    fds.extend(x);
+                }
+            }
+
+            // The control buffer is rounded up for alignment, so it can hold
+            // a few more descriptors than were asked for.
+            if msg.flags.contains(ReturnFlags::CTRUNC) || fds.len() > max_fds {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "received more file descriptors than requested",
+                ));
+            }
+
+            Ok((msg.bytes, fds))
+        })
+        .await
+    }
+
+    /// Send `len` bytes of `file`, starting at `offset`.
+    ///
+    /// On Linux, this uses `sendfile(2)` so the data never enters userspace.
+    /// Elsewhere, or if the kernel does not support `sendfile` for this file,
+    /// the data is read from the file and written to the socket in chunks.
+    /// Either way, the position of `file` is not changed. Reading the file
+    /// blocks, like any regular file I/O.
+    ///
+    /// Returns how many bytes were sent, which is less than `len` if the file
+    /// ends early. If an error occurs after some bytes have been sent, the
+    /// number sent so far is returned instead, so the caller can resume from
+    /// there and see the error on the next call.
+    pub async fn send_file(&self, file: &File, offset: u64, len: u64) -> Result<u64> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        match self.sendfile(file, offset, len).await {
+            Err(e) if e.raw_os_error() == Some(Errno::INVAL.raw_os_error()) => {}
+            x => return x,
+        }
+
+        self.send_file_buffered(file, offset, len).await
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    async fn sendfile(&self, file: &File, offset: u64, len: u64) -> Result<u64> {
+        let mut sent = 0;
+
+        while sent < len {
+            let count = chunk_len(len - sent, SEND_FILE_CHUNK);
+            let result = self
+                .async_io(Interest::WRITABLE, || {
+                    let mut pos = offset + sent;
+                    Ok(rustix::fs::sendfile(self, file, Some(&mut pos), count)?)
+                })
+                .await;
+
+            match result {
+                // The file ended before `len`.
+                Ok(0) => break,
+                Ok(n) => sent += n as u64,
+                Err(e) if sent == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        Ok(sent)
+    }
+
+    async fn send_file_buffered(&self, file: &File, offset: u64, len: u64) -> Result<u64> {
+        let mut buf = vec![0; chunk_len(len, SEND_FILE_BUF_LEN)];
+        let mut sent = 0;
+
+        while sent < len {
+            let want = chunk_len(len - sent, buf.len());
+            let n = match file.read_at(&mut buf[..want], offset + sent) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) if sent == 0 => return Err(e),
+                Err(_) => break,
+            };
+
+            let mut written = 0;
+            while written < n {
+                let result = self
+                    .async_io(Interest::WRITABLE, || {
+                        Ok(rustix::net::send(
+                            self,
+                            &buf[written..n],
+                            SendFlags::NOSIGNAL,
+                        )?)
+                    })
+                    .await;
+
+                match result {
+                    Ok(k) => written += k,
+                    Err(e) if sent == 0 && written == 0 => return Err(e),
+                    Err(_) => return Ok(sent + written as u64),
+                }
+            }
+
+            sent += n as u64;
+        }
+
+        Ok(sent)
+    }
+}
+
+/// Most bytes handed to the kernel by one call of [`Stream::send_file`].
+const SEND_FILE_CHUNK: usize = 1 << 20;
+
+/// Size of the buffer [`Stream::send_file`] uses when it cannot use
+/// `sendfile(2)`.
+const SEND_FILE_BUF_LEN: usize = 64 * 1024;
+
+fn chunk_len(remaining: u64, max: usize) -> usize {
+    usize::try_from(remaining).map_or(max, |x| x.min(max))
+}
+
+/// Receive urgent data, waiting for the priority band.
+///
+/// tokio registers streams for read and write readiness only, so the priority
+/// band is watched through a duplicate of the descriptor.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+async fn recv_oob(x: &tokio::net::TcpStream, buf: &mut [u8]) -> Result<usize> {
+    let fd = tokio::io::unix::AsyncFd::with_interest(
+        x.as_fd().try_clone_to_owned()?,
+        Interest::PRIORITY,
+    )?;
+
+    fd.async_io(Interest::PRIORITY, |fd| {
+        match rustix::net::recv(fd, &mut *buf, RecvFlags::OOB) {
+            Ok((n, _)) => Ok(n),
+            // The urgent byte was already read. Only the priority readiness
+            // of the duplicate is cleared, so reads are not affected.
+            Err(Errno::INVAL) => Err(ErrorKind::WouldBlock.into()),
+            Err(e) => Err(e.into()),
+        }
+    })
+    .await
+}
+
+/// Receive urgent data, checking for it whenever the socket is readable.
+///
+/// Failing to receive urgent data says nothing about the normal data, so the
+/// read readiness is never cleared here. That would make a concurrent read
+/// wait for data that is already there.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+async fn recv_oob(x: &tokio::net::TcpStream, buf: &mut [u8]) -> Result<usize> {
+    loop {
+        x.readable().await?;
+
+        match rustix::net::recv(x, &mut *buf, RecvFlags::OOB) {
+            Ok((n, _)) => return Ok(n),
+            Err(Errno::INVAL | Errno::WOULDBLOCK) => sleep(Duration::from_millis(10)).await,
+            Err(e) => return Err(e.into()),
+        }
+    }
 }
 
 impl AsFd for Stream {
@@ -201,7 +1980,6 @@ impl AsRawFd for Stream {
 
 crate::macros::impl_async_read_write! {
     type: Stream,
-    proj: StreamProj,
 }
 
 impl fmt::Debug for Stream {
@@ -212,3 +1990,43 @@ impl fmt::Debug for Stream {
         }
     }
 }
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+            Self::Unix(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A standard library stream of either kind.
+///
+/// See [`Stream::from_std`] and [`Stream::into_std`].
+#[derive(Debug)]
+pub enum StdStream {
+    Tcp(std::net::TcpStream),
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl From<std::net::TcpStream> for StdStream {
+    fn from(x: std::net::TcpStream) -> Self {
+        Self::Tcp(x)
+    }
+}
+
+impl From<std::os::unix::net::UnixStream> for StdStream {
+    fn from(x: std::os::unix::net::UnixStream) -> Self {
+        Self::Unix(x)
+    }
+}
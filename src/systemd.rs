@@ -0,0 +1,256 @@
+use std::collections::BTreeSet;
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::sync::Mutex;
+
+use crate::datagram::to_rustix_addr;
+use crate::utils::{Result, check_socket, unsupported};
+use crate::{Listener, SocketAddr};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The first descriptor systemd passes (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// The descriptors passed by systemd that were already taken over.
+static ADOPTED: Mutex<BTreeSet<RawFd>> = Mutex::new(BTreeSet::new());
+
+impl Listener {
+    /// Take over the listeners passed by systemd socket activation.
+    ///
+    /// Returns the listeners in the order of `LISTEN_FDS`, starting at
+    /// descriptor 3, each with its name from `LISTEN_FDNAMES`
+    /// (`FileDescriptorName=` in the socket unit, or the name given to
+    /// [`Listener::store_in_systemd`]). Names that are missing or empty are
+    /// `None`.
+    ///
+    /// If `LISTEN_PID` or `LISTEN_FDS` is not set, or `LISTEN_PID` is not the
+    /// pid of this process, the descriptors were meant for another process and
+    /// the result is empty. If they are set but not numbers, this fails with
+    /// [`ErrorKind::InvalidInput`]. So does any of the descriptors that is not
+    /// a listening stream socket of a supported family, like one of a
+    /// `ListenDatagram=` or `Accept=yes` unit. Such a descriptor is left open,
+    /// but the listeners before it are closed.
+    ///
+    /// Each listener is a close-on-exec copy of its descriptor, and the
+    /// descriptor systemd passed is closed once the copy is checked, so child
+    /// processes do not inherit it. Each can only be taken over once, so
+    /// calling this again fails.
+    ///
+    /// The environment variables are left as they are. Removing them is not
+    /// sound while other threads may read the environment, which they do in a
+    /// tokio runtime, for example to resolve hostnames. Children still ignore
+    /// them, since `LISTEN_PID` does not match their pid.
+    ///
+    /// Only available with the `systemd` feature on Linux.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn from_systemd() -> Result<Vec<(Option<String>, Self)>> {
+        let Some(n) = listen_fds()? else {
+            return Ok(Vec::new());
+        };
+
+        let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+        let mut names = names.split(':');
+
+        (LISTEN_FDS_START..LISTEN_FDS_START + n)
+            .map(|fd| {
+                let name = names.next().filter(|x| !x.is_empty()).map(str::to_owned);
+                Ok((name, adopt(fd)?))
+            })
+            .collect()
+    }
+
+    /// Take over the listener named `name` in `LISTEN_FDNAMES`, like
+    /// [`Listener::from_systemd`], and leave the other descriptors alone.
+    ///
+    /// Listeners stored with [`Listener::store_in_systemd`] come back under
+    /// the name they were stored with. If several descriptors have the name,
+    /// this takes the first one. Returns `None` if none has it, or if the
+    /// descriptors were meant for another process.
+    ///
+    /// Only available with the `systemd` feature on Linux.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn from_systemd_named(name: &str) -> Result<Option<Self>> {
+        let Some(n) = listen_fds()? else {
+            return Ok(None);
+        };
+
+        let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+
+        names
+            .split(':')
+            .zip(LISTEN_FDS_START..LISTEN_FDS_START + n)
+            .find(|(x, _)| *x == name)
+            .map(|(_, fd)| adopt(fd))
+            .transpose()
+    }
+
+    /// Keep the listener in the file descriptor store of the service, so it
+    /// survives a restart (`FDSTORE=1`).
+    ///
+    /// systemd passes it back to the next instance of the service with the
+    /// others, named `name`, see [`Listener::from_systemd_named`]. This only
+    /// works if the service sets `FileDescriptorStoreMax=`, otherwise systemd
+    /// drops the descriptor. Storing a listener again under the same name
+    /// keeps both.
+    ///
+    /// `name` must be 1 to 255 printable ASCII characters other than `:`,
+    /// else this fails with [`ErrorKind::InvalidInput`]. If `$NOTIFY_SOCKET`
+    /// is not set, because the process was not started by systemd, this fails
+    /// with [`ErrorKind::NotFound`].
+    ///
+    /// Only available with the `systemd` feature on Linux.
+    pub fn store_in_systemd(&self, name: &str) -> Result<()> {
+        if name.is_empty()
+            || name.len() > 255
+            || !name.bytes().all(|x| x.is_ascii_graphic() && x != b':')
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "invalid file descriptor name",
+            ));
+        }
+
+        let msg = format!("FDSTORE=1\nFDNAME={name}");
+        notify(msg.as_bytes(), self.as_fd())
+    }
+}
+
+/// Send `msg` to the service manager, with `fd` attached.
+fn notify(msg: &[u8], fd: BorrowedFd<'_>) -> Result<()> {
+    use std::io::IoSlice;
+    use std::mem::MaybeUninit;
+
+    use rustix::net::{SendAncillaryBuffer, SendAncillaryMessage, SendFlags, sendmsg_addr};
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Err(Error::new(ErrorKind::NotFound, "NOTIFY_SOCKET is not set"));
+    };
+
+    let addr = match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::unix_abstract(name)?,
+        None if path.as_bytes().starts_with(b"/") => SocketAddr::unix(&path)?,
+        None => {
+            return Err(unsupported(&format!(
+                "NOTIFY_SOCKET {}",
+                path.to_string_lossy()
+            )));
+        }
+    };
+
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+
+    let fds = [fd];
+    let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(1))];
+    let mut control = SendAncillaryBuffer::new(&mut space);
+    control.push(SendAncillaryMessage::ScmRights(&fds));
+
+    sendmsg_addr(
+        &socket,
+        &to_rustix_addr(&addr)?,
+        &[IoSlice::new(msg)],
+        &mut control,
+        SendFlags::empty(),
+    )?;
+
+    Ok(())
+}
+
+/// The number of descriptors passed to this process, if any.
+fn listen_fds() -> Result<Option<RawFd>> {
+    let (Ok(pid), Ok(fds)) = (std::env::var("LISTEN_PID"), std::env::var("LISTEN_FDS")) else {
+        return Ok(None);
+    };
+
+    let pid: u32 = pid
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid LISTEN_PID"))?;
+
+    if pid != std::process::id() {
+        return Ok(None);
+    }
+
+    let n: RawFd = fds
+        .parse()
+        .ok()
+        .filter(|x| (0..=RawFd::MAX - LISTEN_FDS_START).contains(x))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid LISTEN_FDS"))?;
+
+    Ok(Some(n))
+}
+
+fn adopt(fd: RawFd) -> Result<Listener> {
+    let context =
+        |e: Error| Error::new(e.kind(), format!("descriptor {fd} passed by systemd: {e}"));
+
+    let mut adopted = ADOPTED.lock().unwrap_or_else(|e| e.into_inner());
+    if adopted.contains(&fd) {
+        return Err(context(Error::new(
+            ErrorKind::InvalidInput,
+            "already adopted",
+        )));
+    }
+
+    let owned = copy(fd).map_err(context)?;
+
+    let domain = check_socket(owned.as_fd(), true)
+        .map_err(|e| {
+            if e.raw_os_error() == Some(rustix::io::Errno::NOTSOCK.raw_os_error()) {
+                Error::new(ErrorKind::InvalidInput, "not a socket")
+            } else {
+                e
+            }
+        })
+        .map_err(context)?;
+
+    // The number was checked against `ADOPTED` above and `LISTEN_FDS` hands it
+    // to this process, so nothing else owns it.
+    nix::unistd::close(fd).map_err(|e| context(e.into()))?;
+    adopted.insert(fd);
+
+    Listener::from_fd(owned, domain)
+}
+
+/// Make an owned, close-on-exec copy of descriptor `fd` by passing it to this
+/// process over a socket pair.
+fn copy(fd: RawFd) -> Result<OwnedFd> {
+    use std::io::{IoSlice, IoSliceMut};
+    use std::mem::MaybeUninit;
+
+    use nix::sys::socket::{ControlMessage, MsgFlags, UnixAddr, sendmsg};
+    use rustix::net::{RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, recvmsg};
+
+    let (a, b) = std::os::unix::net::UnixDatagram::pair()?;
+
+    sendmsg::<UnixAddr>(
+        a.as_raw_fd(),
+        &[IoSlice::new(&[0])],
+        &[ControlMessage::ScmRights(&[fd])],
+        MsgFlags::empty(),
+        None,
+    )?;
+
+    let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(1))];
+    let mut control = RecvAncillaryBuffer::new(&mut space);
+    recvmsg(
+        &b,
+        &mut [IoSliceMut::new(&mut [0])],
+        &mut control,
+        RecvFlags::CMSG_CLOEXEC,
+    )?;
+
+    control
+        .drain()
+        .find_map(|x| match x {
+            RecvAncillaryMessage::ScmRights(mut x) => x.next(),
+            _ => None,
+        })
+        .ok_or_else(|| Error::other("no descriptor received"))
+}
@@ -0,0 +1,99 @@
+use std::fs::DirBuilder;
+use std::io::ErrorKind;
+#[cfg(feature = "test-util")]
+use std::net::Ipv4Addr;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils::Result;
+use crate::{Listener, SocketAddr};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Removes a temporary socket and its private directory on drop.
+///
+/// Returned by [`Listener::bind_unix_temp`].
+#[derive(Debug)]
+pub struct TempSocketGuard {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl TempSocketGuard {
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempSocketGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_dir(&self.dir);
+    }
+}
+
+fn create_private_dir(prefix: &str) -> Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let base = std::env::temp_dir();
+    let pid = std::process::id();
+
+    loop {
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.subsec_nanos())
+            .unwrap_or(0);
+
+        let dir = base.join(format!("{prefix}{pid}-{n}-{nanos:08x}"));
+
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl Listener {
+    /// Bind a unix listener in a fresh private temporary directory.
+    ///
+    /// The directory is created with mode `0700` under [`std::env::temp_dir`].
+    /// Both the socket file and the directory are removed when the returned
+    /// guard is dropped. Use [`Listener::local_addr`] to get the address
+    /// clients should connect to.
+    pub fn bind_unix_temp(prefix: &str) -> Result<(Self, TempSocketGuard)> {
+        let dir = create_private_dir(prefix)?;
+        let path = dir.join("socket");
+
+        let guard = TempSocketGuard { dir, path };
+
+        // A long temporary directory should fail with a clear error.
+        SocketAddr::unix(&guard.path)?;
+        let listener = tokio::net::UnixListener::bind(&guard.path)?;
+
+        Ok((listener.into(), guard))
+    }
+
+    /// Bind a TCP listener on `127.0.0.1` with a port picked by the system.
+    ///
+    /// Must be called within a tokio runtime.
+    #[cfg(feature = "test-util")]
+    pub fn bind_ephemeral_tcp() -> Result<Self> {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        listener.set_nonblocking(true)?;
+        tokio::net::TcpListener::from_std(listener).map(Into::into)
+    }
+
+    /// Bind a unix listener at a unique path, like
+    /// [`Listener::bind_unix_temp`].
+    ///
+    /// Must be called within a tokio runtime.
+    #[cfg(feature = "test-util")]
+    pub fn bind_ephemeral_unix() -> Result<(Self, TempSocketGuard)> {
+        Self::bind_unix_temp("tokio-anysocket-")
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io::IoSlice;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker, ready};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep, sleep_until};
+
+use crate::utils::Result;
+use crate::{OwnedReadHalf, OwnedWriteHalf, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A bandwidth limit for a [`Limiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Limit {
+    /// Bytes per second.
+    pub rate: u64,
+    /// How many bytes can be transferred at once after a pause. The bucket
+    /// holds this many tokens when full.
+    pub burst: u64,
+}
+
+impl Limit {
+    /// A limit of `rate` bytes per second, with a burst of one second worth of
+    /// bytes.
+    ///
+    /// # Panics
+    ///
+    /// If `rate` is zero.
+    #[must_use]
+    pub fn per_second(rate: u64) -> Self {
+        assert!(rate > 0, "rate must not be zero");
+        Self { rate, burst: rate }
+    }
+
+    /// # Panics
+    ///
+    /// If `burst` is zero.
+    #[must_use]
+    pub fn burst(mut self, burst: u64) -> Self {
+        assert!(burst > 0, "burst must not be zero");
+        self.burst = burst;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: Option<Limit>,
+    /// Negative after concurrent users took more than there was.
+    tokens: f64,
+    last: Instant,
+    /// The users waiting for tokens, woken when the limit changes.
+    waiting: HashMap<u64, Waker>,
+    next_id: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = f64::min(
+            self.tokens + elapsed * limit.rate as f64,
+            limit.burst as f64,
+        );
+        self.last = now;
+    }
+}
+
+/// A token bucket that limits the bandwidth of the [`ThrottledStream`]s using
+/// it.
+///
+/// Clones share the bucket, so using one limiter for several streams, or for
+/// both directions of one, caps their combined bandwidth. The limit can be
+/// changed at any time with [`Limiter::set_limit`], which also applies to
+/// reads and writes that are already waiting.
+///
+/// The bucket starts full.
+#[derive(Clone)]
+pub struct Limiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl Limiter {
+    /// A limiter with `limit`, or one that does not limit anything until a
+    /// limit is set if `None`.
+    pub fn new(limit: Option<Limit>) -> Self {
+        let bucket = Bucket {
+            limit,
+            tokens: limit.map_or(0.0, |x| x.burst as f64),
+            last: Instant::now(),
+            waiting: HashMap::new(),
+            next_id: 0,
+        };
+
+        Self {
+            bucket: Arc::new(Mutex::new(bucket)),
+        }
+    }
+
+    fn bucket(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    #[must_use]
+    pub fn limit(&self) -> Option<Limit> {
+        self.bucket().limit
+    }
+
+    /// Change the limit, or remove it with `None`.
+    ///
+    /// The tokens in the bucket are kept, up to the new burst. Removing the
+    /// limit and setting one again refills the bucket.
+    pub fn set_limit(&self, limit: Option<Limit>) {
+        let mut bucket = self.bucket();
+        let now = Instant::now();
+
+        match (bucket.limit, limit) {
+            (Some(old), Some(new)) => {
+                bucket.refill(old, now);
+                bucket.tokens = f64::min(bucket.tokens, new.burst as f64);
+            }
+            (None, Some(new)) => bucket.tokens = new.burst as f64,
+            (_, None) => {}
+        }
+
+        bucket.limit = limit;
+        bucket.last = now;
+
+        for (_, waker) in bucket.waiting.drain() {
+            waker.wake();
+        }
+    }
+
+    fn register(&self) -> u64 {
+        let mut bucket = self.bucket();
+        bucket.next_id += 1;
+        bucket.next_id
+    }
+
+    /// Take up to `want` bytes worth of tokens, or the time until there are
+    /// some.
+    fn acquire(&self, id: u64, want: usize, waker: &Waker) -> Result<usize, Instant> {
+        let mut bucket = self.bucket();
+
+        let Some(limit) = bucket.limit else {
+            return Ok(want);
+        };
+
+        let now = Instant::now();
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.waiting.remove(&id);
+
+            // Truncates, and saturates on 32-bit targets.
+            let available = bucket.tokens as usize;
+            return Ok(want.min(available));
+        }
+
+        bucket.waiting.insert(id, waker.clone());
+
+        let wait = (1.0 - bucket.tokens) / limit.rate as f64;
+        Err(now + Duration::from_secs_f64(wait))
+    }
+
+    fn consume(&self, n: usize) {
+        let mut bucket = self.bucket();
+        if bucket.limit.is_some() {
+            bucket.tokens -= n as f64;
+        }
+    }
+
+    fn unregister(&self, id: u64) {
+        self.bucket().waiting.remove(&id);
+    }
+}
+
+impl fmt::Debug for Limiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Limiter")
+            .field("limit", &self.limit())
+            .finish_non_exhaustive()
+    }
+}
+
+/// One direction of a throttled stream.
+struct Throttle {
+    limiter: Limiter,
+    id: u64,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Throttle {
+    fn new(limiter: Limiter) -> Self {
+        let id = limiter.register();
+
+        Self {
+            limiter,
+            id,
+            sleep: None,
+        }
+    }
+
+    /// Wait until at least one of `want` bytes may be transferred, and return
+    /// how many.
+    fn poll_acquire(&mut self, cx: &mut Context<'_>, want: usize) -> Poll<usize> {
+        if want == 0 {
+            return Poll::Ready(0);
+        }
+
+        loop {
+            let deadline = match self.limiter.acquire(self.id, want, cx.waker()) {
+                Ok(n) => return Poll::Ready(n),
+                Err(x) => x,
+            };
+
+            let sleep = match &mut self.sleep {
+                Some(x) => {
+                    x.as_mut().reset(deadline);
+                    x
+                }
+                None => self.sleep.insert(Box::pin(sleep_until(deadline))),
+            };
+
+            ready!(sleep.as_mut().poll(cx));
+        }
+    }
+
+    fn consume<T>(&self, poll: &Poll<Result<T>>, n: impl FnOnce(&T) -> usize) {
+        if let Poll::Ready(Ok(x)) = poll {
+            self.limiter.consume(n(x));
+        }
+    }
+}
+
+impl Drop for Throttle {
+    fn drop(&mut self) {
+        self.limiter.unregister(self.id);
+    }
+}
+
+impl fmt::Debug for Throttle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.limiter.limit().fmt(f)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+macro_rules! impl_throttled {
+    ($T:ident, $inner:ty) => {
+        impl $T {
+            #[must_use]
+            pub fn get_ref(&self) -> &$inner {
+                &self.inner
+            }
+
+            #[must_use]
+            pub fn get_mut(&mut self) -> &mut $inner {
+                &mut self.inner
+            }
+
+            pub fn into_inner(self) -> $inner {
+                self.inner
+            }
+        }
+
+        impl Deref for $T {
+            type Target = $inner;
+
+            fn deref(&self) -> &Self::Target {
+                &self.inner
+            }
+        }
+
+        impl DerefMut for $T {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.inner
+            }
+        }
+    };
+}
+
+macro_rules! impl_throttled_read {
+    ($T:ident) => {
+        impl $T {
+            /// The limiter of reads.
+            #[must_use]
+            pub fn read_limiter(&self) -> &Limiter {
+                &self.read.limiter
+            }
+        }
+
+        impl AsyncRead for $T {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<Result<()>> {
+                let this = self.get_mut();
+                let allowed = ready!(this.read.poll_acquire(cx, buf.remaining()));
+
+                if allowed == buf.remaining() {
+                    let before = buf.filled().len();
+                    let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+                    this.read.consume(&poll, |_| buf.filled().len() - before);
+                    return poll;
+                }
+
+                let mut limited = ReadBuf::new(buf.initialize_unfilled_to(allowed));
+                let poll = Pin::new(&mut this.inner).poll_read(cx, &mut limited);
+                let n = limited.filled().len();
+
+                this.read.consume(&poll, |_| n);
+                buf.advance(n);
+                poll
+            }
+        }
+    };
+}
+
+macro_rules! impl_throttled_write {
+    ($T:ident) => {
+        impl $T {
+            /// The limiter of writes.
+            #[must_use]
+            pub fn write_limiter(&self) -> &Limiter {
+                &self.write.limiter
+            }
+        }
+
+        impl AsyncWrite for $T {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<Result<usize>> {
+                let this = self.get_mut();
+                let allowed = ready!(this.write.poll_acquire(cx, buf.len()));
+
+                let poll = Pin::new(&mut this.inner).poll_write(cx, &buf[..allowed]);
+                this.write.consume(&poll, |&n| n);
+                poll
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[IoSlice<'_>],
+            ) -> Poll<Result<usize>> {
+                let this = self.get_mut();
+                let len = bufs.iter().map(|x| x.len()).sum();
+                let allowed = ready!(this.write.poll_acquire(cx, len));
+
+                let poll = if allowed == len {
+                    Pin::new(&mut this.inner).poll_write_vectored(cx, bufs)
+                } else {
+                    let bufs = limit_slices(bufs, allowed);
+                    Pin::new(&mut this.inner).poll_write_vectored(cx, &bufs)
+                };
+
+                this.write.consume(&poll, |&n| n);
+                poll
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                self.inner.is_write_vectored()
+            }
+        }
+    };
+}
+
+/// The first `len` bytes of `bufs`.
+fn limit_slices<'a>(bufs: &'a [IoSlice<'_>], mut len: usize) -> Vec<IoSlice<'a>> {
+    let mut limited = Vec::new();
+
+    for buf in bufs {
+        if len == 0 {
+            break;
+        }
+
+        let n = usize::min(buf.len(), len);
+        limited.push(IoSlice::new(&buf[..n]));
+        len -= n;
+    }
+
+    limited
+}
+
+/// A [`Stream`] whose reads and writes are limited to a bandwidth.
+///
+/// Each direction takes tokens from the bucket of its [`Limiter`], one per
+/// byte. Reads and writes are cut short to the tokens available, and wait for
+/// the bucket to refill when it is empty. A transfer of `n` bytes starting
+/// with a full bucket so takes about `(n - burst) / rate` seconds. Vectored
+/// writes are limited to the total length of their buffers.
+///
+/// Reads are limited by how much is taken from the socket, so the peer is
+/// slowed down by the usual flow control once the receive buffer is full.
+///
+/// Only reads and writes through [`AsyncRead`] and [`AsyncWrite`] are
+/// limited. The methods of the inner stream are available through [`Deref`],
+/// but using it to read or write skips the limit.
+///
+/// Created by [`Stream::throttled`].
+///
+/// # Panics
+///
+/// Waiting for tokens panics outside of a tokio runtime with the time driver
+/// enabled.
+#[derive(Debug)]
+pub struct ThrottledStream {
+    inner: Stream,
+    read: Throttle,
+    write: Throttle,
+}
+
+impl ThrottledStream {
+    /// Limit reads with `read` and writes with `write`.
+    ///
+    /// Pass the same limiter for both to limit their combined bandwidth.
+    pub fn new(stream: Stream, read: Limiter, write: Limiter) -> Self {
+        Self {
+            inner: stream,
+            read: Throttle::new(read),
+            write: Throttle::new(write),
+        }
+    }
+
+    /// Split into halves which keep using the same limiters.
+    pub fn into_split(self) -> (ThrottledOwnedReadHalf, ThrottledOwnedWriteHalf) {
+        let (r, w) = self.inner.into_split();
+
+        let r = ThrottledOwnedReadHalf {
+            inner: r,
+            read: self.read,
+        };
+
+        let w = ThrottledOwnedWriteHalf {
+            inner: w,
+            write: self.write,
+        };
+
+        (r, w)
+    }
+}
+
+impl_throttled!(ThrottledStream, Stream);
+impl_throttled_read!(ThrottledStream);
+impl_throttled_write!(ThrottledStream);
+
+/// The read half of a [`ThrottledStream`].
+#[derive(Debug)]
+pub struct ThrottledOwnedReadHalf {
+    inner: OwnedReadHalf,
+    read: Throttle,
+}
+
+impl_throttled!(ThrottledOwnedReadHalf, OwnedReadHalf);
+impl_throttled_read!(ThrottledOwnedReadHalf);
+
+/// The write half of a [`ThrottledStream`].
+#[derive(Debug)]
+pub struct ThrottledOwnedWriteHalf {
+    inner: OwnedWriteHalf,
+    write: Throttle,
+}
+
+impl_throttled!(ThrottledOwnedWriteHalf, OwnedWriteHalf);
+impl_throttled_write!(ThrottledOwnedWriteHalf);
@@ -0,0 +1,266 @@
+use std::fmt;
+use std::future::Future;
+use std::io::{Error, ErrorKind, IoSlice};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep, sleep};
+
+use crate::utils::Result;
+use crate::{OwnedReadHalf, OwnedWriteHalf, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Fails an operation that has made no progress for `timeout`.
+///
+/// The timer is armed when the operation first returns pending and disarmed
+/// as soon as it completes, so every bit of progress starts a new period.
+struct Timer {
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    armed: bool,
+    what: &'static str,
+}
+
+impl Timer {
+    fn new(timeout: Option<Duration>, what: &'static str) -> Self {
+        Self {
+            timeout,
+            sleep: None,
+            armed: false,
+            what,
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        self.armed = false;
+    }
+
+    fn poll<T>(&mut self, cx: &mut Context<'_>, poll: Poll<Result<T>>) -> Poll<Result<T>> {
+        let Some(timeout) = self.timeout else {
+            return poll;
+        };
+
+        if poll.is_ready() {
+            self.armed = false;
+            return poll;
+        }
+
+        let sleep = match &mut self.sleep {
+            Some(x) if self.armed => x,
+            Some(x) => {
+                x.as_mut().reset(Instant::now() + timeout);
+                x
+            }
+            None => self.sleep.insert(Box::pin(sleep(timeout))),
+        };
+        self.armed = true;
+
+        ready!(sleep.as_mut().poll(cx));
+        self.armed = false;
+
+        Poll::Ready(Err(Error::new(
+            ErrorKind::TimedOut,
+            format!("{} made no progress in time", self.what),
+        )))
+    }
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.timeout.fmt(f)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+macro_rules! impl_timed {
+    ($T:ident, $inner:ty) => {
+        impl $T {
+            #[must_use]
+            pub fn get_ref(&self) -> &$inner {
+                &self.inner
+            }
+
+            #[must_use]
+            pub fn get_mut(&mut self) -> &mut $inner {
+                &mut self.inner
+            }
+
+            pub fn into_inner(self) -> $inner {
+                self.inner
+            }
+        }
+
+        impl Deref for $T {
+            type Target = $inner;
+
+            fn deref(&self) -> &Self::Target {
+                &self.inner
+            }
+        }
+
+        impl DerefMut for $T {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.inner
+            }
+        }
+    };
+}
+
+macro_rules! impl_timed_read {
+    ($T:ident) => {
+        impl $T {
+            #[must_use]
+            pub fn read_timeout(&self) -> Option<Duration> {
+                self.read.timeout
+            }
+
+            pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+                self.read.set_timeout(timeout);
+            }
+        }
+
+        impl AsyncRead for $T {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<Result<()>> {
+                let this = self.get_mut();
+                let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+                this.read.poll(cx, poll)
+            }
+        }
+    };
+}
+
+macro_rules! impl_timed_write {
+    ($T:ident) => {
+        impl $T {
+            #[must_use]
+            pub fn write_timeout(&self) -> Option<Duration> {
+                self.write.timeout
+            }
+
+            pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+                self.write.set_timeout(timeout);
+            }
+        }
+
+        impl AsyncWrite for $T {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<Result<usize>> {
+                let this = self.get_mut();
+                let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+                this.write.poll(cx, poll)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                let this = self.get_mut();
+                let poll = Pin::new(&mut this.inner).poll_flush(cx);
+                this.write.poll(cx, poll)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                let this = self.get_mut();
+                let poll = Pin::new(&mut this.inner).poll_shutdown(cx);
+                this.write.poll(cx, poll)
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[IoSlice<'_>],
+            ) -> Poll<Result<usize>> {
+                let this = self.get_mut();
+                let poll = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+                this.write.poll(cx, poll)
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                self.inner.is_write_vectored()
+            }
+        }
+    };
+}
+
+/// A [`Stream`] whose reads and writes fail when they make no progress for a
+/// while.
+///
+/// A read or write through [`AsyncRead`] or [`AsyncWrite`] that stays pending
+/// for longer than its timeout fails with [`ErrorKind::TimedOut`]. The period
+/// starts over whenever any bytes are transferred, so a long transfer only
+/// times out if it stalls, not because it takes long overall. Without a
+/// timeout, the direction behaves like the inner stream.
+///
+/// Created by [`Stream::with_timeouts`].
+///
+/// # Panics
+///
+/// Reading or writing with a timeout panics outside of a tokio runtime with
+/// the time driver enabled.
+#[derive(Debug)]
+pub struct TimedStream {
+    inner: Stream,
+    read: Timer,
+    write: Timer,
+}
+
+impl TimedStream {
+    pub fn new(stream: Stream, read: Option<Duration>, write: Option<Duration>) -> Self {
+        Self {
+            inner: stream,
+            read: Timer::new(read, "read"),
+            write: Timer::new(write, "write"),
+        }
+    }
+
+    /// Split into halves which each keep their own timeout.
+    pub fn into_split(self) -> (TimedOwnedReadHalf, TimedOwnedWriteHalf) {
+        let (r, w) = self.inner.into_split();
+
+        let r = TimedOwnedReadHalf {
+            inner: r,
+            read: Timer::new(self.read.timeout, "read"),
+        };
+
+        let w = TimedOwnedWriteHalf {
+            inner: w,
+            write: Timer::new(self.write.timeout, "write"),
+        };
+
+        (r, w)
+    }
+}
+
+impl_timed!(TimedStream, Stream);
+impl_timed_read!(TimedStream);
+impl_timed_write!(TimedStream);
+
+/// The read half of a [`TimedStream`].
+#[derive(Debug)]
+pub struct TimedOwnedReadHalf {
+    inner: OwnedReadHalf,
+    read: Timer,
+}
+
+impl_timed!(TimedOwnedReadHalf, OwnedReadHalf);
+impl_timed_read!(TimedOwnedReadHalf);
+
+/// The write half of a [`TimedStream`].
+#[derive(Debug)]
+pub struct TimedOwnedWriteHalf {
+    inner: OwnedWriteHalf,
+    write: Timer,
+}
+
+impl_timed!(TimedOwnedWriteHalf, OwnedWriteHalf);
+impl_timed_write!(TimedOwnedWriteHalf);
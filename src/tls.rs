@@ -0,0 +1,723 @@
+//! TLS over the streams of this crate, through `tokio-rustls` with the `tls`
+//! feature or `tokio-native-tls` with the `tls-native` feature.
+//!
+//! Both features can be enabled at once. Each [`TlsConnector`] and
+//! [`TlsAcceptor`] uses the library of the connector or acceptor it was made
+//! from, and [`MaybeTlsStream`] has a variant for each.
+//!
+//! A [`TlsConnector`] connects to `tls+<addr>` addresses, like
+//! `tls+tcp://example.com:443` or `tls+unix:///run/app.sock`, by connecting
+//! to `<addr>` and performing the TLS handshake. Other addresses are connected
+//! to in plain text, so whether a connection uses TLS can come from
+//! configuration. Either way, the result is a [`MaybeTlsStream`], which keeps
+//! the address methods of the underlying [`crate::Stream`].
+//!
+//! The server name of the handshake (SNI), which the certificate of the server
+//! is checked against, is the host of `tcp://` addresses. Unix addresses have
+//! none, so connecting to them needs [`TlsConnector::server_name`].
+//!
+//! A [`TlsListener`] performs the handshake of accepted connections, in the
+//! background and with a timeout, so that slow clients do not hold up the
+//! others.
+//!
+//! Errors of the handshake wrap a [`HandshakeError`], so they can be told
+//! apart from errors while connecting or accepting.
+
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::task::JoinSet;
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls::pki_types::ServerName;
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+use crate::SocketAddr;
+use crate::socket_addr::tcp_hostname;
+use crate::utils::Result;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Connects to `tls+<addr>` addresses with TLS and to other addresses
+/// without.
+///
+/// It is made from the connector of a TLS library, with [`From`], which holds
+/// the trusted roots, the ALPN protocols and the client certificate for mutual
+/// TLS, if any. Cloning is cheap and shares the configuration.
+#[derive(Clone)]
+pub struct TlsConnector {
+    backend: Backend,
+    server_name: Option<String>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    #[cfg(feature = "tls")]
+    Rustls(tokio_rustls::TlsConnector),
+    #[cfg(feature = "tls-native")]
+    Native(tokio_native_tls::TlsConnector),
+}
+
+#[cfg(feature = "tls")]
+impl From<tokio_rustls::TlsConnector> for TlsConnector {
+    fn from(x: tokio_rustls::TlsConnector) -> Self {
+        Self {
+            backend: Backend::Rustls(x),
+            server_name: None,
+        }
+    }
+}
+
+/// Only available with the `tls-native` feature.
+#[cfg(feature = "tls-native")]
+impl From<tokio_native_tls::TlsConnector> for TlsConnector {
+    fn from(x: tokio_native_tls::TlsConnector) -> Self {
+        Self {
+            backend: Backend::Native(x),
+            server_name: None,
+        }
+    }
+}
+
+impl TlsConnector {
+    /// Use rustls with `config`.
+    ///
+    /// Only available with the `tls` feature.
+    #[cfg(feature = "tls")]
+    #[must_use]
+    pub fn new(config: Arc<ClientConfig>) -> Self {
+        tokio_rustls::TlsConnector::from(config).into()
+    }
+
+    /// Use rustls, trusting the certificates of `roots`, with the `ring`
+    /// crypto provider, the default protocol versions and no client
+    /// certificate.
+    ///
+    /// Only available with the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn with_roots(roots: RootCertStore) -> Result<Self> {
+        let provider = tokio_rustls::rustls::crypto::ring::default_provider();
+
+        let config = ClientConfig::builder_with_provider(provider.into())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Ok(Self::new(config.into()))
+    }
+
+    /// Use native-tls with the defaults of the platform, which trust the roots
+    /// of the platform trust store.
+    ///
+    /// Only available with the `tls-native` feature.
+    #[cfg(feature = "tls-native")]
+    pub fn native() -> Result<Self> {
+        let connector = tokio_native_tls::native_tls::TlsConnector::new().map_err(native_error)?;
+        Ok(tokio_native_tls::TlsConnector::from(connector).into())
+    }
+
+    /// Offer `protocols` with ALPN, most preferred first, instead of the ones
+    /// of the configuration.
+    ///
+    /// Only available with the `tls` feature.
+    ///
+    /// # Panics
+    ///
+    /// If the connector uses native-tls, which takes the protocols when the
+    /// connector is built (`TlsConnectorBuilder::request_alpns`).
+    #[cfg(feature = "tls")]
+    #[must_use]
+    pub fn alpn_protocols(mut self, protocols: Vec<Vec<u8>>) -> Self {
+        match &mut self.backend {
+            Backend::Rustls(x) => {
+                let mut config = ClientConfig::clone(x.config());
+                config.alpn_protocols = protocols;
+                *x = Arc::new(config).into();
+            }
+            #[cfg(feature = "tls-native")]
+            Backend::Native(_) => panic!("native-tls connectors take ALPN protocols when built"),
+        }
+
+        self
+    }
+
+    /// Use `name` as the server name of all handshakes, instead of the host of
+    /// the address.
+    #[must_use]
+    pub fn server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Connect to `addr`, with TLS if it starts with `tls+`.
+    ///
+    /// The rest of the address is connected to like with
+    /// [`crate::Stream::connect`]. Addresses without a server name fail with
+    /// [`ErrorKind::InvalidInput`] before connecting.
+    pub async fn connect(&self, addr: &str) -> Result<MaybeTlsStream> {
+        let Some(addr) = strip_tls_scheme(addr) else {
+            return crate::Stream::connect(addr)
+                .await
+                .map(MaybeTlsStream::Plain);
+        };
+
+        let server_name = match (&self.server_name, host(addr)) {
+            (Some(x), _) => x.clone(),
+            (None, Some(x)) => x,
+            (None, None) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("no server name for tls+{addr}"),
+                ));
+            }
+        };
+
+        let stream = crate::Stream::connect(addr).await?;
+        self.handshake_with(stream, &server_name).await
+    }
+
+    /// Perform the handshake as the client over `stream`, with the server
+    /// name set by [`TlsConnector::server_name`] or else `server_name`.
+    pub async fn handshake(
+        &self,
+        stream: crate::Stream,
+        server_name: &str,
+    ) -> Result<MaybeTlsStream> {
+        let server_name = self.server_name.as_deref().unwrap_or(server_name);
+        self.handshake_with(stream, server_name).await
+    }
+
+    async fn handshake_with(
+        &self,
+        stream: crate::Stream,
+        server_name: &str,
+    ) -> Result<MaybeTlsStream> {
+        let addr = stream.peer_addr().ok();
+
+        let result = match &self.backend {
+            #[cfg(feature = "tls")]
+            Backend::Rustls(x) => {
+                let server_name = ServerName::try_from(server_name.to_owned())
+                    .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+                x.connect(server_name, stream)
+                    .await
+                    .map(|x| MaybeTlsStream::Rustls(Box::new(x.into())))
+            }
+            #[cfg(feature = "tls-native")]
+            Backend::Native(x) => x
+                .connect(server_name, stream)
+                .await
+                .map(|x| MaybeTlsStream::Native(Box::new(x)))
+                .map_err(native_error),
+        };
+
+        result.map_err(|e| HandshakeError::into_error(e, addr))
+    }
+}
+
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let backend = match self.backend {
+            #[cfg(feature = "tls")]
+            Backend::Rustls(_) => "rustls",
+            #[cfg(feature = "tls-native")]
+            Backend::Native(_) => "native-tls",
+        };
+
+        f.debug_struct("TlsConnector")
+            .field("backend", &backend)
+            .field("server_name", &self.server_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Get `<addr>` of a `tls+<addr>` address.
+fn strip_tls_scheme(addr: &str) -> Option<&str> {
+    addr.get(..4)
+        .filter(|x| x.eq_ignore_ascii_case("tls+"))
+        .map(|_| &addr[4..])
+}
+
+/// The host of a `tcp://` address, which may be an IP address.
+fn host(addr: &str) -> Option<String> {
+    match addr.parse() {
+        Ok(SocketAddr::Tcp(x)) => Some(x.ip().to_string()),
+        Ok(_) => None,
+        Err(_) => tcp_hostname(addr).map(|(_, host, _)| host.to_owned()),
+    }
+}
+
+/// Convert an error of native-tls, keeping the kind of the I/O error behind
+/// it, if any.
+#[cfg(feature = "tls-native")]
+fn native_error(e: tokio_native_tls::native_tls::Error) -> Error {
+    let kind = std::iter::successors(Some(&e as &dyn std::error::Error), |x| x.source())
+        .find_map(|x| x.downcast_ref::<Error>())
+        .map_or(ErrorKind::InvalidData, Error::kind);
+
+    Error::new(kind, e)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Options of a [`TlsListener`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct HandshakeOptions {
+    /// How long a client may take to complete the handshake. Defaults to 10
+    /// seconds.
+    pub timeout: Option<Duration>,
+
+    /// Maximum number of handshakes running at once.
+    ///
+    /// When the limit is reached, no new connections are accepted until a
+    /// handshake finishes. Must not be zero.
+    pub max_handshakes: Option<usize>,
+}
+
+impl Default for HandshakeOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(10)),
+            max_handshakes: None,
+        }
+    }
+}
+
+impl HandshakeOptions {
+    #[must_use]
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[must_use]
+    pub fn max_handshakes(mut self, max: usize) -> Self {
+        self.max_handshakes = Some(max);
+        self
+    }
+}
+
+/// A [`crate::Listener`] that performs the TLS handshake of accepted
+/// connections as the server.
+///
+/// Each handshake runs in a task of its own, so [`TlsListener::accept`] keeps
+/// accepting connections while clients are in the middle of theirs, and
+/// returns whichever connection completes its handshake first. Handshakes that
+/// take longer than [`HandshakeOptions::timeout`] fail with
+/// [`ErrorKind::TimedOut`].
+///
+/// A failed handshake is returned as an error that wraps a [`HandshakeError`].
+/// It only concerns that connection, so keep accepting after it. Handshakes
+/// still running when the listener is dropped are aborted.
+pub struct TlsListener {
+    listener: crate::Listener,
+    acceptor: TlsAcceptor,
+    options: HandshakeOptions,
+    handshakes: JoinSet<Result<(MaybeTlsStream, SocketAddr)>>,
+}
+
+impl TlsListener {
+    #[must_use]
+    pub fn new(listener: crate::Listener, acceptor: impl Into<TlsAcceptor>) -> Self {
+        Self::with_options(listener, acceptor, HandshakeOptions::default())
+    }
+
+    /// # Panics
+    ///
+    /// If [`HandshakeOptions::max_handshakes`] is zero.
+    #[must_use]
+    pub fn with_options(
+        listener: crate::Listener,
+        acceptor: impl Into<TlsAcceptor>,
+        options: HandshakeOptions,
+    ) -> Self {
+        let acceptor = acceptor.into();
+
+        assert_ne!(
+            options.max_handshakes,
+            Some(0),
+            "max_handshakes must not be zero"
+        );
+
+        Self {
+            listener,
+            acceptor,
+            options,
+            handshakes: JoinSet::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &crate::Listener {
+        &self.listener
+    }
+
+    #[must_use]
+    pub fn options(&self) -> &HandshakeOptions {
+        &self.options
+    }
+
+    /// Get the inner listener. Running handshakes are aborted.
+    pub fn into_inner(self) -> crate::Listener {
+        self.listener
+    }
+
+    /// Accept a connection and complete its handshake.
+    ///
+    /// Errors of the listener are returned as they are, like with
+    /// [`crate::Listener::accept`].
+    pub async fn accept(&mut self) -> Result<(MaybeTlsStream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    pub fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(MaybeTlsStream, SocketAddr)>> {
+        let max = self.options.max_handshakes.unwrap_or(usize::MAX);
+
+        while self.handshakes.len() < max {
+            match self.listener.poll_accept(cx) {
+                Poll::Ready(Ok((stream, addr))) => self.start(stream, addr),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => break,
+            }
+        }
+
+        // With no handshakes running, the listener registered the waker.
+        match self.handshakes.poll_join_next(cx) {
+            Poll::Ready(Some(Ok(x))) => Poll::Ready(x),
+            Poll::Ready(Some(Err(e))) => match e.try_into_panic() {
+                Ok(x) => std::panic::resume_unwind(x),
+                Err(e) => Poll::Ready(Err(Error::other(e))),
+            },
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn start(&mut self, stream: crate::Stream, addr: SocketAddr) {
+        let acceptor = self.acceptor.clone();
+        let timeout = self.options.timeout;
+
+        self.handshakes.spawn(async move {
+            let handshake = acceptor.accept(stream);
+
+            let result = match timeout {
+                Some(x) => tokio::time::timeout(x, handshake)
+                    .await
+                    .unwrap_or_else(|_| Err(Error::from(ErrorKind::TimedOut))),
+                None => handshake.await,
+            };
+
+            match result {
+                Ok(x) => Ok((x, addr)),
+                Err(e) => Err(HandshakeError::into_error(e, Some(addr))),
+            }
+        });
+    }
+}
+
+impl fmt::Debug for TlsListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsListener")
+            .field("listener", &self.listener)
+            .field("options", &self.options)
+            .field("handshakes", &self.handshakes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Performs the handshake of a [`TlsListener`] as the server.
+///
+/// It is made from the acceptor of a TLS library, with [`From`], which holds
+/// the certificate of the server, the ALPN protocols and whether clients must
+/// authenticate.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    backend: AcceptorBackend,
+}
+
+#[derive(Clone)]
+enum AcceptorBackend {
+    #[cfg(feature = "tls")]
+    Rustls(tokio_rustls::TlsAcceptor),
+    #[cfg(feature = "tls-native")]
+    Native(tokio_native_tls::TlsAcceptor),
+}
+
+/// Only available with the `tls` feature.
+#[cfg(feature = "tls")]
+impl From<tokio_rustls::TlsAcceptor> for TlsAcceptor {
+    fn from(x: tokio_rustls::TlsAcceptor) -> Self {
+        Self {
+            backend: AcceptorBackend::Rustls(x),
+        }
+    }
+}
+
+/// Only available with the `tls-native` feature.
+#[cfg(feature = "tls-native")]
+impl From<tokio_native_tls::TlsAcceptor> for TlsAcceptor {
+    fn from(x: tokio_native_tls::TlsAcceptor) -> Self {
+        Self {
+            backend: AcceptorBackend::Native(x),
+        }
+    }
+}
+
+impl TlsAcceptor {
+    async fn accept(self, stream: crate::Stream) -> Result<MaybeTlsStream> {
+        match self.backend {
+            #[cfg(feature = "tls")]
+            AcceptorBackend::Rustls(x) => x
+                .accept(stream)
+                .await
+                .map(|x| MaybeTlsStream::Rustls(Box::new(x.into()))),
+            #[cfg(feature = "tls-native")]
+            AcceptorBackend::Native(x) => x
+                .accept(stream)
+                .await
+                .map(|x| MaybeTlsStream::Native(Box::new(x)))
+                .map_err(native_error),
+        }
+    }
+}
+
+impl fmt::Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let backend = match self.backend {
+            #[cfg(feature = "tls")]
+            AcceptorBackend::Rustls(_) => "rustls",
+            #[cfg(feature = "tls-native")]
+            AcceptorBackend::Native(_) => "native-tls",
+        };
+
+        f.debug_struct("TlsAcceptor")
+            .field("backend", &backend)
+            .finish_non_exhaustive()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A connection of a [`TlsConnector`] or a [`TlsListener`], with or without
+/// TLS.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MaybeTlsStream {
+    Plain(crate::Stream),
+    /// Only available with the `tls` feature.
+    #[cfg(feature = "tls")]
+    Rustls(Box<tokio_rustls::TlsStream<crate::Stream>>),
+    /// Only available with the `tls-native` feature.
+    #[cfg(feature = "tls-native")]
+    Native(Box<tokio_native_tls::TlsStream<crate::Stream>>),
+}
+
+impl MaybeTlsStream {
+    /// The underlying connection.
+    #[must_use]
+    pub fn get_ref(&self) -> &crate::Stream {
+        match self {
+            Self::Plain(x) => x,
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => x.get_ref().0,
+            #[cfg(feature = "tls-native")]
+            Self::Native(x) => x.get_ref().get_ref().get_ref(),
+        }
+    }
+
+    /// Whether the connection uses TLS.
+    #[must_use]
+    pub fn is_tls(&self) -> bool {
+        !matches!(self, Self::Plain(_))
+    }
+
+    /// The protocol chosen with ALPN, if any.
+    #[must_use]
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::Plain(_) => None,
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => x.get_ref().1.alpn_protocol().map(<[u8]>::to_vec),
+            #[cfg(feature = "tls-native")]
+            Self::Native(x) => x.get_ref().negotiated_alpn().ok().flatten(),
+        }
+    }
+
+    /// The certificate the peer authenticated with, in DER, if any. Servers
+    /// only get one from clients if they ask for it.
+    #[must_use]
+    pub fn peer_certificate(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::Plain(_) => None,
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => x
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(<[_]>::first)
+                .map(|x| x.to_vec()),
+            #[cfg(feature = "tls-native")]
+            Self::Native(x) => x
+                .get_ref()
+                .peer_certificate()
+                .ok()
+                .flatten()
+                .and_then(|x| x.to_der().ok()),
+        }
+    }
+
+    /// The server name the client asked for (SNI), on the server side.
+    ///
+    /// Always `None` with native-tls, which does not report it.
+    #[must_use]
+    pub fn server_name(&self) -> Option<String> {
+        match self {
+            Self::Plain(_) => None,
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => match &**x {
+                tokio_rustls::TlsStream::Server(x) => {
+                    x.get_ref().1.server_name().map(str::to_owned)
+                }
+                tokio_rustls::TlsStream::Client(_) => None,
+            },
+            #[cfg(feature = "tls-native")]
+            Self::Native(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_tcp(&self) -> bool {
+        self.get_ref().is_tcp()
+    }
+
+    #[must_use]
+    pub fn is_unix(&self) -> bool {
+        self.get_ref().is_unix()
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        match self.get_mut() {
+            Self::Plain(x) => Pin::new(x).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => Pin::new(x).poll_read(cx, buf),
+            #[cfg(feature = "tls-native")]
+            Self::Native(x) => Pin::new(x).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(x) => Pin::new(x).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => Pin::new(x).poll_write(cx, buf),
+            #[cfg(feature = "tls-native")]
+            Self::Native(x) => Pin::new(x).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self.get_mut() {
+            Self::Plain(x) => Pin::new(x).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => Pin::new(x).poll_flush(cx),
+            #[cfg(feature = "tls-native")]
+            Self::Native(x) => Pin::new(x).poll_flush(cx),
+        }
+    }
+
+    /// With TLS, send a `close_notify` alert before shutting down the
+    /// connection.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self.get_mut() {
+            Self::Plain(x) => Pin::new(x).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            Self::Rustls(x) => Pin::new(x).poll_shutdown(cx),
+            #[cfg(feature = "tls-native")]
+            Self::Native(x) => Pin::new(x).poll_shutdown(cx),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A failed TLS handshake.
+///
+/// The [`Error`] of a failed handshake wraps a `HandshakeError`, and keeps the
+/// kind of the underlying error. It can be reached with [`Error::get_ref`] and
+/// a downcast. Errors while connecting or accepting, before the handshake, do
+/// not have one.
+#[derive(Debug)]
+pub struct HandshakeError {
+    error: Error,
+    addr: Option<SocketAddr>,
+}
+
+impl HandshakeError {
+    /// The address of the peer, if known.
+    #[must_use]
+    pub fn peer_addr(&self) -> Option<&SocketAddr> {
+        self.addr.as_ref()
+    }
+
+    /// The underlying error, of the TLS library or of the connection.
+    #[must_use]
+    pub fn get_ref(&self) -> &Error {
+        &self.error
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Error {
+        self.error
+    }
+
+    fn into_error(error: Error, addr: Option<SocketAddr>) -> Error {
+        Error::new(error.kind(), Self { error, addr })
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.addr {
+            Some(addr) => write!(f, "TLS handshake with {addr} failed: {}", self.error),
+            None => write!(f, "TLS handshake failed: {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
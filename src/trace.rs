@@ -0,0 +1,129 @@
+use std::future::Future;
+#[cfg(feature = "tracing")]
+use std::io::Error;
+#[cfg(feature = "tracing")]
+use std::os::fd::AsFd;
+
+use crate::utils::Result;
+use crate::{Listener, SocketAddr, Stream};
+
+// The span and event names and their fields are documented in the README and
+// must not change. Without the `tracing` feature, these only run the operation.
+
+/// Connect to `addr` with `f` in an `anysocket.connect` span.
+pub async fn connect<F, Fut>(addr: SocketAddr, f: F) -> Result<Stream>
+where
+    F: FnOnce(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<Stream>>,
+{
+    #[cfg(feature = "tracing")]
+    {
+        use tracing::Instrument;
+
+        let span = tracing::debug_span!(
+            "anysocket.connect",
+            addr = %addr,
+            outcome = tracing::field::Empty,
+            error = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+            stream.id = tracing::field::Empty,
+        );
+
+        let start = std::time::Instant::now();
+        let result = f(addr).instrument(span.clone()).await;
+        span.record("elapsed_us", elapsed_us(start));
+        record_outcome(&span, result.as_ref().map(stream_id));
+        result
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    f(addr).await
+}
+
+/// Bind to `addr` with `f` in an `anysocket.bind` span.
+pub async fn bind<F, Fut>(addr: SocketAddr, f: F) -> Result<Listener>
+where
+    F: FnOnce(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<Listener>>,
+{
+    #[cfg(feature = "tracing")]
+    {
+        use tracing::Instrument;
+
+        let span = tracing::debug_span!(
+            "anysocket.bind",
+            addr = %addr,
+            outcome = tracing::field::Empty,
+            error = tracing::field::Empty,
+            local_addr = tracing::field::Empty,
+        );
+
+        let result = f(addr).instrument(span.clone()).await;
+        if let Some(addr) = result.as_ref().ok().and_then(|x| x.local_addr().ok()) {
+            span.record("local_addr", tracing::field::display(addr));
+        }
+
+        record_outcome(&span, result.as_ref().map(|_| None));
+        result
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    f(addr).await
+}
+
+/// Emit an `anysocket.accept` event for the result of accepting.
+#[cfg_attr(not(feature = "tracing"), inline(always))]
+pub fn accepted(result: &Result<(Stream, SocketAddr)>) {
+    #[cfg(feature = "tracing")]
+    match result {
+        Ok((stream, peer)) => tracing::debug!(
+            name: "anysocket.accept",
+            peer = %peer,
+            stream.id = stream_id(stream),
+            outcome = "ok",
+        ),
+        Err(e) => tracing::debug!(
+            name: "anysocket.accept",
+            error = %e,
+            outcome = "failed",
+        ),
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    let _ = result;
+}
+
+/// The `stream.id` of a socket: its `SO_COOKIE` on Linux and its descriptor
+/// elsewhere.
+#[cfg(feature = "tracing")]
+fn stream_id(x: &impl AsFd) -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    return crate::utils::socket_cookie(x.as_fd()).ok();
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        use std::os::fd::AsRawFd;
+        u64::try_from(x.as_fd().as_raw_fd()).ok()
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn elapsed_us(start: std::time::Instant) -> u64 {
+    u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX)
+}
+
+#[cfg(feature = "tracing")]
+fn record_outcome(span: &tracing::Span, result: Result<Option<u64>, &Error>) {
+    match result {
+        Ok(id) => {
+            span.record("outcome", "ok");
+            if let Some(id) = id {
+                span.record("stream.id", id);
+            }
+        }
+        Err(e) => {
+            span.record("outcome", "failed");
+            span.record("error", tracing::field::display(e));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt;
+use std::future::{Future, poll_fn};
+use std::io::IoSlice;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, ready};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{AcquireError, Notify, OwnedSemaphorePermit, Semaphore};
+
+use crate::utils::Result;
+use crate::{Listener, OwnedReadHalf, OwnedWriteHalf, SocketAddr, Stream};
+
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+struct Tracker {
+    active: AtomicUsize,
+    idle: Notify,
+}
+
+/// Keeps a connection accounted as active in its [`TrackedListener`].
+///
+/// The connection stops being counted when the guard is dropped. Split halves
+/// share a single guard so a connection is only released once both halves are
+/// gone.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    tracker: Arc<Tracker>,
+    /// The connection's slot if the listener has a limit.
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl ConnectionGuard {
+    fn new(tracker: &Arc<Tracker>, permit: Option<OwnedSemaphorePermit>) -> Self {
+        tracker.active.fetch_add(1, Ordering::AcqRel);
+
+        Self {
+            tracker: Arc::clone(tracker),
+            _permit: permit,
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if self.tracker.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tracker.idle.notify_waiters();
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+type Acquire = Pin<Box<dyn Future<Output = Result<OwnedSemaphorePermit, AcquireError>> + Send>>;
+
+/// Where a [`TrackedListener`] with a limit is in getting a slot for the next
+/// connection.
+enum Slot {
+    Empty,
+    Acquiring(Acquire),
+    Acquired(OwnedSemaphorePermit),
+}
+
+/// A [`Listener`] that counts how many of its accepted connections are alive.
+///
+/// It can also limit how many connections are alive at once. See
+/// [`TrackedListener::with_limit`].
+pub struct TrackedListener {
+    listener: Listener,
+    tracker: Arc<Tracker>,
+    limit: Option<(usize, Arc<Semaphore>)>,
+    slot: Mutex<Slot>,
+}
+
+impl From<Listener> for TrackedListener {
+    fn from(listener: Listener) -> Self {
+        Self::new(listener)
+    }
+}
+
+impl TrackedListener {
+    pub fn new(listener: Listener) -> Self {
+        Self {
+            listener,
+            tracker: Arc::default(),
+            limit: None,
+            slot: Mutex::new(Slot::Empty),
+        }
+    }
+
+    /// Like [`TrackedListener::new`], but keep at most `max` connections alive
+    /// at once.
+    ///
+    /// Accepting waits for one of the connections to be dropped before taking
+    /// another one from the listener, so new connections queue up in the
+    /// listen backlog.
+    pub fn with_limit(listener: Listener, max: usize) -> Self {
+        Self {
+            limit: Some((max, Arc::new(Semaphore::new(max)))),
+            ..Self::new(listener)
+        }
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &Listener {
+        &self.listener
+    }
+
+    pub fn into_inner(self) -> Listener {
+        self.listener
+    }
+
+    /// Number of accepted connections that have not been dropped yet.
+    #[must_use]
+    pub fn active_connections(&self) -> usize {
+        self.tracker.active.load(Ordering::Acquire)
+    }
+
+    /// The limit set with [`TrackedListener::with_limit`].
+    #[must_use]
+    pub fn max_connections(&self) -> Option<usize> {
+        self.limit.as_ref().map(|(max, _)| *max)
+    }
+
+    /// Wait until there are no active connections.
+    ///
+    /// Connections accepted while waiting are taken into account.
+    pub async fn wait_idle(&self) {
+        loop {
+            let notified = self.tracker.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.active_connections() == 0 {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(TrackedStream, SocketAddr)>> {
+        let mut slot = self.slot.lock().unwrap_or_else(|e| e.into_inner());
+
+        ready!(self.poll_slot(&mut slot, cx));
+        let (stream, addr) = ready!(self.listener.poll_accept(cx))?;
+
+        let permit = match std::mem::replace(&mut *slot, Slot::Empty) {
+            Slot::Acquired(permit) => Some(permit),
+            _ => None,
+        };
+
+        Poll::Ready(Ok((self.track(stream, permit), addr)))
+    }
+
+    /// Get a slot for the next connection if there is a limit.
+    fn poll_slot(&self, slot: &mut Slot, cx: &mut Context<'_>) -> Poll<()> {
+        let Some((_, limit)) = &self.limit else {
+            return Poll::Ready(());
+        };
+
+        loop {
+            match slot {
+                Slot::Empty => {
+                    *slot = Slot::Acquiring(Box::pin(Arc::clone(limit).acquire_owned()));
+                }
+                Slot::Acquiring(acquire) => {
+                    let permit = ready!(acquire.as_mut().poll(cx));
+                    *slot = Slot::Acquired(permit.expect("semaphore is never closed"));
+                }
+                Slot::Acquired(_) => return Poll::Ready(()),
+            }
+        }
+    }
+
+    pub async fn accept(&self) -> Result<(TrackedStream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn track(&self, stream: Stream, permit: Option<OwnedSemaphorePermit>) -> TrackedStream {
+        TrackedStream {
+            inner: stream,
+            guard: Arc::new(ConnectionGuard::new(&self.tracker, permit)),
+        }
+    }
+}
+
+impl fmt::Debug for TrackedListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrackedListener")
+            .field("listener", &self.listener)
+            .field("active_connections", &self.active_connections())
+            .field("max_connections", &self.max_connections())
+            .finish_non_exhaustive()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+macro_rules! impl_tracked {
+    ($T:ident, $inner:ty) => {
+        impl $T {
+            #[must_use]
+            pub fn get_ref(&self) -> &$inner {
+                &self.inner
+            }
+
+            #[must_use]
+            pub fn get_mut(&mut self) -> &mut $inner {
+                &mut self.inner
+            }
+
+            /// Take the inner value apart from its guard.
+            ///
+            /// The connection stays counted as active for as long as the
+            /// guard is alive.
+            pub fn into_parts(self) -> ($inner, Arc<ConnectionGuard>) {
+                (self.inner, self.guard)
+            }
+        }
+
+        impl Deref for $T {
+            type Target = $inner;
+
+            fn deref(&self) -> &Self::Target {
+                &self.inner
+            }
+        }
+
+        impl DerefMut for $T {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.inner
+            }
+        }
+
+        impl fmt::Debug for $T {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.inner.fmt(f)
+            }
+        }
+    };
+}
+
+macro_rules! impl_tracked_read {
+    ($T:ident) => {
+        impl AsyncRead for $T {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<Result<()>> {
+                Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+            }
+        }
+    };
+}
+
+macro_rules! impl_tracked_write {
+    ($T:ident) => {
+        impl AsyncWrite for $T {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<Result<usize>> {
+                Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+            }
+
+            fn poll_write_vectored(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[IoSlice<'_>],
+            ) -> Poll<Result<usize>> {
+                Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                self.inner.is_write_vectored()
+            }
+        }
+    };
+}
+
+/// A [`Stream`] accepted from a [`TrackedListener`].
+pub struct TrackedStream {
+    inner: Stream,
+    guard: Arc<ConnectionGuard>,
+}
+
+impl TrackedStream {
+    pub fn into_split(self) -> (TrackedOwnedReadHalf, TrackedOwnedWriteHalf) {
+        let (r, w) = self.inner.into_split();
+
+        let r = TrackedOwnedReadHalf {
+            inner: r,
+            guard: Arc::clone(&self.guard),
+        };
+
+        let w = TrackedOwnedWriteHalf {
+            inner: w,
+            guard: self.guard,
+        };
+
+        (r, w)
+    }
+}
+
+impl_tracked!(TrackedStream, Stream);
+impl_tracked_read!(TrackedStream);
+impl_tracked_write!(TrackedStream);
+
+/// The read half of a [`TrackedStream`].
+pub struct TrackedOwnedReadHalf {
+    inner: OwnedReadHalf,
+    guard: Arc<ConnectionGuard>,
+}
+
+impl_tracked!(TrackedOwnedReadHalf, OwnedReadHalf);
+impl_tracked_read!(TrackedOwnedReadHalf);
+
+/// The write half of a [`TrackedStream`].
+pub struct TrackedOwnedWriteHalf {
+    inner: OwnedWriteHalf,
+    guard: Arc<ConnectionGuard>,
+}
+
+impl_tracked!(TrackedOwnedWriteHalf, OwnedWriteHalf);
+impl_tracked_write!(TrackedOwnedWriteHalf);
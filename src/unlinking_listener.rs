@@ -0,0 +1,124 @@
+use std::io::ErrorKind;
+use std::ops::Deref;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use crate::Listener;
+use crate::utils::Result;
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// The socket file of a listener, identified by device and inode so that a
+/// file which replaced it is never removed.
+#[derive(Debug)]
+struct SocketFile {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+    armed: bool,
+}
+
+impl SocketFile {
+    fn new(listener: &Listener) -> Result<Option<Self>> {
+        if !listener.is_unix() {
+            return Ok(None);
+        }
+
+        let addr = listener.local_addr()?;
+        let Some(path) = addr.as_pathname() else {
+            return Ok(None);
+        };
+
+        // The working directory may change before the listener is dropped.
+        let path = std::path::absolute(path)?;
+
+        let meta = match std::fs::symlink_metadata(&path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        if !meta.file_type().is_socket() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            path,
+            dev: meta.dev(),
+            ino: meta.ino(),
+            armed: true,
+        }))
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let Ok(meta) = std::fs::symlink_metadata(&self.path) else {
+            return;
+        };
+
+        if meta.dev() == self.dev && meta.ino() == self.ino {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// A [`Listener`] that removes its unix socket file when dropped.
+///
+/// The device and inode of the socket file are recorded when this is created,
+/// and the file is only removed if the path still refers to the same file. A
+/// socket that another process has bound at the path in the meantime is left
+/// alone. TCP listeners and listeners in the abstract namespace have no file,
+/// so nothing is removed for them.
+///
+/// Created by [`Listener::unlink_on_drop`]. The listener is available through
+/// [`Deref`].
+#[derive(Debug)]
+pub struct UnlinkingListener {
+    listener: Listener,
+    file: Option<SocketFile>,
+}
+
+impl UnlinkingListener {
+    /// Record the socket file of `listener`.
+    ///
+    /// This should be called right after binding, as whatever file is at the
+    /// path now is the one that will be removed. If it is not a socket, nothing
+    /// is removed.
+    pub fn new(listener: Listener) -> Result<Self> {
+        let file = SocketFile::new(&listener)?;
+        Ok(Self { listener, file })
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &Listener {
+        &self.listener
+    }
+
+    /// Get the listener back without removing the socket file.
+    pub fn into_inner(mut self) -> Listener {
+        if let Some(file) = &mut self.file {
+            file.armed = false;
+        }
+
+        self.listener
+    }
+
+    /// The absolute path of the socket file that will be removed, if any.
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|x| x.path.as_path())
+    }
+}
+
+impl Deref for UnlinkingListener {
+    type Target = Listener;
+
+    fn deref(&self) -> &Self::Target {
+        &self.listener
+    }
+}
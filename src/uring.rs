@@ -0,0 +1,246 @@
+//! Streams and listeners driven by io_uring, through [`tokio_uring`].
+//!
+//! These cover a subset of [`crate::Stream`] and [`crate::Listener`]: binding,
+//! connecting, accepting, reading and writing. They take the same addresses,
+//! and listeners can be bound with [`crate::BindOptions`], so configuration
+//! code does not change. Reads and writes pass ownership of
+//! the buffer to the kernel and hand it back with the result, as in
+//! `tokio_uring`, so there are no [`AsyncRead`](tokio::io::AsyncRead) or
+//! [`AsyncWrite`](tokio::io::AsyncWrite) implementations.
+//!
+//! All methods take `&self`, so a stream can be read and written at the same
+//! time from two tasks by sharing it in an [`Rc`](std::rc::Rc) instead of
+//! splitting it.
+//!
+//! Everything here must be used within [`tokio_uring::start`], and panics
+//! otherwise.
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::net::Shutdown;
+
+use tokio_uring::BufResult;
+use tokio_uring::buf::{BoundedBuf, BoundedBufMut};
+
+use crate::listener::replaced_addr;
+use crate::utils::{DisplayAddr, Result, unix_addr_to_path};
+use crate::{AddrErrors, SocketAddr, StdStream, ToSocketAddrs};
+
+///////////////////////////////////////////////////////////////////////////////
+
+pub enum Stream {
+    Tcp(tokio_uring::net::TcpStream),
+    Unix(tokio_uring::net::UnixStream),
+}
+
+impl From<tokio_uring::net::TcpStream> for Stream {
+    fn from(x: tokio_uring::net::TcpStream) -> Self {
+        Self::Tcp(x)
+    }
+}
+
+impl From<tokio_uring::net::UnixStream> for Stream {
+    fn from(x: tokio_uring::net::UnixStream) -> Self {
+        Self::Unix(x)
+    }
+}
+
+impl Stream {
+    #[must_use]
+    pub fn is_tcp(&self) -> bool {
+        matches!(self, Self::Tcp(..))
+    }
+
+    #[must_use]
+    pub fn is_unix(&self) -> bool {
+        matches!(self, Self::Unix(..))
+    }
+}
+
+impl Stream {
+    /// Connect to `addr`, trying each address in turn like
+    /// [`crate::Stream::connect`].
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let addrs = addr.to_socket_addrs_async().await?;
+
+        let mut errors = Vec::new();
+        for addr in addrs {
+            match Self::_connect(addr.clone()).await {
+                Ok(x) => return Ok(x),
+                Err(e) => errors.push((addr, e)),
+            }
+        }
+
+        Err(AddrErrors::into_error(errors))
+    }
+
+    async fn _connect(addr: SocketAddr) -> Result<Self> {
+        match addr {
+            SocketAddr::Tcp(x) => tokio_uring::net::TcpStream::connect(x)
+                .await
+                .map(Into::into),
+            SocketAddr::Unix(x) => {
+                if x.is_unnamed() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "cannot connect to an unnamed unix address",
+                    ));
+                }
+
+                let x = x.into();
+                tokio_uring::net::UnixStream::connect(unix_addr_to_path(&x))
+                    .await
+                    .map(Into::into)
+            }
+        }
+    }
+
+    /// Drive a connection made elsewhere, like one taken out of a
+    /// [`crate::Stream`] with [`crate::Stream::into_std`].
+    ///
+    /// The socket is switched to blocking mode, which io_uring expects.
+    pub fn from_std(stream: StdStream) -> Result<Self> {
+        match stream {
+            StdStream::Tcp(x) => {
+                x.set_nonblocking(false)?;
+                Ok(tokio_uring::net::TcpStream::from_std(x).into())
+            }
+            StdStream::Unix(x) => {
+                x.set_nonblocking(false)?;
+                Ok(tokio_uring::net::UnixStream::from_std(x).into())
+            }
+        }
+    }
+
+    /// Read into `buf`, returning the number of bytes read and the buffer.
+    ///
+    /// Reading `0` bytes into a non-empty buffer means the peer has closed
+    /// the connection.
+    pub async fn read<T: BoundedBufMut>(&self, buf: T) -> BufResult<usize, T> {
+        match self {
+            Self::Tcp(x) => x.read(buf).await,
+            Self::Unix(x) => x.read(buf).await,
+        }
+    }
+
+    /// Write some of `buf`, returning the number of bytes written and the
+    /// buffer.
+    pub async fn write<T: BoundedBuf>(&self, buf: T) -> BufResult<usize, T> {
+        match self {
+            Self::Tcp(x) => x.write(buf).submit().await,
+            Self::Unix(x) => x.write(buf).submit().await,
+        }
+    }
+
+    /// Write all of `buf`, returning the buffer.
+    pub async fn write_all<T: BoundedBuf>(&self, buf: T) -> BufResult<(), T> {
+        match self {
+            Self::Tcp(x) => x.write_all(buf).await,
+            Self::Unix(x) => x.write_all(buf).await,
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        match self {
+            Self::Tcp(x) => x.shutdown(how),
+            Self::Unix(x) => x.shutdown(how),
+        }
+    }
+
+    /// Disable Nagle's algorithm (`TCP_NODELAY`).
+    ///
+    /// This does nothing for unix connections.
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        match self {
+            Self::Tcp(x) => x.set_nodelay(nodelay),
+            Self::Unix(_) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Tcp(_) => "TcpStream",
+            Self::Unix(_) => "UnixStream",
+        };
+
+        f.debug_struct(name).finish_non_exhaustive()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+pub enum Listener {
+    Tcp(tokio_uring::net::TcpListener),
+    /// Unix connections are accepted through tokio, since `tokio_uring`
+    /// cannot listen on unix sockets, and then read and written with
+    /// io_uring.
+    Unix(tokio::net::UnixListener),
+}
+
+impl Listener {
+    #[must_use]
+    pub fn is_tcp(&self) -> bool {
+        matches!(self, Self::Tcp(..))
+    }
+
+    #[must_use]
+    pub fn is_unix(&self) -> bool {
+        matches!(self, Self::Unix(..))
+    }
+}
+
+impl Listener {
+    /// Bind to `addr` like [`crate::Listener::bind`].
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::from_listener(crate::Listener::bind(addr).await?)
+    }
+
+    /// Accept the connections of `listener` from now on, so it can be bound
+    /// with [`crate::Listener::bind_with`] first.
+    pub fn from_listener(listener: crate::Listener) -> Result<Self> {
+        match listener {
+            crate::Listener::Tcp(x) => {
+                let x = x.into_std()?;
+                x.set_nonblocking(false)?;
+                Ok(Self::Tcp(tokio_uring::net::TcpListener::from_std(x)))
+            }
+            crate::Listener::Unix(x) => Ok(Self::Unix(x)),
+        }
+    }
+
+    pub async fn accept(&self) -> Result<(Stream, SocketAddr)> {
+        match self {
+            Self::Tcp(x) => {
+                let (stream, addr) = x.accept().await?;
+                Ok((stream.into(), addr.into()))
+            }
+            Self::Unix(x) => {
+                let (stream, addr) = x.accept().await?;
+                let stream = Stream::from_std(StdStream::Unix(stream.into_std()?))?;
+                Ok((stream, addr.into()))
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        match self {
+            Self::Tcp(x) => x.local_addr().map(Into::into),
+            Self::Unix(x) => x.local_addr().map(|x| replaced_addr(x.into())),
+        }
+    }
+}
+
+impl fmt::Debug for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Tcp(_) => "TcpListener",
+            Self::Unix(_) => "UnixListener",
+        };
+
+        f.debug_struct(name)
+            .field("addr", &format_args!("{}", DisplayAddr(self.local_addr())))
+            .finish()
+    }
+}
@@ -1,8 +1,18 @@
 use std::borrow::Cow;
 use std::ffi::OsString;
-use std::io::Error;
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind};
+use std::os::fd::BorrowedFd;
 use std::os::unix::ffi::OsStringExt;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::SocketAddr;
 
 #[cfg(target_os = "android")]
 use std::os::android::net::SocketAddrExt;
@@ -40,3 +50,223 @@ pub fn unix_addr_to_path<'a>(x: &'a std::os::unix::net::SocketAddr) -> Cow<'a, P
 
     Cow::Borrowed(path)
 }
+
+pub fn unsupported(what: &str) -> Error {
+    Error::new(ErrorKind::Unsupported, format!("{what} is not supported"))
+}
+
+/// Whether the socket is a Multipath TCP socket (`SO_PROTOCOL`).
+#[cfg(target_os = "linux")]
+pub fn is_mptcp(fd: BorrowedFd<'_>) -> Result<bool> {
+    let protocol = rustix::net::sockopt::socket_protocol(fd)?;
+    Ok(protocol == Some(rustix::net::ipproto::MPTCP))
+}
+
+/// Read the kernel socket cookie (`SO_COOKIE`).
+pub fn socket_cookie(fd: BorrowedFd<'_>) -> Result<u64> {
+    #[cfg(target_os = "linux")]
+    return Ok(rustix::net::sockopt::socket_cookie(fd)?);
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = fd;
+        Err(unsupported("SO_COOKIE"))
+    }
+}
+
+/// Receive from `fd` without removing the data from the queue (`MSG_PEEK`).
+pub fn recv_peek(fd: BorrowedFd<'_>, buf: &mut [u8]) -> Result<usize> {
+    let (n, _) = rustix::net::recv(fd, buf, rustix::net::RecvFlags::PEEK)?;
+    Ok(n)
+}
+
+/// Wait for read readiness and peek into `buf`, retrying spurious wakeups.
+pub fn poll_peek_with(
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+    mut poll_read_ready: impl FnMut(&mut Context<'_>) -> Poll<Result<()>>,
+    mut try_peek: impl FnMut(&mut [u8]) -> Result<usize>,
+) -> Poll<Result<usize>> {
+    loop {
+        ready!(poll_read_ready(cx))?;
+
+        match try_peek(buf.initialize_unfilled()) {
+            Ok(n) => {
+                buf.advance(n);
+                return Poll::Ready(Ok(n));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Check that `fd` is a stream socket of a supported family that is listening
+/// or not, as expected, and return its family.
+pub fn check_socket(fd: BorrowedFd<'_>, listening: bool) -> Result<socket2::Domain> {
+    let x = socket2::SockRef::from(&fd);
+
+    if x.r#type()? != socket2::Type::STREAM {
+        return Err(Error::new(ErrorKind::InvalidInput, "not a stream socket"));
+    }
+
+    if x.is_listener()? != listening {
+        let msg = if listening {
+            "socket is not listening"
+        } else {
+            "socket is listening"
+        };
+
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+
+    check_domain(&x)
+}
+
+/// Check that `fd` is a datagram socket of a supported family and return its
+/// family.
+pub fn check_datagram_socket(fd: BorrowedFd<'_>) -> Result<socket2::Domain> {
+    let x = socket2::SockRef::from(&fd);
+
+    if x.r#type()? != socket2::Type::DGRAM {
+        return Err(Error::new(ErrorKind::InvalidInput, "not a datagram socket"));
+    }
+
+    check_domain(&x)
+}
+
+fn check_domain(x: &socket2::SockRef<'_>) -> Result<socket2::Domain> {
+    let domain = x.local_addr()?.domain();
+    if ![
+        socket2::Domain::IPV4,
+        socket2::Domain::IPV6,
+        socket2::Domain::UNIX,
+    ]
+    .contains(&domain)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "unsupported socket address family",
+        ));
+    }
+
+    Ok(domain)
+}
+
+pub fn to_sock_addr(addr: &SocketAddr) -> Result<socket2::SockAddr> {
+    match addr {
+        SocketAddr::Tcp(x) => Ok((*x).into()),
+        SocketAddr::Unix(x) => {
+            let x = std::os::unix::net::SocketAddr::from(x.clone());
+
+            if x.is_unnamed() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "cannot use an unnamed unix address",
+                ));
+            }
+
+            socket2::SockAddr::unix(unix_addr_to_path(&x))
+        }
+    }
+}
+
+/// Like [`to_sock_addr`], but an unnamed unix address asks the kernel to pick
+/// an unused abstract name, where that is supported (autobind).
+pub fn to_bind_sock_addr(addr: &SocketAddr) -> Result<socket2::SockAddr> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if addr.is_unnamed() {
+        // Binding an address that is only the family triggers autobind.
+        return socket2::SockAddr::unix("");
+    }
+
+    to_sock_addr(addr)
+}
+
+pub fn domain_of(addr: &SocketAddr) -> socket2::Domain {
+    match addr {
+        SocketAddr::Tcp(x) => socket2::Domain::for_address(*x),
+        SocketAddr::Unix(_) => socket2::Domain::UNIX,
+    }
+}
+
+/// Read from `reader` into the unfilled part of `buf`.
+///
+/// The unfilled part of a hyper buffer may be uninitialized, and filling it
+/// directly needs unsafe code. This reads into an initialized buffer on the
+/// stack and copies what was read over instead.
+#[cfg(feature = "hyper")]
+pub fn poll_read_hyper<R>(
+    reader: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    mut buf: hyper::rt::ReadBufCursor<'_>,
+) -> Poll<Result<()>>
+where
+    R: AsyncRead + ?Sized,
+{
+    let mut tmp = [0; 16 * 1024];
+    let len = buf.remaining().min(tmp.len());
+
+    let mut tmp = ReadBuf::new(&mut tmp[..len]);
+    ready!(reader.poll_read(cx, &mut tmp))?;
+    buf.put_slice(tmp.filled());
+    Poll::Ready(Ok(()))
+}
+
+pub struct DisplayAddr(pub Result<SocketAddr>);
+
+impl fmt::Display for DisplayAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Ok(x) => x.fmt(f),
+            Err(e) => write!(f, "<error: {e}>"),
+        }
+    }
+}
+
+/// Format a connection as `<local> -> <peer>`.
+pub fn fmt_connection(
+    f: &mut fmt::Formatter<'_>,
+    local: Result<SocketAddr>,
+    peer: Result<SocketAddr>,
+) -> fmt::Result {
+    write!(f, "{} -> {}", DisplayAddr(local), DisplayAddr(peer))
+}
+
+/// Read from a connection whose write direction has been shut down until the
+/// peer closes it too, passing what is read to `on_data`.
+///
+/// Fails with [`ErrorKind::TimedOut`] if the peer does not close it within
+/// `timeout`.
+pub async fn drain_until_closed<R>(
+    reader: &mut R,
+    timeout: Duration,
+    mut on_data: impl FnMut(&[u8]),
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    let drain = async {
+        let mut buf = vec![0; 8 * 1024];
+
+        loop {
+            let mut buf = ReadBuf::new(&mut buf);
+            poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf)).await?;
+
+            if buf.filled().is_empty() {
+                return Ok(());
+            }
+
+            on_data(buf.filled());
+        }
+    };
+
+    tokio::time::timeout(timeout, drain)
+        .await
+        .unwrap_or_else(|_| {
+            Err(Error::new(
+                ErrorKind::TimedOut,
+                "peer did not close the connection in time",
+            ))
+        })
+}
@@ -1,20 +1,22 @@
 use std::fmt;
-use std::io::IoSlice;
+use std::io::{Error, IoSlice};
+use std::net::Shutdown;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use pin_project::pin_project;
+use socket2::SockRef;
 use tokio::io::{AsyncWrite, Interest, Ready};
 
-use crate::SocketAddr;
-use crate::utils::Result;
+use crate::utils::{Result, drain_until_closed, fmt_connection, socket_cookie};
+use crate::{OwnedReadHalf, ReuniteError, SocketAddr, Stream};
 
 ///////////////////////////////////////////////////////////////////////////////
 
-#[pin_project(project = WriteHalfProj)]
 pub enum WriteHalf<'a> {
-    Tcp(#[pin] tokio::net::tcp::WriteHalf<'a>),
-    Unix(#[pin] tokio::net::unix::WriteHalf<'a>),
+    Tcp(tokio::net::tcp::WriteHalf<'a>),
+    Unix(tokio::net::unix::WriteHalf<'a>),
 }
 
 impl<'a> From<tokio::net::tcp::WriteHalf<'a>> for WriteHalf<'a> {
@@ -29,7 +31,7 @@ impl<'a> From<tokio::net::unix::WriteHalf<'a>> for WriteHalf<'a> {
     }
 }
 
-impl WriteHalf<'_> {
+impl<'a> WriteHalf<'a> {
     #[must_use]
     pub fn is_tcp(&self) -> bool {
         matches!(self, Self::Tcp(..))
@@ -39,9 +41,86 @@ impl WriteHalf<'_> {
     pub fn is_unix(&self) -> bool {
         matches!(self, Self::Unix(..))
     }
+
+    /// `"tcp"` or `"unix"`, the scheme of the socket's addresses.
+    #[must_use]
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Tcp(..) => "tcp",
+            Self::Unix(..) => "unix",
+        }
+    }
+
+    /// The underlying tokio half, if this is a TCP half.
+    ///
+    /// This is an escape hatch for what this type does not wrap. Prefer its
+    /// own methods, which work the same for both kinds of sockets.
+    #[must_use]
+    pub fn as_tcp(&self) -> Option<&tokio::net::tcp::WriteHalf<'a>> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_tcp_mut(&mut self) -> Option<&mut tokio::net::tcp::WriteHalf<'a>> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// unix half.
+    ///
+    /// See [`WriteHalf::as_tcp`].
+    pub fn into_tcp(self) -> Result<tokio::net::tcp::WriteHalf<'a>, Self> {
+        match self {
+            Self::Tcp(x) => Ok(x),
+            x => Err(x),
+        }
+    }
+
+    /// The underlying tokio half, if this is a unix half.
+    ///
+    /// See [`WriteHalf::as_tcp`].
+    #[must_use]
+    pub fn as_unix(&self) -> Option<&tokio::net::unix::WriteHalf<'a>> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_unix_mut(&mut self) -> Option<&mut tokio::net::unix::WriteHalf<'a>> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// TCP half.
+    ///
+    /// See [`WriteHalf::as_tcp`].
+    pub fn into_unix(self) -> Result<tokio::net::unix::WriteHalf<'a>, Self> {
+        match self {
+            Self::Unix(x) => Ok(x),
+            x => Err(x),
+        }
+    }
 }
 
 impl<'a> WriteHalf<'a> {
+    pub async fn async_io<R>(&self, interest: Interest, f: impl FnMut() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().async_io(interest, f).await,
+            Self::Unix(x) => x.as_ref().async_io(interest, f).await,
+        }
+    }
+
     pub fn local_addr(&self) -> Result<SocketAddr> {
         match self {
             Self::Tcp(x) => x.local_addr().map(Into::into),
@@ -56,6 +135,13 @@ impl<'a> WriteHalf<'a> {
         }
     }
 
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Tcp(x) => x.as_ref().poll_write_ready(cx),
+            Self::Unix(x) => x.as_ref().poll_write_ready(cx),
+        }
+    }
+
     pub async fn ready(&self, interest: Interest) -> Result<Ready> {
         match self {
             Self::Tcp(x) => x.ready(interest).await,
@@ -63,6 +149,31 @@ impl<'a> WriteHalf<'a> {
         }
     }
 
+    /// The kernel's unique identifier for the socket (`SO_COOKIE`).
+    ///
+    /// Only supported on Linux.
+    pub fn socket_cookie(&self) -> Result<u64> {
+        match self {
+            Self::Tcp(x) => socket_cookie(x.as_ref().as_fd()),
+            Self::Unix(x) => socket_cookie(x.as_ref().as_fd()),
+        }
+    }
+
+    /// Get and clear the pending error of the socket (`SO_ERROR`).
+    ///
+    /// This affects the underlying socket, so it is visible to the other half
+    /// too.
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        SockRef::from(self).take_error()
+    }
+
+    pub fn try_io<R>(&self, interest: Interest, f: impl FnOnce() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().try_io(interest, f),
+            Self::Unix(x) => x.as_ref().try_io(interest, f),
+        }
+    }
+
     pub fn try_write(&self, buf: &[u8]) -> Result<usize> {
         match self {
             Self::Tcp(x) => x.try_write(buf),
@@ -85,9 +196,26 @@ impl<'a> WriteHalf<'a> {
     }
 }
 
+impl AsFd for WriteHalf<'_> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_fd(),
+            Self::Unix(x) => x.as_ref().as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for WriteHalf<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_raw_fd(),
+            Self::Unix(x) => x.as_ref().as_raw_fd(),
+        }
+    }
+}
+
 crate::macros::impl_async_write! {
     type: WriteHalf<'_>,
-    proj: WriteHalfProj,
 }
 
 impl fmt::Debug for WriteHalf<'_> {
@@ -99,12 +227,28 @@ impl fmt::Debug for WriteHalf<'_> {
     }
 }
 
+impl fmt::Display for WriteHalf<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+            Self::Unix(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+        }
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 
-#[pin_project(project = OwnedWriteHalfProj)]
 pub enum OwnedWriteHalf {
-    Tcp(#[pin] tokio::net::tcp::OwnedWriteHalf),
-    Unix(#[pin] tokio::net::unix::OwnedWriteHalf),
+    Tcp(tokio::net::tcp::OwnedWriteHalf),
+    Unix(tokio::net::unix::OwnedWriteHalf),
 }
 
 impl From<tokio::net::tcp::OwnedWriteHalf> for OwnedWriteHalf {
@@ -129,9 +273,86 @@ impl OwnedWriteHalf {
     pub fn is_unix(&self) -> bool {
         matches!(self, Self::Unix(..))
     }
+
+    /// `"tcp"` or `"unix"`, the scheme of the socket's addresses.
+    #[must_use]
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Tcp(..) => "tcp",
+            Self::Unix(..) => "unix",
+        }
+    }
+
+    /// The underlying tokio half, if this is a TCP half.
+    ///
+    /// This is an escape hatch for what this type does not wrap. Prefer its
+    /// own methods, which work the same for both kinds of sockets.
+    #[must_use]
+    pub fn as_tcp(&self) -> Option<&tokio::net::tcp::OwnedWriteHalf> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_tcp_mut(&mut self) -> Option<&mut tokio::net::tcp::OwnedWriteHalf> {
+        match self {
+            Self::Tcp(x) => Some(x),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// unix half.
+    ///
+    /// See [`OwnedWriteHalf::as_tcp`].
+    pub fn into_tcp(self) -> Result<tokio::net::tcp::OwnedWriteHalf, Self> {
+        match self {
+            Self::Tcp(x) => Ok(x),
+            x => Err(x),
+        }
+    }
+
+    /// The underlying tokio half, if this is a unix half.
+    ///
+    /// See [`OwnedWriteHalf::as_tcp`].
+    #[must_use]
+    pub fn as_unix(&self) -> Option<&tokio::net::unix::OwnedWriteHalf> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_unix_mut(&mut self) -> Option<&mut tokio::net::unix::OwnedWriteHalf> {
+        match self {
+            Self::Unix(x) => Some(x),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    /// Unwrap the underlying tokio half, or get `self` back if this is a
+    /// TCP half.
+    ///
+    /// See [`OwnedWriteHalf::as_tcp`].
+    pub fn into_unix(self) -> Result<tokio::net::unix::OwnedWriteHalf, Self> {
+        match self {
+            Self::Unix(x) => Ok(x),
+            x => Err(x),
+        }
+    }
 }
 
 impl OwnedWriteHalf {
+    pub async fn async_io<R>(&self, interest: Interest, f: impl FnMut() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().async_io(interest, f).await,
+            Self::Unix(x) => x.as_ref().async_io(interest, f).await,
+        }
+    }
+
     pub fn forget(self) {
         match self {
             Self::Tcp(x) => x.forget(),
@@ -153,6 +374,13 @@ impl OwnedWriteHalf {
         }
     }
 
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self {
+            Self::Tcp(x) => x.as_ref().poll_write_ready(cx),
+            Self::Unix(x) => x.as_ref().poll_write_ready(cx),
+        }
+    }
+
     pub async fn ready(&self, interest: Interest) -> Result<Ready> {
         match self {
             Self::Tcp(x) => x.ready(interest).await,
@@ -160,6 +388,76 @@ impl OwnedWriteHalf {
         }
     }
 
+    /// Put the stream back together.
+    ///
+    /// See [`OwnedReadHalf::reunite`].
+    pub fn reunite(self, other: OwnedReadHalf) -> Result<Stream, ReuniteError> {
+        other.reunite(self)
+    }
+
+    /// Shut down the read half, write half or both halves of the connection.
+    ///
+    /// This affects the underlying socket, so it is visible to the other half
+    /// too. See [`Stream::shutdown_socket`].
+    pub fn shutdown_socket(&self, how: Shutdown) -> Result<()> {
+        SockRef::from(self).shutdown(how)
+    }
+
+    /// Close the connection without losing data or making it reset, reading
+    /// from `read`, the other half of the connection, until the peer closes
+    /// its side.
+    ///
+    /// See [`Stream::close_graceful`].
+    pub async fn close_graceful(self, read: OwnedReadHalf, timeout: Duration) -> Result<()> {
+        self.close_graceful_with(read, timeout, |_| {}).await
+    }
+
+    /// Like [`OwnedWriteHalf::close_graceful`], but pass what the peer still
+    /// sends to `on_data` instead of discarding it.
+    pub async fn close_graceful_with(
+        self,
+        mut read: OwnedReadHalf,
+        timeout: Duration,
+        on_data: impl FnMut(&[u8]),
+    ) -> Result<()> {
+        self.shutdown_socket(Shutdown::Write)?;
+        drain_until_closed(&mut read, timeout, on_data).await
+    }
+
+    /// The kernel's unique identifier for the socket (`SO_COOKIE`).
+    ///
+    /// Only supported on Linux.
+    pub fn socket_cookie(&self) -> Result<u64> {
+        match self {
+            Self::Tcp(x) => socket_cookie(x.as_ref().as_fd()),
+            Self::Unix(x) => socket_cookie(x.as_ref().as_fd()),
+        }
+    }
+
+    /// The socket, for the options this crate does not wrap.
+    ///
+    /// See [`Stream::as_socket`]. Options apply to the whole connection, so
+    /// they are visible to the other half too.
+    #[must_use]
+    pub fn as_socket(&self) -> SockRef<'_> {
+        SockRef::from(self)
+    }
+
+    /// Get and clear the pending error of the socket (`SO_ERROR`).
+    ///
+    /// This affects the underlying socket, so it is visible to the other half
+    /// too.
+    pub fn take_error(&self) -> Result<Option<Error>> {
+        SockRef::from(self).take_error()
+    }
+
+    pub fn try_io<R>(&self, interest: Interest, f: impl FnOnce() -> Result<R>) -> Result<R> {
+        match self {
+            Self::Tcp(x) => x.as_ref().try_io(interest, f),
+            Self::Unix(x) => x.as_ref().try_io(interest, f),
+        }
+    }
+
     pub fn try_write(&self, buf: &[u8]) -> Result<usize> {
         match self {
             Self::Tcp(x) => x.try_write(buf),
@@ -182,9 +480,26 @@ impl OwnedWriteHalf {
     }
 }
 
+impl AsFd for OwnedWriteHalf {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_fd(),
+            Self::Unix(x) => x.as_ref().as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for OwnedWriteHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Tcp(x) => x.as_ref().as_raw_fd(),
+            Self::Unix(x) => x.as_ref().as_raw_fd(),
+        }
+    }
+}
+
 crate::macros::impl_async_write! {
     type: OwnedWriteHalf,
-    proj: OwnedWriteHalfProj,
 }
 
 impl fmt::Debug for OwnedWriteHalf {
@@ -195,3 +510,20 @@ impl fmt::Debug for OwnedWriteHalf {
         }
     }
 }
+
+impl fmt::Display for OwnedWriteHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+            Self::Unix(x) => fmt_connection(
+                f,
+                x.local_addr().map(Into::into),
+                x.peer_addr().map(Into::into),
+            ),
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Byte streams carried over WebSocket connections, through
+//! [`tokio_tungstenite`].
+//!
+//! This lets a protocol pass through proxies and firewalls that only allow
+//! HTTP. [`Stream`] performs the HTTP upgrade over a [`crate::Stream`], so the
+//! connection can be TCP or unix, and then implements [`AsyncRead`] and
+//! [`AsyncWrite`] like the streams of this crate. Writes are sent as binary
+//! messages of at most [`MAX_FRAME_SIZE`] bytes. Reads return the payloads of
+//! binary messages as one continuous stream, regardless of how they were
+//! split into messages. A close message from the peer is the end of the
+//! stream.
+//!
+//! Unlike TCP, WebSocket connections cannot be half-closed. Shutting down a
+//! [`Stream`] closes both directions, and once the peer has closed the
+//! connection, writes fail with [`ErrorKind::BrokenPipe`].
+//!
+//! `wss://` is not supported. Wrap a TLS stream, like one of the `tls` module,
+//! with [`tokio_tungstenite::client_async`] instead.
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures_util::{Sink, Stream as _};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Bytes, Message};
+
+use crate::utils::{Result, unsupported};
+use crate::{SocketAddr, ToSocketAddrs};
+
+/// The largest binary message a write sends. Larger writes are only partially
+/// written, like with any other stream.
+pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+///////////////////////////////////////////////////////////////////////////////
+
+pub struct Stream {
+    inner: WebSocketStream<crate::Stream>,
+    /// The rest of the last binary message that did not fit in a read.
+    incoming: Bytes,
+    eof: bool,
+}
+
+impl Stream {
+    /// Connect to a `ws://<host>[:<port>]/<path>` URL.
+    ///
+    /// The host is resolved like in `tcp://<host>:<port>` addresses, and the
+    /// port defaults to 80. `wss://` URLs fail with [`ErrorKind::Unsupported`].
+    pub async fn connect(url: &str) -> Result<Self> {
+        let request = url.into_client_request().map_err(into_io_error)?;
+        let uri = request.uri();
+
+        let port = match uri.scheme_str() {
+            Some(x) if x.eq_ignore_ascii_case("ws") => uri.port_u16().unwrap_or(80),
+            Some(x) if x.eq_ignore_ascii_case("wss") => return Err(unsupported("wss://")),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "websocket url must start with ws://",
+                ));
+            }
+        };
+
+        let Some(host) = uri.host() else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "websocket url has no host",
+            ));
+        };
+
+        let stream = crate::Stream::connect(format!("tcp://{host}:{port}")).await?;
+        Self::client(stream, request).await
+    }
+
+    /// Connect to `addr` and upgrade the connection with `request`, which can
+    /// be a `ws://` URL or a [`Request`](tokio_tungstenite::tungstenite::http::Request)
+    /// with extra headers.
+    ///
+    /// The URL only fills in the HTTP request. It is not used to pick what to
+    /// connect to, so this also works over unix sockets.
+    pub async fn connect_with<A, R>(addr: A, request: R) -> Result<Self>
+    where
+        A: ToSocketAddrs,
+        R: IntoClientRequest + Unpin,
+    {
+        let stream = crate::Stream::connect(addr).await?;
+        Self::client(stream, request).await
+    }
+
+    /// Upgrade a connection as the client.
+    pub async fn client<R>(stream: crate::Stream, request: R) -> Result<Self>
+    where
+        R: IntoClientRequest + Unpin,
+    {
+        let (inner, _) = tokio_tungstenite::client_async(request, stream)
+            .await
+            .map_err(into_io_error)?;
+
+        Ok(Self::new(inner))
+    }
+
+    /// Upgrade a connection as the server, answering the HTTP request of the
+    /// client.
+    pub async fn server(stream: crate::Stream) -> Result<Self> {
+        let inner = tokio_tungstenite::accept_async(stream)
+            .await
+            .map_err(into_io_error)?;
+
+        Ok(Self::new(inner))
+    }
+
+    fn new(inner: WebSocketStream<crate::Stream>) -> Self {
+        Self {
+            inner,
+            incoming: Bytes::new(),
+            eof: false,
+        }
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &crate::Stream {
+        self.inner.get_ref()
+    }
+
+    /// The underlying connection. Data that was already read from it but not
+    /// returned yet is lost.
+    pub fn into_inner(self) -> crate::Stream {
+        self.inner.into_inner()
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().local_addr()
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        // Pings are answered and pongs skipped by the loop.
+        while this.incoming.is_empty() {
+            if this.eof || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(x))) => this.incoming = x,
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "received a text websocket message",
+                    )));
+                }
+                Some(Ok(Message::Close(_))) | None => this.eof = true,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+
+        let n = usize::min(this.incoming.len(), buf.remaining());
+        buf.put_slice(&this.incoming.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        ready!(Pin::new(&mut this.inner).poll_ready(cx)).map_err(into_io_error)?;
+
+        let n = usize::min(buf.len(), MAX_FRAME_SIZE);
+        let message = Message::binary(buf[..n].to_vec());
+        Pin::new(&mut this.inner)
+            .start_send(message)
+            .map_err(into_io_error)?;
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    /// Send a close message and wait for the peer to answer it.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketStream")
+            .field("inner", self.get_ref())
+            .field("eof", &self.eof)
+            .finish_non_exhaustive()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// A [`crate::Listener`] that upgrades accepted connections to WebSocket
+/// connections.
+///
+/// [`Listener::accept`] waits for the HTTP request of the client, so a slow
+/// client holds up everyone else. Servers that cannot trust their clients
+/// should accept with [`Listener::get_ref`] and call [`Stream::server`] in a
+/// task of its own, with a timeout.
+pub struct Listener {
+    listener: crate::Listener,
+}
+
+impl Listener {
+    /// Bind to `addr` like [`crate::Listener::bind`].
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        crate::Listener::bind(addr).await.map(Self::new)
+    }
+
+    #[must_use]
+    pub fn new(listener: crate::Listener) -> Self {
+        Self { listener }
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &crate::Listener {
+        &self.listener
+    }
+
+    pub fn into_inner(self) -> crate::Listener {
+        self.listener
+    }
+
+    /// Accept a connection and upgrade it.
+    ///
+    /// A client that fails the upgrade is an error, but does not affect the
+    /// listener.
+    pub async fn accept(&self) -> Result<(Stream, SocketAddr)> {
+        let (stream, addr) = self.listener.accept().await?;
+        Ok((Stream::server(stream).await?, addr))
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl fmt::Debug for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketListener")
+            .field("listener", &self.listener)
+            .finish()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+fn into_io_error(e: tokio_tungstenite::tungstenite::Error) -> Error {
+    use tokio_tungstenite::tungstenite::Error as WsError;
+    use tokio_tungstenite::tungstenite::error::ProtocolError;
+
+    match e {
+        WsError::Io(e) => e,
+        WsError::ConnectionClosed
+        | WsError::AlreadyClosed
+        | WsError::Protocol(ProtocolError::SendAfterClosing) => {
+            Error::new(ErrorKind::BrokenPipe, e)
+        }
+        e => Error::new(ErrorKind::InvalidData, e),
+    }
+}
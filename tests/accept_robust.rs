@@ -0,0 +1,71 @@
+#![cfg(target_os = "linux")]
+
+use std::os::fd::AsRawFd;
+use std::time::{Duration, Instant};
+
+use tokio_anysocket::{AcceptErrorAction, Listener, Stream};
+
+fn fd_limit() -> libc::rlimit {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid `rlimit` to write to.
+    let x = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    assert_eq!(x, 0, "getrlimit failed");
+    limit
+}
+
+fn set_fd_limit(soft: libc::rlim_t) {
+    let limit = libc::rlimit {
+        rlim_cur: soft,
+        ..fd_limit()
+    };
+    // SAFETY: This test is the only one in this binary, so nothing else
+    // opens descriptors while the limit is lowered.
+    let x = unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) };
+    assert_eq!(x, 0, "setrlimit failed");
+}
+
+#[tokio::test]
+async fn accept_robust_backs_off_until_accept_works() {
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = Stream::connect(&addr).await.unwrap();
+
+    // Every descriptor from the lowest free one up is over the limit, so
+    // accepting fails with `EMFILE` until it is raised again.
+    let lowest = std::fs::File::open("/dev/null").unwrap().as_raw_fd();
+    let original = fd_limit().rlim_cur;
+    set_fd_limit(lowest.try_into().unwrap());
+
+    let mut errors = 0;
+    let start = Instant::now();
+    let (_server, peer) = listener
+        .accept_robust(Duration::from_millis(20), |e| {
+            assert_eq!(e.raw_os_error(), Some(libc::EMFILE), "{e}");
+            errors += 1;
+            if errors == 4 {
+                set_fd_limit(original);
+            }
+            AcceptErrorAction::Backoff
+        })
+        .await
+        .unwrap();
+    assert_eq!(peer, client.local_addr().unwrap());
+    assert_eq!(errors, 4);
+
+    // The delays double from 5ms and are capped: 5, 10, 20 and 20.
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(55), "{elapsed:?}");
+
+    // Giving up returns the error.
+    let _client = Stream::connect(&addr).await.unwrap();
+    set_fd_limit(lowest.try_into().unwrap());
+    let e = listener
+        .accept_robust(Duration::from_millis(20), |_| AcceptErrorAction::Fail)
+        .await
+        .unwrap_err();
+    set_fd_limit(original);
+    assert_eq!(e.raw_os_error(), Some(libc::EMFILE), "{e}");
+}
@@ -0,0 +1,247 @@
+use tokio_anysocket::{AddrFilter, AddrPattern, SocketAddr, Verdict};
+
+fn addr(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+}
+
+fn pattern(s: &str) -> AddrPattern {
+    s.parse().unwrap()
+}
+
+#[test]
+fn cidr_v4_boundaries() {
+    let net = pattern("tcp://10.1.2.0/24");
+    assert!(net.matches(&addr("tcp://10.1.2.0:1")));
+    assert!(net.matches(&addr("tcp://10.1.2.255:1")));
+    assert!(!net.matches(&addr("tcp://10.1.1.255:1")));
+    assert!(!net.matches(&addr("tcp://10.1.3.0:1")));
+
+    // Host bits in the pattern are ignored.
+    assert_eq!(pattern("tcp://10.1.2.77/24"), net);
+    assert_eq!(net.to_string(), "tcp://10.1.2.0/24");
+
+    let all = pattern("tcp://0.0.0.0/0");
+    assert!(all.matches(&addr("tcp://0.0.0.0:1")));
+    assert!(all.matches(&addr("tcp://255.255.255.255:1")));
+    assert!(!all.matches(&addr("tcp://[::1]:1")));
+
+    let one = pattern("tcp://192.0.2.1");
+    assert_eq!(one, pattern("tcp://192.0.2.1/32"));
+    assert!(one.matches(&addr("tcp://192.0.2.1:80")));
+    assert!(!one.matches(&addr("tcp://192.0.2.0:80")));
+    assert!(!one.matches(&addr("tcp://192.0.2.2:80")));
+
+    let odd = pattern("tcp://172.16.0.0/12");
+    assert!(odd.matches(&addr("tcp://172.31.255.255:1")));
+    assert!(!odd.matches(&addr("tcp://172.32.0.0:1")));
+    assert!(!odd.matches(&addr("tcp://172.15.255.255:1")));
+
+    for bad in ["tcp://10.0.0.0/33", "tcp://10.0.0.0/-1", "tcp://10.0.0.0/x"] {
+        assert!(bad.parse::<AddrPattern>().is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn cidr_v6_boundaries() {
+    let net = pattern("tcp://[2001:db8::]/32");
+    assert!(net.matches(&addr("tcp://[2001:db8::]:1")));
+    assert!(net.matches(&addr("tcp://[2001:db8:ffff:ffff:ffff:ffff:ffff:ffff]:1")));
+    assert!(!net.matches(&addr("tcp://[2001:db7:ffff:ffff:ffff:ffff:ffff:ffff]:1")));
+    assert!(!net.matches(&addr("tcp://[2001:db9::]:1")));
+    assert!(!net.matches(&addr("tcp://10.0.0.1:1")));
+
+    // Brackets are optional, and the bits after the prefix are ignored.
+    assert_eq!(pattern("tcp://2001:db8:1::/32"), net);
+
+    let all = pattern("tcp://::/0");
+    assert!(all.matches(&addr("tcp://[::]:1")));
+    assert!(all.matches(&addr("tcp://[ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff]:1")));
+    assert!(!all.matches(&addr("tcp://127.0.0.1:1")));
+
+    let one = pattern("tcp://[::1]");
+    assert_eq!(one, pattern("tcp://::1/128"));
+    assert!(one.matches(&addr("tcp://[::1]:1")));
+    assert!(!one.matches(&addr("tcp://[::2]:1")));
+    assert!(!one.matches(&addr("tcp://[::]:1")));
+
+    // The prefix ends in the middle of a byte.
+    let odd = pattern("tcp://fd00::/7");
+    assert!(odd.matches(&addr("tcp://[fc00::1]:1")));
+    assert!(odd.matches(&addr("tcp://[fdff::1]:1")));
+    assert!(!odd.matches(&addr("tcp://[fe00::1]:1")));
+
+    assert!("tcp://::/129".parse::<AddrPattern>().is_err());
+}
+
+#[test]
+fn ipv4_mapped_ipv6() {
+    let mapped = addr("tcp://[::ffff:10.0.0.1]:1");
+
+    // Dual-stack listeners report IPv4 peers this way.
+    assert!(pattern("tcp://10.0.0.0/8").matches(&mapped));
+    assert!(pattern("tcp://10.0.0.1").matches(&mapped));
+    assert!(!pattern("tcp://192.168.0.0/16").matches(&mapped));
+
+    // IPv6 prefixes see the mapped address as it is.
+    assert!(pattern("tcp://[::ffff:0:0]/96").matches(&mapped));
+    assert!(!pattern("tcp://[2001:db8::]/32").matches(&mapped));
+
+    let filter: AddrFilter = "deny tcp://10.0.0.1, allow *".parse().unwrap();
+    assert!(!filter.matches(&mapped));
+    assert!(filter.matches(&addr("tcp://[::ffff:10.0.0.2]:1")));
+}
+
+#[test]
+fn deny_before_allow() {
+    let filter: AddrFilter = "deny tcp://10.1.0.0/16, allow tcp://10.0.0.0/8"
+        .parse()
+        .unwrap();
+
+    assert_eq!(filter.verdict(&addr("tcp://10.1.2.3:1")), Verdict::Reject);
+    assert_eq!(filter.verdict(&addr("tcp://10.2.2.3:1")), Verdict::Accept);
+    // No rule matches.
+    assert_eq!(filter.verdict(&addr("tcp://192.0.2.1:1")), Verdict::Reject);
+
+    // The first matching rule wins, so an allow before a narrower deny lets
+    // everything through.
+    let filter = AddrFilter::new()
+        .allow(pattern("tcp://10.0.0.0/8"))
+        .deny(pattern("tcp://10.1.0.0/16"));
+    assert_eq!(filter.verdict(&addr("tcp://10.1.2.3:1")), Verdict::Accept);
+
+    let filter = AddrFilter::new()
+        .deny(pattern("tcp://10.1.0.0/16"))
+        .allow(pattern("*"));
+    assert_eq!(filter.verdict(&addr("tcp://10.1.2.3:1")), Verdict::Reject);
+    assert_eq!(filter.verdict(&addr("tcp://10.2.2.3:1")), Verdict::Accept);
+    assert_eq!(filter.verdict(&addr("unix:///run/a.sock")), Verdict::Accept);
+
+    assert!(!AddrFilter::new().matches(&addr("tcp://10.2.2.3:1")));
+    assert_eq!(filter.to_string(), "deny tcp://10.1.0.0/16, allow *");
+}
+
+#[test]
+fn unix_peers() {
+    let unnamed = std::os::unix::net::UnixDatagram::unbound()
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let unnamed = SocketAddr::Unix(unnamed.into());
+    assert!(unnamed.is_unnamed());
+
+    assert!(pattern("unix://*").matches(&unnamed));
+    assert!(pattern("*").matches(&unnamed));
+    assert!(!pattern("tcp://*").matches(&unnamed));
+    assert!(!pattern("unix:///**").matches(&unnamed));
+    assert!(!pattern("tcp://0.0.0.0/0").matches(&addr("unix:///run/a.sock")));
+
+    let glob = pattern("unix:///run/trusted/*.sock");
+    assert!(glob.matches(&addr("unix:///run/trusted/a.sock")));
+    assert!(glob.matches(&addr("unix:///run/trusted/.sock")));
+    assert!(!glob.matches(&addr("unix:///run/trusted/sub/a.sock")));
+    assert!(!glob.matches(&addr("unix:///run/trusted/a.socket")));
+
+    let deep = pattern("unix:///run/**/?.sock");
+    assert!(deep.matches(&addr("unix:///run/a/b/c.sock")));
+    assert!(!deep.matches(&addr("unix:///run/a/b/cd.sock")));
+
+    // A literal star.
+    let star = pattern("unix:///run/%2A.sock");
+    assert!(star.matches(&addr("unix:///run/*.sock")));
+    assert!(!star.matches(&addr("unix:///run/a.sock")));
+
+    let filter: AddrFilter = "deny unix:///run/trusted/evil.sock, allow unix:///run/trusted/*"
+        .parse()
+        .unwrap();
+    assert!(!filter.matches(&addr("unix:///run/trusted/evil.sock")));
+    assert!(filter.matches(&addr("unix:///run/trusted/good.sock")));
+    assert!(!filter.matches(&unnamed));
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let name = pattern("unix://@anysocket");
+        assert!(name.matches(&SocketAddr::unix_abstract(b"anysocket").unwrap()));
+        assert!(!name.matches(&SocketAddr::unix_abstract(b"anysocket2").unwrap()));
+        assert!(!name.matches(&addr("unix:///anysocket")));
+        assert!(pattern("unix://*").matches(&SocketAddr::unix_abstract(b"x").unwrap()));
+    }
+}
+
+#[tokio::test]
+async fn filtered_addrs_drops_rejected_peers() {
+    use std::time::Duration;
+
+    use tokio::io::AsyncReadExt;
+    use tokio_anysocket::{ConnectOptions, Listener, Stream};
+
+    let dir = std::env::temp_dir().join(format!("anysocket-addr-filter-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let listener = Listener::bind(SocketAddr::unix(dir.join("server.sock")).unwrap())
+        .await
+        .unwrap();
+    let server = listener.local_addr().unwrap();
+
+    let filter = format!(
+        "deny unix://{}/evil.sock, allow unix://{}/*.sock",
+        dir.display(),
+        dir.display()
+    );
+    let listener = listener.filtered_addrs(filter.parse().unwrap());
+
+    let connect = |name: &str| {
+        let options =
+            ConnectOptions::default().local_addr(SocketAddr::unix(dir.join(name)).unwrap());
+        let server = server.clone();
+        async move { Stream::connect_with(server, &options).await.unwrap() }
+    };
+
+    let mut evil = connect("evil.sock").await;
+    // Unnamed, so only `unix://*` would match it.
+    let mut unnamed = Stream::connect(&server).await.unwrap();
+    let _good = connect("good.sock").await;
+
+    let (_stream, peer) = listener.accept().await.unwrap();
+    assert_eq!(peer, SocketAddr::unix(dir.join("good.sock")).unwrap());
+
+    // Both were closed without being returned.
+    let mut buf = [0; 1];
+    for peer in [&mut evil, &mut unnamed] {
+        let n = tokio::time::timeout(Duration::from_secs(5), peer.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(n, 0);
+    }
+    assert_eq!(listener.rejected(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn filtered_addrs_drops_rejected_tcp_peers() {
+    use tokio::io::AsyncReadExt;
+    use tokio_anysocket::{ConnectOptions, Listener, Stream};
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap();
+
+    // All of 127.0.0.0/8 is loopback on Linux.
+    let listener = listener.filtered_addrs(
+        "deny tcp://127.0.0.2, allow tcp://127.0.0.0/8"
+            .parse()
+            .unwrap(),
+    );
+
+    let options = ConnectOptions::default().local_addr(addr("tcp://127.0.0.2:0"));
+    let mut rejected = Stream::connect_with(&server, &options).await.unwrap();
+    let _accepted = Stream::connect(&server).await.unwrap();
+
+    let (_stream, peer) = listener.accept().await.unwrap();
+    assert_eq!(peer.ip(), Some([127, 0, 0, 1].into()));
+    assert_eq!(listener.rejected(), 1);
+
+    let mut buf = [0; 1];
+    assert_eq!(rejected.read(&mut buf).await.unwrap(), 0);
+}
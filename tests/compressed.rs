@@ -0,0 +1,160 @@
+#![cfg(feature = "compression")]
+
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+use tokio_anysocket::{CompressedStream, Compression, CompressionConfig, Stream};
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i % 251) as u8 ^ (i / 4096) as u8)
+        .collect()
+}
+
+#[tokio::test]
+async fn request_response_needs_only_flush() {
+    for algorithm in [Compression::Zstd, Compression::Gzip] {
+        let config = CompressionConfig::default().algorithm(algorithm);
+        let (a, b) = Stream::pair_tcp().await.unwrap();
+        let mut client = a.compressed(config.clone());
+        let mut server = b.compressed(config);
+
+        for i in 0..10u8 {
+            let request = [i; 32];
+            client.write_all(&request).await.unwrap();
+            client.flush().await.unwrap();
+
+            // The connection stays open, the flush alone makes the request
+            // readable.
+            let mut buf = [0; 32];
+            timeout(TIMEOUT, server.read_exact(&mut buf))
+                .await
+                .expect("request stalled after flush")
+                .unwrap();
+            assert_eq!(buf, request, "{algorithm:?}");
+
+            server.write_all(&buf[..4]).await.unwrap();
+            server.flush().await.unwrap();
+
+            let mut buf = [0; 4];
+            timeout(TIMEOUT, client.read_exact(&mut buf))
+                .await
+                .expect("response stalled after flush")
+                .unwrap();
+            assert_eq!(buf, [i; 4], "{algorithm:?}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn unflushed_writes_are_held_back() {
+    let (a, b) = Stream::pair_tcp().await.unwrap();
+    let mut client = a.compressed(CompressionConfig::default());
+    let mut server = b.compressed(CompressionConfig::default());
+
+    client.write_all(b"hello").await.unwrap();
+
+    let mut buf = [0; 5];
+    let r = timeout(TIMEOUT, server.read_exact(&mut buf)).await;
+    assert!(r.is_err(), "data arrived before the flush");
+
+    client.flush().await.unwrap();
+    timeout(TIMEOUT, server.read_exact(&mut buf))
+        .await
+        .expect("data stalled after flush")
+        .unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+#[tokio::test]
+async fn flush_without_writes_sends_nothing() {
+    let (a, b) = Stream::pair_tcp().await.unwrap();
+    let mut client = CompressedStream::new(a.metered(), CompressionConfig::default());
+    let mut server = b.compressed(CompressionConfig::default());
+
+    client.flush().await.unwrap();
+    assert_eq!(client.get_ref().metrics().bytes_written(), 0);
+
+    client.write_all(b"x").await.unwrap();
+    client.flush().await.unwrap();
+    let sent = client.get_ref().metrics().bytes_written();
+    assert_ne!(sent, 0);
+
+    client.flush().await.unwrap();
+    assert_eq!(client.get_ref().metrics().bytes_written(), sent);
+
+    let mut buf = [0; 1];
+    server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"x");
+}
+
+#[tokio::test]
+async fn bulk_roundtrip() {
+    for algorithm in [Compression::Zstd, Compression::Gzip] {
+        for level in [None, Some(1), Some(9)] {
+            let config = CompressionConfig::default()
+                .algorithm(algorithm)
+                .level(level);
+            let (a, b) = Stream::pair_tcp().await.unwrap();
+            let mut client = CompressedStream::new(a.metered(), config.clone());
+            let mut server = b.compressed(config);
+
+            let expected = data(1 << 20);
+            let sent = expected.clone();
+            let writer = tokio::spawn(async move {
+                // Several frames, of uneven sizes.
+                for chunk in sent.chunks(100_000) {
+                    client.write_all(chunk).await.unwrap();
+                    client.flush().await.unwrap();
+                }
+                client.shutdown().await.unwrap();
+                client.get_ref().metrics().bytes_written()
+            });
+
+            let mut received = Vec::new();
+            server.read_to_end(&mut received).await.unwrap();
+            assert!(received == expected, "{algorithm:?} {level:?}");
+
+            let compressed = writer.await.unwrap();
+            assert!(
+                compressed < expected.len() as u64 / 4,
+                "{algorithm:?} {level:?}: {compressed}"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn truncated_frame_fails() {
+    let (a, b) = Stream::pair_tcp().await.unwrap();
+    let mut client = a.compressed(CompressionConfig::default());
+    let mut server = b.compressed(CompressionConfig::default());
+
+    client.write_all(&data(100_000)).await.unwrap();
+    // Close the inner stream without ending the frame.
+    client.get_mut().shutdown().await.unwrap();
+
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).await.unwrap_err();
+}
+
+#[tokio::test]
+async fn passthrough() {
+    let (a, mut b) = Stream::pair_tcp().await.unwrap();
+    let mut a = a.compressed(CompressionConfig::default().passthrough(true));
+
+    a.write_all(b"plain").await.unwrap();
+    let mut buf = [0; 5];
+    timeout(TIMEOUT, b.read_exact(&mut buf))
+        .await
+        .expect("passthrough held data back")
+        .unwrap();
+    assert_eq!(&buf, b"plain");
+
+    b.write_all(b"reply").await.unwrap();
+    a.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"reply");
+}
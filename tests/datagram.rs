@@ -0,0 +1,148 @@
+#[tokio::test]
+async fn adopt_bound_udp_socket() {
+    use std::os::fd::OwnedFd;
+
+    use tokio_anysocket::socket2::{Domain, Socket, Type};
+    use tokio_anysocket::{Datagram, SocketAddr, StdDatagram};
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, None).unwrap();
+    socket.set_reuse_address(true).unwrap();
+    let local = std::net::SocketAddr::from(([127, 0, 0, 1], 0));
+    socket.bind(&local.into()).unwrap();
+
+    let server = Datagram::try_from(OwnedFd::from(socket)).unwrap();
+    assert!(server.is_udp());
+    let addr = server.local_addr().unwrap();
+    assert!(matches!(addr, SocketAddr::Tcp(x) if x.port() != 0));
+
+    let client = Datagram::bind("tcp://127.0.0.1:0").await.unwrap();
+    client.send_to(b"hello", &addr).await.unwrap();
+
+    let mut buf = [0; 16];
+    let (n, _) = server.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"hello");
+
+    // The socket goes back to blocking mode, with its options intact.
+    let StdDatagram::Udp(std) = server.into_std().unwrap() else {
+        panic!("not a UDP socket");
+    };
+    let socket = Socket::from(std);
+    assert!(!socket.nonblocking().unwrap());
+    assert!(socket.reuse_address().unwrap());
+
+    let server = Datagram::from_std(socket.into()).unwrap();
+    assert_eq!(server.local_addr().unwrap(), addr);
+}
+
+#[tokio::test]
+async fn adopt_unix_datagram() {
+    use std::os::fd::OwnedFd;
+
+    use tokio_anysocket::{Datagram, StdDatagram};
+
+    let (a, b) = std::os::unix::net::UnixDatagram::pair().unwrap();
+
+    let a = Datagram::try_from(OwnedFd::from(a)).unwrap();
+    assert!(a.is_unix());
+    let b = Datagram::from_std_unix(b).unwrap();
+
+    a.send(b"ping").await.unwrap();
+    let mut buf = [0; 16];
+    let n = b.recv(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"ping");
+
+    let StdDatagram::Unix(b) = b.into_std().unwrap() else {
+        panic!("not a unix socket");
+    };
+    b.send(b"pong").unwrap();
+    let n = a.recv(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"pong");
+}
+
+#[tokio::test]
+async fn adopt_rejects_stream_sockets() {
+    use std::io::ErrorKind;
+    use std::os::fd::OwnedFd;
+
+    use tokio_anysocket::Datagram;
+
+    let (a, _b) = std::os::unix::net::UnixStream::pair().unwrap();
+    let e = Datagram::try_from(OwnedFd::from(a)).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+    let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let e = Datagram::try_from(OwnedFd::from(tcp)).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn socket_ref_sets_options() {
+    use tokio_anysocket::Datagram;
+
+    let socket = Datagram::bind("tcp://127.0.0.1:0").await.unwrap();
+    let x = socket.socket_ref();
+    assert!(x.nonblocking().unwrap());
+
+    x.set_recv_buffer_size(64 * 1024).unwrap();
+    assert!(socket.socket_ref().recv_buffer_size().unwrap() >= 64 * 1024);
+
+    let (a, _b) = std::os::unix::net::UnixDatagram::pair().unwrap();
+    let a = Datagram::from_std_unix(a).unwrap();
+    assert_eq!(
+        a.socket_ref().r#type().unwrap(),
+        tokio_anysocket::socket2::Type::DGRAM
+    );
+}
+
+#[tokio::test]
+async fn send_and_recv_over_udp_and_unix() {
+    use tokio_anysocket::{Datagram, SocketAddr};
+
+    let dir = std::env::temp_dir();
+    let pid = std::process::id();
+    let server_path = dir.join(format!("anysocket-datagram-server-{pid}.sock"));
+    let client_path = dir.join(format!("anysocket-datagram-client-{pid}.sock"));
+
+    let pairs = [
+        (
+            SocketAddr::tcp([127, 0, 0, 1], 0),
+            SocketAddr::tcp([127, 0, 0, 1], 0),
+        ),
+        (
+            SocketAddr::unix(&server_path).unwrap(),
+            SocketAddr::unix(&client_path).unwrap(),
+        ),
+    ];
+
+    for (server, client) in pairs {
+        let server = Datagram::bind(&server).await.unwrap();
+        let client = Datagram::bind(&client).await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let client_addr = client.local_addr().unwrap();
+
+        // Each datagram arrives whole and names its sender.
+        client.send_to(b"first", &server_addr).await.unwrap();
+        client.send_to(b"second", &server_addr).await.unwrap();
+        let mut buf = [0; 16];
+        let (n, from) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"first");
+        assert_eq!(from, client_addr);
+        let (n, from) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"second");
+        assert_eq!(from, client_addr);
+
+        server.send_to(b"reply", &from).await.unwrap();
+        let (n, from) = client.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"reply");
+        assert_eq!(from, server_addr);
+
+        client.connect(&server_addr).await.unwrap();
+        assert_eq!(client.peer_addr().unwrap(), server_addr);
+        client.send(b"connected").await.unwrap();
+        let n = server.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"connected");
+    }
+
+    std::fs::remove_file(server_path).unwrap();
+    std::fs::remove_file(client_path).unwrap();
+}
@@ -0,0 +1,866 @@
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn bind_each_interface() {
+    use tokio_anysocket::{Listener, Stream};
+
+    let (mut listener, errors) = Listener::bind_each_interface(0, true).await.unwrap();
+    assert!(errors.is_empty(), "{errors:?}");
+
+    let addrs = listener.local_addrs().unwrap();
+    let ips: Vec<_> = addrs.iter().map(|x| x.ip().unwrap()).collect();
+    assert!(ips.iter().all(|x| !x.is_unspecified()));
+    assert!(ips.iter().any(|x| x.is_loopback()));
+
+    for addr in &addrs {
+        let _client = Stream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        assert_eq!(server.local_addr().unwrap(), *addr);
+    }
+
+    // Nothing changed, so nothing is rebound.
+    assert!(listener.rescan().await.unwrap().is_empty());
+    assert_eq!(listener.local_addrs().unwrap(), addrs);
+
+    // Without loopback, there may be no address left at all.
+    if let Ok((listener, _)) = Listener::bind_each_interface(0, false).await {
+        let addrs = listener.local_addrs().unwrap();
+        assert!(addrs.iter().all(|x| !x.ip().unwrap().is_loopback()));
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn reuseport_cbpf() {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use tokio_anysocket::{BindOptions, Listener, ReuseportBalance, SockFilter, Stream};
+
+    // `ret #1` sends every connection to the second listener.
+    const RET_K: u16 = 0x06;
+    let options = BindOptions::default()
+        .reuse_port(true)
+        .reuseport_cbpf(ReuseportBalance::Custom(vec![SockFilter::new(
+            RET_K, 0, 0, 1,
+        )]));
+
+    let first = Listener::bind_with("tcp://127.0.0.1:0", &options)
+        .await
+        .unwrap();
+    let addr = first.local_addr().unwrap();
+    let options = BindOptions::default().reuse_port(true);
+    let second = Listener::bind_with(&addr, &options).await.unwrap();
+
+    for _ in 0..8 {
+        let _client = Stream::connect(&addr).await.unwrap();
+        second.accept().await.unwrap();
+    }
+
+    let accept = tokio::time::timeout(Duration::from_millis(100), first.accept()).await;
+    assert!(accept.is_err());
+
+    for balance in [
+        ReuseportBalance::Cpu,
+        ReuseportBalance::Random { listeners: 2 },
+    ] {
+        let options = BindOptions::default()
+            .reuse_port(true)
+            .reuseport_cbpf(balance);
+        let listener = Listener::bind_with("tcp://127.0.0.1:0", &options)
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = Stream::connect(&addr).await.unwrap();
+        listener.accept().await.unwrap();
+    }
+
+    let options = BindOptions::default().reuseport_cbpf(ReuseportBalance::Cpu);
+    let e = Listener::bind_with("unix://@anysocket-reuseport-cbpf", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+#[tokio::test]
+async fn sock_ref_reads_accept_conn() {
+    use socket2::SockRef;
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    let path = std::env::temp_dir().join(format!("anysocket-sockref-{}.sock", std::process::id()));
+    let addrs = [
+        SocketAddr::tcp([127, 0, 0, 1], 0),
+        SocketAddr::unix(&path).unwrap(),
+    ];
+
+    for addr in addrs {
+        let listener = Listener::bind(&addr).await.unwrap();
+        assert!(SockRef::from(&listener).is_listener().unwrap(), "{addr}");
+
+        let addr = listener.local_addr().unwrap();
+        let client = Stream::connect(&addr).await.unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        assert!(!SockRef::from(&server).is_listener().unwrap());
+
+        let (read, write) = server.split();
+        assert!(!SockRef::from(&read).is_listener().unwrap());
+        assert!(!SockRef::from(&write).is_listener().unwrap());
+
+        let (read, write) = client.into_split();
+        assert!(!SockRef::from(&read).is_listener().unwrap());
+        assert!(!SockRef::from(&write).is_listener().unwrap());
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "tokio-util")]
+#[tokio::test]
+async fn tokio_util_listener() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    async fn accept_one<L>(listener: &mut L) -> (L::Io, L::Addr)
+    where
+        L: tokio_anysocket::tokio_util::net::Listener,
+    {
+        listener.accept().await.unwrap()
+    }
+
+    let tcp = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let (unix, _guard) = Listener::bind_unix_temp("tokio-util-").unwrap();
+
+    for mut listener in [tcp, unix] {
+        let local = tokio_anysocket::tokio_util::net::Listener::local_addr(&listener).unwrap();
+        assert_eq!(local, listener.local_addr().unwrap());
+
+        let connect = local.clone();
+        let client = tokio::spawn(async move {
+            let mut x = Stream::connect(connect).await.unwrap();
+            x.write_all(b"hi").await.unwrap();
+        });
+
+        let (mut stream, peer): (Stream, SocketAddr) = accept_one(&mut listener).await;
+        assert_eq!(peer.is_tcp(), local.is_tcp());
+
+        let mut buf = [0; 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hi");
+        client.await.unwrap();
+    }
+}
+
+#[cfg(feature = "tonic")]
+#[tokio::test]
+async fn tonic_connect_info() {
+    use std::sync::{Arc, Mutex};
+
+    use hyper_util::rt::TokioIo;
+    use tokio_anysocket::tonic::transport::{Endpoint, Server};
+    use tokio_anysocket::tonic::{Request, service::interceptor::InterceptedService};
+    use tokio_anysocket::{ConnectInfo, Listener, Stream};
+    use tonic_health::pb::HealthCheckRequest;
+    use tonic_health::pb::health_client::HealthClient;
+
+    let tcp = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let (unix, _guard) = Listener::bind_unix_temp("tonic-").unwrap();
+
+    for listener in [tcp, unix] {
+        let local = listener.local_addr().unwrap();
+
+        let seen = Arc::new(Mutex::new(None));
+        let (_reporter, health) = tonic_health::server::health_reporter();
+        let health = InterceptedService::new(health, {
+            let seen = seen.clone();
+            move |req: Request<()>| {
+                *seen.lock().unwrap() = req.extensions().get::<ConnectInfo>().cloned();
+                Ok(req)
+            }
+        });
+
+        let mut incoming = listener.into_incoming();
+        let incoming =
+            futures_util::stream::poll_fn(move |cx| incoming.poll_next(cx).map_ok(|(x, _)| x));
+        let server = tokio::spawn(
+            Server::builder()
+                .add_service(health)
+                .serve_with_incoming(incoming),
+        );
+
+        let client_addr = Arc::new(Mutex::new(None));
+        let connector = tower::service_fn({
+            let local = local.clone();
+            let client_addr = client_addr.clone();
+            move |_| {
+                let local = local.clone();
+                let client_addr = client_addr.clone();
+                async move {
+                    let stream = Stream::connect(local).await?;
+                    *client_addr.lock().unwrap() = Some(stream.local_addr()?);
+                    std::io::Result::Ok(TokioIo::new(stream))
+                }
+            }
+        });
+        let channel = Endpoint::from_static("http://localhost")
+            .connect_with_connector(connector)
+            .await
+            .unwrap();
+
+        HealthClient::new(channel)
+            .check(HealthCheckRequest::default())
+            .await
+            .unwrap();
+
+        let info = seen.lock().unwrap().take().expect("no connect info");
+        assert_eq!(info.local_addr.as_ref(), Some(&local));
+        assert_eq!(info.peer_addr, *client_addr.lock().unwrap());
+        assert_eq!(info.peer_cred.is_some(), local.is_unix());
+
+        server.abort();
+    }
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn axum_serve() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::axum::{Router, routing::get};
+    use tokio_anysocket::{Listener, Stream};
+
+    let (unix, _guard) = Listener::bind_unix_temp("axum-").unwrap();
+    let tcp = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+
+    for listener in [unix, tcp] {
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/hello", get(|| async { "hello from axum" }));
+        let server = tokio::spawn(tokio_anysocket::axum::serve(listener, app).into_future());
+
+        let mut stream = Stream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with("\r\n\r\nhello from axum"), "{response}");
+
+        server.abort();
+    }
+}
+
+/// Two listeners on consecutive loopback ports.
+async fn consecutive_listeners() -> (tokio_anysocket::Listener, tokio_anysocket::Listener, u16) {
+    use tokio_anysocket::{Listener, SocketAddr};
+
+    loop {
+        let first = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        let port = first.local_addr().unwrap().port().unwrap();
+        let Some(next) = port.checked_add(1) else {
+            continue;
+        };
+
+        if let Ok(second) = Listener::bind(SocketAddr::tcp([127, 0, 0, 1], next)).await {
+            return (first, second, port);
+        }
+    }
+}
+
+#[tokio::test]
+async fn bind_range_skips_ports_in_use() {
+    use tokio_anysocket::{Listener, PortRange};
+
+    // Only the first port stays taken.
+    let (_first, second, port) = consecutive_listeners().await;
+    drop(second);
+
+    let range = PortRange::new([127, 0, 0, 1], port..=port + 1).unwrap();
+    let listener = Listener::bind_range(&range).await.unwrap();
+    assert_eq!(listener.local_addr().unwrap().port(), Some(port + 1));
+}
+
+#[tokio::test]
+async fn bind_range_names_exhausted_range() {
+    use std::io::ErrorKind;
+
+    use tokio_anysocket::{Listener, PortRange};
+
+    let (_first, _second, port) = consecutive_listeners().await;
+
+    let range = PortRange::new([127, 0, 0, 1], port..=port + 1).unwrap();
+    let e = Listener::bind_range(&range).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::AddrInUse, "{e}");
+    assert_eq!(
+        e.to_string(),
+        format!(
+            "all ports in tcp://127.0.0.1:{port}-{} are in use",
+            port + 1
+        )
+    );
+}
+
+#[tokio::test]
+async fn unix_atomic_replace_takes_over_a_live_path() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{BindOptions, Listener, SocketAddr, Stream};
+
+    let dir = std::env::temp_dir().join(format!("anysocket-replace-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let addr = SocketAddr::unix(dir.join("server.sock")).unwrap();
+
+    let old = Listener::bind(&addr).await.unwrap();
+    let mut old_client = Stream::connect(&addr).await.unwrap();
+    let (mut old_server, _) = old.accept().await.unwrap();
+
+    let options = BindOptions::default().unix_atomic_replace(true);
+    let new = Listener::bind_with(&addr, &options).await.unwrap();
+    assert_eq!(new.local_addr().unwrap(), addr);
+
+    // Only the target and no temporary file are left.
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+
+    let _new_client = Stream::connect(&addr).await.unwrap();
+    new.accept().await.unwrap();
+
+    old_client.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    old_server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+
+    // A path that only looks like a temporary one is reported as it is.
+    let lookalike = SocketAddr::unix(dir.join("server.sock.anysocket-0123456789abcdef")).unwrap();
+    let listener = Listener::bind(&lookalike).await.unwrap();
+    assert_eq!(listener.local_addr().unwrap(), lookalike);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn bind_with_reclaims_stale_sockets() {
+    use std::io::ErrorKind;
+
+    use tokio_anysocket::{BindOptions, Listener, SocketAddr, Stream};
+
+    let dir = std::env::temp_dir().join(format!("anysocket-reclaim-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.sock");
+    let addr = SocketAddr::unix(&path).unwrap();
+
+    // Neither removes a file that is not a socket.
+    std::fs::write(&path, "data").unwrap();
+    for options in [
+        BindOptions::default(),
+        BindOptions::default().reclaim_stale_unix(true),
+    ] {
+        let e = Listener::bind_with(&addr, &options).await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::AddrInUse, "{e}");
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    let options = BindOptions::default().reclaim_stale_unix(true);
+
+    // A live listener keeps its path.
+    let live = Listener::bind(&addr).await.unwrap();
+    let e = Listener::bind_with(&addr, &options).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::AddrInUse, "{e}");
+    let _client = Stream::connect(&addr).await.unwrap();
+    live.accept().await.unwrap();
+
+    // Dropping the listener leaks its socket file, which now refuses
+    // connections and is taken over.
+    drop(live);
+    assert!(path.exists());
+    let listener = Listener::bind_with(&addr, &options).await.unwrap();
+    let _client = Stream::connect(&addr).await.unwrap();
+    listener.accept().await.unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn accept_many_takes_a_connection_storm() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    const N: u16 = 500;
+    const MAX: usize = 64;
+
+    let path = std::env::temp_dir().join(format!("anysocket-storm-{}.sock", std::process::id()));
+    let addrs = [
+        SocketAddr::tcp([127, 0, 0, 1], 0),
+        SocketAddr::unix(&path).unwrap(),
+    ];
+
+    for addr in addrs {
+        let listener = Listener::bind(&addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let clients: Vec<_> = (0..N)
+            .map(|i| {
+                let addr = addr.clone();
+                tokio::spawn(async move {
+                    let mut stream = Stream::connect(&addr).await.unwrap();
+                    stream.write_all(&i.to_be_bytes()).await.unwrap();
+
+                    let mut buf = [0; 2];
+                    stream.read_exact(&mut buf).await.unwrap();
+                    assert_eq!(u16::from_be_bytes(buf), i);
+                })
+            })
+            .collect();
+
+        let mut accepted = Vec::new();
+        while accepted.len() < usize::from(N) {
+            let n = listener.accept_many(&mut accepted, MAX).await.unwrap();
+            assert!((1..=MAX).contains(&n), "{addr}: {n}");
+        }
+        assert_eq!(accepted.len(), usize::from(N), "{addr}");
+
+        // Every connection works, and each is a different client.
+        for (mut server, _) in accepted {
+            let mut buf = [0; 2];
+            server.read_exact(&mut buf).await.unwrap();
+            server.write_all(&buf).await.unwrap();
+        }
+
+        for client in clients {
+            client.await.unwrap();
+        }
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn unlink_on_drop_removes_only_its_own_file() {
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    let dir = std::env::temp_dir().join(format!("anysocket-unlink-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.sock");
+    let addr = SocketAddr::unix(&path).unwrap();
+
+    let listener = Listener::bind(&addr)
+        .await
+        .unwrap()
+        .unlink_on_drop()
+        .unwrap();
+    assert_eq!(listener.path(), Some(path.as_path()));
+    let _client = Stream::connect(&addr).await.unwrap();
+    listener.accept().await.unwrap();
+
+    drop(listener);
+    assert!(!path.exists());
+
+    // A file put at the path after binding belongs to someone else.
+    let listener = Listener::bind(&addr)
+        .await
+        .unwrap()
+        .unlink_on_drop()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, "other").unwrap();
+
+    drop(listener);
+    assert_eq!(std::fs::read(&path).unwrap(), b"other");
+
+    // So does a socket bound there by another listener.
+    std::fs::remove_file(&path).unwrap();
+    let listener = Listener::bind(&addr)
+        .await
+        .unwrap()
+        .unlink_on_drop()
+        .unwrap();
+    let other = Listener::bind(&addr).await.unwrap();
+
+    drop(listener);
+    let _client = Stream::connect(&addr).await.unwrap();
+    other.accept().await.unwrap();
+
+    // TCP listeners have no file.
+    let tcp = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    assert_eq!(tcp.unlink_on_drop().unwrap().path(), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn bind_with_sets_mode_and_owner() {
+    use std::io::ErrorKind;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use tokio_anysocket::{BindOptions, Listener, SocketAddr, Stream};
+
+    let dir = std::env::temp_dir().join(format!("anysocket-mode-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let owner = std::fs::metadata(&dir).unwrap();
+    let addr = SocketAddr::unix(dir.join("server.sock")).unwrap();
+
+    for mode in [0o600, 0o660, 0o666] {
+        let options = BindOptions::default()
+            .mode(mode)
+            .uid(owner.uid())
+            .gid(owner.gid());
+        let listener = Listener::bind_with(&addr, &options).await.unwrap();
+
+        let meta = std::fs::symlink_metadata(dir.join("server.sock")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, mode, "{mode:o}");
+        assert_eq!((meta.uid(), meta.gid()), (owner.uid(), owner.gid()));
+
+        let _client = Stream::connect(&addr).await.unwrap();
+        listener.accept().await.unwrap();
+    }
+
+    let options = BindOptions::default().mode(0o600);
+    let e = Listener::bind_with("tcp://127.0.0.1:0", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput, "{e}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn bind_with_creates_parent_dirs() {
+    use std::io::ErrorKind;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tokio_anysocket::{BindOptions, Listener, SocketAddr, Stream};
+
+    let dir = std::env::temp_dir().join(format!("anysocket-dirs-{}", std::process::id()));
+    let nested = dir.join("a/b/c");
+    let addr = SocketAddr::unix(nested.join("server.sock")).unwrap();
+
+    let e = Listener::bind_with(&addr, &BindOptions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound, "{e}");
+
+    let options = BindOptions::default().create_dirs(true).dir_mode(0o700);
+    let listener = Listener::bind_with(&addr, &options).await.unwrap();
+    for x in [dir.join("a"), dir.join("a/b"), nested.clone()] {
+        let mode = std::fs::metadata(&x).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700, "{}", x.display());
+    }
+
+    let _client = Stream::connect(&addr).await.unwrap();
+    listener.accept().await.unwrap();
+
+    // A dangling symlink is not followed to create its target.
+    let target = dir.join("target");
+    std::os::unix::fs::symlink(&target, dir.join("link")).unwrap();
+    let addr = SocketAddr::unix(dir.join("link/server.sock")).unwrap();
+    let e = Listener::bind_with(&addr, &options).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotADirectory, "{e}");
+    assert!(e.to_string().contains("link"), "{e}");
+    assert!(!target.exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn try_clone_handles_share_connections() {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    let path = std::env::temp_dir().join(format!("anysocket-clone-{}.sock", std::process::id()));
+    let addrs = [
+        SocketAddr::tcp([127, 0, 0, 1], 0),
+        SocketAddr::unix(&path).unwrap(),
+    ];
+
+    for addr in addrs {
+        let listener = Listener::bind(&addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let clone = listener.try_clone().unwrap();
+
+        // Each handle waits for exactly one connection at the same time.
+        let accept_one = |listener: Listener| {
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1];
+                stream.read_exact(&mut buf).await.unwrap();
+                buf[0]
+            })
+        };
+        let tasks = [accept_one(listener), accept_one(clone)];
+
+        let mut clients = Vec::new();
+        for i in [1, 2] {
+            let mut client = Stream::connect(&addr).await.unwrap();
+            client.write_all(&[i]).await.unwrap();
+            clients.push(client);
+        }
+
+        let mut got = Vec::new();
+        for task in tasks {
+            let x = tokio::time::timeout(Duration::from_secs(5), task).await;
+            got.push(x.expect("both handles accept").unwrap());
+        }
+        got.sort_unstable();
+        assert_eq!(got, [1, 2], "{addr}");
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[tokio::test]
+async fn bind_autobind() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    let addr = SocketAddr::unix_autobind().unwrap();
+    assert!(addr.is_unnamed());
+
+    let a = Listener::bind(&addr).await.unwrap();
+    let b = Listener::bind(&addr).await.unwrap();
+
+    // The kernel picked a different abstract name for each.
+    let a_addr = a.local_addr().unwrap();
+    let name = a_addr.as_abstract_name().expect("an abstract name");
+    assert!(!name.is_empty());
+    assert_ne!(b.local_addr().unwrap(), a_addr);
+
+    let mut client = Stream::connect(&a_addr).await.unwrap();
+    let (mut server, _) = a.accept().await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn accept_with_applies_options() {
+    use std::time::Duration;
+
+    use tokio_anysocket::{AcceptOptions, KeepaliveConfig, Listener, SocketAddr, Stream};
+
+    let keepalive = KeepaliveConfig {
+        time: Duration::from_secs(30),
+        interval: Duration::from_secs(5),
+        retries: 4,
+    };
+    let options = AcceptOptions::default()
+        .nodelay(true)
+        .keepalive(keepalive)
+        .recv_buffer_size(64 * 1024);
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let _client = Stream::connect(&addr).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    assert!(!server.nodelay().unwrap());
+
+    let _client = Stream::connect(&addr).await.unwrap();
+    let (server, _) = listener.accept_with(&options).await.unwrap();
+    assert!(server.nodelay().unwrap());
+    assert_eq!(server.keepalive().unwrap(), Some(keepalive));
+    assert!(server.recv_buffer_size().unwrap() >= 64 * 1024);
+
+    // The TCP-only options are skipped for unix connections.
+    let path =
+        std::env::temp_dir().join(format!("anysocket-accept-with-{}.sock", std::process::id()));
+    let listener = Listener::bind(SocketAddr::unix(&path).unwrap())
+        .await
+        .unwrap();
+    let _client = Stream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (server, _) = listener.accept_with(&options).await.unwrap();
+    assert!(server.nodelay().unwrap());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn ttl_and_hop_limit_read_back() {
+    use tokio_anysocket::Listener;
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    for ttl in [1, 64, 255] {
+        listener.set_ttl(ttl).unwrap();
+        assert_eq!(listener.ttl().unwrap(), ttl);
+    }
+
+    // Hosts without IPv6 cannot bind the loopback address at all.
+    let Ok(listener) = Listener::bind("tcp://[::1]:0").await else {
+        return;
+    };
+    for hops in [1, 64, 255] {
+        listener.set_unicast_hops_v6(hops).unwrap();
+        assert_eq!(listener.unicast_hops_v6().unwrap(), hops);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn reuse_port_shares_connections() {
+    use std::io::ErrorKind;
+
+    use tokio_anysocket::{BindOptions, Listener, Stream};
+
+    let options = BindOptions::default().reuse_port(true);
+    let first = Listener::bind_with("tcp://127.0.0.1:0", &options)
+        .await
+        .unwrap();
+    let addr = first.local_addr().unwrap();
+    let second = Listener::bind_with(&addr, &options).await.unwrap();
+
+    let e = Listener::bind_with(&addr, &BindOptions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::AddrInUse);
+
+    // The kernel hashes each connection to one of the listeners, so with this
+    // many both get some.
+    let mut clients = Vec::new();
+    let mut counts = [0; 2];
+    for _ in 0..64 {
+        clients.push(Stream::connect(&addr).await.unwrap());
+        tokio::select! {
+            x = first.accept() => { x.unwrap(); counts[0] += 1; }
+            x = second.accept() => { x.unwrap(); counts[1] += 1; }
+        }
+    }
+    assert!(counts[0] > 0 && counts[1] > 0, "{counts:?}");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn backlog_limits_the_accept_queue() {
+    use std::time::Duration;
+
+    use tokio_anysocket::{BindOptions, Listener, Stream};
+
+    let options = BindOptions::default().backlog(1);
+    let listener = Listener::bind_with("tcp://127.0.0.1:0", &options)
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Linux queues one more connection than the backlog and then drops SYNs.
+    let mut queued = Vec::new();
+    while let Ok(x) = tokio::time::timeout(Duration::from_millis(200), Stream::connect(&addr)).await
+    {
+        queued.push(x.unwrap());
+        assert!(queued.len() <= 2, "the backlog was not applied");
+    }
+    assert_eq!(queued.len(), 2);
+
+    // Accepting one makes room for the next.
+    listener.accept().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), Stream::connect(&addr))
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn dual_stack_listener_accepts_ipv4() {
+    use std::io::ErrorKind;
+
+    use tokio_anysocket::{BindOptions, Listener, SocketAddr, Stream};
+
+    // Hosts without IPv6 cannot bind the unspecified address at all.
+    let options = BindOptions::default().v6_only(false);
+    let Ok(listener) = Listener::bind_with("tcp://[::]:0", &options).await else {
+        return;
+    };
+    let port = listener.local_addr().unwrap().port().unwrap();
+
+    let client = Stream::connect(SocketAddr::tcp([127, 0, 0, 1], port))
+        .await
+        .unwrap();
+    let (_server, peer) = listener.accept().await.unwrap();
+    let ip = peer.ip().unwrap();
+    assert!(ip.is_ipv6(), "{peer}");
+    assert_eq!(
+        ip.to_canonical(),
+        client.local_addr().unwrap().ip().unwrap()
+    );
+
+    // With `v6_only`, the same address is out of reach over IPv4.
+    let options = BindOptions::default().v6_only(true);
+    let listener = Listener::bind_with("tcp://[::]:0", &options).await.unwrap();
+    let port = listener.local_addr().unwrap().port().unwrap();
+    let e = Stream::connect(SocketAddr::tcp([127, 0, 0, 1], port))
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::ConnectionRefused);
+
+    let e = Listener::bind_with("tcp://127.0.0.1:0", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn multi_listener_takes_turns() {
+    use std::time::Duration;
+
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    let path = std::env::temp_dir().join(format!("anysocket-multi-{}.sock", std::process::id()));
+    let listener = Listener::bind_all([
+        SocketAddr::tcp([127, 0, 0, 1], 0),
+        SocketAddr::unix(&path).unwrap(),
+    ])
+    .await
+    .unwrap();
+    let addrs = listener.local_addrs().unwrap();
+
+    let mut clients = Vec::new();
+    for addr in &addrs {
+        for _ in 0..4 {
+            clients.push(Stream::connect(addr).await.unwrap());
+        }
+    }
+    // Let the runtime see that both listeners are ready.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Both have connections waiting, so neither is served twice in a row.
+    let mut order = Vec::new();
+    for _ in 0..8 {
+        let (server, _) = listener.accept().await.unwrap();
+        order.push(server.is_unix());
+    }
+    assert_eq!(order, [false, true, false, true, false, true, false, true]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn incoming_yields_each_connection() {
+    use futures_util::StreamExt;
+    use futures_util::stream::poll_fn;
+    use tokio_anysocket::{Listener, Stream};
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        clients.push(Stream::connect(&addr).await.unwrap());
+    }
+
+    let mut incoming = listener.incoming();
+    let mut incoming = poll_fn(|cx| incoming.poll_next(cx));
+    for client in &clients {
+        let (server, peer) = incoming.next().await.unwrap().unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+        assert_eq!(server.peer_addr().unwrap(), peer);
+    }
+
+    let client = Stream::connect(&addr).await.unwrap();
+    let mut incoming = listener.into_incoming();
+    let (_, peer) = incoming.next().await.unwrap().unwrap();
+    assert_eq!(peer, client.local_addr().unwrap());
+    assert_eq!(incoming.into_inner().local_addr().unwrap(), addr);
+}
@@ -0,0 +1,103 @@
+use std::io::{ErrorKind, IoSlice};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_anysocket::Stream;
+
+#[tokio::test]
+async fn counts_vectored_writes() {
+    let (a, b) = Stream::pair().unwrap();
+    let mut a = a.metered();
+    let mut b = b.metered();
+
+    let bufs = [
+        IoSlice::new(b"hello"),
+        IoSlice::new(b""),
+        IoSlice::new(b", vectored"),
+        IoSlice::new(b" world"),
+    ];
+
+    // Only the bytes the socket took count, not the length of all buffers.
+    let n = a.write_vectored(&bufs).await.unwrap();
+    assert!(n > 0);
+    assert_eq!(a.metrics().bytes_written(), n as u64);
+
+    let m = a.try_write_vectored(&bufs[2..]).unwrap();
+    let written = (n + m) as u64;
+    assert_eq!(a.metrics().bytes_written(), written);
+    drop(a);
+
+    let mut buf = Vec::new();
+    b.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(b.metrics().bytes_read(), buf.len() as u64);
+    assert_eq!(buf.len() as u64, written);
+    assert_eq!(b.metrics().bytes_written(), 0);
+}
+
+#[tokio::test]
+async fn counts_partial_writes() {
+    let (a, b) = Stream::pair_tcp().await.unwrap();
+    a.set_send_buffer_size(4096).unwrap();
+    let a = a.metered();
+    let b = b.metered();
+
+    // Fill the send buffer, so the last write only takes part of the data.
+    let data = vec![7u8; 64 * 1024];
+    let (first, second) = data.split_at(data.len() / 2);
+    let bufs = [IoSlice::new(first), IoSlice::new(second)];
+    let mut partial = false;
+    let mut written = 0u64;
+    for i in 0.. {
+        let result = if i % 2 == 0 {
+            a.try_write(&data)
+        } else {
+            a.try_write_vectored(&bufs)
+        };
+
+        match result {
+            Ok(n) => {
+                partial |= n < data.len();
+                written += n as u64;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    assert!(partial);
+    assert_eq!(a.metrics().bytes_written(), written);
+
+    // A write that fails does not count.
+    assert!(a.try_write(&data).is_err());
+    assert_eq!(a.metrics().bytes_written(), written);
+
+    let metrics = b.metrics().clone();
+    let (mut read, _write) = b.into_split();
+    let mut buf = vec![0; written as usize];
+    read.read_exact(&mut buf).await.unwrap();
+    assert_eq!(metrics.bytes_read(), written);
+}
+
+#[tokio::test]
+async fn split_halves_share_metrics() {
+    let (a, b) = Stream::pair().unwrap();
+    let a = a.metered();
+    let metrics = a.metrics().clone();
+    let (mut read, mut write) = a.into_split();
+
+    let writer = tokio::spawn(async move {
+        let data = vec![1u8; 256 * 1024];
+        write.write_all(&data).await.unwrap();
+        write
+    });
+
+    let mut b = b;
+    let mut buf = vec![0; 256 * 1024];
+    b.read_exact(&mut buf).await.unwrap();
+    let _write = writer.await.unwrap();
+    assert_eq!(metrics.bytes_written(), 256 * 1024);
+
+    b.write_all(b"reply").await.unwrap();
+    let mut buf = [0; 5];
+    read.read_exact(&mut buf).await.unwrap();
+    assert_eq!(metrics.bytes_read(), 5);
+}
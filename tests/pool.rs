@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio_anysocket::{Listener, Pool, PoolConfig, SocketAddr};
+
+async fn listener() -> (Listener, SocketAddr) {
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+#[tokio::test]
+async fn reuses_idle_connections() {
+    let (listener, addr) = listener().await;
+    let pool = Pool::default();
+
+    let stream = pool.get(&addr).await.unwrap();
+    let (_server, _) = listener.accept().await.unwrap();
+    assert!(!stream.is_reused());
+    let local = stream.local_addr().unwrap();
+
+    drop(stream);
+    assert_eq!(pool.idle_connections(), 1);
+
+    let stream = pool.get(&addr).await.unwrap();
+    assert!(stream.is_reused());
+    assert_eq!(stream.local_addr().unwrap(), local);
+    assert_eq!(pool.idle_connections(), 0);
+
+    // No second connection was made.
+    let accept = tokio::time::timeout(Duration::from_millis(50), listener.accept()).await;
+    assert!(accept.is_err());
+}
+
+#[tokio::test]
+async fn evicts_after_idle_timeout() {
+    let (listener, addr) = listener().await;
+    let config = PoolConfig::default().idle_timeout(Some(Duration::from_millis(50)));
+    let pool = Pool::new(config);
+
+    drop(pool.get(&addr).await.unwrap());
+    let (_first, _) = listener.accept().await.unwrap();
+    assert_eq!(pool.idle_connections(), 1);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let stream = pool.get(&addr).await.unwrap();
+    assert!(!stream.is_reused());
+    let (_second, _) = listener.accept().await.unwrap();
+    assert_eq!(pool.idle_connections(), 0);
+}
+
+#[tokio::test]
+async fn does_not_pool_failed_streams() {
+    let (listener, addr) = listener().await;
+    let pool = Pool::default();
+
+    let mut stream = pool.get(&addr).await.unwrap();
+    let (_server, _) = listener.accept().await.unwrap();
+
+    // Writing after shutting down fails, which poisons the stream.
+    stream.shutdown().await.unwrap();
+    assert!(stream.write_all(b"x").await.is_err());
+    assert!(stream.is_poisoned());
+
+    drop(stream);
+    assert_eq!(pool.idle_connections(), 0);
+
+    let mut stream = pool.get(&addr).await.unwrap();
+    stream.poison();
+    drop(stream);
+    assert_eq!(pool.idle_connections(), 0);
+}
+
+#[tokio::test]
+async fn skips_connections_closed_by_the_peer() {
+    let (listener, addr) = listener().await;
+    let pool = Pool::default();
+
+    drop(pool.get(&addr).await.unwrap());
+    let (server, _) = listener.accept().await.unwrap();
+    drop(server);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let stream = pool.get(&addr).await.unwrap();
+    assert!(!stream.is_reused());
+}
@@ -0,0 +1,376 @@
+use std::io::ErrorKind;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+use tokio_anysocket::{
+    ConnectOptions, HttpConnectProxy, Listener, SocketAddr, Socks5Proxy, Stream,
+};
+
+/// What a client asked the SOCKS5 responder for.
+#[derive(Debug, Default, PartialEq)]
+struct Socks5Request {
+    auth: Option<(String, String)>,
+    atyp: u8,
+    host: Vec<u8>,
+    port: u16,
+}
+
+/// Serve one SOCKS5 client: require username and password if `password` is
+/// set, reply to the request with `reply`, and echo whatever comes after.
+async fn socks5_responder(
+    password: Option<(&'static str, &'static str)>,
+    reply: u8,
+) -> (SocketAddr, JoinHandle<Socks5Request>) {
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let task = tokio::spawn(async move {
+        let (mut s, _) = listener.accept().await.unwrap();
+
+        let mut head = [0; 2];
+        s.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[0], 5, "greeting version");
+        let mut methods = vec![0; head[1].into()];
+        s.read_exact(&mut methods).await.unwrap();
+
+        let mut auth = None;
+        if let Some((username, password)) = password {
+            if !methods.contains(&2) {
+                s.write_all(&[5, 0xff]).await.unwrap();
+                return Socks5Request {
+                    auth,
+                    ..Default::default()
+                };
+            }
+            s.write_all(&[5, 2]).await.unwrap();
+
+            assert_eq!(s.read_u8().await.unwrap(), 1, "password request version");
+            let given = (read_field(&mut s).await, read_field(&mut s).await);
+            let ok = given.0 == username && given.1 == password;
+            auth = Some(given);
+
+            s.write_all(&[1, if ok { 0 } else { 1 }]).await.unwrap();
+            if !ok {
+                return Socks5Request {
+                    auth,
+                    ..Default::default()
+                };
+            }
+        } else {
+            assert!(methods.contains(&0), "no-auth not offered: {methods:?}");
+            s.write_all(&[5, 0]).await.unwrap();
+        }
+
+        let mut request = [0; 4];
+        s.read_exact(&mut request).await.unwrap();
+        assert_eq!(request[..3], [5, 1, 0], "CONNECT request");
+        let atyp = request[3];
+        let len = match atyp {
+            1 => 4,
+            4 => 16,
+            3 => s.read_u8().await.unwrap().into(),
+            x => panic!("unknown address type {x}"),
+        };
+        let mut host = vec![0; len];
+        s.read_exact(&mut host).await.unwrap();
+        let port = s.read_u16().await.unwrap();
+
+        // Bound to 10.0.0.1:1080, or a hostname, to check both are skipped.
+        if atyp == 3 {
+            s.write_all(&[5, reply, 0, 3, 5]).await.unwrap();
+            s.write_all(b"proxy\x04\x38").await.unwrap();
+        } else {
+            s.write_all(&[5, reply, 0, 1, 10, 0, 0, 1, 0x04, 0x38])
+                .await
+                .unwrap();
+        }
+
+        if reply == 0 {
+            let mut buf = [0; 5];
+            s.read_exact(&mut buf).await.unwrap();
+            s.write_all(&buf).await.unwrap();
+        }
+
+        Socks5Request {
+            auth,
+            atyp,
+            host,
+            port,
+        }
+    });
+
+    (addr, task)
+}
+
+/// Read a length-prefixed field of a SOCKS5 password request.
+async fn read_field(s: &mut Stream) -> String {
+    let len = s.read_u8().await.unwrap();
+    let mut x = vec![0; len.into()];
+    s.read_exact(&mut x).await.unwrap();
+    String::from_utf8(x).unwrap()
+}
+
+async fn echo(mut stream: Stream) {
+    stream.write_all(b"hello").await.unwrap();
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello", "echoed through the tunnel");
+}
+
+#[tokio::test]
+async fn socks5_no_auth() {
+    let (proxy, server) = socks5_responder(None, 0).await;
+    let options = ConnectOptions::default().proxy(Socks5Proxy::new(proxy.clone()));
+
+    let (stream, addr) = Stream::connect_with_full("tcp://192.0.2.7:8080", &options)
+        .await
+        .unwrap();
+    // The address asked for, not the proxy.
+    assert_eq!(addr, "tcp://192.0.2.7:8080".parse().unwrap());
+    assert_eq!(stream.peer_addr().unwrap(), proxy);
+    echo(stream).await;
+
+    let request = server.await.unwrap();
+    assert_eq!(
+        request,
+        Socks5Request {
+            auth: None,
+            atyp: 1,
+            host: vec![192, 0, 2, 7],
+            port: 8080,
+        }
+    );
+
+    let (proxy, server) = socks5_responder(None, 0).await;
+    let options = ConnectOptions::default().proxy(Socks5Proxy::new(proxy));
+    let stream = Stream::connect_host_with("[2001:db8::1]", 443, &options)
+        .await
+        .unwrap();
+    echo(stream).await;
+
+    let request = server.await.unwrap();
+    assert_eq!(request.atyp, 4);
+    assert_eq!(
+        request.host,
+        "2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+    );
+    assert_eq!(request.port, 443);
+}
+
+#[tokio::test]
+async fn socks5_password() {
+    let (proxy, server) = socks5_responder(Some(("user", "secret")), 0).await;
+    let options = ConnectOptions::default().proxy(Socks5Proxy::new(proxy).auth("user", "secret"));
+
+    let stream = Stream::connect_with("tcp://192.0.2.7:8080", &options)
+        .await
+        .unwrap();
+    echo(stream).await;
+
+    let request = server.await.unwrap();
+    assert_eq!(request.auth, Some(("user".into(), "secret".into())));
+
+    // Wrong password.
+    let (proxy, server) = socks5_responder(Some(("user", "secret")), 0).await;
+    let options = ConnectOptions::default().proxy(Socks5Proxy::new(proxy).auth("user", "guess"));
+    let e = Stream::connect_host_with("192.0.2.7", 8080, &options)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermissionDenied, "{e}");
+    server.await.unwrap();
+
+    // No credentials to offer.
+    let (proxy, server) = socks5_responder(Some(("user", "secret")), 0).await;
+    let options = ConnectOptions::default().proxy(Socks5Proxy::new(proxy));
+    let e = Stream::connect_host_with("192.0.2.7", 8080, &options)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermissionDenied, "{e}");
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn socks5_hostname() {
+    let (proxy, server) = socks5_responder(None, 0).await;
+    let options = ConnectOptions::default().proxy(Socks5Proxy::new(proxy));
+
+    // The proxy resolves the name, so it need not resolve here.
+    let stream = Stream::connect_host_with("backend.invalid", 5432, &options)
+        .await
+        .unwrap();
+    echo(stream).await;
+
+    let request = server.await.unwrap();
+    assert_eq!(request.atyp, 3);
+    assert_eq!(request.host, b"backend.invalid");
+    assert_eq!(request.port, 5432);
+}
+
+#[tokio::test]
+async fn socks5_reply_errors() {
+    for (code, kind) in [
+        (0x01, ErrorKind::Other),
+        (0x02, ErrorKind::PermissionDenied),
+        (0x03, ErrorKind::NetworkUnreachable),
+        (0x04, ErrorKind::HostUnreachable),
+        (0x05, ErrorKind::ConnectionRefused),
+        (0x06, ErrorKind::TimedOut),
+        (0x07, ErrorKind::Unsupported),
+        (0x08, ErrorKind::Unsupported),
+    ] {
+        let (proxy, server) = socks5_responder(None, code).await;
+        let options = ConnectOptions::default().proxy(Socks5Proxy::new(proxy));
+
+        let e = Stream::connect_host_with("backend.invalid", 80, &options)
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), kind, "{code}: {e}");
+        assert!(e.to_string().contains("SOCKS5 proxy"), "{e}");
+        server.await.unwrap();
+    }
+}
+
+/// Serve one HTTP CONNECT client: answer with `response`, then echo what
+/// comes after. Returns the request head.
+async fn http_responder(response: Vec<u8>) -> (SocketAddr, JoinHandle<String>) {
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let task = tokio::spawn(async move {
+        let (mut s, _) = listener.accept().await.unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(s.read_u8().await.unwrap());
+        }
+
+        // The client hangs up on responses it rejects.
+        let mut buf = [0; 5];
+        if s.write_all(&response).await.is_ok() && s.read_exact(&mut buf).await.is_ok() {
+            s.write_all(&buf).await.unwrap();
+        }
+
+        String::from_utf8(head).unwrap()
+    });
+
+    (addr, task)
+}
+
+#[tokio::test]
+async fn http_connect_ok() {
+    let (proxy, server) =
+        http_responder(b"HTTP/1.1 200 Connection established\r\n\r\n".to_vec()).await;
+    let options = ConnectOptions::default().proxy(HttpConnectProxy::new(proxy));
+
+    let stream = Stream::connect_host_with("backend.invalid", 443, &options)
+        .await
+        .unwrap();
+    echo(stream).await;
+
+    let head = server.await.unwrap();
+    assert_eq!(
+        head,
+        "CONNECT backend.invalid:443 HTTP/1.1\r\nHost: backend.invalid:443\r\n\r\n"
+    );
+
+    let (proxy, server) = http_responder(b"HTTP/1.0 200 OK\r\nVia: test\r\n\r\n".to_vec()).await;
+    let options =
+        ConnectOptions::default().proxy(HttpConnectProxy::new(proxy).auth("user", "secret"));
+
+    let stream = Stream::connect_with("tcp://[2001:db8::1]:443", &options)
+        .await
+        .unwrap();
+    echo(stream).await;
+
+    let head = server.await.unwrap();
+    assert!(
+        head.starts_with("CONNECT [2001:db8::1]:443 HTTP/1.1\r\n"),
+        "{head}"
+    );
+    assert!(
+        head.contains("\r\nProxy-Authorization: Basic dXNlcjpzZWNyZXQ=\r\n"),
+        "{head}"
+    );
+}
+
+#[tokio::test]
+async fn http_connect_status() {
+    for (status, kind) in [
+        ("403 Forbidden", ErrorKind::PermissionDenied),
+        (
+            "407 Proxy Authentication Required",
+            ErrorKind::PermissionDenied,
+        ),
+        ("502 Bad Gateway", ErrorKind::ConnectionRefused),
+        ("504 Gateway Timeout", ErrorKind::TimedOut),
+        ("500 Internal Server Error", ErrorKind::Other),
+        ("101 Switching Protocols", ErrorKind::Other),
+    ] {
+        let response = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n");
+        let (proxy, server) = http_responder(response.into_bytes()).await;
+        let options = ConnectOptions::default().proxy(HttpConnectProxy::new(proxy));
+
+        let e = Stream::connect_host_with("backend.invalid", 443, &options)
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), kind, "{status}: {e}");
+        assert_eq!(e.to_string(), format!("HTTP proxy: {status}"));
+        server.await.unwrap();
+    }
+
+    for response in [
+        "HTTP/2 200\r\n\r\n",
+        "HTTP/1.1 2000 OK\r\n\r\n",
+        "SSH-2.0\r\n\r\n",
+    ] {
+        let (proxy, server) = http_responder(response.into()).await;
+        let options = ConnectOptions::default().proxy(HttpConnectProxy::new(proxy));
+
+        let e = Stream::connect_host_with("backend.invalid", 443, &options)
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData, "{response:?}: {e}");
+        server.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn http_connect_head_too_long() {
+    let mut response = b"HTTP/1.1 200 OK\r\nX-Padding: ".to_vec();
+    response.resize(16 * 1024, b'x');
+    response.extend(b"\r\n\r\n");
+
+    let (proxy, server) = http_responder(response).await;
+    let options = ConnectOptions::default().proxy(HttpConnectProxy::new(proxy));
+
+    let e = Stream::connect_host_with("backend.invalid", 443, &options)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData, "{e}");
+    assert!(e.to_string().contains("too long"), "{e}");
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn http_connect_pipelined_data() {
+    // The proxy sends tunneled data in the same segment as the response.
+    let mut response = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+    response.extend(b"SSH-2.0-server\r\n");
+
+    let (proxy, server) = http_responder(response).await;
+    let options = ConnectOptions::default().proxy(HttpConnectProxy::new(proxy));
+
+    let mut stream = Stream::connect_host_with("backend.invalid", 22, &options)
+        .await
+        .unwrap();
+
+    let mut banner = [0; 16];
+    stream.read_exact(&mut banner).await.unwrap();
+    assert_eq!(&banner, b"SSH-2.0-server\r\n");
+
+    echo(stream).await;
+    server.await.unwrap();
+}
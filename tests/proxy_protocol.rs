@@ -0,0 +1,272 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_anysocket::{ProxyAddrs, ProxyHeader, ProxyVersion, SocketAddr};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A v2 header for a TCP4 connection from 10.0.0.1:12345 to 10.0.0.2:443, as
+/// HAProxy sends it.
+const V2_INET: &[u8] = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c\
+    \x0a\x00\x00\x01\x0a\x00\x00\x02\x30\x39\x01\xbb";
+
+fn tcp(addr: &str) -> SocketAddr {
+    addr.parse::<std::net::SocketAddr>().unwrap().into()
+}
+
+async fn parse(bytes: &[u8]) -> std::io::Result<ProxyHeader> {
+    ProxyHeader::read_from(&mut &bytes[..], TIMEOUT).await
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let v1_tcp4 = b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n";
+    let v1_tcp6 = b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 80\r\n";
+    let v1_unknown = b"PROXY UNKNOWN\r\n";
+    let v2_local = b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00";
+
+    for bytes in [&v1_tcp4[..], v1_tcp6, v1_unknown, V2_INET, v2_local] {
+        let header = parse(bytes).await.unwrap();
+        assert_eq!(header.to_bytes(), bytes, "{header:?}");
+    }
+
+    let header = parse(v1_tcp4).await.unwrap();
+    assert_eq!(header.version, ProxyVersion::V1);
+    let addrs = header.addrs.unwrap();
+    assert_eq!(addrs.source, tcp("192.168.0.1:56324"));
+    assert_eq!(addrs.destination, tcp("192.168.0.11:443"));
+
+    let header = parse(V2_INET).await.unwrap();
+    assert_eq!(header.version, ProxyVersion::V2);
+    let addrs = header.addrs.unwrap();
+    assert_eq!(addrs.source, tcp("10.0.0.1:12345"));
+    assert_eq!(addrs.destination, tcp("10.0.0.2:443"));
+
+    assert!(parse(v1_unknown).await.unwrap().addrs.is_none());
+    assert!(parse(v2_local).await.unwrap().addrs.is_none());
+}
+
+#[tokio::test]
+async fn leaves_proxied_data() {
+    // The same header with an ALPN TLV, which is skipped.
+    let mut bytes = V2_INET.to_vec();
+    bytes[15] += 5;
+    bytes.extend(b"\x01\x00\x02h2");
+    bytes.extend(b"GET / HTTP/1.1\r\n");
+
+    let mut reader = &bytes[..];
+    let header = ProxyHeader::read_from(&mut reader, TIMEOUT).await.unwrap();
+    assert_eq!(header.addrs.unwrap().source, tcp("10.0.0.1:12345"));
+    assert_eq!(reader, b"GET / HTTP/1.1\r\n");
+
+    let mut reader = &b"PROXY UNKNOWN\r\nhello"[..];
+    ProxyHeader::read_from(&mut reader, TIMEOUT).await.unwrap();
+    assert_eq!(reader, b"hello");
+}
+
+#[tokio::test]
+async fn malformed() {
+    let cases: &[&[u8]] = &[
+        b"GET / HTTP/1.1\r\n\r\n",
+        b"PROXY TCP5 1.2.3.4 5.6.7.8 1 2\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 1\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 1 2 3\r\n",
+        b"PROXY TCP4 ::1 ::1 1 2\r\n",
+        b"PROXY TCP4 1.2.3.4 5.6.7.8 1 70000\r\n",
+        b"PROXY \xff\r\n",
+        // Version 1 in the binary format.
+        b"\r\n\r\n\0\r\nQUIT\n\x11\x11\x00\x0c\
+          \x0a\x00\x00\x01\x0a\x00\x00\x02\x30\x39\x01\xbb",
+        // An unknown command.
+        b"\r\n\r\n\0\r\nQUIT\n\x22\x00\x00\x00",
+        // An address block too short for AF_INET.
+        b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x04\x0a\x00\x00\x01",
+        // An unknown address family.
+        b"\r\n\r\n\0\r\nQUIT\n\x21\x41\x00\x00",
+        // A signature that goes wrong after the first bytes.
+        b"\r\n\r\n\0\r\nQUIX\n\x21\x00\x00\x00",
+    ];
+
+    for bytes in cases {
+        let e = parse(bytes).await.unwrap_err();
+        assert_eq!(
+            e.kind(),
+            ErrorKind::InvalidData,
+            "{:?}: {e}",
+            String::from_utf8_lossy(bytes)
+        );
+    }
+
+    // The v1 line is limited to 107 bytes, and nothing after that is read.
+    let mut long = b"PROXY UNKNOWN ".to_vec();
+    long.resize(200, b'x');
+    let mut reader = &long[..];
+    let e = ProxyHeader::read_from(&mut reader, TIMEOUT)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData, "{e}");
+    assert_eq!(reader.len(), 200 - 107);
+
+    // The connection closes in the middle of the header.
+    let e = parse(&V2_INET[..20]).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::UnexpectedEof, "{e}");
+}
+
+#[tokio::test]
+async fn times_out() {
+    let (mut client, mut server) = tokio::io::duplex(64);
+
+    client.write_all(b"PROXY TCP4 1.2.3.4").await.unwrap();
+    let e = ProxyHeader::read_from(&mut server, Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::TimedOut, "{e}");
+
+    // Nothing at all.
+    let (_client, mut server) = tokio::io::duplex(64);
+    let e = ProxyHeader::read_from(&mut server, Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::TimedOut, "{e}");
+}
+
+#[tokio::test]
+async fn partial_reads() {
+    for header in [&b"PROXY TCP6 ::1 ::2 1 2\r\n"[..], V2_INET] {
+        let (mut client, mut server) = tokio::io::duplex(64);
+
+        let mut bytes = header.to_vec();
+        bytes.extend(b"data");
+        let writer = tokio::spawn(async move {
+            for byte in bytes {
+                client.write_all(&[byte]).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            client
+        });
+
+        let header = ProxyHeader::read_from(&mut server, TIMEOUT).await.unwrap();
+        assert!(header.addrs.is_some());
+
+        let _client = writer.await.unwrap();
+        let mut rest = [0; 4];
+        server.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest, b"data");
+    }
+}
+
+#[tokio::test]
+async fn encode_v1() {
+    let header = |source: &str, destination: &str| ProxyHeader {
+        version: ProxyVersion::V1,
+        addrs: Some(ProxyAddrs {
+            source: tcp(source),
+            destination: tcp(destination),
+        }),
+    };
+
+    assert_eq!(
+        header("1.2.3.4:5", "6.7.8.9:10").to_bytes(),
+        b"PROXY TCP4 1.2.3.4 6.7.8.9 5 10\r\n"
+    );
+    assert_eq!(
+        header("[::1]:5", "[2001:db8::2]:10").to_bytes(),
+        b"PROXY TCP6 ::1 2001:db8::2 5 10\r\n"
+    );
+
+    // Mixed families and unix addresses cannot be sent.
+    assert_eq!(
+        header("1.2.3.4:5", "[::1]:10").to_bytes(),
+        b"PROXY UNKNOWN\r\n"
+    );
+
+    let unix = ProxyHeader {
+        version: ProxyVersion::V1,
+        addrs: Some(ProxyAddrs {
+            source: SocketAddr::unix("/run/a.sock").unwrap(),
+            destination: SocketAddr::unix("/run/b.sock").unwrap(),
+        }),
+    };
+    assert_eq!(unix.to_bytes(), b"PROXY UNKNOWN\r\n");
+
+    let unknown = ProxyHeader {
+        version: ProxyVersion::V1,
+        addrs: None,
+    };
+    assert_eq!(unknown.to_bytes(), b"PROXY UNKNOWN\r\n");
+}
+
+#[tokio::test]
+async fn encode_v2() {
+    const SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+    let header = |source: SocketAddr, destination: SocketAddr| ProxyHeader {
+        version: ProxyVersion::V2,
+        addrs: Some(ProxyAddrs {
+            source,
+            destination,
+        }),
+    };
+
+    let inet = header(tcp("10.0.0.1:12345"), tcp("10.0.0.2:443"));
+    assert_eq!(inet.to_bytes(), V2_INET);
+
+    let inet6 = header(tcp("[::1]:1"), tcp("[::2]:2")).to_bytes();
+    assert_eq!(&inet6[..12], SIGNATURE);
+    assert_eq!(&inet6[12..16], b"\x21\x21\x00\x24");
+    assert_eq!(inet6.len(), 16 + 36);
+
+    let unix = header(
+        SocketAddr::unix("/run/a.sock").unwrap(),
+        SocketAddr::unix("/run/b.sock").unwrap(),
+    );
+    let bytes = unix.to_bytes();
+    assert_eq!(&bytes[12..16], b"\x21\x31\x00\xd8");
+    assert_eq!(&bytes[16..28], b"/run/a.sock\0");
+    assert_eq!(&bytes[16 + 108..16 + 120], b"/run/b.sock\0");
+
+    let parsed = parse(&bytes).await.unwrap().addrs.unwrap();
+    assert_eq!(parsed.source, SocketAddr::unix("/run/a.sock").unwrap());
+    assert_eq!(parsed.destination, SocketAddr::unix("/run/b.sock").unwrap());
+
+    // Mixed families are sent as AF_UNSPEC, no addresses as LOCAL.
+    let mixed = header(tcp("1.2.3.4:5"), tcp("[::1]:10"));
+    assert_eq!(&mixed.to_bytes()[12..], b"\x21\x00\x00\x00");
+
+    let local = ProxyHeader {
+        version: ProxyVersion::V2,
+        addrs: None,
+    };
+    assert_eq!(&local.to_bytes()[12..], b"\x20\x00\x00\x00");
+}
+
+#[tokio::test]
+async fn slow_client_does_not_hold_up_accept() {
+    use tokio_anysocket::{Listener, Stream};
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Connects, but never sends a header.
+    let _slow = Stream::connect(&addr).await.unwrap();
+    let (slow, _) = listener.accept().await.unwrap();
+    let slow = tokio::spawn(async move {
+        let mut slow = slow;
+        ProxyHeader::read_from(&mut slow, Duration::from_millis(200)).await
+    });
+
+    let header = ProxyHeader {
+        version: ProxyVersion::V1,
+        addrs: None,
+    };
+    let _fast = Stream::connect_with_proxy_header(&addr, &header)
+        .await
+        .unwrap();
+    let (mut fast, _) = listener.accept().await.unwrap();
+    ProxyHeader::read_from(&mut fast, TIMEOUT).await.unwrap();
+    assert!(!slow.is_finished());
+
+    let e = slow.await.unwrap().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::TimedOut, "{e}");
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+use tokio_anysocket::{ReadExactState, Stream, WriteAllState};
+
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+async fn read_resumes<R, W>(reader: &mut R, writer: &mut W)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut state = ReadExactState::new(8);
+
+    writer.write_all(b"0123").await.unwrap();
+    timeout(TIMEOUT, state.progress(reader)).await.unwrap_err();
+    assert_eq!(
+        state.filled(),
+        b"0123",
+        "bytes read before cancelling were lost"
+    );
+    assert_eq!(state.remaining(), 4, "wrong remaining count");
+
+    writer.write_all(b"4567").await.unwrap();
+    state.progress(reader).await.unwrap();
+    assert!(state.is_complete(), "read did not finish");
+    assert_eq!(
+        state.into_inner(),
+        b"01234567",
+        "bytes were lost or repeated"
+    );
+}
+
+async fn write_resumes<R, W>(reader: &mut R, writer: &mut W)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    // Far more than the socket buffers hold, so the first attempt stalls.
+    let data: Vec<u8> = (0..32 << 20).map(|i| (i % 251) as u8).collect();
+    let mut state = WriteAllState::new(data.clone());
+
+    timeout(TIMEOUT, state.progress(writer)).await.unwrap_err();
+    assert!(state.written() > 0, "nothing was written before cancelling");
+    assert!(!state.is_complete(), "write finished without a reader");
+
+    let mut received = vec![0; data.len()];
+    let (written, read) = tokio::join!(state.progress(writer), reader.read_exact(&mut received));
+    written.unwrap();
+    read.unwrap();
+    assert!(state.is_complete(), "write did not finish");
+    assert!(received == data, "bytes were lost or repeated");
+}
+
+async fn check(mut a: Stream, mut b: Stream) {
+    read_resumes(&mut a, &mut b).await;
+    write_resumes(&mut b, &mut a).await;
+
+    {
+        let (mut a_read, mut a_write) = a.split();
+        let (mut b_read, mut b_write) = b.split();
+        read_resumes(&mut a_read, &mut b_write).await;
+        write_resumes(&mut b_read, &mut a_write).await;
+    }
+
+    let (mut a_read, mut a_write) = a.into_split();
+    let (mut b_read, mut b_write) = b.into_split();
+    read_resumes(&mut a_read, &mut b_write).await;
+    write_resumes(&mut b_read, &mut a_write).await;
+}
+
+#[tokio::test]
+async fn cancelled_progress_resumes_on_unix() {
+    let (a, b) = Stream::pair().unwrap();
+    check(a, b).await;
+}
+
+#[tokio::test]
+async fn cancelled_progress_resumes_on_tcp() {
+    let (a, b) = Stream::pair_tcp().await.unwrap();
+    check(a, b).await;
+}
@@ -0,0 +1,145 @@
+#![cfg(feature = "serde")]
+
+use std::os::unix::ffi::OsStrExt;
+
+use serde::{Deserialize, Serialize};
+use tokio_anysocket::SocketAddr;
+
+/// TOML documents have to be tables.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    addr: SocketAddr,
+}
+
+fn addrs() -> Vec<SocketAddr> {
+    let mut addrs = vec![
+        SocketAddr::tcp([127, 0, 0, 1], 8080),
+        SocketAddr::tcp(std::net::Ipv6Addr::LOCALHOST, 443),
+        SocketAddr::unix("/tmp/anysocket serde.sock").unwrap(),
+        SocketAddr::unix(std::ffi::OsStr::from_bytes(b"/tmp/anysocket-\xff.sock")).unwrap(),
+    ];
+
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        addrs.push(SocketAddr::unix_abstract("anysocket serde").unwrap());
+        addrs.push(SocketAddr::unix_abstract(b"anysocket-\xff\x00\xfe").unwrap());
+    }
+
+    addrs
+}
+
+fn bincode_config() -> bincode::config::Configuration {
+    bincode::config::standard()
+}
+
+#[test]
+fn json_roundtrip() {
+    for addr in addrs() {
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(json, serde_json::to_string(&addr.to_string()).unwrap());
+
+        let x: SocketAddr = serde_json::from_str(&json).unwrap();
+        assert_eq!(x, addr, "{json}");
+    }
+}
+
+#[test]
+fn toml_roundtrip() {
+    for addr in addrs() {
+        let config = Config { addr };
+        let toml = toml::to_string(&config).unwrap();
+
+        let x: Config = toml::from_str(&toml).unwrap();
+        assert_eq!(x, config, "{toml}");
+    }
+}
+
+#[test]
+fn bincode_roundtrip() {
+    for addr in addrs() {
+        let bytes = bincode::serde::encode_to_vec(&addr, bincode_config()).unwrap();
+
+        let (x, len): (SocketAddr, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode_config()).unwrap();
+        assert_eq!(x, addr);
+        assert_eq!(len, bytes.len());
+    }
+
+    // The binary form holds the address itself, not its string.
+    let addr = SocketAddr::tcp([127, 0, 0, 1], 8080);
+    let bytes = bincode::serde::encode_to_vec(&addr, bincode_config()).unwrap();
+    assert!(bytes.len() < addr.to_string().len(), "{bytes:?}");
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn bincode_keeps_non_utf8_abstract_names() {
+    let name = b"\xff\xfe\x00anysocket";
+    let addr = SocketAddr::unix_abstract(name).unwrap();
+
+    let bytes = bincode::serde::encode_to_vec(&addr, bincode_config()).unwrap();
+    assert!(bytes.windows(name.len()).any(|x| x == name));
+
+    let (x, _): (SocketAddr, _) =
+        bincode::serde::decode_from_slice(&bytes, bincode_config()).unwrap();
+    assert_eq!(x, addr);
+}
+
+#[test]
+fn old_strings_load() {
+    let x: SocketAddr = serde_json::from_str(r#""tcp://127.0.0.1:8080""#).unwrap();
+    assert_eq!(x, SocketAddr::tcp([127, 0, 0, 1], 8080));
+
+    let x: SocketAddr = serde_json::from_str(r#""unix:///tmp/anysocket%20serde.sock""#).unwrap();
+    assert_eq!(x, SocketAddr::unix("/tmp/anysocket serde.sock").unwrap());
+
+    let x: Config = toml::from_str(r#"addr = "tcp://[::1]:443""#).unwrap();
+    assert_eq!(x.addr, SocketAddr::tcp(std::net::Ipv6Addr::LOCALHOST, 443));
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let x: Config = toml::from_str(r#"addr = "unix://@anysocket""#).unwrap();
+        assert_eq!(x.addr, SocketAddr::unix_abstract("anysocket").unwrap());
+    }
+}
+
+#[test]
+fn old_strings_load_from_bincode() {
+    // Older versions serialized every address as its string.
+    let long = format!("/tmp/{}", " ".repeat(90));
+    let mut addrs = vec![
+        SocketAddr::tcp([127, 0, 0, 1], 8080),
+        SocketAddr::tcp(std::net::Ipv6Addr::LOCALHOST, 443),
+        SocketAddr::unix("/tmp/anysocket serde.sock").unwrap(),
+        // Longer than 250 bytes once escaped, so the length takes more than
+        // one byte.
+        SocketAddr::unix(&long).unwrap(),
+    ];
+
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        addrs.push(SocketAddr::unix_abstract(b"anysocket-\xff\x00").unwrap());
+    }
+
+    for addr in addrs {
+        let old = addr.to_string();
+        let bytes = bincode::serde::encode_to_vec(&old, bincode_config()).unwrap();
+
+        let (x, len): (SocketAddr, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode_config()).unwrap();
+        assert_eq!(x, addr, "{old}");
+        assert_eq!(len, bytes.len());
+    }
+
+    let bytes = bincode::serde::encode_to_vec("tcp://nowhere", bincode_config()).unwrap();
+    let x = bincode::serde::decode_from_slice::<SocketAddr, _>(&bytes, bincode_config());
+    assert!(x.is_err());
+}
+
+#[tokio::test]
+async fn unnamed_fails() {
+    let (a, _b) = tokio_anysocket::Stream::pair().unwrap();
+    let addr = a.local_addr().unwrap();
+    assert!(addr.is_unnamed());
+
+    assert!(serde_json::to_string(&addr).is_err());
+    assert!(bincode::serde::encode_to_vec(&addr, bincode_config()).is_err());
+}
@@ -0,0 +1,592 @@
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::{Arc, Mutex};
+
+use tokio_anysocket::{
+    AddrErrors, AddrList, AddrParseError, ConnectOptions, FromIter, Listener, Resolve, Resolving,
+    SocketAddr, Stream, Tcp, ToSocketAddrs,
+};
+
+/// Resolves `good` to `addr`, fails every other hostname, and records what it
+/// was asked.
+#[derive(Debug)]
+struct TestResolver {
+    addr: std::net::SocketAddr,
+    asked: Mutex<Vec<String>>,
+}
+
+impl Resolve for TestResolver {
+    fn resolve<'a>(&'a self, host: &'a str, _: u16) -> Resolving<'a> {
+        self.asked.lock().unwrap().push(host.to_owned());
+
+        Box::pin(async move {
+            match host {
+                "good" => Ok(vec![self.addr]),
+                _ => Err(ErrorKind::NotFound.into()),
+            }
+        })
+    }
+}
+
+async fn tcp_listener() -> (Listener, std::net::SocketAddr) {
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let SocketAddr::Tcp(addr) = listener.local_addr().unwrap() else {
+        unreachable!()
+    };
+
+    (listener, addr)
+}
+
+#[tokio::test]
+async fn from_iter_resolves_lazily() {
+    let (_listener, addr) = tcp_listener().await;
+    let resolver = Arc::new(TestResolver {
+        addr,
+        asked: Mutex::default(),
+    });
+    let options = ConnectOptions::default().resolver(resolver.clone());
+
+    let port = addr.port();
+    let addrs = FromIter(vec![
+        format!("tcp://bad:{port}"),
+        format!("tcp://good:{port}"),
+        format!("tcp://later:{port}"),
+    ]);
+
+    Stream::connect_with(addrs, &options).await.unwrap();
+    assert_eq!(*resolver.asked.lock().unwrap(), ["bad", "good"]);
+}
+
+#[tokio::test]
+async fn from_iter_reports_unresolved() {
+    let (listener, addr) = tcp_listener().await;
+    drop(listener);
+
+    let resolver = Arc::new(TestResolver {
+        addr,
+        asked: Mutex::default(),
+    });
+    let options = ConnectOptions::default().resolver(resolver);
+
+    let port = addr.port();
+    let addrs = FromIter(vec![
+        format!("tcp://bad:{port}"),
+        format!("tcp://good:{port}"),
+    ]);
+
+    let e = Stream::connect_with(addrs, &options).await.unwrap_err();
+    let errors = e.get_ref().unwrap().downcast_ref::<AddrErrors>().unwrap();
+    assert_eq!(errors.errors().len(), 1);
+    assert_eq!(errors.unresolved().len(), 1);
+    assert_eq!(errors.unresolved()[0].kind(), ErrorKind::NotFound);
+}
+
+#[tokio::test]
+async fn addr_list_falls_back() {
+    let (listener, addr) = tcp_listener().await;
+
+    let list: AddrList = format!("unix:///nonexistent/anysocket.sock,tcp://{addr}")
+        .parse()
+        .unwrap();
+
+    let stream = Stream::connect(&list).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), SocketAddr::Tcp(addr));
+    assert_eq!(server.peer_addr().unwrap(), stream.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn addr_list_reports_unresolved() {
+    let (listener, addr) = tcp_listener().await;
+    drop(listener);
+
+    let resolver = Arc::new(TestResolver {
+        addr,
+        asked: Mutex::default(),
+    });
+    let options = ConnectOptions::default().resolver(resolver.clone());
+
+    let port = addr.port();
+    let list: AddrList = format!("tcp://bad:{port},tcp://good:{port}")
+        .parse()
+        .unwrap();
+
+    let e = Stream::connect_with(&list, &options).await.unwrap_err();
+    let errors = e.get_ref().unwrap().downcast_ref::<AddrErrors>().unwrap();
+    assert_eq!(errors.errors().len(), 1);
+    assert_eq!(errors.unresolved().len(), 1);
+    assert_eq!(errors.unresolved()[0].kind(), ErrorKind::NotFound);
+
+    // Resolving without connecting reports every entry if none resolves.
+    let list: AddrList = format!("tcp://bad:{port},tcp://worse:{port}")
+        .parse()
+        .unwrap();
+
+    let e = list.to_socket_addrs_with(&*resolver).await.unwrap_err();
+    let errors = e.get_ref().unwrap().downcast_ref::<AddrErrors>().unwrap();
+    assert_eq!(errors.unresolved().len(), 2);
+    assert_eq!(
+        *resolver.asked.lock().unwrap(),
+        ["bad", "good", "bad", "worse"]
+    );
+}
+
+#[tokio::test]
+async fn connect_error_keeps_the_address() {
+    let (listener, addr) = tcp_listener().await;
+    drop(listener);
+
+    let e = Stream::connect(addr).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::ConnectionRefused);
+    assert!(e.to_string().starts_with(&format!("tcp://{addr}: ")), "{e}");
+
+    let errors = e.get_ref().unwrap().downcast_ref::<AddrErrors>().unwrap();
+    assert_eq!(errors.errors().len(), 1);
+    assert_eq!(errors.errors()[0].0, SocketAddr::Tcp(addr));
+    assert_eq!(errors.errors()[0].1.kind(), ErrorKind::ConnectionRefused);
+    assert!(errors.unresolved().is_empty());
+}
+
+#[tokio::test]
+async fn bind_error_keeps_the_address() {
+    let (_listener, addr) = tcp_listener().await;
+
+    let e = Listener::bind(addr).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::AddrInUse);
+
+    let errors = e.get_ref().unwrap().downcast_ref::<AddrErrors>().unwrap();
+    assert_eq!(errors.errors().len(), 1);
+    assert_eq!(errors.errors()[0].0, SocketAddr::Tcp(addr));
+    assert_eq!(errors.errors()[0].1.kind(), ErrorKind::AddrInUse);
+}
+
+fn display_roundtrip(addr: &SocketAddr, expected: &str) {
+    let s = addr.to_string();
+    assert_eq!(s, expected, "{addr:?}");
+
+    let x: SocketAddr = s.parse().unwrap();
+    assert_eq!(x, *addr, "{s}");
+}
+
+fn parse_error(s: &str) -> Option<AddrParseError> {
+    let e = s.parse::<SocketAddr>().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput, "{s}");
+    e.into_inner()?.downcast().ok().map(|x| *x)
+}
+
+#[test]
+fn display_roundtrips_unix_paths() {
+    display_roundtrip(
+        &SocketAddr::unix("/tmp/any\nsocket\r.sock").unwrap(),
+        "unix:///tmp/any%0Asocket%0D.sock",
+    );
+    display_roundtrip(
+        &SocketAddr::unix("/tmp/100%\tsure.sock").unwrap(),
+        "unix:///tmp/100%25%09sure.sock",
+    );
+    display_roundtrip(
+        &SocketAddr::unix(OsStr::from_bytes(b"/tmp/\xff\xfe.sock")).unwrap(),
+        "unix:///tmp/%FF%FE.sock",
+    );
+    display_roundtrip(
+        &SocketAddr::unix("/tmp/\u{e9}t\u{e9}.sock").unwrap(),
+        "unix:///tmp/\u{e9}t\u{e9}.sock",
+    );
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn display_roundtrips_abstract_names() {
+    display_roundtrip(
+        &SocketAddr::unix_abstract(b"\0any\0socket\0").unwrap(),
+        "unix://@%00any%00socket%00",
+    );
+    display_roundtrip(
+        &SocketAddr::unix_abstract("any/socket/").unwrap(),
+        "unix://@any/socket/",
+    );
+    display_roundtrip(
+        &SocketAddr::unix_abstract(b"\xffany\x80socket").unwrap(),
+        "unix://@%FFany%80socket",
+    );
+
+    // A path starting with `@` is not an abstract name.
+    display_roundtrip(
+        &SocketAddr::unix("@anysocket").unwrap(),
+        "unix://%40anysocket",
+    );
+}
+
+#[test]
+fn parse_rejects_empty_and_raw_nul() {
+    for s in ["unix://", "unix:"] {
+        assert!(
+            matches!(parse_error(s), Some(AddrParseError::EmptyPath)),
+            "{s}"
+        );
+    }
+
+    assert!(matches!(
+        parse_error("unix:///tmp/any\0socket"),
+        Some(AddrParseError::NulByte)
+    ));
+    assert!(matches!(
+        parse_error("unix:///tmp/%zz"),
+        Some(AddrParseError::InvalidEscape)
+    ));
+}
+
+fn collect<A: ToSocketAddrs + ?Sized>(addr: &A) -> Vec<SocketAddr> {
+    addr.to_socket_addrs().unwrap().collect()
+}
+
+#[test]
+fn collections_keep_order() {
+    let a = SocketAddr::tcp([127, 0, 0, 1], 1);
+    let b = SocketAddr::tcp([127, 0, 0, 2], 2);
+    let c = SocketAddr::unix("/tmp/anysocket.sock").unwrap();
+
+    assert_eq!(
+        collect(&vec![a.clone(), b.clone(), c.clone()]),
+        [a.clone(), b.clone(), c.clone()]
+    );
+    assert_eq!(collect(&Vec::<SocketAddr>::new()), []);
+    assert_eq!(
+        collect(&["tcp://127.0.0.2:2", "tcp://127.0.0.1:1"]),
+        [b.clone(), a.clone()]
+    );
+    assert_eq!(collect::<[SocketAddr; 0]>(&[]), []);
+    assert_eq!(
+        collect(&[vec![c.clone()], vec![a.clone(), b.clone()]]),
+        [c, a, b]
+    );
+
+    // One bad element fails the whole collection.
+    assert!(
+        ["tcp://127.0.0.1:1", "tcp://bad"]
+            .to_socket_addrs()
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn collections_of_non_sync_elements() {
+    use std::cell::Cell;
+
+    /// Send, but not Sync.
+    struct Port(Cell<u16>);
+
+    impl ToSocketAddrs for Port {
+        type Iter = std::iter::Once<SocketAddr>;
+
+        fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
+            Ok(std::iter::once(SocketAddr::tcp(
+                [127, 0, 0, 1],
+                self.0.get(),
+            )))
+        }
+    }
+
+    fn send<T: Send>(x: T) -> T {
+        x
+    }
+
+    let port = |x| SocketAddr::tcp([127, 0, 0, 1], x);
+    let ports = vec![Port(Cell::new(1)), Port(Cell::new(2))];
+
+    let x: Vec<_> = send(ports.to_socket_addrs_async()).await.unwrap().collect();
+    assert_eq!(x, [port(1), port(2)]);
+
+    ports[1].0.set(3);
+    let x: Vec<_> = send(ports.as_slice().to_socket_addrs_async())
+        .await
+        .unwrap()
+        .collect();
+    assert_eq!(x, [port(1), port(3)]);
+
+    let ports = [Port(Cell::new(4))];
+    let x: Vec<_> = send(ports.to_socket_addrs_async()).await.unwrap().collect();
+    assert_eq!(x, [port(4)]);
+}
+
+#[tokio::test]
+async fn implementations_with_only_to_socket_addrs() {
+    use tokio_anysocket::{Listener, Stream};
+
+    /// Written against the trait before it had async methods, with an
+    /// iterator that is not Send.
+    struct Boxed(SocketAddr);
+
+    impl ToSocketAddrs for Boxed {
+        type Iter = Box<dyn Iterator<Item = SocketAddr>>;
+
+        fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
+            Ok(Box::new(std::iter::once(self.0.clone())))
+        }
+    }
+
+    let listener = Listener::bind(Boxed(SocketAddr::tcp([127, 0, 0, 1], 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let x: Vec<_> = Boxed(addr.clone())
+        .to_socket_addrs_async()
+        .await
+        .unwrap()
+        .collect();
+    assert_eq!(x, std::slice::from_ref(&addr));
+
+    let _client = tokio::spawn(Stream::connect(Boxed(addr)))
+        .await
+        .unwrap()
+        .unwrap();
+    listener.accept().await.unwrap();
+}
+
+#[tokio::test]
+async fn tcp_resolves_on_the_blocking_pool() {
+    /// Records the thread it is resolved on.
+    #[derive(Clone)]
+    struct Lookup(Arc<Mutex<Option<std::thread::ThreadId>>>);
+
+    impl std::net::ToSocketAddrs for Lookup {
+        type Iter = std::option::IntoIter<std::net::SocketAddr>;
+
+        fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
+            *self.0.lock().unwrap() = Some(std::thread::current().id());
+            Ok(Some("127.0.0.1:80".parse().unwrap()).into_iter())
+        }
+    }
+
+    let thread = Arc::new(Mutex::new(None));
+    let addrs = Tcp(Lookup(thread.clone()));
+
+    let x: Vec<_> = addrs.to_socket_addrs_async().await.unwrap().collect();
+    assert_eq!(x, [SocketAddr::tcp([127, 0, 0, 1], 80)]);
+    let resolved_on = thread.lock().unwrap().take().unwrap();
+    assert_ne!(resolved_on, std::thread::current().id());
+
+    collect(&addrs);
+    let resolved_on = thread.lock().unwrap().take().unwrap();
+    assert_eq!(resolved_on, std::thread::current().id());
+
+    let x: Vec<_> = Tcp("localhost:80")
+        .to_socket_addrs_async()
+        .await
+        .unwrap()
+        .collect();
+    assert!(!x.is_empty());
+}
+
+#[test]
+fn path_buf_is_a_unix_path() {
+    let path = std::path::PathBuf::from("/tmp/anysocket.sock");
+    assert_eq!(collect(&path), [SocketAddr::unix(&path).unwrap()]);
+
+    // Not a TCP address, even if it looks like one.
+    let path = std::path::PathBuf::from("127.0.0.1:80");
+    assert_eq!(collect(&path), [SocketAddr::unix(&path).unwrap()]);
+
+    let path = std::path::PathBuf::new();
+    assert!(path.to_socket_addrs().is_err());
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    assert_eq!(
+        collect(&std::path::PathBuf::from("@/anysocket")),
+        [SocketAddr::unix_abstract("anysocket").unwrap()]
+    );
+}
+
+#[tokio::test]
+async fn string_tuple_resolves_hostnames_only() {
+    let addr = "127.0.0.1:80".parse().unwrap();
+    let resolver = TestResolver {
+        addr,
+        asked: Mutex::default(),
+    };
+
+    // IP literals are parsed without asking the resolver.
+    for (host, expected) in [
+        ("127.0.0.1", SocketAddr::tcp([127, 0, 0, 1], 80)),
+        ("::1", SocketAddr::tcp(std::net::Ipv6Addr::LOCALHOST, 80)),
+    ] {
+        let tuple = (host.to_owned(), 80);
+        assert_eq!(collect(&tuple), std::slice::from_ref(&expected));
+
+        let x: Vec<_> = tuple
+            .to_socket_addrs_with(&resolver)
+            .await
+            .unwrap()
+            .collect();
+        assert_eq!(x, std::slice::from_ref(&expected));
+    }
+    assert!(resolver.asked.lock().unwrap().is_empty());
+
+    let x: Vec<_> = ("good".to_owned(), 80)
+        .to_socket_addrs_with(&resolver)
+        .await
+        .unwrap()
+        .collect();
+    assert_eq!(x, [SocketAddr::Tcp(addr)]);
+
+    let e = ("bad".to_owned(), 80)
+        .to_socket_addrs_with(&resolver)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound);
+    assert!(e.to_string().contains("bad:80"), "{e}");
+    assert_eq!(*resolver.asked.lock().unwrap(), ["good", "bad"]);
+}
+
+#[tokio::test]
+async fn hostnames_only_resolve_async() {
+    let addr = "127.0.0.1:80".parse().unwrap();
+    let resolver = TestResolver {
+        addr,
+        asked: Mutex::default(),
+    };
+
+    let names = [
+        "tcp://good:80",
+        "tcp4://good:80",
+        "tcp+srv://_http._tcp.good",
+    ];
+    for name in names {
+        let e = name.to_socket_addrs().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput, "{name}");
+        assert!(e.to_string().contains(name), "{e}");
+    }
+
+    let e = ("good", 80).to_socket_addrs().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    assert!(e.to_string().contains("good:80"), "{e}");
+    assert!(resolver.asked.lock().unwrap().is_empty());
+
+    let x: Vec<_> = "tcp://good:80"
+        .to_socket_addrs_with(&resolver)
+        .await
+        .unwrap()
+        .collect();
+    assert_eq!(x, [SocketAddr::Tcp(addr)]);
+
+    let x: Vec<_> = "tcp://localhost:80"
+        .to_socket_addrs_async()
+        .await
+        .unwrap()
+        .collect();
+    assert!(!x.is_empty());
+    assert!(x.iter().all(|x| x.port() == Some(80)), "{x:?}");
+}
+
+#[tokio::test]
+async fn cow_str_parses_like_str() {
+    use std::borrow::Cow;
+
+    let borrowed: Cow<'_, str> = Cow::Borrowed("tcp://127.0.0.1:80");
+    let owned: Cow<'_, str> = Cow::Owned("unix:///tmp/anysocket.sock".to_owned());
+
+    assert_eq!(collect(&borrowed), [SocketAddr::tcp([127, 0, 0, 1], 80)]);
+    assert_eq!(
+        collect(&owned),
+        [SocketAddr::unix("/tmp/anysocket.sock").unwrap()]
+    );
+
+    let x: Vec<_> = borrowed.to_socket_addrs_async().await.unwrap().collect();
+    assert_eq!(x, [SocketAddr::tcp([127, 0, 0, 1], 80)]);
+
+    let bad: Cow<'_, str> = Cow::Borrowed("127.0.0.1:80");
+    assert!(bad.to_socket_addrs().is_err());
+}
+
+#[test]
+fn std_unix_addr_is_kept() {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("anysocket-std-addr-{}.sock", std::process::id()));
+    let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+    let addr = listener.local_addr().unwrap();
+    assert_eq!(collect(&addr), [SocketAddr::unix(&path).unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+
+    // Unnamed addresses stay unnamed.
+    let addr = std::os::unix::net::UnixDatagram::unbound()
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let x = collect(&addr);
+    assert_eq!(x.len(), 1);
+    assert!(x[0].is_unnamed());
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn ipv6_zones_name_interfaces() {
+    let index: u32 = std::fs::read_to_string("/sys/class/net/lo/ifindex")
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+
+    let addr: SocketAddr = "tcp://[fe80::1%lo]:9000".parse().unwrap();
+    let SocketAddr::Tcp(std::net::SocketAddr::V6(x)) = &addr else {
+        panic!("{addr:?} is not an IPv6 address");
+    };
+    assert_eq!(x.scope_id(), index);
+    assert_eq!(x.port(), 9000);
+
+    // The index is written, and parses back to the same address.
+    let s = addr.to_string();
+    assert_eq!(s, format!("tcp://[fe80::1%{index}]:9000"));
+    assert_eq!(s.parse::<SocketAddr>().unwrap(), addr);
+
+    assert!(matches!(
+        parse_error("tcp://[fe80::1%anysocket0]:9000"),
+        Some(AddrParseError::UnknownInterface(x)) if x == "anysocket0"
+    ));
+}
+
+#[test]
+fn parse_lenient_ambiguities() {
+    let lenient = |s: &str| SocketAddr::parse_lenient(s).unwrap();
+    let unix = |s: &str| SocketAddr::unix(s).unwrap();
+
+    for s in ["/run/app.sock", "./app.sock", "../app.sock", "./@app"] {
+        assert_eq!(lenient(s), unix(s), "{s}");
+    }
+
+    // Taken as they are, not percent-decoded.
+    assert_eq!(lenient("/tmp/a%20b"), unix("/tmp/a%20b"));
+
+    assert_eq!(
+        lenient("127.0.0.1:8080"),
+        SocketAddr::tcp([127, 0, 0, 1], 8080)
+    );
+    assert_eq!(lenient("[::1]:80"), "tcp://[::1]:80".parse().unwrap());
+
+    // Addresses with a scheme parse as usual.
+    for s in [
+        "tcp://127.0.0.1:1",
+        "unix:///tmp/a%20b",
+        "unix:relative.sock",
+    ] {
+        assert_eq!(lenient(s), s.parse().unwrap(), "{s}");
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    assert_eq!(lenient("@app"), SocketAddr::unix_abstract("app").unwrap());
+
+    // A bare name could be a relative path or a misspelled hostname, and
+    // hostnames are not resolved.
+    for s in ["app.sock", "localhost:80", "C:\\app.sock", "127.0.0.1", ""] {
+        let e = SocketAddr::parse_lenient(s).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput, "{s}");
+        let e = e
+            .into_inner()
+            .unwrap()
+            .downcast::<AddrParseError>()
+            .unwrap();
+        assert!(matches!(*e, AddrParseError::MissingScheme), "{s}: {e}");
+    }
+}
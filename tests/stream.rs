@@ -0,0 +1,1031 @@
+#[cfg(feature = "bytes")]
+#[tokio::test]
+async fn read_buf_grows_bytes_mut() {
+    use tokio::io::AsyncWriteExt;
+    use tokio_anysocket::Stream;
+    use tokio_anysocket::bytes::BytesMut;
+
+    const LEN: usize = 256 * 1024;
+
+    let (a, b) = Stream::pair().unwrap();
+    let data: Vec<u8> = (0..LEN).map(|i| (i % 251) as u8).collect();
+
+    let writer = tokio::spawn({
+        let data = data.clone();
+        let mut a = a;
+        async move { a.write_all(&data).await.unwrap() }
+    });
+
+    let (read, _write) = b.into_split();
+    let mut buf = BytesMut::with_capacity(16);
+    let mut wait = true;
+    while buf.len() < LEN {
+        wait = !wait;
+        if wait {
+            read.read_buf(&mut buf).await.unwrap();
+        } else {
+            match read.try_read_buf(&mut buf) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    writer.await.unwrap();
+    assert!(buf.capacity() > 16);
+    assert_eq!(&buf[..], &data[..]);
+}
+
+#[cfg(feature = "futures-io")]
+#[tokio::test]
+async fn futures_io_roundtrip() {
+    use std::future::poll_fn;
+    use std::io::IoSlice;
+    use std::pin::Pin;
+
+    use tokio_anysocket::Stream;
+    use tokio_anysocket::futures_io::{AsyncRead, AsyncWrite};
+
+    async fn write_all<W: AsyncWrite + Unpin>(w: &mut W, bufs: &[&[u8]]) {
+        let mut bufs: Vec<&[u8]> = bufs.to_vec();
+        while !bufs.is_empty() {
+            let slices: Vec<IoSlice<'_>> = bufs.iter().map(|x| IoSlice::new(x)).collect();
+            let mut n = poll_fn(|cx| Pin::new(&mut *w).poll_write_vectored(cx, &slices))
+                .await
+                .unwrap();
+
+            while n > 0 {
+                if n >= bufs[0].len() {
+                    n -= bufs.remove(0).len();
+                } else {
+                    bufs[0] = &bufs[0][n..];
+                    n = 0;
+                }
+            }
+        }
+
+        poll_fn(|cx| Pin::new(&mut *w).poll_flush(cx))
+            .await
+            .unwrap();
+        poll_fn(|cx| Pin::new(&mut *w).poll_close(cx))
+            .await
+            .unwrap();
+    }
+
+    async fn read_to_end<R: AsyncRead + Unpin>(r: &mut R) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            match poll_fn(|cx| Pin::new(&mut *r).poll_read(cx, &mut buf)).await {
+                Ok(0) => return data,
+                Ok(n) => data.extend_from_slice(&buf[..n]),
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    let (a, b) = Stream::pair().unwrap();
+
+    let (mut read, mut write) = a.into_split();
+    let (mut b_read, mut b_write) = b.into_split();
+
+    write_all(&mut write, &[b"hello ", b"from ", b"a"]).await;
+    assert_eq!(read_to_end(&mut b_read).await, b"hello from a");
+
+    write_all(&mut b_write, &[b"and b"]).await;
+    assert_eq!(read_to_end(&mut read).await, b"and b");
+
+    let (mut a, mut b) = Stream::pair().unwrap();
+    write_all(&mut a, &[b"whole ", b"stream"]).await;
+
+    let (mut b_read, _b_write) = b.split();
+    assert_eq!(read_to_end(&mut b_read).await, b"whole stream");
+}
+
+#[cfg(feature = "hyper")]
+#[tokio::test]
+async fn hyper_serves_tcp_and_unix() {
+    use std::convert::Infallible;
+
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::{Bytes, Incoming};
+    use hyper::service::service_fn;
+    use hyper::{Request, Response};
+    use tokio_anysocket::{Listener, OwnedReadHalf, OwnedWriteHalf, Stream};
+
+    fn assert_halves<R: hyper::rt::Read, W: hyper::rt::Write>() {}
+    assert_halves::<OwnedReadHalf, OwnedWriteHalf>();
+
+    let (unix, _guard) = Listener::bind_unix_temp("hyper-").unwrap();
+    let tcp = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+
+    for listener in [tcp, unix] {
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let service = service_fn(move |req: Request<Incoming>| {
+                let body = format!("{} {} from {peer:?}", req.method(), req.uri());
+                async move { Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(body)))) }
+            });
+
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(stream, service)
+                .await
+                .unwrap();
+        });
+
+        let stream = Stream::connect(addr).await.unwrap();
+        let local = stream.local_addr().unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(stream).await.unwrap();
+        let conn = tokio::spawn(conn);
+
+        let req = Request::get("/hello")
+            .header("host", "localhost")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), 200);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, format!("GET /hello from {local:?}"));
+
+        drop(sender);
+        conn.await.unwrap().unwrap();
+        server.await.unwrap();
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn fastopen_connect() {
+    use std::io::ErrorKind;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{ConnectOptions, Listener, Stream};
+
+    let options = ConnectOptions::default().fastopen(true);
+
+    let e = Stream::connect_with("unix:///nonexistent.sock", &options)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Kernels without Fast Open support reject the option.
+    let mut client = match Stream::connect_with(&addr, &options).await {
+        Ok(x) => x,
+        Err(e) if e.raw_os_error().is_some() && e.kind() != ErrorKind::ConnectionRefused => {
+            return;
+        }
+        Err(e) => panic!("{e}"),
+    };
+
+    client.write_all(b"ping").await.unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+
+    let mut buf = [0; 4];
+    server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn poll_read_ready_unix() {
+    use std::future::poll_fn;
+    use std::task::Poll;
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+    use tokio_anysocket::Stream;
+
+    let (mut a, b) = Stream::pair().unwrap();
+    assert!(b.is_unix());
+
+    // The idle connection is writable, but must not be reported readable.
+    for _ in 0..3 {
+        poll_fn(|cx| {
+            assert!(b.poll_read_ready(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let (read, _write) = b.into_split();
+    poll_fn(|cx| {
+        assert!(read.poll_read_ready(cx).is_pending());
+        Poll::Ready(())
+    })
+    .await;
+
+    a.write_all(b"x").await.unwrap();
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        poll_fn(|cx| read.poll_read_ready(cx)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+}
+
+#[tokio::test]
+async fn nodelay_and_ttl() {
+    use std::io::ErrorKind;
+    use tokio_anysocket::{Listener, Stream};
+
+    let (tcp, _peer) = Stream::pair_tcp().await.unwrap();
+    for nodelay in [true, false] {
+        tcp.set_nodelay(nodelay).unwrap();
+        assert_eq!(tcp.nodelay().unwrap(), nodelay);
+    }
+    tcp.set_ttl(42).unwrap();
+    assert_eq!(tcp.ttl().unwrap(), 42);
+
+    // Unix sockets ignore `TCP_NODELAY`, but have no time-to-live or hop
+    // limit to report.
+    let (unix, _peer) = Stream::pair().unwrap();
+    unix.set_nodelay(false).unwrap();
+    assert!(unix.nodelay().unwrap());
+    assert_eq!(unix.ttl().unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(unix.set_ttl(42).unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        unix.unicast_hops_v6().unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    listener.set_ttl(42).unwrap();
+    assert_eq!(listener.ttl().unwrap(), 42);
+
+    let path = std::env::temp_dir().join(format!("anysocket-ttl-{}.sock", std::process::id()));
+    let listener = Listener::bind(path.as_path()).await.unwrap();
+    assert_eq!(listener.ttl().unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        listener.set_ttl(42).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(
+        listener.unicast_hops_v6().unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(
+        listener.set_unicast_hops_v6(42).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn queue_depths() {
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+    use tokio_anysocket::Stream;
+
+    for (mut a, b) in [Stream::pair().unwrap(), Stream::pair_tcp().await.unwrap()] {
+        assert_eq!(b.bytes_readable().unwrap(), 0);
+
+        a.write_all(&[1; 1000]).await.unwrap();
+        b.readable().await.unwrap();
+        // TCP may not have delivered everything yet.
+        for _ in 0..100 {
+            if b.bytes_readable().unwrap() >= 1000 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(b.bytes_readable().unwrap() >= 1000);
+
+        // Reading takes bytes off the queue.
+        let mut buf = [0; 100];
+        assert_eq!(b.try_read(&mut buf).unwrap(), 100);
+        assert!(b.bytes_readable().unwrap() >= 900);
+    }
+}
+
+#[tokio::test]
+async fn liveness_probe() {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::Stream;
+
+    const BOUND: Duration = Duration::from_secs(2);
+
+    for (server, client) in [Stream::pair().unwrap(), Stream::pair_tcp().await.unwrap()] {
+        assert!(client.is_open().unwrap());
+        drop(server);
+
+        let start = std::time::Instant::now();
+        while client.is_open().unwrap() {
+            assert!(start.elapsed() < BOUND);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        tokio::time::timeout(BOUND, client.closed())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    // Data sent before closing keeps the connection open until it is read.
+    for (mut server, mut client) in [Stream::pair().unwrap(), Stream::pair_tcp().await.unwrap()] {
+        server.write_all(b"last words").await.unwrap();
+        drop(server);
+
+        tokio::time::timeout(BOUND, client.closed())
+            .await
+            .unwrap()
+            .unwrap();
+        for _ in 0..3 {
+            assert!(client.is_open().unwrap());
+        }
+
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"last words");
+        assert!(!client.is_open().unwrap());
+    }
+}
+
+#[tokio::test]
+async fn candidate_order() {
+    use tokio_anysocket::{
+        AddrErrors, CandidateOrder, ConnectOptions, ConnectStrategy, Listener, RoundRobin,
+        SocketAddr, Stream,
+    };
+
+    async fn refused_tcp() -> SocketAddr {
+        let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    /// The addresses in the order they were tried, all of which fail.
+    async fn tried(addrs: &[SocketAddr], options: &ConnectOptions) -> Vec<SocketAddr> {
+        let e = Stream::connect_with(addrs, options).await.unwrap_err();
+        let errors = e.get_ref().unwrap().downcast_ref::<AddrErrors>();
+        let errors = errors.expect("every address fails");
+        errors.errors().iter().map(|(x, _)| x.clone()).collect()
+    }
+
+    let dir = std::env::temp_dir().join(format!("anysocket-order-{}", std::process::id()));
+    let unix = |name: &str| SocketAddr::unix(dir.join(name)).unwrap();
+    let (t1, t2, t3) = (
+        refused_tcp().await,
+        refused_tcp().await,
+        refused_tcp().await,
+    );
+    let (u1, u2) = (unix("a.sock"), unix("b.sock"));
+    let addrs = [t1.clone(), u1.clone(), t2.clone(), u2.clone(), t3.clone()];
+
+    // Each group rotates on its own, after unix addresses were moved first.
+    let options = ConnectOptions::default()
+        .strategy(ConnectStrategy::PreferUnix)
+        .candidate_order(CandidateOrder::RoundRobin(RoundRobin::new()));
+    let expected = [
+        [&u1, &u2, &t1, &t2, &t3],
+        [&u2, &u1, &t2, &t3, &t1],
+        [&u1, &u2, &t3, &t1, &t2],
+    ];
+    for expected in expected {
+        let x = tried(&addrs, &options).await;
+        assert_eq!(x.iter().collect::<Vec<_>>(), expected);
+    }
+
+    // Shuffling never moves a unix address before a TCP one.
+    let options = ConnectOptions::default()
+        .strategy(ConnectStrategy::PreferTcp)
+        .candidate_order(CandidateOrder::Shuffle);
+    let mut firsts = std::collections::HashSet::new();
+    for _ in 0..20 {
+        let x = tried(&addrs, &options).await;
+        assert!(x[..3].iter().all(SocketAddr::is_tcp), "{x:?}");
+        assert!(x[3..].iter().all(SocketAddr::is_unix), "{x:?}");
+
+        let mut sorted = x.clone();
+        sorted.sort();
+        let mut all = addrs.to_vec();
+        all.sort();
+        assert_eq!(sorted, all);
+
+        firsts.insert(x[0].clone());
+    }
+    assert!(firsts.len() > 1, "{firsts:?}");
+
+    // Without a ranking strategy, all addresses are one group.
+    let options =
+        ConnectOptions::default().candidate_order(CandidateOrder::RoundRobin(RoundRobin::new()));
+    assert_eq!(tried(&addrs, &options).await, addrs);
+    let x = tried(&addrs, &options).await;
+    assert_eq!(x, [u1, t2, u2, t3, t1]);
+}
+
+#[cfg(feature = "hyper-util")]
+#[tokio::test]
+async fn connector_serves_hyper_util_client() {
+    use std::convert::Infallible;
+    use std::io::ErrorKind;
+
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::{Bytes, Incoming};
+    use hyper::service::service_fn;
+    use hyper::{Request, Response, Uri};
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+    use tokio_anysocket::{Connector, Listener};
+
+    let (unix, guard) = Listener::bind_unix_temp("connector-").unwrap();
+    let tcp = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+
+    let tokio_anysocket::SocketAddr::Tcp(tcp_addr) = tcp.local_addr().unwrap() else {
+        panic!("not a TCP listener");
+    };
+    let uris = [
+        Connector::unix_uri(guard.path(), "/over/unix?x=1").unwrap(),
+        format!("http://localhost:{}/over/tcp", tcp_addr.port())
+            .parse::<Uri>()
+            .unwrap(),
+    ];
+
+    for listener in [unix, tcp] {
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = service_fn(|req: Request<Incoming>| async move {
+                    let body = Bytes::from(req.uri().to_string());
+                    Ok::<_, Infallible>(Response::new(Full::new(body)))
+                });
+
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new().serve_connection(stream, service),
+                );
+            }
+        });
+    }
+
+    let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(Connector::new());
+
+    for (uri, expected) in uris.into_iter().zip(["/over/unix?x=1", "/over/tcp"]) {
+        let res = client.get(uri).await.unwrap();
+        assert_eq!(res.status(), 200);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, expected);
+    }
+
+    for uri in ["https://localhost/", "unix://2f6/", "unix://zz/"] {
+        let e = Connector::new()
+            .connect(&uri.parse().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput, "{uri}: {e}");
+    }
+}
+
+/// A TCP address that never answers: a listener whose accept queue is full,
+/// so further SYNs are dropped.
+#[cfg(target_os = "linux")]
+async fn blackhole() -> (tokio::net::TcpListener, Vec<tokio::net::TcpStream>) {
+    use std::time::Duration;
+
+    use tokio::net::{TcpSocket, TcpStream};
+
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let listener = socket.listen(0).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut queued = Vec::new();
+    loop {
+        match tokio::time::timeout(Duration::from_millis(200), TcpStream::connect(addr)).await {
+            Ok(x) => queued.push(x.unwrap()),
+            Err(_) => return (listener, queued),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn connect_happy_falls_back() {
+    use std::time::{Duration, Instant};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{HappyEyeballsConfig, Listener, SocketAddr, Stream};
+
+    let (hole, _queued) = blackhole().await;
+    let hole = SocketAddr::from(hole.local_addr().unwrap());
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let good = listener.local_addr().unwrap();
+
+    let config = HappyEyeballsConfig::default().attempt_delay(Duration::from_millis(100));
+    let start = Instant::now();
+    let (mut stream, addr) = Stream::connect_happy_full([hole.clone(), good.clone()], &config)
+        .await
+        .unwrap();
+    assert_eq!(addr, good);
+
+    // The second attempt started after the delay, long before the first one
+    // would have given up.
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(100), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    assert_eq!(stream.peer_addr().unwrap(), good);
+
+    let (mut server, _) = listener.accept().await.unwrap();
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+
+    // A refused attempt fails right away, with its own error.
+    let refused = {
+        let x = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        x.local_addr().unwrap()
+    };
+    let e = Stream::connect_happy([refused.clone()], &config)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::ConnectionRefused, "{e}");
+}
+
+#[tokio::test]
+async fn connect_full_returns_the_address() {
+    use tokio_anysocket::{ConnectOptions, Listener, SocketAddr, Stream};
+
+    let refused = {
+        let x = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        x.local_addr().unwrap()
+    };
+    let (unix, _guard) = Listener::bind_unix_temp("connect-full-").unwrap();
+    let unix = unix.local_addr().unwrap();
+    let tcp = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let tcp = tcp.local_addr().unwrap();
+
+    let addrs: [SocketAddr; 3] = [refused.clone(), unix.clone(), tcp.clone()];
+    let (stream, addr) = Stream::connect_full(&addrs[..]).await.unwrap();
+    assert_eq!(addr, unix);
+    // The peer of a unix connection is the listener's path too.
+    assert_eq!(stream.peer_addr().unwrap(), unix);
+
+    let (_, addr) = Stream::connect_full(&addrs[2..]).await.unwrap();
+    assert_eq!(addr, tcp);
+
+    let options = ConnectOptions::default();
+    let (_, addr) = Stream::connect_with_full([refused, tcp.clone()], &options)
+        .await
+        .unwrap();
+    assert_eq!(addr, tcp);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn copy_bidirectional_moves_everything() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{Stream, copy_bidirectional};
+
+    /// Send `data`, then read everything the other side sends.
+    async fn exchange(mut stream: Stream, data: Vec<u8>) -> Vec<u8> {
+        let (mut read, mut write) = stream.split();
+        let send = async {
+            write.write_all(&data).await.unwrap();
+            write.shutdown().await.unwrap();
+        };
+        let mut received = Vec::new();
+        let recv = read.read_to_end(&mut received);
+        let (_, n) = tokio::join!(send, recv);
+        assert_eq!(n.unwrap(), received.len());
+        received
+    }
+
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len)
+            .map(|x| u8::try_from(x % 251).unwrap() ^ seed)
+            .collect()
+    }
+
+    async fn pair(tcp: bool) -> (Stream, Stream) {
+        if tcp {
+            Stream::pair_tcp().await.unwrap()
+        } else {
+            Stream::pair().unwrap()
+        }
+    }
+
+    const A_TO_B: usize = 32 * 1024 * 1024;
+    const B_TO_A: usize = 24 * 1024 * 1024 + 7;
+
+    for (a_tcp, b_tcp) in [(true, true), (false, false), (true, false)] {
+        let (client_a, mut proxy_a) = pair(a_tcp).await;
+        let (mut proxy_b, client_b) = pair(b_tcp).await;
+
+        let proxy =
+            tokio::spawn(async move { copy_bidirectional(&mut proxy_a, &mut proxy_b).await });
+
+        let a_data = pattern(A_TO_B, 0x00);
+        let b_data = pattern(B_TO_A, 0x5a);
+        let (at_a, at_b) = tokio::join!(
+            exchange(client_a, a_data.clone()),
+            exchange(client_b, b_data.clone())
+        );
+
+        let counts = proxy.await.unwrap().unwrap();
+        assert_eq!(counts, (A_TO_B as u64, B_TO_A as u64), "{a_tcp} {b_tcp}");
+        assert!(at_b == a_data, "{a_tcp} {b_tcp}: a to b differs");
+        assert!(at_a == b_data, "{a_tcp} {b_tcp}: b to a differs");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_file_sends_the_file() {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    use std::io::{Seek, Write};
+
+    use tokio::io::AsyncReadExt;
+    use tokio_anysocket::Stream;
+
+    fn hash(data: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    const LEN: usize = 8 * 1024 * 1024 + 13;
+    const OFFSET: usize = 4099;
+
+    let path = std::env::temp_dir().join(format!("anysocket-sendfile-{}", std::process::id()));
+    let data: Vec<u8> = (0..LEN)
+        .map(|x| u8::try_from(x * 7 % 253).unwrap())
+        .collect();
+    let mut file = std::fs::File::options()
+        .create(true)
+        .truncate(true)
+        .read(true)
+        .write(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(&data).unwrap();
+    file.rewind().unwrap();
+
+    let file = std::sync::Arc::new(file);
+    let expected = hash(&data[OFFSET..]);
+
+    for (a, mut b) in [Stream::pair_tcp().await.unwrap(), Stream::pair().unwrap()] {
+        let sender = {
+            let file = file.clone();
+            tokio::spawn(async move {
+                // Asking for more than is left sends up to the end.
+                let n = a.send_file(&file, OFFSET as u64, LEN as u64).await.unwrap();
+                assert_eq!(n, (LEN - OFFSET) as u64);
+            })
+        };
+
+        let mut received = Vec::new();
+        b.read_to_end(&mut received).await.unwrap();
+        sender.await.unwrap();
+
+        assert_eq!(received.len(), LEN - OFFSET);
+        assert_eq!(hash(&received), expected);
+    }
+
+    // The position of the file was not touched.
+    assert_eq!((&*file).stream_position().unwrap(), 0);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[tokio::test]
+async fn cork_and_quickack() {
+    use std::io::{ErrorKind, Write};
+
+    use socket2::SockRef;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::Stream;
+
+    let (mut a, mut b) = Stream::pair_tcp().await.unwrap();
+
+    assert!(!a.cork().unwrap());
+    a.set_cork(true).unwrap();
+    assert!(a.cork().unwrap());
+    a.set_cork(false).unwrap();
+    assert!(!a.cork().unwrap());
+
+    for quickack in [true, false, true] {
+        a.set_quickack(quickack).unwrap();
+        assert_eq!(SockRef::from(&a).tcp_quickack().unwrap(), quickack);
+    }
+
+    // A header written while corked goes out with the file after it.
+    let path = std::env::temp_dir().join(format!("anysocket-cork-{}", std::process::id()));
+    let body: Vec<u8> = (0..100_000)
+        .map(|x| u8::try_from(x % 199).unwrap())
+        .collect();
+    std::fs::File::create(&path)
+        .unwrap()
+        .write_all(&body)
+        .unwrap();
+    let file = std::fs::File::open(&path).unwrap();
+
+    a.set_cork(true).unwrap();
+    a.write_all(b"HEADER\n").await.unwrap();
+    let n = a.send_file(&file, 0, body.len() as u64).await.unwrap();
+    assert_eq!(n, body.len() as u64);
+    a.set_cork(false).unwrap();
+
+    let mut buf = vec![0; 7 + body.len()];
+    b.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf[..7], b"HEADER\n");
+    assert!(buf[7..] == body, "the body differs");
+
+    let (unix, _peer) = Stream::pair().unwrap();
+    assert_eq!(unix.cork().unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        unix.set_cork(true).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(
+        unix.set_quickack(true).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn reunite_checks_the_halves() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::{ReuniteError, Stream};
+
+    let (a, b) = Stream::pair().unwrap();
+    let (a_read, a_write) = a.into_split();
+    let (b_read, b_write) = b.into_split();
+
+    let err = a_read.reunite(b_write).unwrap_err();
+    assert!(matches!(err, ReuniteError::DifferentConnections(..)));
+    let (a_read, b_write) = err.into_halves();
+
+    let (tcp, _peer) = Stream::pair_tcp().await.unwrap();
+    let (tcp_read, tcp_write) = tcp.into_split();
+
+    let err = a_read.reunite(tcp_write).unwrap_err();
+    assert!(matches!(err, ReuniteError::DifferentKinds(..)));
+    let (a_read, tcp_write) = err.into_halves();
+
+    let err = a_write.reunite(tcp_read).unwrap_err();
+    assert!(matches!(err, ReuniteError::DifferentKinds(..)));
+    let (tcp_read, a_write) = err.into_halves();
+
+    // The halves given back still reunite with their own partners.
+    let mut a = a_read.reunite(a_write).unwrap();
+    let mut b = b_write.reunite(b_read).unwrap();
+    let tcp = tcp_read.reunite(tcp_write).unwrap();
+    assert!(a.is_unix());
+    assert!(tcp.is_tcp());
+
+    a.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    b.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn zero_linger_resets_the_connection() {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use tokio::io::AsyncReadExt;
+    use tokio_anysocket::Stream;
+
+    let (a, mut b) = Stream::pair_tcp().await.unwrap();
+    assert_eq!(a.linger().unwrap(), None);
+    a.set_linger(Some(Duration::ZERO)).unwrap();
+    assert_eq!(a.linger().unwrap(), Some(Duration::ZERO));
+    drop(a);
+
+    let mut buf = [0; 16];
+    let err = b.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+}
+
+#[tokio::test]
+async fn keepalive_reads_back() {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use tokio_anysocket::{KeepaliveConfig, Stream};
+
+    let (tcp, _peer) = Stream::pair_tcp().await.unwrap();
+    assert_eq!(tcp.keepalive().unwrap(), None);
+
+    let keepalive = KeepaliveConfig {
+        time: Duration::from_secs(60),
+        interval: Duration::from_secs(10),
+        retries: 3,
+    };
+    tcp.set_keepalive(Some(keepalive)).unwrap();
+    assert_eq!(tcp.keepalive().unwrap(), Some(keepalive));
+
+    let keepalive = KeepaliveConfig {
+        time: Duration::from_secs(7),
+        interval: Duration::from_secs(2),
+        retries: 9,
+    };
+    tcp.set_keepalive(Some(keepalive)).unwrap();
+    assert_eq!(tcp.keepalive().unwrap(), Some(keepalive));
+
+    tcp.set_keepalive(None).unwrap();
+    assert_eq!(tcp.keepalive().unwrap(), None);
+
+    let (unix, _peer) = Stream::pair().unwrap();
+    assert_eq!(unix.keepalive().unwrap_err().kind(), ErrorKind::Unsupported);
+    assert_eq!(
+        unix.set_keepalive(Some(keepalive)).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+}
+
+#[tokio::test]
+async fn peek_does_not_consume() {
+    use std::future::poll_fn;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadBuf};
+    use tokio_anysocket::Stream;
+
+    for (mut a, mut b) in [Stream::pair().unwrap(), Stream::pair_tcp().await.unwrap()] {
+        a.write_all(b"hello").await.unwrap();
+
+        let mut buf = [0; 5];
+        assert_eq!(b.peek(&mut buf).await.unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+        assert_eq!(b.try_peek(&mut buf).unwrap(), 5);
+
+        let mut storage = [0; 5];
+        let mut read_buf = ReadBuf::new(&mut storage);
+        let n = poll_fn(|cx| b.poll_peek(cx, &mut read_buf)).await.unwrap();
+        assert_eq!(n, 5);
+        assert_eq!(read_buf.filled(), b"hello");
+
+        {
+            let (read, _) = b.split();
+            assert_eq!(read.peek(&mut buf).await.unwrap(), 5);
+            assert_eq!(read.try_peek(&mut buf).unwrap(), 5);
+        }
+
+        let (mut read, _write) = b.into_split();
+        assert_eq!(read.peek(&mut buf).await.unwrap(), 5);
+        assert_eq!(read.try_peek(&mut buf).unwrap(), 5);
+
+        // Everything peeked is still there to read.
+        buf = [0; 5];
+        read.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+}
+
+#[tokio::test]
+async fn shutdown_socket_sends_eof() {
+    use std::net::Shutdown;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_anysocket::Stream;
+
+    for (mut a, mut b) in [Stream::pair().unwrap(), Stream::pair_tcp().await.unwrap()] {
+        a.write_all(b"last").await.unwrap();
+        a.shutdown_socket(Shutdown::Write).unwrap();
+
+        let mut buf = Vec::new();
+        b.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"last");
+
+        // Reading still works in the other direction.
+        b.write_all(b"reply").await.unwrap();
+        let mut buf = [0; 5];
+        a.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"reply");
+    }
+
+    // Shutting down through either owned half ends the stream for the peer.
+    for (a, mut b) in [Stream::pair().unwrap(), Stream::pair_tcp().await.unwrap()] {
+        // Dropping an owned write half shuts down writing too, so keep it.
+        let (read, _write) = a.into_split();
+        read.shutdown_socket(Shutdown::Write).unwrap();
+        assert_eq!(b.read(&mut [0; 1]).await.unwrap(), 0);
+    }
+    for (a, mut b) in [Stream::pair().unwrap(), Stream::pair_tcp().await.unwrap()] {
+        let (_read, write) = a.into_split();
+        write.shutdown_socket(Shutdown::Write).unwrap();
+        assert_eq!(b.read(&mut [0; 1]).await.unwrap(), 0);
+    }
+}
+
+#[tokio::test]
+async fn pass_a_pipe() {
+    use std::fs::File;
+    use std::io::{ErrorKind, Read, Write};
+    use std::os::fd::AsFd;
+
+    use tokio_anysocket::Stream;
+
+    let (a, b) = Stream::pair().unwrap();
+    let (reader, mut writer) = std::io::pipe().unwrap();
+
+    assert_eq!(a.send_fds(b"x", &[reader.as_fd()]).await.unwrap(), 1);
+    drop(reader);
+
+    let mut buf = [0; 4];
+    let (n, fds) = b.recv_fds(&mut buf, 4).await.unwrap();
+    assert_eq!(&buf[..n], b"x");
+    assert_eq!(fds.len(), 1);
+
+    // The received descriptor is the read end of the same pipe.
+    writer.write_all(b"through the pipe").unwrap();
+    drop(writer);
+    let mut received = String::new();
+    File::from(fds.into_iter().next().unwrap())
+        .read_to_string(&mut received)
+        .unwrap();
+    assert_eq!(received, "through the pipe");
+
+    let (reader, _writer) = std::io::pipe().unwrap();
+    let e = a.send_fds(b"", &[reader.as_fd()]).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+    let (tcp, _peer) = Stream::pair_tcp().await.unwrap();
+    let e = tcp.send_fds(b"x", &[reader.as_fd()]).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    let e = tcp.recv_fds(&mut buf, 1).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn connect_timeout_gives_up_on_a_blackhole() {
+    use std::io::ErrorKind;
+    use std::time::{Duration, Instant};
+
+    use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+    let (hole, _queued) = blackhole().await;
+    let hole = SocketAddr::from(hole.local_addr().unwrap());
+
+    let start = Instant::now();
+    let e = Stream::connect_timeout(&hole, Duration::from_millis(200))
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::TimedOut, "{e}");
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+
+    // A timed out address is skipped for the next one.
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let good = listener.local_addr().unwrap();
+    let stream = Stream::connect_timeout([hole, good.clone()], Duration::from_millis(200))
+        .await
+        .unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), good);
+}
+
+#[tokio::test]
+async fn connect_with_retry_waits_for_the_listener() {
+    use std::io::ErrorKind;
+    use std::time::{Duration, Instant};
+
+    use tokio_anysocket::{Listener, RetryPolicy, SocketAddr, Stream};
+
+    let path = std::env::temp_dir().join(format!("anysocket-retry-{}.sock", std::process::id()));
+    let addr = SocketAddr::unix(&path).unwrap();
+    let policy = RetryPolicy::default()
+        .initial_delay(Duration::from_millis(20))
+        .max_delay(Duration::from_millis(50))
+        .max_attempts(Some(100));
+
+    // The socket does not exist until the listener comes up a bit later.
+    let server = tokio::spawn({
+        let addr = addr.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let listener = Listener::bind(&addr).await.unwrap();
+            listener.accept().await.unwrap();
+        }
+    });
+
+    let start = Instant::now();
+    let stream = Stream::connect_with_retry(&addr, &policy).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert_eq!(stream.peer_addr().unwrap(), addr);
+    server.await.unwrap();
+
+    // Without a listener, the policy gives up with the last error.
+    std::fs::remove_file(&path).unwrap();
+    let policy = policy.max_attempts(Some(3));
+    let start = Instant::now();
+    let e = Stream::connect_with_retry(&addr, &policy)
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound, "{e}");
+    // Two delays of at least 16ms and 32ms after jitter.
+    assert!(start.elapsed() >= Duration::from_millis(48));
+}
@@ -0,0 +1,106 @@
+#![cfg(all(feature = "systemd", target_os = "linux"))]
+
+use std::io::ErrorKind;
+use std::os::fd::{AsRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+/// Put a copy of `fd` at descriptor `target`, like systemd does.
+fn place(fd: &impl AsRawFd, target: RawFd) {
+    // SAFETY: `target` is only used by this test, and the test is the only one
+    // in this binary.
+    let x = unsafe { libc::dup2(fd.as_raw_fd(), target) };
+    assert_eq!(x, target, "dup2 failed");
+}
+
+fn set_env(pid: u32, fds: usize, names: &str) {
+    // SAFETY: This test is the only one in this binary, and no other thread
+    // runs while it changes the environment.
+    unsafe {
+        std::env::set_var("LISTEN_PID", pid.to_string());
+        std::env::set_var("LISTEN_FDS", fds.to_string());
+        std::env::set_var("LISTEN_FDNAMES", names);
+    }
+}
+
+// Each step depends on the descriptors and environment of the one before, so
+// this is one test, and it sets up the descriptors before the runtime takes
+// any.
+#[test]
+fn from_systemd() {
+    // Keep the sockets themselves off the descriptors they get copied to.
+    while std::fs::File::open("/dev/null").unwrap().into_raw_fd() < 4 {}
+
+    let dir = std::env::temp_dir().join(format!("anysocket-systemd-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let web = UnixListener::bind(dir.join("web.sock")).unwrap();
+    let admin = UnixListener::bind(dir.join("admin.sock")).unwrap();
+    let (conn, _peer) = UnixStream::pair().unwrap();
+
+    place(&conn, 3);
+    place(&admin, 4);
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let _guard = rt.enter();
+
+    // Meant for another process.
+    set_env(1, 2, "web:admin");
+    assert!(Listener::from_systemd().unwrap().is_empty());
+
+    set_env(std::process::id(), 2, "web:admin");
+    let e = Listener::from_systemd().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput, "{e}");
+    assert!(e.to_string().contains("descriptor 3"), "{e}");
+
+    place(&web, 3);
+    set_env(std::process::id(), 2, "web:");
+    let listeners = Listener::from_systemd().unwrap();
+
+    for fd in [3, 4] {
+        // SAFETY: `F_GETFD` only reads the flags of the descriptor.
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_eq!(flags, -1, "{fd} is still open");
+    }
+
+    for (_, listener) in &listeners {
+        let fd = listener.as_raw_fd();
+
+        // SAFETY: `F_GETFD` only reads the flags of the descriptor.
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0, "{fd} is inherited by children");
+    }
+
+    let names: Vec<_> = listeners.iter().map(|(x, _)| x.as_deref()).collect();
+    assert_eq!(names, [Some("web"), None]);
+
+    rt.block_on(async {
+        for ((_, listener), path) in listeners.iter().zip(["web.sock", "admin.sock"]) {
+            assert_eq!(
+                listener.local_addr().unwrap(),
+                SocketAddr::unix(dir.join(path)).unwrap()
+            );
+
+            let _client = Stream::connect(listener.local_addr().unwrap())
+                .await
+                .unwrap();
+            listener.accept().await.unwrap();
+        }
+    });
+
+    // The descriptors are not taken over twice.
+    let e = Listener::from_systemd().unwrap_err();
+    assert!(e.to_string().contains("already adopted"), "{e}");
+
+    set_env(std::process::id(), 1, "");
+    // SAFETY: As in `set_env`.
+    unsafe { std::env::set_var("LISTEN_FDS", "x") };
+    let e = Listener::from_systemd().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput, "{e}");
+
+    drop(listeners);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,117 @@
+#![cfg(all(feature = "systemd", target_os = "linux"))]
+
+use std::io::{ErrorKind, IoSliceMut};
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixDatagram, UnixStream};
+
+use rustix::net::{RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, recvmsg};
+use tokio_anysocket::Listener;
+
+/// Put a copy of `fd` at descriptor `target`, like systemd does.
+fn place(fd: &impl AsRawFd, target: RawFd) {
+    // SAFETY: `target` is only used by this test, and the test is the only one
+    // in this binary.
+    let x = unsafe { libc::dup2(fd.as_raw_fd(), target) };
+    assert_eq!(x, target, "dup2 failed");
+}
+
+/// Receive a notification and the descriptor attached to it.
+fn recv_notification(manager: &UnixDatagram) -> (String, OwnedFd) {
+    let mut buf = [0; 256];
+    let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(1))];
+    let mut control = RecvAncillaryBuffer::new(&mut space);
+
+    let msg = recvmsg(
+        manager,
+        &mut [IoSliceMut::new(&mut buf)],
+        &mut control,
+        RecvFlags::empty(),
+    )
+    .unwrap();
+    let msg = String::from_utf8(buf[..msg.bytes].to_vec()).unwrap();
+
+    let fd = control
+        .drain()
+        .find_map(|x| match x {
+            RecvAncillaryMessage::ScmRights(mut fds) => fds.next(),
+            _ => None,
+        })
+        .expect("no descriptor attached");
+
+    (msg, fd)
+}
+
+// The steps share the environment and descriptors 3 and 4, so this is one
+// test, and it sets up the descriptors before the runtime takes any.
+#[test]
+fn store_and_retrieve() {
+    // Keep the sockets themselves off the descriptors they get copied to.
+    while std::fs::File::open("/dev/null").unwrap().into_raw_fd() < 4 {}
+
+    let dir = std::env::temp_dir().join(format!("anysocket-fdstore-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let manager = UnixDatagram::bind(dir.join("notify")).unwrap();
+    let (conn, _peer) = UnixStream::pair().unwrap();
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let _guard = rt.enter();
+
+    let listener = rt.block_on(Listener::bind("tcp://127.0.0.1:0")).unwrap();
+
+    // SAFETY: This test is the only one in this binary, and no other thread
+    // runs while it changes the environment.
+    unsafe { std::env::remove_var("NOTIFY_SOCKET") };
+    let e = listener.store_in_systemd("web").unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound, "{e}");
+
+    // SAFETY: As above.
+    unsafe { std::env::set_var("NOTIFY_SOCKET", dir.join("notify")) };
+
+    for name in ["", "a:b", "with space", &"x".repeat(256)] {
+        let e = listener.store_in_systemd(name).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput, "{name:?}");
+    }
+
+    listener.store_in_systemd("web").unwrap();
+    let (msg, fd) = recv_notification(&manager);
+    assert_eq!(msg, "FDSTORE=1\nFDNAME=web");
+
+    // The next instance gets the stored listener back with its name.
+    place(&conn, 3);
+    place(&fd, 4);
+    drop(fd);
+
+    // SAFETY: As above.
+    unsafe {
+        std::env::set_var("LISTEN_PID", std::process::id().to_string());
+        std::env::set_var("LISTEN_FDS", "2");
+        std::env::set_var("LISTEN_FDNAMES", "conn:web");
+    }
+
+    assert!(Listener::from_systemd_named("missing").unwrap().is_none());
+
+    let restored = Listener::from_systemd_named("web").unwrap().unwrap();
+    assert_eq!(
+        restored.local_addr().unwrap(),
+        listener.local_addr().unwrap()
+    );
+
+    // SAFETY: `F_GETFD` only reads the flags of the descriptor.
+    let flags = unsafe { [libc::fcntl(3, libc::F_GETFD), libc::fcntl(4, libc::F_GETFD)] };
+    assert_eq!(
+        flags[0] & libc::FD_CLOEXEC,
+        0,
+        "the connection was taken over"
+    );
+    assert_eq!(flags[1], -1, "the listener was left open");
+
+    // The listener is not taken twice.
+    let e = Listener::from_systemd_named("web").unwrap_err();
+    assert!(e.to_string().contains("already adopted"), "{e}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,59 @@
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_anysocket::Stream;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+#[tokio::test]
+async fn read_times_out_mid_message() {
+    let (mut server, client) = Stream::pair_tcp().await.unwrap();
+    let mut client = client.with_timeouts(Some(TIMEOUT), None);
+
+    // Half of the message arrives, then the server goes quiet.
+    server.write_all(&[1; 50]).await.unwrap();
+
+    let start = Instant::now();
+    let mut buf = [0; 100];
+    let e = client.read_exact(&mut buf).await.unwrap_err();
+    let elapsed = start.elapsed();
+
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+    assert!(elapsed >= TIMEOUT, "{elapsed:?}");
+    assert!(elapsed < TIMEOUT * 3, "{elapsed:?}");
+    assert_eq!(buf[..50], [1; 50]);
+}
+
+#[tokio::test]
+async fn read_timeout_resets_on_progress() {
+    let (mut server, client) = Stream::pair_tcp().await.unwrap();
+    let mut client = client.with_timeouts(Some(TIMEOUT), None);
+
+    // Every chunk comes within the timeout, but the whole message does not.
+    let writer = tokio::spawn(async move {
+        for _ in 0..5 {
+            server.write_all(&[1; 10]).await.unwrap();
+            tokio::time::sleep(TIMEOUT / 2).await;
+        }
+        server
+    });
+
+    let mut buf = [0; 50];
+    client.read_exact(&mut buf).await.unwrap();
+    let _server = writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn write_times_out_when_peer_stops_reading() {
+    let (_server, client) = Stream::pair_tcp().await.unwrap();
+    client.set_send_buffer_size(4096).unwrap();
+    let (_read, mut write) = client.with_timeouts(None, Some(TIMEOUT)).into_split();
+
+    let start = Instant::now();
+    let data = vec![0; 16 * 1024 * 1024];
+    let e = write.write_all(&data).await.unwrap_err();
+
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() >= TIMEOUT);
+}
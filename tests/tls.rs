@@ -0,0 +1,521 @@
+#![cfg(any(feature = "tls", feature = "tls-native"))]
+
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+use tokio_anysocket::tls::{
+    HandshakeError, HandshakeOptions, MaybeTlsStream, TlsAcceptor, TlsConnector, TlsListener,
+};
+use tokio_anysocket::{Listener, SocketAddr, Stream};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// A certificate and its key, in the encodings of both backends.
+struct Leaf {
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    der: Vec<u8>,
+    #[cfg_attr(not(feature = "tls-native"), allow(dead_code))]
+    pem: String,
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    key_der: Vec<u8>,
+    #[cfg_attr(not(feature = "tls-native"), allow(dead_code))]
+    key_pem: String,
+}
+
+struct Pki {
+    ca: Vec<u8>,
+    server: Leaf,
+    client: Leaf,
+}
+
+fn pki() -> Pki {
+    let mut params = CertificateParams::new(Vec::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    // OpenSSL takes certificates with the same subject as their issuer for
+    // self-signed ones, so every certificate needs its own name.
+    params.distinguished_name.push(DnType::CommonName, "ca");
+    let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+
+    let leaf = |name: &str| {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![name.to_owned()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        let cert = params.signed_by(&key, &ca).unwrap();
+
+        Leaf {
+            der: cert.der().to_vec(),
+            pem: cert.pem(),
+            key_der: key.serialize_der(),
+            key_pem: key.serialize_pem(),
+        }
+    };
+
+    Pki {
+        ca: ca.der().to_vec(),
+        server: leaf("localhost"),
+        client: leaf("client"),
+    }
+}
+
+/// The handshake tests run once for each enabled backend, with both ends
+/// using it.
+#[derive(Debug, Clone, Copy)]
+enum Backend {
+    #[cfg(feature = "tls")]
+    Rustls,
+    #[cfg(feature = "tls-native")]
+    Native,
+}
+
+const BACKENDS: &[Backend] = &[
+    #[cfg(feature = "tls")]
+    Backend::Rustls,
+    #[cfg(feature = "tls-native")]
+    Backend::Native,
+];
+
+impl Backend {
+    /// A connector that trusts only the CA of `pki`, or nothing if not
+    /// `trusted`, and sends the client certificate if `client_auth`.
+    fn connector(self, pki: &Pki, trusted: bool, client_auth: bool, alpn: &[&str]) -> TlsConnector {
+        match self {
+            #[cfg(feature = "tls")]
+            Self::Rustls => rustls::connector(pki, trusted, client_auth, alpn),
+            #[cfg(feature = "tls-native")]
+            Self::Native => native::connector(pki, trusted, client_auth, alpn),
+        }
+    }
+
+    /// An acceptor with the server certificate of `pki`, offering `h2` and
+    /// `http/1.1`.
+    fn acceptor(self, pki: &Pki) -> TlsAcceptor {
+        match self {
+            #[cfg(feature = "tls")]
+            Self::Rustls => rustls::acceptor(pki, false),
+            #[cfg(feature = "tls-native")]
+            Self::Native => native::acceptor(pki),
+        }
+    }
+
+    /// Whether accepted streams report the server name the client sent.
+    fn reports_server_name(self) -> bool {
+        match self {
+            #[cfg(feature = "tls")]
+            Self::Rustls => true,
+            #[cfg(feature = "tls-native")]
+            Self::Native => false,
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+mod rustls {
+    use std::sync::Arc;
+
+    use tokio_anysocket::tls::{TlsAcceptor, TlsConnector};
+    use tokio_anysocket::tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use tokio_anysocket::tokio_rustls::rustls::server::WebPkiClientVerifier;
+    use tokio_anysocket::tokio_rustls::rustls::{
+        ClientConfig, RootCertStore, ServerConfig, crypto,
+    };
+
+    use super::{Leaf, Pki};
+
+    fn provider() -> Arc<crypto::CryptoProvider> {
+        crypto::ring::default_provider().into()
+    }
+
+    fn roots(pki: &Pki) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from(pki.ca.clone())).unwrap();
+        roots
+    }
+
+    fn identity(leaf: &Leaf) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+        let key = PrivateKeyDer::try_from(leaf.key_der.clone()).unwrap();
+        (vec![CertificateDer::from(leaf.der.clone())], key)
+    }
+
+    pub fn connector(pki: &Pki, trusted: bool, client_auth: bool, alpn: &[&str]) -> TlsConnector {
+        let roots = if trusted {
+            roots(pki)
+        } else {
+            RootCertStore::empty()
+        };
+
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+
+        let config = if client_auth {
+            let (chain, key) = identity(&pki.client);
+            builder.with_client_auth_cert(chain, key).unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let alpn = alpn.iter().map(|x| x.as_bytes().to_vec()).collect();
+        TlsConnector::new(config.into()).alpn_protocols(alpn)
+    }
+
+    pub fn acceptor(pki: &Pki, client_auth: bool) -> TlsAcceptor {
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap();
+
+        let builder = if client_auth {
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(roots(pki).into(), provider())
+                    .build()
+                    .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let (chain, key) = identity(&pki.server);
+        let mut config = builder.with_single_cert(chain, key).unwrap();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        tokio_anysocket::tokio_rustls::TlsAcceptor::from(Arc::new(config)).into()
+    }
+}
+
+#[cfg(feature = "tls-native")]
+mod native {
+    use tokio_anysocket::tls::{TlsAcceptor, TlsConnector};
+    use tokio_anysocket::tokio_native_tls::native_tls::{self, Certificate, Identity};
+
+    use super::{Leaf, Pki};
+
+    fn identity(leaf: &Leaf) -> Identity {
+        Identity::from_pkcs8(leaf.pem.as_bytes(), leaf.key_pem.as_bytes()).unwrap()
+    }
+
+    pub fn connector(pki: &Pki, trusted: bool, client_auth: bool, alpn: &[&str]) -> TlsConnector {
+        let mut builder = native_tls::TlsConnector::builder();
+        builder.disable_built_in_roots(true).request_alpns(alpn);
+
+        if trusted {
+            builder.add_root_certificate(Certificate::from_der(&pki.ca).unwrap());
+        }
+
+        if client_auth {
+            builder.identity(identity(&pki.client));
+        }
+
+        tokio_anysocket::tokio_native_tls::TlsConnector::from(builder.build().unwrap()).into()
+    }
+
+    pub fn acceptor(pki: &Pki) -> TlsAcceptor {
+        let acceptor = native_tls::TlsAcceptor::builder(identity(&pki.server))
+            .accept_alpn(&["h2", "http/1.1"])
+            .build()
+            .unwrap();
+
+        tokio_anysocket::tokio_native_tls::TlsAcceptor::from(acceptor).into()
+    }
+}
+
+fn listener(listener: Listener, acceptor: TlsAcceptor) -> TlsListener {
+    let options = HandshakeOptions::default().timeout(Some(HANDSHAKE_TIMEOUT));
+    TlsListener::with_options(listener, acceptor, options)
+}
+
+/// Connect with TLS and echo 5 bytes back.
+fn client(connector: TlsConnector, addr: String) -> JoinHandle<std::io::Result<MaybeTlsStream>> {
+    tokio::spawn(async move {
+        let mut stream = connector.connect(&addr).await?;
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await?;
+        stream.write_all(&buf).await?;
+        stream.shutdown().await?;
+        Ok(stream)
+    })
+}
+
+/// Check that the client of `stream` echoes what it is sent.
+///
+/// The stream is borrowed so that it stays open until the client has shut
+/// down its end.
+async fn check_echo(stream: &mut MaybeTlsStream) {
+    stream.write_all(b"hello").await.unwrap();
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, b"hello", "echoed data differs");
+}
+
+fn is_handshake_error(e: &std::io::Error) -> bool {
+    e.get_ref().is_some_and(|x| x.is::<HandshakeError>())
+}
+
+#[tokio::test]
+async fn tcp_with_sni_and_alpn() {
+    let pki = pki();
+
+    for &backend in BACKENDS {
+        let inner = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        let SocketAddr::Tcp(addr) = inner.local_addr().unwrap() else {
+            panic!("not a TCP listener");
+        };
+        let mut listener = listener(inner, backend.acceptor(&pki));
+
+        let connector = backend.connector(&pki, true, false, &["http/1.1"]);
+        let client = client(connector, format!("tls+tcp://localhost:{}", addr.port()));
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert!(stream.is_tls(), "{backend:?}");
+        assert_eq!(stream.alpn_protocol().as_deref(), Some(&b"http/1.1"[..]));
+        if backend.reports_server_name() {
+            assert_eq!(stream.server_name().as_deref(), Some("localhost"));
+        }
+        check_echo(&mut stream).await;
+
+        let stream = client.await.unwrap().unwrap();
+        assert!(stream.is_tls(), "{backend:?}");
+        assert!(stream.is_tcp(), "{backend:?}");
+        assert_eq!(stream.peer_addr().unwrap(), SocketAddr::Tcp(addr));
+        assert_eq!(stream.alpn_protocol().as_deref(), Some(&b"http/1.1"[..]));
+    }
+}
+
+#[tokio::test]
+async fn unix_needs_a_server_name() {
+    let pki = pki();
+
+    for &backend in BACKENDS {
+        let (inner, _guard) = Listener::bind_unix_temp("tls-").unwrap();
+        let addr = format!("tls+{:?}", inner.local_addr().unwrap());
+        let mut listener = listener(inner, backend.acceptor(&pki));
+
+        // Unix addresses have no host to take the server name from.
+        let connector = backend.connector(&pki, true, false, &[]);
+        let e = connector.connect(&addr).await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput, "{backend:?}: {e}");
+
+        let client = client(connector.server_name("localhost"), addr);
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert!(stream.is_unix(), "{backend:?}");
+        assert_eq!(stream.alpn_protocol(), None);
+        check_echo(&mut stream).await;
+
+        let stream = client.await.unwrap().unwrap();
+        assert!(stream.is_tls(), "{backend:?}");
+        assert!(stream.is_unix(), "{backend:?}");
+    }
+}
+
+#[tokio::test]
+async fn plain_addresses_skip_tls() {
+    let pki = pki();
+
+    for &backend in BACKENDS {
+        let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        let addr = format!("{:?}", listener.local_addr().unwrap());
+
+        let connector = backend.connector(&pki, true, false, &[]);
+        let (stream, accepted) = tokio::join!(connector.connect(&addr), listener.accept());
+        let mut stream = stream.unwrap();
+        let (mut accepted, _) = accepted.unwrap();
+
+        assert!(!stream.is_tls(), "{backend:?}");
+        assert_eq!(stream.alpn_protocol(), None);
+
+        stream.write_all(b"plain").await.unwrap();
+        let mut buf = [0; 5];
+        accepted.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"plain");
+    }
+}
+
+#[tokio::test]
+async fn handshake_errors_are_distinguishable() {
+    let pki = pki();
+
+    for &backend in BACKENDS {
+        for connector in [
+            // A certificate of an unknown issuer.
+            backend.connector(&pki, false, false, &[]),
+            // A certificate for another name.
+            backend
+                .connector(&pki, true, false, &[])
+                .server_name("example.com"),
+        ] {
+            let inner = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+            let addr = format!("tls+{:?}", inner.local_addr().unwrap());
+            let mut listener = listener(inner, backend.acceptor(&pki));
+
+            let (e, accepted) = tokio::join!(connector.connect(&addr), listener.accept());
+            let e = e.unwrap_err();
+            assert!(is_handshake_error(&e), "{backend:?}: {e}");
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "{backend:?}: {e}");
+
+            let e = accepted.unwrap_err();
+            assert!(is_handshake_error(&e), "{backend:?}: {e}");
+        }
+
+        // Nothing is listening, so there is no handshake.
+        let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        let addr = format!("tls+{:?}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let connector = backend.connector(&pki, true, false, &[]);
+        let e = connector.connect(&addr).await.unwrap_err();
+        assert!(!is_handshake_error(&e), "{backend:?}: {e}");
+    }
+}
+
+#[tokio::test]
+async fn listener_accepts_while_handshakes_stall() {
+    let pki = pki();
+
+    for &backend in BACKENDS {
+        let inner = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        let addr = format!("{:?}", inner.local_addr().unwrap());
+        let mut listener = listener(inner, backend.acceptor(&pki));
+
+        // Connects, but never starts the handshake.
+        let stalled = Stream::connect(addr.as_str()).await.unwrap();
+        let stalled_addr = stalled.local_addr().unwrap();
+
+        let connector = backend
+            .connector(&pki, true, false, &["h2"])
+            .server_name("localhost");
+        let good = client(connector, format!("tls+{addr}"));
+
+        let start = Instant::now();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT, "{backend:?}");
+        assert_eq!(stream.alpn_protocol().as_deref(), Some(&b"h2"[..]));
+        assert_eq!(stream.peer_certificate(), None);
+        check_echo(&mut stream).await;
+        good.await.unwrap().unwrap();
+
+        let e = listener.accept().await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut, "{backend:?}: {e}");
+        let handshake = e
+            .get_ref()
+            .and_then(|x| x.downcast_ref::<HandshakeError>())
+            .unwrap();
+        assert_eq!(handshake.peer_addr(), Some(&stalled_addr));
+    }
+}
+
+#[tokio::test]
+async fn listener_limits_concurrent_handshakes() {
+    let pki = pki();
+
+    for &backend in BACKENDS {
+        let inner = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+        let addr = format!("{:?}", inner.local_addr().unwrap());
+        let options = HandshakeOptions::default()
+            .timeout(Some(HANDSHAKE_TIMEOUT))
+            .max_handshakes(1);
+        let mut listener = TlsListener::with_options(inner, backend.acceptor(&pki), options);
+
+        let _stalled = Stream::connect(addr.as_str()).await.unwrap();
+
+        let connector = backend
+            .connector(&pki, true, false, &[])
+            .server_name("localhost");
+        let good = client(connector, format!("tls+{addr}"));
+
+        // The stalled handshake takes the only slot until it times out.
+        let start = Instant::now();
+        let e = listener.accept().await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut, "{backend:?}: {e}");
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert!(start.elapsed() >= HANDSHAKE_TIMEOUT, "{backend:?}");
+        check_echo(&mut stream).await;
+        good.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn listener_survives_rejected_handshakes() {
+    let pki = pki();
+
+    for &backend in BACKENDS {
+        let (inner, _guard) = Listener::bind_unix_temp("tls-").unwrap();
+        let addr = format!("tls+{:?}", inner.local_addr().unwrap());
+        let mut listener = listener(inner, backend.acceptor(&pki));
+
+        // The certificate of the server is not valid for this name, so the
+        // client aborts the handshake.
+        let connector = backend
+            .connector(&pki, true, false, &[])
+            .server_name("example.com");
+        let rejected = client(connector, addr.clone());
+
+        let e = listener.accept().await.unwrap_err();
+        assert!(is_handshake_error(&e), "{backend:?}: {e}");
+        let e = rejected.await.unwrap().unwrap_err();
+        assert!(is_handshake_error(&e), "{backend:?}: {e}");
+
+        let connector = backend
+            .connector(&pki, true, false, &[])
+            .server_name("localhost");
+        let good = client(connector, addr);
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        check_echo(&mut stream).await;
+        good.await.unwrap().unwrap();
+    }
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn mutual_tls() {
+    let pki = pki();
+
+    // native-tls cannot ask clients for a certificate, so the server is always
+    // rustls, but clients of both backends can send one.
+    for &backend in BACKENDS {
+        let (inner, _guard) = Listener::bind_unix_temp("tls-").unwrap();
+        let addr = format!("tls+{:?}", inner.local_addr().unwrap());
+        let mut listener = listener(inner, rustls::acceptor(&pki, true));
+
+        let connector = backend
+            .connector(&pki, true, true, &[])
+            .server_name("localhost");
+        let client = client(connector, addr);
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(stream.peer_certificate(), Some(pki.client.der.clone()));
+        check_echo(&mut stream).await;
+
+        let stream = client.await.unwrap().unwrap();
+        assert_eq!(stream.peer_certificate(), Some(pki.server.der.clone()));
+    }
+}
+
+#[cfg(all(feature = "tls", feature = "tls-native"))]
+#[tokio::test]
+async fn backends_interoperate() {
+    let pki = pki();
+
+    for (client_backend, server_backend) in [
+        (Backend::Rustls, Backend::Native),
+        (Backend::Native, Backend::Rustls),
+    ] {
+        let (inner, _guard) = Listener::bind_unix_temp("tls-").unwrap();
+        let addr = format!("tls+{:?}", inner.local_addr().unwrap());
+        let mut listener = listener(inner, server_backend.acceptor(&pki));
+
+        let connector = client_backend
+            .connector(&pki, true, false, &["h2"])
+            .server_name("localhost");
+        let client = client(connector, addr);
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(stream.alpn_protocol().as_deref(), Some(&b"h2"[..]));
+        check_echo(&mut stream).await;
+        client.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+#![cfg(feature = "tracing")]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tokio_anysocket::{Listener, Stream};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type Fields = HashMap<String, String>;
+
+/// Records every span and event with its fields.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Records>);
+
+#[derive(Default)]
+struct Records {
+    spans: Mutex<Vec<(String, Fields)>>,
+    events: Mutex<Vec<(String, Fields)>>,
+}
+
+struct Visitor<'a>(&'a mut Fields);
+
+impl Visit for Visitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let mut fields = Fields::new();
+        attrs.record(&mut Visitor(&mut fields));
+
+        let mut spans = self.0.spans.lock().unwrap();
+        spans.push((attrs.metadata().name().to_owned(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.0.spans.lock().unwrap();
+        let index = usize::try_from(span.into_u64()).unwrap() - 1;
+        values.record(&mut Visitor(&mut spans[index].1));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::new();
+        event.record(&mut Visitor(&mut fields));
+
+        let name = event.metadata().name().to_owned();
+        self.0.events.lock().unwrap().push((name, fields));
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+fn find<'a>(records: &'a [(String, Fields)], name: &str) -> Vec<&'a Fields> {
+    records
+        .iter()
+        .filter(|(x, _)| x == name)
+        .map(|(_, x)| x)
+        .collect()
+}
+
+#[tokio::test]
+async fn spans_and_events() {
+    let recorder = Recorder::default();
+    let _guard = tracing::subscriber::set_default(recorder.clone());
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = Stream::connect(&addr).await.unwrap();
+    let (server, peer) = listener.accept().await.unwrap();
+
+    drop(listener);
+    Stream::connect(&addr).await.unwrap_err();
+
+    let spans = recorder.0.spans.lock().unwrap();
+
+    let bind = find(&spans, "anysocket.bind");
+    assert_eq!(bind.len(), 1);
+    assert_eq!(bind[0]["addr"], "tcp://127.0.0.1:0");
+    assert_eq!(bind[0]["outcome"], "ok");
+    assert_eq!(bind[0]["local_addr"], addr.to_string());
+
+    let connect = find(&spans, "anysocket.connect");
+    assert_eq!(connect.len(), 2);
+    assert_eq!(connect[0]["addr"], addr.to_string());
+    assert_eq!(connect[0]["outcome"], "ok");
+    assert!(connect[0].contains_key("elapsed_us"));
+    assert_eq!(connect[1]["outcome"], "failed");
+    assert!(connect[1].contains_key("error"));
+
+    let events = recorder.0.events.lock().unwrap();
+    let accept = find(&events, "anysocket.accept");
+    assert_eq!(accept.len(), 1);
+    assert_eq!(accept[0]["peer"], peer.to_string());
+    assert_eq!(accept[0]["outcome"], "ok");
+
+    #[cfg(target_os = "linux")]
+    {
+        let (read, _write) = client.into_split();
+        let id = read.socket_cookie().unwrap().to_string();
+        assert_eq!(connect[0]["stream.id"], id);
+
+        let id = server.socket_cookie().unwrap().to_string();
+        assert_eq!(accept[0]["stream.id"], id);
+    }
+}
+
+#[tokio::test]
+async fn accept_many_emits_accept_events() {
+    let recorder = Recorder::default();
+    let _guard = tracing::subscriber::set_default(recorder.clone());
+
+    let listener = Listener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        clients.push(Stream::connect(&addr).await.unwrap());
+    }
+
+    let mut accepted = Vec::new();
+    while accepted.len() < 3 {
+        listener.accept_many(&mut accepted, 3).await.unwrap();
+    }
+
+    let events = recorder.0.events.lock().unwrap();
+    let accept = find(&events, "anysocket.accept");
+    assert_eq!(accept.len(), 3);
+
+    for ((_, peer), event) in accepted.iter().zip(accept) {
+        assert_eq!(event["peer"], peer.to_string());
+        assert_eq!(event["outcome"], "ok");
+    }
+}
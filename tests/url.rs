@@ -0,0 +1,97 @@
+#![cfg(feature = "url")]
+
+use std::io::ErrorKind;
+
+use tokio_anysocket::{AddrParseError, SocketAddr};
+use url::Url;
+
+fn roundtrip(addr: &SocketAddr, expected: &str) {
+    let url = addr.to_url().unwrap();
+    assert_eq!(url.as_str(), expected, "{addr}");
+
+    let x = SocketAddr::try_from(&url).unwrap();
+    assert_eq!(x, *addr, "{url}");
+
+    // The URL also parses back from its string.
+    let x = SocketAddr::try_from(Url::parse(expected).unwrap()).unwrap();
+    assert_eq!(x, *addr, "{url}");
+}
+
+#[test]
+fn tcp_roundtrip() {
+    roundtrip(
+        &SocketAddr::tcp([127, 0, 0, 1], 8080),
+        "tcp://127.0.0.1:8080",
+    );
+    roundtrip(
+        &SocketAddr::tcp(std::net::Ipv6Addr::LOCALHOST, 443),
+        "tcp://[::1]:443",
+    );
+}
+
+#[test]
+fn unix_roundtrip() {
+    roundtrip(
+        &SocketAddr::unix("/run/app.sock").unwrap(),
+        "unix:///run/app.sock",
+    );
+    roundtrip(
+        &SocketAddr::unix("/tmp/my app/socket 1.sock").unwrap(),
+        "unix:///tmp/my%20app/socket%201.sock",
+    );
+    roundtrip(
+        &SocketAddr::unix("run/app.sock").unwrap(),
+        "unix:run/app.sock",
+    );
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn abstract_roundtrip() {
+    roundtrip(
+        &SocketAddr::unix_abstract("anysocket").unwrap(),
+        "unix+abstract:anysocket",
+    );
+    roundtrip(
+        &SocketAddr::unix_abstract("any socket").unwrap(),
+        "unix+abstract:any%20socket",
+    );
+    roundtrip(
+        &SocketAddr::unix_abstract(b"\xffany\x00socket").unwrap(),
+        "unix+abstract:%FFany%00socket",
+    );
+
+    let url = Url::parse("unix-abstract:anysocket").unwrap();
+    assert_eq!(
+        SocketAddr::try_from(&url).unwrap(),
+        SocketAddr::unix_abstract("anysocket").unwrap(),
+    );
+}
+
+#[test]
+fn unsupported_scheme() {
+    let url = Url::parse("http://127.0.0.1:8080").unwrap();
+
+    let e = SocketAddr::try_from(&url).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    assert!(e.to_string().contains("`http`"), "{e}");
+    assert!(matches!(
+        e.get_ref().unwrap().downcast_ref::<AddrParseError>(),
+        Some(AddrParseError::UnsupportedScheme(x)) if x == "http",
+    ));
+}
+
+#[test]
+fn rejects_extra_components() {
+    for url in [
+        "tcp://user@127.0.0.1:8080",
+        "tcp://127.0.0.1:8080/path",
+        "tcp://127.0.0.1:8080?query",
+        "unix:///run/app.sock#fragment",
+        "unix://host/run/app.sock",
+    ] {
+        let url = Url::parse(url).unwrap();
+        let e = SocketAddr::try_from(&url).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput, "{url}");
+    }
+}